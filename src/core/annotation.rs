@@ -0,0 +1,278 @@
+use crate::core::core::MatrixWrapper;
+use crate::core::helper::{split_u64_to_u32s, Feature};
+use crate::graph::bubble::BubbleIndex;
+use crate::graph::parser::haplotype_counts;
+
+use gfa_reader::{Gfa, Pansn};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Header of the feature annotation (TSV)
+pub const ANNOTATION_HEADER: &str =
+    "#ID\tLENGTH\tGC\tPATHS\tSAMPLES\tSOURCE_LENGTH\tTARGET_LENGTH\tSPAN";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Annotation of a single feature (variant)
+///
+/// - Length and GC content of the feature sequence (edge: source + target node)
+/// - Number of paths and samples traversing the feature
+/// - Edges and bubbles: length of source and target (sink) node
+/// - Subpaths: spanned bp of the traversal
+pub struct FeatureAnnotation {
+    /// Variant identifier (same as in the bim file)
+    pub id: String,
+    pub length: u64,
+    /// Number of G and C
+    pub gc: u64,
+    pub paths: usize,
+    pub samples: usize,
+    pub source_length: Option<u64>,
+    pub target_length: Option<u64>,
+    pub span: Option<u64>,
+}
+
+impl FeatureAnnotation {
+    /// GC content (fraction), None if the feature has no sequence
+    pub fn gc_content(&self) -> Option<f64> {
+        if self.length == 0 {
+            None
+        } else {
+            Some(self.gc as f64 / self.length as f64)
+        }
+    }
+}
+
+impl fmt::Display for FeatureAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let na = |x: Option<u64>| x.map_or("NA".to_string(), |y| y.to_string());
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id,
+            self.length,
+            self.gc_content()
+                .map_or("NA".to_string(), |x| format!("{:.4}", x)),
+            self.paths,
+            self.samples,
+            na(self.source_length),
+            na(self.target_length),
+            na(self.span)
+        )
+    }
+}
+
+/// Length and number of G/C of a sequence
+pub fn sequence_gc(sequence: &str) -> (u64, u64) {
+    let gc = sequence
+        .bytes()
+        .filter(|x| matches!(x, b'G' | b'C' | b'g' | b'c'))
+        .count();
+    (sequence.len() as u64, gc as u64)
+}
+
+/// Number of paths and samples traversing each feature (sorted geno names)
+pub fn traversal_counts(
+    pansn: &Pansn<u32, (), ()>,
+    geno_names: &[u64],
+    feature: Feature,
+    bubbles: &BubbleIndex,
+) -> Vec<(usize, usize)> {
+    let mut counts = vec![(0, 0); geno_names.len()];
+    for genome in pansn.genomes.iter() {
+        let mut traversed = vec![false; geno_names.len()];
+        for path in genome.haplotypes.iter().flat_map(|x| x.paths.iter()) {
+            for (j, count) in haplotype_counts(&[path], geno_names, feature, bubbles)
+                .iter()
+                .enumerate()
+            {
+                if *count > 0 {
+                    counts[j].0 += 1;
+                    traversed[j] = true;
+                }
+            }
+        }
+        for (j, x) in traversed.iter().enumerate() {
+            if *x {
+                counts[j].1 += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// # Annotation of subpath features (sorted traversals of a node)
+///
+/// One row for each group of equal traversals (same order as the genotypes)
+/// - Length and GC content of the node
+/// - Span: bp of the traversal
+/// - Paths: number of haplotypes with this traversal
+pub fn traversal_annotation(
+    graph: &Gfa<u32, (), ()>,
+    node_id: u32,
+    window: usize,
+    traversals: &[(usize, usize, &[u32])],
+) -> Vec<FeatureAnnotation> {
+    let (length, gc) = sequence_gc(graph.get_sequence_by_id(&node_id));
+    let mut annotation: Vec<FeatureAnnotation> = Vec::new();
+    let mut haplotypes: Vec<Vec<[usize; 2]>> = Vec::new();
+    for (i, traversal) in traversals.iter().enumerate() {
+        if i == 0 || traversal.2 != traversals[i - 1].2 {
+            annotation.push(FeatureAnnotation {
+                id: format!("{}_{}_{}", node_id, window, annotation.len()),
+                length,
+                gc,
+                span: Some(
+                    traversal
+                        .2
+                        .iter()
+                        .map(|x| graph.get_sequence_by_id(x).len() as u64)
+                        .sum(),
+                ),
+                ..FeatureAnnotation::default()
+            });
+            haplotypes.push(Vec::new());
+        }
+        haplotypes
+            .last_mut()
+            .unwrap()
+            .push([traversal.0, traversal.1]);
+    }
+    for (x, mut haplotypes) in annotation.iter_mut().zip(haplotypes) {
+        haplotypes.sort();
+        haplotypes.dedup();
+        x.paths = haplotypes.len();
+        haplotypes.dedup_by_key(|y| y[0]);
+        x.samples = haplotypes.len();
+    }
+    annotation
+}
+
+/// Write the feature annotation (prefix.annotation.tsv)
+pub fn write_annotation(
+    out_prefix: &str,
+    annotation: &[FeatureAnnotation],
+) -> Result<(), io::Error> {
+    let output = [out_prefix, "annotation", "tsv"].join(".");
+    let mut f = BufWriter::new(File::create(output)?);
+    writeln!(f, "{}", ANNOTATION_HEADER)?;
+    for x in annotation.iter() {
+        writeln!(f, "{}", x)?;
+    }
+    f.flush()
+}
+
+impl MatrixWrapper {
+    /// # Annotation of all features (geno names)
+    ///
+    /// - Node, dirnode: node sequence
+    /// - Edge: source and target node sequence
+    /// - Bubble: sequence of the allele (traversal), source and target are the bubble ends
+    /// - Segment: sequence of all nodes, source and target are the first and last node (counts of the first node)
+    /// - Alignment (cov): node sequence, single base if a sequence position is given (sequence = true)
+    pub fn feature_annotation(
+        &self,
+        graph: &Gfa<u32, (), ()>,
+        pansn: &Pansn<u32, (), ()>,
+        feature: Feature,
+        sequence: bool,
+    ) -> Vec<FeatureAnnotation> {
+        // Alignments and segments are counted on the (first) nodes
+        let on_nodes = feature == Feature::Alignment || feature == Feature::Segment;
+        let nodes = if on_nodes {
+            self.geno_names
+                .iter()
+                .map(|x| split_u64_to_u32s(*x).0 as u64)
+                .collect::<Vec<u64>>()
+        } else {
+            self.geno_names.clone()
+        };
+        let mut count_names = nodes.clone();
+        count_names.dedup();
+        let counts = traversal_counts(
+            pansn,
+            &count_names,
+            if on_nodes { Feature::Node } else { feature },
+            &self.bubbles,
+        );
+
+        // Segments are ranges of nodes (sorted node ids)
+        let mut node_ids = Vec::new();
+        if feature == Feature::Segment {
+            node_ids = graph.segments.iter().map(|x| x.id).collect::<Vec<u32>>();
+            node_ids.sort_unstable();
+        }
+
+        let mut j = 0;
+        self.geno_names
+            .iter()
+            .zip(nodes.iter())
+            .map(|(x, node)| {
+                while count_names[j] != *node {
+                    j += 1;
+                }
+                let mut annotation = FeatureAnnotation {
+                    id: feature.to_string_u64(*x),
+                    paths: counts[j].0,
+                    samples: counts[j].1,
+                    ..FeatureAnnotation::default()
+                };
+                match feature {
+                    Feature::Node => {
+                        let node = *x as u32;
+                        (annotation.length, annotation.gc) =
+                            sequence_gc(graph.get_sequence_by_id(&node));
+                    }
+                    Feature::DirNode => {
+                        let node = (*x / 2) as u32;
+                        (annotation.length, annotation.gc) =
+                            sequence_gc(graph.get_sequence_by_id(&node));
+                    }
+                    Feature::Edge => {
+                        let (source, target) = split_u64_to_u32s(*x);
+                        let source = sequence_gc(graph.get_sequence_by_id(&(source / 2)));
+                        let target = sequence_gc(graph.get_sequence_by_id(&(target / 2)));
+                        annotation.length = source.0 + target.0;
+                        annotation.gc = source.1 + target.1;
+                        annotation.source_length = Some(source.0);
+                        annotation.target_length = Some(target.0);
+                    }
+                    Feature::Segment => {
+                        let (first, last) = split_u64_to_u32s(*x);
+                        let start = node_ids.partition_point(|y| *y < first);
+                        let end = node_ids.partition_point(|y| *y <= last);
+                        for node in node_ids[start..end].iter() {
+                            let (length, gc) = sequence_gc(graph.get_sequence_by_id(node));
+                            annotation.length += length;
+                            annotation.gc += gc;
+                        }
+                        annotation.source_length =
+                            Some(graph.get_sequence_by_id(&first).len() as u64);
+                        annotation.target_length =
+                            Some(graph.get_sequence_by_id(&last).len() as u64);
+                    }
+                    Feature::Bubble => {
+                        if let Some((bubble, allele)) = self.bubbles.get(*x) {
+                            (annotation.length, annotation.gc) = bubble.gc(graph, allele);
+                            annotation.source_length =
+                                Some(graph.get_sequence_by_id(&(bubble.source / 2)).len() as u64);
+                            annotation.target_length =
+                                Some(graph.get_sequence_by_id(&(bubble.sink / 2)).len() as u64);
+                        }
+                    }
+                    _ => {
+                        let (node, offset) = split_u64_to_u32s(*x);
+                        let node_sequence = graph.get_sequence_by_id(&node);
+                        (annotation.length, annotation.gc) = if sequence {
+                            let offset = offset as usize;
+                            sequence_gc(node_sequence.get(offset..offset + 1).unwrap_or(""))
+                        } else {
+                            sequence_gc(node_sequence)
+                        };
+                    }
+                }
+                annotation
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,294 @@
+use crate::core::error::Gfa2binError;
+use crate::core::matrix::GenotypeMatrix;
+
+use bitvec::order::Lsb0;
+use bitvec::prelude::BitVec;
+use bitvec::slice::BitSlice;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Magic bytes + SNP-major mode
+pub const BED_HEADER: [u8; 3] = [108, 27, 1];
+
+/// Mode byte (third byte) of SNP-major and sample-major (individual-major) bed files
+pub const BED_MODE_SNP_MAJOR: u8 = 1;
+pub const BED_MODE_SAMPLE_MAJOR: u8 = 0;
+
+/// PLINK 1 genotype codes (first bit, second bit) of a sample
+///
+/// https://zzz.bwh.harvard.edu/plink/binary.shtml
+pub const BED_ABSENT: (bool, bool) = (false, false);
+pub const BED_MISSING: (bool, bool) = (true, false);
+pub const BED_HET: (bool, bool) = (false, true);
+pub const BED_PRESENT: (bool, bool) = (true, true);
+
+/// Check if the genotype of a sample is missing
+pub fn is_missing(variant: &BitSlice<u8, Lsb0>, sample: usize) -> bool {
+    variant[sample * 2] && !variant[sample * 2 + 1]
+}
+
+/// Number of present alleles of a sample (0, 1, 2), None if missing
+pub fn allele_count(variant: &BitSlice<u8, Lsb0>, sample: usize) -> Option<u8> {
+    match (variant[sample * 2], variant[sample * 2 + 1]) {
+        (false, false) => Some(0),
+        (false, true) => Some(1),
+        (true, true) => Some(2),
+        (true, false) => None,
+    }
+}
+
+/// Set the genotype of a sample
+pub fn set_genotype(variant: &mut BitSlice<u8, Lsb0>, sample: usize, genotype: (bool, bool)) {
+    variant.set(sample * 2, genotype.0);
+    variant.set(sample * 2 + 1, genotype.1);
+}
+
+/// Number of bytes for a single variant (4 samples per byte)
+pub fn bytes_per_variant(samples_number: usize) -> usize {
+    samples_number.div_ceil(4)
+}
+
+/// # Streaming reader for PLINK bed files
+///
+/// Reads blocks of variants, each block is a GenotypeMatrix (two bits per sample)
+/// Memory is bounded by the block size, not by the size of the file
+/// Sample-major (individual-major) files are detected by the mode byte and transposed block by block
+pub struct BedReader {
+    reader: BufReader<File>,
+    pub samples_number: usize,
+    pub snp_number: usize,
+    pub sample_major: bool,
+    bytes_per_variant: usize,
+    block_size: usize,
+    position: usize,
+}
+
+impl BedReader {
+    /// Open a bed file and check header and size
+    pub fn new(
+        filename: &str,
+        samples_number: usize,
+        snp_number: usize,
+        block_size: usize,
+    ) -> Result<Self, Gfa2binError> {
+        let file = File::open(filename).map_err(|e| {
+            io::Error::new(e.kind(), format!("{} can not be read ({})", filename, e))
+        })?;
+        let file_size = file.metadata()?.len() as usize;
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0; 3];
+        if file_size < 3 {
+            return Err(Gfa2binError::Format(format!(
+                "{} is not a PLINK bed file (magic bytes)",
+                filename
+            )));
+        }
+        reader.read_exact(&mut header)?;
+        if header[0..2] != BED_HEADER[0..2] {
+            return Err(Gfa2binError::Format(format!(
+                "{} is not a PLINK bed file (magic bytes)",
+                filename
+            )));
+        }
+        let sample_major = match header[2] {
+            BED_MODE_SNP_MAJOR => false,
+            BED_MODE_SAMPLE_MAJOR => true,
+            mode => {
+                return Err(Gfa2binError::Format(format!(
+                    "{} has an unknown mode ({})",
+                    filename, mode
+                )))
+            }
+        };
+
+        let data_size = if sample_major {
+            bytes_per_variant(snp_number) * samples_number
+        } else {
+            bytes_per_variant(samples_number) * snp_number
+        };
+        if file_size - 3 != data_size {
+            return Err(Gfa2binError::Consistency(format!(
+                "Size of {} ({} bytes) does not fit {} samples and {} variants",
+                filename,
+                file_size - 3,
+                samples_number,
+                snp_number
+            )));
+        }
+
+        // Blocks of sample-major files start at a byte boundary
+        let block_size = if sample_major {
+            block_size.max(1).div_ceil(4) * 4
+        } else {
+            block_size.max(1)
+        };
+
+        Ok(Self {
+            reader,
+            samples_number,
+            snp_number,
+            sample_major,
+            bytes_per_variant: bytes_per_variant(samples_number),
+            block_size,
+            position: 0,
+        })
+    }
+
+    /// Read the next block of variants
+    ///
+    /// Last block might be smaller, empty if everything is read
+    pub fn read_block(&mut self) -> Result<GenotypeMatrix, io::Error> {
+        let number = self.block_size.min(self.snp_number - self.position);
+        self.read_variants(number)
+    }
+
+    /// Read all remaining variants into a single matrix
+    pub fn read_matrix(&mut self) -> Result<GenotypeMatrix, io::Error> {
+        self.read_variants(self.snp_number - self.position)
+    }
+
+    /// Read the next variants into a matrix
+    ///
+    /// SNP-major: raw data is used as it is, sample-major: each sample is read and the block is transposed
+    pub fn read_variants(&mut self, number: usize) -> Result<GenotypeMatrix, io::Error> {
+        let matrix = if !self.sample_major {
+            let mut buffer = vec![0; number * self.bytes_per_variant];
+            self.reader.read_exact(&mut buffer)?;
+            GenotypeMatrix::from_raw(buffer, number, self.samples_number)
+        } else {
+            let bytes_per_sample = bytes_per_variant(self.snp_number);
+            let bytes = bytes_per_variant(number);
+            let mut buffer = vec![0; bytes * self.samples_number];
+            for (sample, row) in buffer.chunks_exact_mut(bytes.max(1)).enumerate() {
+                let offset = 3 + sample * bytes_per_sample + self.position / 4;
+                self.reader.seek(SeekFrom::Start(offset as u64))?;
+                self.reader.read_exact(row)?;
+            }
+            GenotypeMatrix::from_raw(buffer, self.samples_number, number).transpose()
+        };
+        self.position += number;
+        Ok(matrix)
+    }
+}
+
+impl Iterator for BedReader {
+    type Item = Result<GenotypeMatrix, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.snp_number {
+            None
+        } else {
+            Some(self.read_block())
+        }
+    }
+}
+
+/// # Streaming writer for PLINK bed files (SNP-major)
+///
+/// Header is written on creation, variants are appended one by one
+pub struct BedWriter {
+    writer: BufWriter<File>,
+    pub snp_number: usize,
+}
+
+impl BedWriter {
+    pub fn new(filename: &str) -> Result<Self, io::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(&BED_HEADER)?;
+        Ok(Self {
+            writer,
+            snp_number: 0,
+        })
+    }
+
+    /// Write a single variant
+    pub fn write_variant(&mut self, variant: &BitVec<u8, Lsb0>) -> Result<(), io::Error> {
+        self.snp_number += 1;
+        self.writer.write_all(variant.as_raw_slice())
+    }
+
+    /// Write a block of variants
+    pub fn write_block(&mut self, block: &[BitVec<u8, Lsb0>]) -> Result<(), io::Error> {
+        for variant in block.iter() {
+            self.write_variant(variant)?;
+        }
+        Ok(())
+    }
+
+    /// Write all variants of a matrix (same layout, no conversion)
+    pub fn write_matrix(&mut self, matrix: &GenotypeMatrix) -> Result<(), io::Error> {
+        self.snp_number += matrix.variants_number();
+        self.writer.write_all(matrix.as_raw_slice())
+    }
+
+    /// Flush the buffer
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+}
+
+/// # Streaming writer for sample-major (individual-major) bed files
+///
+/// Each sample is written as a single row (two bits per variant)
+/// Used if samples are generated one after another (e.g. one pack file per sample)
+pub struct SampleMajorWriter {
+    writer: BufWriter<File>,
+    pub samples_number: usize,
+    pub snp_number: usize,
+}
+
+impl SampleMajorWriter {
+    pub fn new(filename: &str, snp_number: usize) -> Result<Self, io::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(&[BED_HEADER[0], BED_HEADER[1], BED_MODE_SAMPLE_MAJOR])?;
+        Ok(Self {
+            writer,
+            samples_number: 0,
+            snp_number,
+        })
+    }
+
+    /// Write a single sample
+    pub fn write_sample(&mut self, sample: &BitVec<u8, Lsb0>) -> Result<(), io::Error> {
+        if sample.len() != self.snp_number * 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Sample has {} variants, expected {}",
+                    sample.len() / 2,
+                    self.snp_number
+                ),
+            ));
+        }
+        self.samples_number += 1;
+        let mut raw = sample.as_raw_slice().to_vec();
+        raw.resize(bytes_per_variant(self.snp_number), 0);
+        self.writer.write_all(&raw)
+    }
+
+    /// Flush the buffer
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+}
+
+/// Convert a bed file (any mode) to a SNP-major bed file
+///
+/// Blocks of variants are read and written, memory is bounded by the block size
+pub fn transpose_bed(
+    input: &str,
+    output: &str,
+    samples_number: usize,
+    snp_number: usize,
+    block_size: usize,
+) -> Result<(), Gfa2binError> {
+    let reader = BedReader::new(input, samples_number, snp_number, block_size)?;
+    let mut writer = BedWriter::new(output)?;
+    for block in reader {
+        writer.write_matrix(&block?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
@@ -1,18 +1,20 @@
+use crate::core::bed::BedReader;
+use crate::core::compression::open_reader;
 use crate::core::core::MatrixWrapper;
-use crate::core::helper::Feature;
-use bitvec::order::Lsb0;
-use bitvec::prelude::BitVec;
+use crate::core::error::Gfa2binError;
+use crate::core::helper::{split_u64_to_u32s, Feature, FeatureId};
+use crate::core::metadata::SampleMetadata;
 use gfa_reader::Pansn;
+use hashbrown::HashMap;
 
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Read};
-use std::{fs, io};
+use std::io;
+use std::io::{BufRead, BufWriter};
 
 /// Read number of lines
 pub fn count_lines(file_path: &str) -> Result<usize, std::io::Error> {
-    let file =
-        File::open(file_path).unwrap_or_else(|_| panic!("ERROR: {} CAN NOT BE READ\n", file_path));
-    let reader = BufReader::new(file);
+    let reader = open_reader(file_path)?;
 
     // Count the lines using iterator folding
     let num_lines = reader.lines().count();
@@ -20,12 +22,229 @@ pub fn count_lines(file_path: &str) -> Result<usize, std::io::Error> {
     Ok(num_lines)
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// Single entry (line) of a PLINK bim file
+///
+/// https://www.cog-genomics.org/plink/1.9/formats#bim
+pub struct BimEntry {
+    pub chromosome: String,
+    pub variant_id: String,
+    pub cm_position: f64,
+    pub bp_position: u64,
+    pub allele1: String,
+    pub allele2: String,
+}
+
+impl BimEntry {
+    /// Parse a whitespace separated bim line
+    pub fn from_line(line: &str) -> Result<Self, io::Error> {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() != 6 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "BIM line has {} columns (expected 6): {}",
+                    fields.len(),
+                    line
+                ),
+            ));
+        }
+        Ok(Self {
+            chromosome: fields[0].to_string(),
+            variant_id: fields[1].to_string(),
+            cm_position: fields[2]
+                .parse()
+                .map_err(|_| invalid_field("BIM", "position (cM)", line))?,
+            bp_position: fields[3]
+                .parse()
+                .map_err(|_| invalid_field("BIM", "position (bp)", line))?,
+            allele1: fields[4].to_string(),
+            allele2: fields[5].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for BimEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.chromosome,
+            self.variant_id,
+            self.cm_position,
+            self.bp_position,
+            self.allele1,
+            self.allele2
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Single entry (line) of a PLINK fam file
+///
+/// https://www.cog-genomics.org/plink/1.9/formats#fam
+pub struct FamEntry {
+    pub family_id: String,
+    pub individual_id: String,
+    pub father_id: String,
+    pub mother_id: String,
+    pub sex: u8,
+    pub phenotype: String,
+}
+
+impl FamEntry {
+    /// Entry of a single sample (FID=IID), parents, sex and phenotype unknown
+    pub fn new(sample: &str) -> Self {
+        Self {
+            family_id: sample.to_string(),
+            individual_id: sample.to_string(),
+            father_id: "0".to_string(),
+            mother_id: "0".to_string(),
+            sex: 0,
+            phenotype: "-9".to_string(),
+        }
+    }
+
+    /// Parse a whitespace separated fam line
+    pub fn from_line(line: &str) -> Result<Self, io::Error> {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() != 6 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "FAM line has {} columns (expected 6): {}",
+                    fields.len(),
+                    line
+                ),
+            ));
+        }
+        Ok(Self {
+            family_id: fields[0].to_string(),
+            individual_id: fields[1].to_string(),
+            father_id: fields[2].to_string(),
+            mother_id: fields[3].to_string(),
+            sex: fields[4]
+                .parse()
+                .map_err(|_| invalid_field("FAM", "sex", line))?,
+            phenotype: fields[5].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for FamEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.family_id,
+            self.individual_id,
+            self.father_id,
+            self.mother_id,
+            self.sex,
+            self.phenotype
+        )
+    }
+}
+
+fn invalid_field(file_type: &str, field: &str, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Can not parse {} {}: {}", file_type, field, line),
+    )
+}
+
+/// # Reference coordinates of graph nodes
+///
+/// Node -> (chromosome index, 1-based bp position, node is on the reference)
+/// Nodes off the reference have the position of the nearest reference node
+#[derive(Debug, Clone, Default)]
+pub struct ReferencePositions {
+    pub chromosomes: Vec<String>,
+    pub positions: HashMap<u32, (usize, u64, bool)>,
+}
+
+impl ReferencePositions {
+    /// Chromosome and position of a node with an offset (bp) within the node
+    ///
+    /// Offset is only added for nodes on the reference
+    /// Nodes without reference are reported on the unknown chromosome ("0")
+    pub fn get(&self, node: u32, offset: u64) -> (String, u64) {
+        match self.positions.get(&node) {
+            Some((chromosome, position, true)) => {
+                (self.chromosomes[*chromosome].clone(), position + offset)
+            }
+            Some((chromosome, position, false)) => {
+                (self.chromosomes[*chromosome].clone(), *position)
+            }
+            None => ("0".to_string(), 0),
+        }
+    }
+}
+
+/// Read all entries of a bim file (plain-text or compressed)
+pub fn read_bim(file_path: &str) -> Result<Vec<BimEntry>, io::Error> {
+    let reader = open_reader(file_path)?;
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(BimEntry::from_line(&line)?);
+    }
+    Ok(entries)
+}
+
+/// Read all entries of a fam file (plain-text or compressed)
+pub fn read_fam(file_path: &str) -> Result<Vec<FamEntry>, io::Error> {
+    let reader = open_reader(file_path)?;
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(FamEntry::from_line(&line)?);
+    }
+    Ok(entries)
+}
+
 impl MatrixWrapper {
-    pub fn bfile_wrapper(&mut self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let _bim_count = count_lines(&format!("{}{}", filename, ".bim"))?;
-        let _fam_count = count_lines(&format!("{}{}", filename, ".fam"))?;
+    /// Read a PLINK fileset (bed, bim, fam) by its prefix
+    ///
+    /// Populates the matrix, the bim/fam entries, sample names, geno names and the feature
+    pub fn bfile_wrapper(&mut self, filename: &str) -> Result<(), Gfa2binError> {
+        self.read_bim_fam(filename)?;
+        self.read_bed(
+            &format!("{}{}", filename, ".bed"),
+            self.fam_entries.len(),
+            self.bim_entries.len(),
+        )?;
+        self.shape = (
+            self.matrix_bit.variants_number(),
+            self.sample_names.len() * 2,
+        );
+        Ok(())
+    }
+
+    /// Read bim and fam file of a PLINK fileset (no genotypes)
+    ///
+    /// Bim and fam file can be compressed (e.g. prefix.bim.zst)
+    /// Used for streaming the bed file with a BedReader
+    pub fn read_bim_fam(&mut self, filename: &str) -> Result<(), Gfa2binError> {
+        let bim_entries = read_bim(&format!("{}{}", filename, ".bim"))?;
+        let fam_entries = read_fam(&format!("{}{}", filename, ".fam"))?;
 
-        self.read_bed(&format!("{}{}", filename, ".bed"), 1, 1)?;
+        if !bim_entries.is_empty() {
+            self.feature = get_type_bim(&format!("{}{}", filename, ".bim"))?.0;
+        }
+        self.geno_names = bim2geno_names(&bim_entries, self.feature);
+        self.sample_names = fam_entries
+            .iter()
+            .map(|x| x.individual_id.clone())
+            .collect();
+        self.bim_entries = bim_entries;
+        self.fam_entries = fam_entries;
         Ok(())
     }
 
@@ -37,75 +256,136 @@ impl MatrixWrapper {
     /// File starts with:  01101100 00011011 00000001
     /// Then genotype data: 00 homo (ref), 01 hetero, 11 homo (alternative), 10 missing
     ///
-    ///      01101100
-    ///      HGFEDCBA
+    /// ```text
+    /// 01101100
+    /// HGFEDCBA
     ///
-    ///            AB   00  -- homozygote (first)
-    ///          CD     11  -- other homozygote (second)
-    ///        EF       01  -- heterozygote (third)
-    ///      GH         10  -- missing genotype (fourth)
+    ///       AB   00  -- homozygote (first)
+    ///     CD     11  -- other homozygote (second)
+    ///   EF       01  -- heterozygote (third)
+    /// GH         10  -- missing genotype (fourth)
+    /// ```
     pub fn read_bed(
         &mut self,
         filename: &str,
         samples_number: usize,
         snp_number: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut file =
-            File::open(filename).unwrap_or_else(|_| panic!("ERROR: {} CAN NOT BE READ", filename));
-        let metadata = fs::metadata(filename)?;
-        let mut buffer = vec![0; metadata.len() as usize];
-
-        file.read_exact(&mut buffer)?;
-        // cut off first 3 bytes
-        buffer = buffer[3..].to_vec();
-
-        // do i need this
-        let mut num = samples_number / 4;
-        if (samples_number % 4) > 0 {
-            num += 1;
-        }
-        // Each chunk
-        let chunks = buffer.chunks(num);
-        for chunk in chunks.into_iter() {
-            let mut bv: BitVec<u8, Lsb0> = BitVec::from_slice(chunk);
-            bv.truncate(samples_number * 2);
-
-            self.matrix_bit.push(bv);
-        }
-        assert_eq!(self.matrix_bit.len(), snp_number);
+    ) -> Result<(), Gfa2binError> {
+        // Everything in one block
+        let mut reader = BedReader::new(filename, samples_number, snp_number, snp_number)?;
+        self.matrix_bit = reader.read_matrix()?;
         Ok(())
     }
 
-    /// Read number of lines
-    fn count_lines(file_path: &str) -> Result<usize, std::io::Error> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-
-        // Count the lines using iterator folding
-        let num_lines = reader.lines().count();
+    /// Create bim entries with reference coordinates
+    ///
+    /// The variant identifier is the graph feature (unprojected)
+    pub fn project_bim(&mut self, reference: &ReferencePositions, feature: Feature) {
+        self.bim_entries = self
+            .geno_names
+            .iter()
+            .map(|x| {
+                let (node, offset) = feature2node(*x, feature);
+                let (chromosome, bp_position) = reference.get(node, offset);
+                BimEntry {
+                    chromosome,
+                    variant_id: feature.to_string_u64(*x),
+                    cm_position: 0.0,
+                    bp_position,
+                    allele1: "A".to_string(),
+                    allele2: "T".to_string(),
+                }
+            })
+            .collect();
+    }
+}
 
-        Ok(num_lines)
+/// Node and offset (bp within the node) of a feature
+///
+/// Edges and segments are placed on the first node
+pub fn feature2node(input: u64, feature: Feature) -> (u32, u64) {
+    match feature {
+        Feature::Node => (input as u32, 0),
+        Feature::DirNode => ((input / 2) as u32, 0),
+        Feature::Alignment => {
+            // Node coverage (0, node) or sequence coverage (node, offset)
+            let (left, right) = split_u64_to_u32s(input);
+            if left == 0 {
+                (right, 0)
+            } else {
+                (left, right as u64)
+            }
+        }
+        Feature::Segment => (split_u64_to_u32s(input).0, 0),
+        _ => {
+            let (left, _right) = split_u64_to_u32s(input);
+            (left / 2, 0)
+        }
     }
 }
 
-pub fn get_type_bim(file_path: &str) -> (Feature, Option<Feature>) {
-    let file = File::open(file_path).expect("ERROR: CAN NOT READ FILE\n");
+/// Convert the variant identifiers of a bim file to the internal u64 representation
+///
+/// If any identifier can not be converted (e.g. subpath or window features), the result is empty
+pub fn bim2geno_names(bim_entries: &[BimEntry], feature: Feature) -> Vec<u64> {
+    bim_entries
+        .iter()
+        .map(|x| {
+            FeatureId::decode(&x.variant_id, feature)
+                .ok()
+                .map(|y| y.value)
+        })
+        .collect::<Option<Vec<u64>>>()
+        .unwrap_or_default()
+}
 
-    // Parse plain text or gzipped file
-    let reader = BufReader::new(file);
+/// Feature of a bim file (identified by the variant id of the first line)
+pub fn get_type_bim(file_path: &str) -> Result<(Feature, Option<Feature>), Gfa2binError> {
+    // Parse plain text or compressed file (gzip, bgzip, zstd)
+    let reader = open_reader(file_path)?;
 
     // Read the first line of the file
-    let first_line = reader.lines().next().unwrap().unwrap();
-    let first_line = first_line.split_whitespace().nth(3).unwrap();
-    Feature::identify_feature(first_line)
+    let first_line = match reader.lines().next() {
+        Some(line) => line?,
+        None => {
+            return Err(Gfa2binError::Format(format!(
+                "{}: file is empty",
+                file_path
+            )))
+        }
+    };
+    let variant_id = first_line.split_whitespace().nth(1).ok_or_else(|| {
+        Gfa2binError::Format(format!("{}: no variant id: '{}'", file_path, first_line))
+    })?;
+    Ok(Feature::identify_feature(variant_id))
 }
 
 use std::io::Write;
-pub fn write_dummy_fam(pansn: &Pansn<u32, (), ()>, outfile: &str) -> Result<(), io::Error> {
+/// Write a fam file of all genomes (PanSN)
+///
+/// Without metadata: FID=IID=genome name, parents, sex and phenotype unknown
+pub fn write_dummy_fam(
+    pansn: &Pansn<u32, (), ()>,
+    outfile: &str,
+    metadata: Option<&SampleMetadata>,
+) -> Result<(), io::Error> {
     let file = File::create(outfile)?;
     let mut bufwriter = BufWriter::new(file);
-    for (i, x) in pansn.genomes.iter().enumerate() {
-        writeln!(bufwriter, "{}\t{}\t0\t0\t0\t-9", i, x.name)?;
+    let samples = pansn
+        .genomes
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<Vec<String>>();
+    let entries = match metadata {
+        Some(metadata) => {
+            let (entries, missing) = metadata.fam_entries(&samples);
+            metadata.report(&samples, &missing);
+            entries
+        }
+        None => samples.iter().map(|x| FamEntry::new(x)).collect(),
+    };
+    for x in entries.iter() {
+        writeln!(bufwriter, "{}", x)?;
     }
     Ok(())
 }
@@ -0,0 +1,268 @@
+use crate::core::core::{normalize_vector, MatrixWrapper};
+use crate::core::helper::Feature;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Magic bytes of a bgen file
+pub const BGEN_MAGIC: &[u8; 4] = b"bgen";
+
+/// Length of the header block without free data
+const BGEN_HEADER_LENGTH: u32 = 20;
+
+/// Flags: zlib compression, layout 2, sample identifiers present
+const BGEN_FLAGS: u32 = 1 | (2 << 2) | (1 << 31);
+
+/// Bits per probability
+const BGEN_BITS: u8 = 8;
+
+/// Missing flag of the ploidy byte
+const BGEN_MISSING: u8 = 0x80;
+
+/// Genotype probabilities of a single sample based on the alternative allele probability of each haplotype
+///
+/// Haplotypes are treated as independent, index is the number of alternative alleles
+/// - haploid: [P(A), P(T)]
+/// - diploid: [P(AA), P(AT), P(TT)]
+/// - polyploid: [P(0 x T), ..., P(ploidy x T)]
+pub fn genotype_probabilities(haplotypes: &[f64]) -> Vec<f64> {
+    let mut result = vec![1.0];
+    for p in haplotypes.iter() {
+        let mut next = vec![0.0; result.len() + 1];
+        for (count, value) in result.iter().enumerate() {
+            next[count] += value * (1.0 - p);
+            next[count + 1] += value * p;
+        }
+        result = next;
+    }
+    result
+}
+
+/// Convert probabilities to integers which sum up to 2^B - 1
+///
+/// Remainders are distributed to the largest fractional parts
+fn probabilities2integer(probabilities: &[f64]) -> Vec<u8> {
+    let max = ((1u32 << BGEN_BITS) - 1) as f64;
+    let scaled = probabilities
+        .iter()
+        .map(|x| x.clamp(0.0, 1.0) * max)
+        .collect::<Vec<f64>>();
+    let mut result = scaled.iter().map(|x| x.floor() as u8).collect::<Vec<u8>>();
+    let total: u32 = result.iter().map(|x| *x as u32).sum();
+
+    let mut order = (0..scaled.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| {
+        (scaled[*b] - scaled[*b].floor()).total_cmp(&(scaled[*a] - scaled[*a].floor()))
+    });
+    for index in order
+        .iter()
+        .take((max as u32).saturating_sub(total) as usize)
+    {
+        result[*index] += 1;
+    }
+    result
+}
+
+/// Write a string with a u16 length prefix
+fn write_string_u16<W: Write>(writer: &mut W, value: &str) -> Result<(), io::Error> {
+    writer.write_u16::<LittleEndian>(value.len() as u16)?;
+    writer.write_all(value.as_bytes())
+}
+
+/// # Writer for BGEN 1.2 files
+///
+/// Layout 2, zlib compressed, unphased, 8 bits per probability, two alleles (A, T)
+/// More information: https://www.well.ox.ac.uk/~gav/bgen_format/spec/v1.2.html
+pub struct BgenWriter {
+    writer: BufWriter<File>,
+    ploidy: Vec<u8>,
+}
+
+impl BgenWriter {
+    /// Write header and sample identifier block
+    pub fn new(
+        filename: &str,
+        sample_names: &[String],
+        ploidy: Vec<u8>,
+        snp_number: usize,
+    ) -> Result<Self, io::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        let sample_block_length = 8 + sample_names.iter().map(|x| 2 + x.len() as u32).sum::<u32>();
+
+        // Header block
+        writer.write_u32::<LittleEndian>(BGEN_HEADER_LENGTH + sample_block_length)?;
+        writer.write_u32::<LittleEndian>(BGEN_HEADER_LENGTH)?;
+        writer.write_u32::<LittleEndian>(snp_number as u32)?;
+        writer.write_u32::<LittleEndian>(sample_names.len() as u32)?;
+        writer.write_all(BGEN_MAGIC)?;
+        writer.write_u32::<LittleEndian>(BGEN_FLAGS)?;
+
+        // Sample identifier block
+        writer.write_u32::<LittleEndian>(sample_block_length)?;
+        writer.write_u32::<LittleEndian>(sample_names.len() as u32)?;
+        for name in sample_names.iter() {
+            write_string_u16(&mut writer, name)?;
+        }
+
+        Ok(Self { writer, ploidy })
+    }
+
+    /// Write a single variant
+    ///
+    /// Haplotypes: probability of the alternative allele (T) for each haplotype of each sample
+    /// Missing samples (None) have the missing flag and zero probabilities
+    pub fn write_variant(
+        &mut self,
+        id: &str,
+        chromosome: &str,
+        position: u32,
+        haplotypes: &[Option<Vec<f64>>],
+    ) -> Result<(), io::Error> {
+        // Variant identifying data
+        write_string_u16(&mut self.writer, id)?;
+        write_string_u16(&mut self.writer, id)?;
+        write_string_u16(&mut self.writer, chromosome)?;
+        self.writer.write_u32::<LittleEndian>(position)?;
+        self.writer.write_u16::<LittleEndian>(2)?;
+        for allele in ["A", "T"].iter() {
+            self.writer.write_u32::<LittleEndian>(allele.len() as u32)?;
+            self.writer.write_all(allele.as_bytes())?;
+        }
+
+        // Genotype data (uncompressed)
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(self.ploidy.len() as u32)?;
+        data.write_u16::<LittleEndian>(2)?;
+        data.write_u8(*self.ploidy.iter().min().unwrap_or(&2))?;
+        data.write_u8(*self.ploidy.iter().max().unwrap_or(&2))?;
+        for (ploidy, sample) in self.ploidy.iter().zip(haplotypes.iter()) {
+            data.write_u8(if sample.is_some() {
+                *ploidy
+            } else {
+                ploidy | BGEN_MISSING
+            })?;
+        }
+        data.write_u8(0)?;
+        data.write_u8(BGEN_BITS)?;
+        for (ploidy, sample) in self.ploidy.iter().zip(haplotypes.iter()) {
+            // Last probability is implied
+            match sample {
+                Some(sample) => {
+                    let probabilities = probabilities2integer(&genotype_probabilities(sample));
+                    data.write_all(&probabilities[..probabilities.len() - 1])?;
+                }
+                None => data.write_all(&vec![0; *ploidy as usize])?,
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data)?;
+        let compressed = encoder.finish()?;
+
+        self.writer
+            .write_u32::<LittleEndian>(compressed.len() as u32 + 4)?;
+        self.writer.write_u32::<LittleEndian>(data.len() as u32)?;
+        self.writer.write_all(&compressed)
+    }
+
+    /// Flush the buffer
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+}
+
+impl MatrixWrapper {
+    /// Write a bgen file
+    ///
+    /// Based on real values (no presence/absence) and a threshold, same scaling as bimbam
+    /// Ploidy is taken from the sample index
+    pub fn write_bgen<T>(
+        &self,
+        out_prefix: &str,
+        thresh: &[f32],
+        vv: &[Vec<T>],
+        feature: &Feature,
+    ) -> Result<(), io::Error>
+    where
+        T: Into<f64> + Copy,
+    {
+        let output = [out_prefix, "bgen"].join(".");
+        let ploidy = self
+            .sample_index_u16
+            .iter()
+            .map(|x| (x[1] - x[0] + 1) as u8)
+            .collect::<Vec<u8>>();
+        let mut writer = BgenWriter::new(&output, &self.sample_names, ploidy, vv.len())?;
+
+        for (i, (values, thresh)) in vv.iter().zip(thresh.iter()).enumerate() {
+            // Normalized values are 0.0 - 2.0
+            let p = normalize_vector(values, *thresh as f64);
+            let haplotypes = self
+                .sample_index_u16
+                .iter()
+                .map(|x| {
+                    if self.is_missing(i, x) {
+                        None
+                    } else {
+                        Some(p[x[0]..=x[1]].iter().map(|y| y / 2.0).collect())
+                    }
+                })
+                .collect::<Vec<Option<Vec<f64>>>>();
+
+            let (id, chromosome, position) = if self.bim_entries.is_empty() {
+                (
+                    feature.to_string_u64(self.geno_names[i]),
+                    "graph".to_string(),
+                    feature
+                        .to_string_u64_numeric(self.geno_names[i])
+                        .parse()
+                        .unwrap_or(0),
+                )
+            } else {
+                let entry = &self.bim_entries[i];
+                (
+                    entry.variant_id.clone(),
+                    entry.chromosome.clone(),
+                    entry.bp_position as u32,
+                )
+            };
+            writer.write_variant(&id, &chromosome, position, &haplotypes)?;
+        }
+        writer.finish()
+    }
+
+    /// Write sample file (Oxford format) for bgen
+    ///
+    /// https://www.well.ox.ac.uk/~gav/qctool_v2/documentation/sample_file_formats.html
+    pub fn write_sample_bgen(&self, out_prefix: &str, pheno: f64) -> Result<(), io::Error> {
+        let output = [out_prefix, "sample"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        let pheno = if pheno == f64::MAX {
+            "NA".to_string()
+        } else {
+            pheno.to_string()
+        };
+
+        writeln!(f, "ID_1 ID_2 missing pheno")?;
+        writeln!(f, "0 0 0 P")?;
+        if self.fam_entries.is_empty() {
+            for x in self.sample_names.iter() {
+                writeln!(f, "{} {} 0 {}", x, x, pheno)?;
+            }
+        } else {
+            for x in self.fam_entries.iter() {
+                let phenotype = if x.phenotype == "-9" {
+                    "NA"
+                } else {
+                    &x.phenotype
+                };
+                writeln!(f, "{} {} 0 {}", x.family_id, x.individual_id, phenotype)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use std::io::{self, Write};
+
+/// Maximum size of uncompressed data in a single block (same as htslib)
+pub const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// Empty block which marks the end of a bgzf file
+pub const BGZF_EOF: [u8; 28] = [
+    31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, 66, 67, 2, 0, 27, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// # Writer for bgzf (blocked gzip) files
+///
+/// Every block is a valid gzip member, therefore the output can be read by any gzip reader
+/// Keeps track of virtual offsets (needed for tabix/csi indices)
+/// More information: https://samtools.github.io/hts-specs/SAMv1.pdf (Section 4.1)
+pub struct BgzfWriter<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    compressed_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            compressed_offset: 0,
+        }
+    }
+
+    /// Virtual offset of the next byte
+    ///
+    /// Offset of the block in the compressed file (upper 48 bits) + offset within the block
+    pub fn virtual_offset(&self) -> u64 {
+        (self.compressed_offset << 16) | self.buffer.len() as u64
+    }
+
+    /// Compress and write the buffer as a single block
+    fn write_block(&mut self) -> Result<(), io::Error> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+
+        // Header (18 bytes) + data + crc32 + input size
+        let block_size = 18 + compressed.len() + 8;
+        self.writer
+            .write_all(&[31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, 66, 67, 2, 0])?;
+        self.writer
+            .write_u16::<LittleEndian>((block_size - 1) as u16)?;
+        self.writer.write_all(&compressed)?;
+        self.writer.write_u32::<LittleEndian>(crc.sum())?;
+        self.writer
+            .write_u32::<LittleEndian>(self.buffer.len() as u32)?;
+
+        self.compressed_offset += block_size as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Write remaining data and the EOF marker
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.writer.write_all(&BGZF_EOF)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..size]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(size)
+    }
+
+    /// Only flushes the inner writer, blocks are written when full (or on finish)
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,234 @@
+use crate::core::bgzf::BgzfWriter;
+use crate::core::error::Gfa2binError;
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Magic bytes of a gzip (and bgzip) file
+const GZIP_MAGIC: [u8; 2] = [31, 139];
+
+/// Magic bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [40, 181, 47, 253];
+
+/// Extensions tried if a (plain-text) file does not exist
+const COMPRESSED_EXTENSIONS: [&str; 3] = [".gz", ".bgz", ".zst"];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Compression of text files
+pub enum Compression {
+    None,
+    Gzip,
+    Bgzip,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = Gfa2binError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "bgzip" => Ok(Compression::Bgzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Gfa2binError::Argument(format!(
+                "Unknown compression: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl Compression {
+    pub fn to_string1(&self) -> String {
+        match self {
+            Compression::None => "none".to_string(),
+            Compression::Gzip => "gzip".to_string(),
+            Compression::Bgzip => "bgzip".to_string(),
+            Compression::Zstd => "zstd".to_string(),
+        }
+    }
+
+    /// File extension (including the dot), empty if not compressed
+    pub fn extension(&self) -> &str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip | Compression::Bgzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Detect the compression of a file by its magic bytes
+    ///
+    /// bgzip files are gzip files with a "BC" extra field
+    pub fn detect(file_path: &str) -> Result<Self, io::Error> {
+        let mut header = [0; 14];
+        let mut file = File::open(file_path)?;
+        let mut size = 0;
+        while size < header.len() {
+            let read = file.read(&mut header[size..])?;
+            if read == 0 {
+                break;
+            }
+            size += read;
+        }
+
+        if size >= 2 && header[..2] == GZIP_MAGIC {
+            if size == 14 && header[3] & 4 != 0 && header[12..14] == *b"BC" {
+                Ok(Compression::Bgzip)
+            } else {
+                Ok(Compression::Gzip)
+            }
+        } else if size >= 4 && header[..4] == ZSTD_MAGIC {
+            Ok(Compression::Zstd)
+        } else {
+            Ok(Compression::None)
+        }
+    }
+}
+
+/// Path of a file, also if only a compressed version exists
+///
+/// Tries the path itself first, then the path with a compressed extension (.gz, .bgz, .zst)
+pub fn resolve_path(file_path: &str) -> String {
+    if Path::new(file_path).exists() {
+        return file_path.to_string();
+    }
+    COMPRESSED_EXTENSIONS
+        .iter()
+        .map(|x| format!("{}{}", file_path, x))
+        .find(|x| Path::new(x).exists())
+        .unwrap_or_else(|| file_path.to_string())
+}
+
+/// Open a text file for reading (plain-text, gzip, bgzip or zstd)
+///
+/// Compression is detected by the magic bytes, not the file extension
+pub fn open_reader(file_path: &str) -> Result<Box<dyn BufRead>, io::Error> {
+    let file_path = resolve_path(file_path);
+    let file = File::open(&file_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{} can not be read ({})", file_path, e)))?;
+    let compression = Compression::detect(&file_path)?;
+    Ok(match compression {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip | Compression::Bgzip => {
+            Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file))))
+        }
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+    })
+}
+
+/// Read a whole text file (plain-text or compressed)
+pub fn read_to_string(file_path: &str) -> Result<String, io::Error> {
+    let mut content = String::new();
+    open_reader(file_path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// # Writer for (compressed) text files
+///
+/// Must be finished, otherwise the compressed output is incomplete
+pub enum TextWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Bgzip(BgzfWriter<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl TextWriter {
+    pub fn new(file_path: &str, compression: Compression) -> Result<Self, io::Error> {
+        let writer = BufWriter::new(File::create(file_path)?);
+        Ok(match compression {
+            Compression::None => TextWriter::Plain(writer),
+            Compression::Gzip => {
+                TextWriter::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Compression::Bgzip => TextWriter::Bgzip(BgzfWriter::new(writer)),
+            Compression::Zstd => TextWriter::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    /// Write remaining data (and compression footer)
+    pub fn finish(self) -> Result<(), io::Error> {
+        let mut writer = match self {
+            TextWriter::Plain(writer) => writer,
+            TextWriter::Gzip(writer) => writer.finish()?,
+            TextWriter::Bgzip(writer) => writer.finish()?,
+            TextWriter::Zstd(writer) => writer.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+impl Write for TextWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TextWriter::Plain(writer) => writer.write(buf),
+            TextWriter::Gzip(writer) => writer.write(buf),
+            TextWriter::Bgzip(writer) => writer.write(buf),
+            TextWriter::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TextWriter::Plain(writer) => writer.flush(),
+            TextWriter::Gzip(writer) => writer.flush(),
+            TextWriter::Bgzip(writer) => writer.flush(),
+            TextWriter::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// # Plain-text version of a (possibly compressed) file
+///
+/// For parsers which only accept a file name (e.g. GFA)
+/// Compressed files are decompressed into a temporary file, which is removed on drop
+pub struct PlainFile {
+    path: String,
+    temporary: bool,
+}
+
+impl PlainFile {
+    pub fn new(file_path: &str) -> Result<Self, io::Error> {
+        let file_path = resolve_path(file_path);
+        if Compression::detect(&file_path)? == Compression::None {
+            return Ok(Self {
+                path: file_path,
+                temporary: false,
+            });
+        }
+
+        let file_name = Path::new(&file_path)
+            .file_name()
+            .map_or("input".to_string(), |x| x.to_string_lossy().to_string());
+        let path = std::env::temp_dir()
+            .join(format!("gfa2bin.{}.{}", std::process::id(), file_name))
+            .to_string_lossy()
+            .to_string();
+        let mut writer = BufWriter::new(File::create(&path)?);
+        io::copy(&mut open_reader(&file_path)?, &mut writer)?;
+        writer.flush()?;
+        Ok(Self {
+            path,
+            temporary: true,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for PlainFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::core::compression::TextWriter;
+use crate::core::core::MatrixWrapper;
+use crate::core::error::Gfa2binError;
+use crate::core::pgen::PgenWriter;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use hashbrown::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+/// Header of the copy-number modes (TSV)
+pub const CN_MODE_HEADER: &str = "#ID\tMODE\tSAMPLES";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Output format of copy numbers
+///
+/// - Bimbam: copy numbers instead of dosages (prefix.bimbam)
+/// - Plink2: dosages (pgen, pvar, psam), copy numbers above 2 are not possible
+/// - Tsv: dense matrix with feature and sample names (prefix.cn.tsv)
+/// - Npy: dense matrix (float32, features x samples, NaN is missing) (prefix.cn.npy)
+pub enum CopyNumberFormat {
+    Bimbam,
+    Plink2,
+    Tsv,
+    Npy,
+}
+
+impl FromStr for CopyNumberFormat {
+    type Err = Gfa2binError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bimbam" => Ok(CopyNumberFormat::Bimbam),
+            "plink2" => Ok(CopyNumberFormat::Plink2),
+            "tsv" => Ok(CopyNumberFormat::Tsv),
+            "npy" => Ok(CopyNumberFormat::Npy),
+            _ => Err(Gfa2binError::Argument(format!(
+                "Unknown copy-number format: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl CopyNumberFormat {
+    pub fn to_string1(&self) -> String {
+        match self {
+            CopyNumberFormat::Bimbam => "bimbam".to_string(),
+            CopyNumberFormat::Plink2 => "PLINK2".to_string(),
+            CopyNumberFormat::Tsv => "tsv".to_string(),
+            CopyNumberFormat::Npy => "npy".to_string(),
+        }
+    }
+}
+
+impl MatrixWrapper {
+    /// Copy number of each sample (traversals summed over its haplotypes), capped if set
+    ///
+    /// None if the value of any haplotype is missing
+    pub fn sample_copy_numbers(&self, variant: usize, cap: Option<u16>) -> Vec<Option<u16>> {
+        let values = &self.matrix_u16[variant];
+        self.sample_index_u16
+            .iter()
+            .map(|x| {
+                if self.is_missing(variant, x) {
+                    None
+                } else {
+                    let cn = values[x[0]..=x[1]]
+                        .iter()
+                        .fold(0u16, |acc, y| acc.saturating_add(*y));
+                    Some(cap.map_or(cn, |cap| cn.min(cap)))
+                }
+            })
+            .collect()
+    }
+
+    /// Variant identifiers (bim entries if present)
+    fn variant_ids(&self) -> Vec<String> {
+        self.default_bim_entries()
+            .into_iter()
+            .map(|x| x.variant_id)
+            .collect()
+    }
+
+    /// # Write the copy numbers
+    ///
+    /// Based on the raw traversal counts (matrix_u16), missing values are NA (NaN)
+    pub fn write_copy_numbers(
+        &self,
+        out_prefix: &str,
+        format: CopyNumberFormat,
+        cap: Option<u16>,
+        pheno: f64,
+    ) -> Result<(), io::Error> {
+        match format {
+            CopyNumberFormat::Bimbam => {
+                self.write_cn_bimbam(out_prefix, cap)?;
+                self.write_phenotype_bimbam(0, out_prefix, 1, pheno)?;
+            }
+            CopyNumberFormat::Plink2 => {
+                self.write_cn_pgen(out_prefix, cap)?;
+                self.write_pvar(out_prefix, &self.feature)?;
+                self.write_psam(out_prefix, pheno)?;
+            }
+            CopyNumberFormat::Tsv => self.write_cn_tsv(out_prefix, cap)?,
+            CopyNumberFormat::Npy => self.write_cn_npy(out_prefix, cap)?,
+        }
+        Ok(())
+    }
+
+    /// Bimbam file with copy numbers (same layout as the dosage bimbam)
+    fn write_cn_bimbam(&self, out_prefix: &str, cap: Option<u16>) -> Result<(), io::Error> {
+        let output = [out_prefix, "bimbam"].join(".") + self.compression.extension();
+        let mut f = TextWriter::new(&output, self.compression)?;
+        for (i, id) in self.variant_ids().iter().enumerate() {
+            writeln!(
+                f,
+                "{}, A, T, {}",
+                id,
+                cn_strings(&self.sample_copy_numbers(i, cap)).join(",  ")
+            )?;
+        }
+        f.finish()
+    }
+
+    /// Pgen file with copy numbers as dosages
+    ///
+    /// Dosages are limited to 2, the copy numbers must be capped at 2 or less
+    fn write_cn_pgen(&self, out_prefix: &str, cap: Option<u16>) -> Result<(), io::Error> {
+        if !matches!(cap, Some(x) if x <= 2) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PLINK 2 dosages are limited to 2, copy numbers must be capped at 2 or less",
+            ));
+        }
+        let output = [out_prefix, "pgen"].join(".");
+        let mut writer = PgenWriter::new(
+            &output,
+            self.sample_index_u16.len(),
+            self.matrix_u16.len(),
+            true,
+        )?;
+        for i in 0..self.matrix_u16.len() {
+            let dosages: Vec<Option<f64>> = self
+                .sample_copy_numbers(i, cap)
+                .iter()
+                .map(|x| x.map(f64::from))
+                .collect();
+            writer.write_dosage_variant(&dosages)?;
+        }
+        writer.finish()
+    }
+
+    /// Dense copy-number matrix (prefix.cn.tsv): one row per feature, one column per sample
+    fn write_cn_tsv(&self, out_prefix: &str, cap: Option<u16>) -> Result<(), io::Error> {
+        let output = [out_prefix, "cn", "tsv"].join(".") + self.compression.extension();
+        let mut f = TextWriter::new(&output, self.compression)?;
+        writeln!(f, "#ID\t{}", self.sample_names.join("\t"))?;
+        for (i, id) in self.variant_ids().iter().enumerate() {
+            writeln!(
+                f,
+                "{}\t{}",
+                id,
+                cn_strings(&self.sample_copy_numbers(i, cap)).join("\t")
+            )?;
+        }
+        f.finish()
+    }
+
+    /// # Dense copy-number matrix as NPY (prefix.cn.npy)
+    ///
+    /// NPY 1.0, float32 (little endian), C order, shape (features, samples), NaN is missing
+    /// Feature and sample names are written to prefix.cn.features and prefix.cn.samples
+    fn write_cn_npy(&self, out_prefix: &str, cap: Option<u16>) -> Result<(), io::Error> {
+        let ids = self.variant_ids();
+        let output = [out_prefix, "cn", "npy"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        f.write_all(&npy_header(ids.len(), self.sample_index_u16.len()))?;
+        for i in 0..ids.len() {
+            for x in self.sample_copy_numbers(i, cap).iter() {
+                f.write_f32::<LittleEndian>(x.map_or(f32::NAN, f32::from))?;
+            }
+        }
+        f.flush()?;
+
+        let mut f = BufWriter::new(File::create([out_prefix, "cn", "features"].join("."))?);
+        for id in ids.iter() {
+            writeln!(f, "{}", id)?;
+        }
+        f.flush()?;
+        let mut f = BufWriter::new(File::create([out_prefix, "cn", "samples"].join("."))?);
+        for sample in self.sample_names.iter() {
+            writeln!(f, "{}", sample)?;
+        }
+        f.flush()
+    }
+
+    /// # Most common copy number of each feature
+    ///
+    /// Ties are resolved by the lower copy number, None if all samples are missing
+    /// Returns the mode and the number of samples with this copy number
+    pub fn copy_number_modes(&self, cap: Option<u16>) -> Vec<(Option<u16>, usize)> {
+        (0..self.matrix_u16.len())
+            .map(|i| {
+                let mut counts: HashMap<u16, usize> = HashMap::new();
+                for cn in self.sample_copy_numbers(i, cap).into_iter().flatten() {
+                    *counts.entry(cn).or_default() += 1;
+                }
+                counts
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+                    .map_or((None, 0), |(cn, count)| (Some(cn), count))
+            })
+            .collect()
+    }
+
+    /// Write the copy-number modes (prefix.cn_mode.tsv)
+    pub fn write_cn_modes(&self, out_prefix: &str, cap: Option<u16>) -> Result<(), io::Error> {
+        let output = [out_prefix, "cn_mode", "tsv"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        writeln!(f, "{}", CN_MODE_HEADER)?;
+        for (id, (mode, samples)) in self
+            .variant_ids()
+            .iter()
+            .zip(self.copy_number_modes(cap).iter())
+        {
+            writeln!(
+                f,
+                "{}\t{}\t{}",
+                id,
+                mode.map_or("NA".to_string(), |x| x.to_string()),
+                samples
+            )?;
+        }
+        f.flush()
+    }
+}
+
+/// Copy numbers as strings, missing is NA
+fn cn_strings(copy_numbers: &[Option<u16>]) -> Vec<String> {
+    copy_numbers
+        .iter()
+        .map(|x| x.map_or("NA".to_string(), |x| x.to_string()))
+        .collect()
+}
+
+/// NPY 1.0 header of a float32 matrix (C order), padded to a multiple of 64 bytes
+pub fn npy_header(rows: usize, columns: usize) -> Vec<u8> {
+    let mut dict = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, columns
+    );
+    // Magic (6), version (2), header length (2), dictionary and newline
+    let len = 10 + dict.len() + 1;
+    dict.push_str(&" ".repeat((64 - len % 64) % 64));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
@@ -1,4 +1,10 @@
-use crate::core::helper::{merge_u32_to_u64, Feature};
+use crate::core::bed::{BedWriter, BED_ABSENT, BED_HET, BED_MISSING, BED_PRESENT};
+use crate::core::bfile::{BimEntry, FamEntry};
+use crate::core::compression::{Compression, TextWriter};
+use crate::core::helper::{CollapsePolicy, Feature, FeatureId};
+use crate::core::matrix::GenotypeMatrix;
+use crate::graph::bubble::BubbleIndex;
+use crate::graph::segment::PavSegment;
 
 use bitvec::prelude::*;
 use gfa_reader::Gfa;
@@ -7,7 +13,27 @@ use log::info;
 
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Output format of graph and cov
+pub enum OutputFormat {
+    Plink,
+    Bimbam,
+    Plink2,
+    Bgen,
+}
+
+impl OutputFormat {
+    pub fn to_string1(&self) -> String {
+        match self {
+            OutputFormat::Plink => "PLINK".to_string(),
+            OutputFormat::Bimbam => "bimbam".to_string(),
+            OutputFormat::Plink2 => "PLINK2".to_string(),
+            OutputFormat::Bgen => "bgen".to_string(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 /// Core data structure
@@ -16,22 +42,35 @@ use std::io::{BufWriter, Write};
 /// Can represent two haplotypes (diploid) or one haplotype (haploid)
 pub struct MatrixWrapper {
     // Matrix
-    pub shape: (usize, usize),             // Update
-    pub matrix_u16: Vec<Vec<u16>>,         // Raw values
-    pub matrix_f32: Vec<Vec<f32>>,         // Normalized values
-    pub matrix_bit: Vec<BitVec<u8, Lsb0>>, // BitVec Vec<Vec<bool>>
+    pub shape: (usize, usize),                 // Update
+    pub matrix_u16: Vec<Vec<u16>>,             // Raw values
+    pub matrix_f32: Vec<Vec<f32>>,             // Normalized values
+    pub matrix_bit: GenotypeMatrix,            // Genotypes (two bits each, bed layout)
+    pub matrix_missing: Vec<BitVec<u8, Lsb0>>, // Missing values (same columns as u16/f32), empty if none
 
     // SNP
     pub feature: Feature, // Feature - DIRNODE, NODE, EDGE, subpath, block, window
     pub window_number: Vec<u32>, // Number of window
     pub window_size: usize, // Size of windows
     pub geno_names: Vec<u64>, // Name of all - "SNP" names
-    pub bim_entries: Vec<String>, // Bim entries
+    pub bim_entries: Vec<BimEntry>, // Bim entries
+    pub bubbles: BubbleIndex, // Bubbles and their alleles (bubble feature)
+    pub segments: Vec<PavSegment>, // PAV segments (segment feature)
 
     // Fam - Samples
     pub sample_names: Vec<String>, // Sample names (same order as in the matrix)
-    pub fam_entries: Vec<String>,  // Fam entries
-    pub sample_index_u16: Vec<[usize; 2]>, // Sample index [11, 12] =
+    pub fam_entries: Vec<FamEntry>, // Fam entries
+    pub sample_index_u16: Vec<[usize; 2]>, // Sample index [first, last] haplotype (inclusive)
+    pub collapse: CollapsePolicy,  // PLINK 1 genotype of polyploid samples
+
+    // Output
+    pub compression: Compression, // Compression of text output (bim, fam, bimbam)
+}
+
+impl Default for MatrixWrapper {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatrixWrapper {
@@ -40,8 +79,9 @@ impl MatrixWrapper {
         Self {
             shape: (0, 0),
             matrix_u16: Vec::new(),
-            matrix_bit: Vec::new(),
+            matrix_bit: GenotypeMatrix::default(),
             matrix_f32: Vec::new(),
+            matrix_missing: Vec::new(),
             feature: Feature::Node,
 
             // SNP
@@ -49,11 +89,17 @@ impl MatrixWrapper {
             window_number: Vec::new(),
             window_size: 0,
             bim_entries: Vec::new(),
+            bubbles: BubbleIndex::default(),
+            segments: Vec::new(),
 
             // Fam
             sample_names: Vec::new(),
             fam_entries: Vec::new(),
             sample_index_u16: Vec::new(),
+            collapse: CollapsePolicy::Het,
+
+            // Output
+            compression: Compression::None,
         }
     }
 
@@ -65,39 +111,30 @@ impl MatrixWrapper {
         let mut geno_names = Vec::new();
         match t {
             Feature::Node => {
-                for (_i, x) in data.segments.iter().enumerate() {
+                for x in data.segments.iter() {
                     geno_names.push(x.id as u64);
-                    //bb.insert(x.id as u64, i);
                 }
             }
-            Feature::DirNode => {
-                if !data.links.is_empty() {
-                    let value = &data.links;
-                    let mut edd = HashSet::new();
-                    for x in value.iter() {
-                        edd.insert(x.from as u64 * 2 + x.from_dir as u64);
-                        edd.insert(x.to as u64 * 2 + x.to_dir as u64);
-                    }
-                    let mut edd2 = edd.into_iter().collect::<Vec<u64>>();
-                    edd2.sort();
-
-                    for (_i, x) in edd2.iter().enumerate() {
-                        geno_names.push(*x);
-                    }
+            Feature::DirNode if !data.links.is_empty() => {
+                let value = &data.links;
+                let mut edd = HashSet::new();
+                for x in value.iter() {
+                    edd.insert(FeatureId::dirnode(x.from, x.from_dir).value);
+                    edd.insert(FeatureId::dirnode(x.to, x.to_dir).value);
                 }
+                let mut edd2 = edd.into_iter().collect::<Vec<u64>>();
+                edd2.sort();
+                geno_names.extend(edd2);
             }
-            Feature::Edge => {
-                if !data.links.is_empty() {
-                    let value = &data.links;
-                    for (_i, x) in value.iter().enumerate() {
-                        let (v1, v2, v3, v4) = (x.from, x.from_dir, x.to, x.to_dir);
-                        let u1 = v1 * 2 + v2 as u32;
-                        let u2 = v3 * 2 + v4 as u32;
-                        let uu = merge_u32_to_u64(u1, u2);
-                        geno_names.push(uu);
-                    }
+            Feature::Edge if !data.links.is_empty() => {
+                let value = &data.links;
+                for x in value.iter() {
+                    geno_names.push(FeatureId::edge(x.from, x.from_dir, x.to, x.to_dir).value);
                 }
             }
+            Feature::Bubble => {
+                geno_names = self.bubbles.geno_names();
+            }
             _ => {}
         }
         // Sort it, otherwise does not work
@@ -106,28 +143,37 @@ impl MatrixWrapper {
     }
 
     /// Create a presence/absence matrix based on a threshold
-
+    ///
+    /// Samples with more than two haplotypes are collapsed (see CollapsePolicy)
+    /// Samples with a missing value (any haplotype) are missing
     pub fn matrix2bin<T>(
-        input_data: &Vec<Vec<T>>,
-        relative: &Vec<f32>,
-        sample_index: &Vec<[usize; 2]>,
-    ) -> Vec<BitVec<u8>>
+        input_data: &[Vec<T>],
+        relative: &[f32],
+        sample_index: &[[usize; 2]],
+        collapse: CollapsePolicy,
+        missing: &[BitVec<u8, Lsb0>],
+    ) -> GenotypeMatrix
     where
         T: PartialOrd + Copy + Into<f64>,
     {
-        let mut matrix_bin = Vec::new();
-        for (val, re) in input_data.iter().zip(relative.iter()) {
-            let mut biit = BitVec::new();
-            for aa in sample_index.iter() {
-                if aa[0] == aa[1] {
+        let mut matrix_bin = GenotypeMatrix::new(input_data.len(), sample_index.len());
+        for (i, (val, re)) in input_data.iter().zip(relative.iter()).enumerate() {
+            for (sample, aa) in sample_index.iter().enumerate() {
+                let genotype = if !missing.is_empty() && missing[i][aa[0]..=aa[1]].any() {
+                    BED_MISSING
+                } else if aa[0] == aa[1] {
                     let a = val[aa[0]].into();
                     if a >= *re as f64 {
-                        biit.push(true);
-                        biit.push(true);
+                        BED_PRESENT
                     } else {
-                        biit.push(false);
-                        biit.push(false);
+                        BED_ABSENT
                     }
+                } else if aa[1] - aa[0] > 1 {
+                    let count = val[aa[0]..=aa[1]]
+                        .iter()
+                        .filter(|x| (**x).into() >= *re as f64)
+                        .count();
+                    collapse.collapse(count, aa[1] - aa[0] + 1)
                 } else {
                     let a = val[aa[0]].into();
                     let b = val[aa[1]].into();
@@ -136,79 +182,101 @@ impl MatrixWrapper {
                     let b1 = b >= *re as f64;
 
                     if a1 && b1 {
-                        biit.push(true);
-                        biit.push(true);
+                        BED_PRESENT
                     } else if a1 != b1 {
-                        biit.push(false);
-                        biit.push(true);
+                        BED_HET
                     } else {
-                        biit.push(false);
-                        biit.push(false);
+                        BED_ABSENT
                     }
-                }
+                };
+                matrix_bin.set_genotype(i, sample, genotype);
             }
-            matrix_bin.push(biit)
         }
         matrix_bin
     }
 
     //----------------------------------------------------------------------------------
     /// Write wrapper
+    #[allow(clippy::too_many_arguments)]
     pub fn write_wrapper(
         &mut self,
-        bimbam: bool,
+        output_format: OutputFormat,
         split: usize,
         output_prefix: &str,
         thresh: Vec<f32>,
         feature_enum: Feature,
         pheno: f64,
         remove_non_info: bool,
-    ) {
-        if bimbam {
+    ) -> Result<(), io::Error> {
+        if output_format == OutputFormat::Plink2 {
+            info!("Writing the plink2 pgen/pvar/psam");
+            self.write_pgen(output_prefix, &thresh)?;
+            self.write_pvar(output_prefix, &feature_enum)?;
+            self.write_psam(output_prefix, pheno)?;
+        } else if output_format == OutputFormat::Bgen {
+            info!("Writing the bgen");
+            if self.matrix_f32.is_empty() {
+                self.write_bgen(output_prefix, &thresh, &self.matrix_u16, &feature_enum)?;
+            } else {
+                self.write_bgen(output_prefix, &thresh, &self.matrix_f32, &feature_enum)?;
+            }
+            self.write_sample_bgen(output_prefix, pheno)?;
+        } else if output_format == OutputFormat::Bimbam {
             info!("Writing the bimbam");
 
             if self.matrix_f32.is_empty() {
-                self.write_bimbam(0, output_prefix, 1, &thresh, &self.matrix_u16);
-                self.write_phenotype_bimbam(0, output_prefix, 1, pheno);
+                self.write_bimbam(0, output_prefix, 1, &thresh, &self.matrix_u16)?;
+                self.write_phenotype_bimbam(0, output_prefix, 1, pheno)?;
             } else {
-                self.write_bimbam(0, output_prefix, 1, &thresh, &self.matrix_f32);
-                self.write_phenotype_bimbam(0, output_prefix, 1, pheno);
+                self.write_bimbam(0, output_prefix, 1, &thresh, &self.matrix_f32)?;
+                self.write_phenotype_bimbam(0, output_prefix, 1, pheno)?;
             }
 
             // if plink bed
         } else {
-            if self.matrix_bit.is_empty() {
-                if !self.matrix_f32.is_empty() {
-                    self.matrix_bit = MatrixWrapper::matrix2bin(
-                        &self.matrix_f32,
-                        &thresh,
-                        &self.sample_index_u16,
-                    );
-                } else {
-                    self.matrix_bit = MatrixWrapper::matrix2bin(
-                        &self.matrix_u16,
-                        &thresh,
-                        &self.sample_index_u16,
-                    );
-                }
-            }
+            self.fill_genotypes(&thresh);
             info!(
                 "Matrix [Genotypes X Samples] (before remove): {}, {}",
-                self.matrix_bit.len(),
-                self.matrix_bit[0].len()
+                self.matrix_bit.variants_number(),
+                self.matrix_bit.samples_number() * 2
             );
             if remove_non_info {
                 //self.remove_non_info();
                 info!(
                     "Matrix [Genotypes X Samples] (after remove): {}, {}",
-                    self.matrix_bit.len(),
-                    self.matrix_bit[0].len()
+                    self.matrix_bit.variants_number(),
+                    self.matrix_bit.samples_number() * 2
                 );
             }
 
             // Output
             info!("Writing the plink bed/bim/fam");
-            self.write_chunks(split, output_prefix, feature_enum, pheno);
+            self.write_chunks(split, output_prefix, feature_enum, pheno)?;
+        }
+        Ok(())
+    }
+
+    /// Genotypes (matrix_bit) from the raw or normalized values, if not present yet
+    pub fn fill_genotypes(&mut self, thresh: &[f32]) {
+        if !self.matrix_bit.is_empty() {
+            return;
+        }
+        if !self.matrix_f32.is_empty() {
+            self.matrix_bit = MatrixWrapper::matrix2bin(
+                &self.matrix_f32,
+                thresh,
+                &self.sample_index_u16,
+                self.collapse,
+                &self.matrix_missing,
+            );
+        } else {
+            self.matrix_bit = MatrixWrapper::matrix2bin(
+                &self.matrix_u16,
+                thresh,
+                &self.sample_index_u16,
+                self.collapse,
+                &self.matrix_missing,
+            );
         }
     }
 
@@ -218,22 +286,28 @@ impl MatrixWrapper {
     /// - bed
     /// - bim
     /// - fam
-    pub fn write_chunks(&self, split: usize, output_prefix: &str, feature: Feature, pheno: f64) {
-        let chunk_size = (self.matrix_bit.len() / split) + 1;
-        let chunks = self.matrix_bit.chunks(chunk_size);
-
-        let len = chunks.len();
-        for (index, _y) in chunks.enumerate() {
-            self.write_fam(index, output_prefix, feature, len, pheno);
-            self.write_bed(index, output_prefix, feature, len);
-            self.write_bim(index, output_prefix, &feature, len);
+    pub fn write_chunks(
+        &self,
+        split: usize,
+        output_prefix: &str,
+        feature: Feature,
+        pheno: f64,
+    ) -> Result<(), io::Error> {
+        let chunk_size = (self.matrix_bit.variants_number() / split) + 1;
+        let len = self.matrix_bit.variants_number().div_ceil(chunk_size);
+        for index in 0..len {
+            self.write_fam(index, output_prefix, feature, len, pheno)?;
+            self.write_bed(index, output_prefix, feature, len)?;
+            self.write_bim(index, output_prefix, &feature, len)?;
         }
+        Ok(())
     }
 
     /// Write "empty" fam with no phenotypes
     ///
     /// Contains the names of the samples in the same order as plink bed file
     /// Stays the same for all runs
+    /// Compressed if a compression is set (extension is added)
     pub fn write_fam(
         &self,
         number: usize,
@@ -241,7 +315,7 @@ impl MatrixWrapper {
         _feature: Feature,
         len: usize,
         mut pheno: f64,
-    ) {
+    ) -> Result<(), io::Error> {
         let mut output = [out_prefix, &number.to_string(), "fam"].join(".");
         if len == 1 {
             output = [out_prefix, "fam"].join(".");
@@ -249,44 +323,44 @@ impl MatrixWrapper {
         if pheno == f64::MAX {
             pheno = -9.0
         }
+        output += self.compression.extension();
 
-        let f = File::create(output).expect("Unable to create file");
-        let mut f = BufWriter::new(f);
+        let mut f = TextWriter::new(&output, self.compression)?;
         if self.fam_entries.is_empty() {
             for x in self.sample_names.iter() {
-                writeln!(f, "{}\t{}\t{}\t{}\t{}\t{}", x, x, 0, 0, 0, pheno)
-                    .expect("Can not write file");
+                writeln!(f, "{}\t{}\t{}\t{}\t{}\t{}", x, x, 0, 0, 0, pheno)?;
             }
         } else {
             for x in self.fam_entries.iter() {
-                writeln!(f, "{}", x).expect("Can not write file");
+                writeln!(f, "{}", x)?;
             }
         }
+        f.finish()
     }
 
     /// Write bed file in SNP-major mode
     ///
     /// https://zzz.bwh.harvard.edu/plink/binary.shtml
-    pub fn write_bed(&self, number: usize, out_prefix: &str, _feature: Feature, len: usize) {
+    pub fn write_bed(
+        &self,
+        number: usize,
+        out_prefix: &str,
+        _feature: Feature,
+        len: usize,
+    ) -> Result<(), io::Error> {
         //hexdump -C test.bin
         // xxd -b file
         // xxd file
         // SNP: 00000001 , 0
         // IND: 00000000, 1
 
-        let mut buff: Vec<u8> = vec![108, 27, 1];
-        // Make SNP Vector
-
-        for sel in self.matrix_bit.iter() {
-            buff.extend(sel.as_raw_slice());
-        }
-
         let mut output = [out_prefix, &number.to_string(), "bed"].join(".");
         if len == 1 {
             output = [out_prefix, "bed"].join(".");
         }
-        let mut file = File::create(output).expect("Not able to write ");
-        file.write_all(&buff).expect("Not able to write ")
+        let mut writer = BedWriter::new(&output)?;
+        writer.write_matrix(&self.matrix_bit)?;
+        writer.finish()
     }
 
     /// Write bim file
@@ -300,13 +374,20 @@ impl MatrixWrapper {
     /// Allele 1 (corresponding to clear bits in .bed; usually minor)
     /// Allele 2 (corresponding to set bits in .bed; usually major)
     /// Representation here: [graph, ., 1, 0, A, T]
-    pub fn write_bim(&self, number: usize, out_prefix: &str, feature: &Feature, len: usize) {
+    /// Compressed if a compression is set (extension is added)
+    pub fn write_bim(
+        &self,
+        number: usize,
+        out_prefix: &str,
+        feature: &Feature,
+        len: usize,
+    ) -> Result<(), io::Error> {
         let mut output = [out_prefix, &number.to_string(), "bim"].join(".");
         if len == 1 {
             output = [out_prefix, "bim"].join(".");
         }
-        let file = File::create(output).expect("Unable to create file");
-        let mut f = BufWriter::new(file);
+        output += self.compression.extension();
+        let mut f = TextWriter::new(&output, self.compression)?;
         if self.bim_entries.is_empty() {
             for x in self.geno_names.iter() {
                 writeln!(
@@ -315,67 +396,131 @@ impl MatrixWrapper {
                     feature.to_string_u64(*x),
                     0,
                     feature.to_string_u64_numeric(*x)
-                )
-                .expect("Can not write file");
+                )?;
             }
         } else {
             for x in self.bim_entries.iter() {
-                writeln!(f, "{}", x).expect("Can not write file");
+                writeln!(f, "{}", x)?;
             }
         }
+        f.finish()
+    }
+
+    /// Bim entries of all features (default: chromosome "graph" and the node id as position)
+    pub fn default_bim_entries(&self) -> Vec<BimEntry> {
+        if !self.bim_entries.is_empty() {
+            return self.bim_entries.clone();
+        }
+        self.geno_names
+            .iter()
+            .map(|x| BimEntry {
+                chromosome: "graph".to_string(),
+                variant_id: self.feature.to_string_u64(*x),
+                cm_position: 0.0,
+                bp_position: FeatureId::new(self.feature, *x).position(),
+                allele1: "A".to_string(),
+                allele2: "T".to_string(),
+            })
+            .collect()
+    }
+
+    /// # Append the features of another wrapper (same samples)
+    ///
+    /// Bim entries are kept for both, therefore features of different types can be written together
+    pub fn append_features(&mut self, other: MatrixWrapper) {
+        self.bim_entries = self.default_bim_entries();
+        self.bim_entries.extend(other.default_bim_entries());
+        self.geno_names.extend(other.geno_names);
+        self.matrix_u16.extend(other.matrix_u16);
+        self.matrix_f32.extend(other.matrix_f32);
+        self.matrix_missing.extend(other.matrix_missing);
+        self.matrix_bit.append(&other.matrix_bit);
+        if self.bubbles.is_empty() {
+            self.bubbles = other.bubbles;
+        }
+        self.segments.extend(other.segments);
+        self.shape.0 += other.shape.0;
     }
 
     /// Write a bimbam file
     ///
     /// Based on real values (no presence/absence) and a threshold
-    /// Default genotype is A and T
+    /// Default genotype is A and T, missing values are NA
+    /// Compressed if a compression is set (extension is added)
     pub fn write_bimbam<T>(
         &self,
         number: usize,
         out_prefix: &str,
         len: usize,
-        val: &Vec<f32>,
+        val: &[f32],
         vv: &[Vec<T>],
-    ) where
+    ) -> Result<(), io::Error>
+    where
         T: Into<f64> + Copy,
     {
         let mut output = [out_prefix, &number.to_string(), "bimbam"].join(".");
         if len == 1 {
             output = [out_prefix, "bimbam"].join(".");
         }
-        let file = File::create(output).expect("Unable to create file");
-        let mut f = BufWriter::new(file);
+        output += self.compression.extension();
+        let mut f = TextWriter::new(&output, self.compression)?;
+
+        for ((i, x1), thresh) in self.geno_names.iter().enumerate().zip(val.iter()) {
+            let p2 = self.sample_dosage(i, &vv[i], *thresh);
+            writeln!(
+                f,
+                "{}, A, T, {}",
+                self.bim_entries
+                    .get(i)
+                    .map_or_else(|| self.feature.to_string_u64(*x1), |x| x.variant_id.clone()),
+                p2.iter()
+                    .map(|n| n.map_or("NA".to_string(), |n| n.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(",  ")
+            )?;
+        }
+        f.finish()
+    }
 
-        if self.bim_entries.is_empty() {
-            for ((i, x1), thresh) in self.geno_names.iter().enumerate().zip(val.iter()) {
-                let p = normalize_vector(&vv[i], *thresh as f64);
-                let mut p2 = Vec::new();
-                for x in self.sample_index_u16.iter() {
-                    if x[0] == x[1] {
-                        p2.push(p[x[0]]);
-                    } else {
-                        p2.push(average(&[p[x[0]], p[x[1]]]));
-                    }
-                }
-                writeln!(
-                    f,
-                    "{}, A, T, {}",
-                    self.feature.to_string_u64(*x1),
-                    p2.iter()
-                        .map(|n| n.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",  ")
-                )
-                .expect("Can not write file");
+    /// Dosage (0.0 - 2.0) for each sample
+    ///
+    /// Values are normalized by the threshold, haplotypes of a sample are averaged
+    /// (polyploid: fraction of haplotypes carrying the feature, scaled to 2.0)
+    /// None if the value of any haplotype is missing
+    pub fn sample_dosage<T>(&self, variant: usize, values: &[T], thresh: f32) -> Vec<Option<f64>>
+    where
+        T: Into<f64> + Copy,
+    {
+        let p = normalize_vector(values, thresh as f64);
+        let mut p2 = Vec::with_capacity(self.sample_index_u16.len());
+        for x in self.sample_index_u16.iter() {
+            if self.is_missing(variant, x) {
+                p2.push(None);
+            } else {
+                p2.push(Some(average(&p[x[0]..=x[1]])));
             }
         }
+        p2
+    }
+
+    /// Check if any haplotype (column) of a sample is missing
+    pub fn is_missing(&self, variant: usize, sample_index: &[usize; 2]) -> bool {
+        !self.matrix_missing.is_empty()
+            && self.matrix_missing[variant][sample_index[0]..=sample_index[1]].any()
     }
 
-    /// Write "empty" pheno file for bimbam
+    /// Write pheno file for bimbam
     ///
-    /// Format - simple list of sample names
-    /// - Sample name
-    pub fn write_phenotype_bimbam(&self, number: usize, out_prefix: &str, len: usize, pheno: f64) {
+    /// Format - one phenotype per line (same order as the samples)
+    /// - Phenotype of the fam entries (if set), otherwise the dummy phenotype
+    /// - Missing phenotype is NA
+    pub fn write_phenotype_bimbam(
+        &self,
+        number: usize,
+        out_prefix: &str,
+        len: usize,
+        pheno: f64,
+    ) -> Result<(), io::Error> {
         let mut output = [out_prefix, &number.to_string(), "pheno"].join(".");
         if len == 1 {
             output = [out_prefix, "pheno"].join(".");
@@ -386,20 +531,26 @@ impl MatrixWrapper {
             pheno_string = "NA".to_string();
         }
 
-        let f = File::create(output).expect("Unable to create file");
-        let mut f = BufWriter::new(f);
+        let mut f = BufWriter::new(File::create(output)?);
         if self.fam_entries.is_empty() {
             for _x in self.sample_names.iter() {
-                writeln!(f, "{}", pheno).expect("Can not write file");
+                writeln!(f, "{}", pheno_string)?;
             }
         } else {
-            panic!("Text")
+            for x in self.fam_entries.iter() {
+                if x.phenotype == "-9" {
+                    writeln!(f, "NA")?;
+                } else {
+                    writeln!(f, "{}", x.phenotype)?;
+                }
+            }
         }
+        f.flush()
     }
 }
 
 /// Normalize the vector
-fn normalize_vector<T>(vector: &[T], value: f64) -> Vec<f64>
+pub fn normalize_vector<T>(vector: &[T], value: f64) -> Vec<f64>
 where
     T: Into<f64> + Copy,
 {
@@ -415,6 +566,6 @@ where
     normalized
 }
 
-fn average(l: &[f64; 2]) -> f64 {
-    (l[0] + l[1]) / 2.0
+fn average(l: &[f64]) -> f64 {
+    l.iter().sum::<f64>() / l.len() as f64
 }
@@ -0,0 +1,246 @@
+use crate::core::bed::{BedReader, BedWriter};
+use crate::core::compression::{open_reader, Compression};
+use crate::core::core::MatrixWrapper;
+use crate::core::error::Gfa2binError;
+use crate::core::matrix::GenotypeMatrix;
+use crate::remove::remove_main::retain_by_index;
+
+use hashbrown::HashMap;
+use log::info;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+
+/// Header of the deduplication mapping (TSV)
+pub const DEDUP_HEADER: &str = "#REPRESENTATIVE\tFEATURE";
+
+/// Column names of the variant identifier in association results (PLINK 2, PLINK 1.9, GEMMA)
+pub const RESULT_ID_COLUMNS: [&str; 3] = ["ID", "SNP", "rs"];
+
+impl MatrixWrapper {
+    /// # Keep one representative per unique genotype pattern
+    ///
+    /// Based on the genotypes (matrix_bit), the first feature of each pattern is the representative
+    /// - Raw values (u16, f32) are removed, the genotypes are written
+    /// - Returns the mapping (representative, feature) of all features in input order
+    pub fn dedup_patterns(&mut self) -> Vec<(String, String)> {
+        let variants_number = self.matrix_bit.variants_number();
+        let mut representatives = Vec::with_capacity(variants_number);
+        let mut matrix_bit = GenotypeMatrix::new(0, self.matrix_bit.samples_number());
+        let mut patterns: HashMap<&[u8], usize> = HashMap::new();
+        for i in 0..variants_number {
+            let pattern = self.matrix_bit.raw_variants(i..i + 1);
+            let representative = *patterns.entry(pattern).or_insert(i);
+            if representative == i {
+                matrix_bit.push_variant(self.matrix_bit.variant(i));
+            }
+            representatives.push(representative);
+        }
+        drop(patterns);
+        info!(
+            "Unique genotype patterns: {} (of {})",
+            matrix_bit.variants_number(),
+            variants_number
+        );
+
+        self.matrix_bit = matrix_bit;
+        self.matrix_u16 = Vec::new();
+        self.matrix_f32 = Vec::new();
+        self.matrix_missing = Vec::new();
+        self.bim_entries = self.default_bim_entries();
+        let mapping = self.retain_representatives(&representatives);
+        self.shape.0 = self.matrix_bit.variants_number();
+        mapping
+    }
+
+    /// # Keep the features (bim entries, names) which represent a genotype pattern
+    ///
+    /// Representatives: index of the representative of each feature (see dedup_bed)
+    /// Returns the mapping (representative, feature) of all features in input order
+    pub fn retain_representatives(&mut self, representatives: &[usize]) -> Vec<(String, String)> {
+        let ids: Vec<String> = self
+            .default_bim_entries()
+            .into_iter()
+            .map(|x| x.variant_id)
+            .collect();
+        let mapping = representatives
+            .iter()
+            .zip(ids.iter())
+            .map(|(representative, id)| (ids[*representative].clone(), id.clone()))
+            .collect();
+
+        let remove = representatives
+            .iter()
+            .enumerate()
+            .filter(|(i, representative)| i != *representative)
+            .map(|(i, _)| i)
+            .collect::<HashSet<usize>>();
+        retain_by_index(&mut self.bim_entries, &remove);
+        retain_by_index(&mut self.geno_names, &remove);
+        mapping
+    }
+}
+
+/// # Deduplicate a bed file (any mode) while streaming it
+///
+/// The first variant of each genotype pattern is written (SNP-major), only the unique patterns are kept
+/// Returns the index of the representative of each variant
+pub fn dedup_bed(
+    input: &str,
+    output: &str,
+    samples_number: usize,
+    snp_number: usize,
+    block_size: usize,
+) -> Result<Vec<usize>, Gfa2binError> {
+    let reader = BedReader::new(input, samples_number, snp_number, block_size)?;
+    let mut writer = BedWriter::new(output)?;
+    let mut representatives = Vec::with_capacity(snp_number);
+    let mut patterns: HashMap<Vec<u8>, usize> = HashMap::new();
+    for block in reader {
+        let block = block?;
+        let mut unique = GenotypeMatrix::new(0, samples_number);
+        for i in 0..block.variants_number() {
+            let pattern = block.raw_variants(i..i + 1);
+            let representative = match patterns.get(pattern) {
+                Some(representative) => *representative,
+                None => {
+                    patterns.insert(pattern.to_vec(), representatives.len());
+                    unique.push_variant(block.variant(i));
+                    representatives.len()
+                }
+            };
+            representatives.push(representative);
+        }
+        writer.write_matrix(&unique)?;
+    }
+    writer.finish()?;
+    info!(
+        "Unique genotype patterns: {} (of {})",
+        patterns.len(),
+        snp_number
+    );
+    Ok(representatives)
+}
+
+/// Write the deduplication mapping (prefix.dedup.tsv)
+///
+/// One line per feature, representatives map to themselves
+pub fn write_dedup_mapping(out_prefix: &str, mapping: &[(String, String)]) -> io::Result<()> {
+    let output = [out_prefix, "dedup", "tsv"].join(".");
+    let mut f = BufWriter::new(File::create(output)?);
+    writeln!(f, "{}", DEDUP_HEADER)?;
+    for (representative, id) in mapping.iter() {
+        writeln!(f, "{}\t{}", representative, id)?;
+    }
+    f.flush()
+}
+
+/// Read the deduplication mapping: representative -> all features (input order)
+pub fn read_dedup_mapping(file: &str) -> Result<HashMap<String, Vec<String>>, Gfa2binError> {
+    let mut mapping: HashMap<String, Vec<String>> = HashMap::new();
+    for line in open_reader(file)?.lines() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        match (fields.next(), fields.next()) {
+            (Some(representative), Some(id)) => mapping
+                .entry(representative.to_string())
+                .or_default()
+                .push(id.to_string()),
+            _ => {
+                return Err(Gfa2binError::Format(format!(
+                    "{}: expected two columns (representative, feature): '{}'",
+                    file, line
+                )))
+            }
+        }
+    }
+    Ok(mapping)
+}
+
+/// # Deduplicate a PLINK fileset (bed, bim, fam)
+///
+/// Input and output prefix can be the same (the fileset is read completely before writing)
+/// Writes the fileset and the mapping (output.dedup.tsv)
+pub fn dedup_fileset(
+    input_prefix: &str,
+    output_prefix: &str,
+    compression: Compression,
+) -> Result<(), Gfa2binError> {
+    let mut mw = MatrixWrapper::new();
+    mw.bfile_wrapper(input_prefix)?;
+    mw.compression = compression;
+    let feature = mw.feature;
+
+    let mapping = mw.dedup_patterns();
+    mw.write_chunks(1, output_prefix, feature, f64::MAX)?;
+    write_dedup_mapping(output_prefix, &mapping)?;
+    Ok(())
+}
+
+/// # Expand association results through a deduplication mapping
+///
+/// Each row of a representative is repeated for all features it stands for (ID column replaced)
+/// The ID column is given by name or detected (ID, SNP, rs), output is tab-separated
+pub fn expand_results(
+    results: &str,
+    mapping: &HashMap<String, Vec<String>>,
+    column: Option<&str>,
+    output: &str,
+) -> Result<(), Gfa2binError> {
+    let mut lines = open_reader(results)?.lines();
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Err(Gfa2binError::Format(format!("{}: file is empty", results))),
+    };
+    let names: Vec<&str> = header.split_whitespace().collect();
+    let index = names
+        .iter()
+        .position(|x| match column {
+            Some(column) => *x == column,
+            None => RESULT_ID_COLUMNS.contains(&x.trim_start_matches('#')),
+        })
+        .ok_or_else(|| {
+            Gfa2binError::Argument(format!(
+                "{}: no ID column ({}) in the header",
+                results,
+                column.map_or(RESULT_ID_COLUMNS.join(", "), |x| x.to_string())
+            ))
+        })?;
+
+    let mut f = BufWriter::new(File::create(output)?);
+    writeln!(f, "{}", names.join("\t"))?;
+    let mut missing = 0;
+    for line in lines {
+        let line = line?;
+        let mut fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() <= index {
+            return Err(Gfa2binError::Format(format!(
+                "{}: line has less than {} columns: '{}'",
+                results,
+                index + 1,
+                line
+            )));
+        }
+        match mapping.get(fields[index]) {
+            Some(features) => {
+                for feature in features.iter() {
+                    fields[index] = feature;
+                    writeln!(f, "{}", fields.join("\t"))?;
+                }
+            }
+            // Not in the mapping: kept as it is
+            None => {
+                missing += 1;
+                writeln!(f, "{}", fields.join("\t"))?;
+            }
+        }
+    }
+    if missing > 0 {
+        info!("Results which are not in the mapping: {}", missing);
+    }
+    f.flush()?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+/// # Error type of gfa2bin
+///
+/// - Io: reading or writing a file failed
+/// - Format: input file is malformed (e.g. bed magic bytes, unparsable line)
+/// - Argument: invalid or conflicting command line arguments
+/// - Consistency: inputs do not fit together (e.g. different fam files, sample not in the graph)
+pub enum Gfa2binError {
+    Io(io::Error),
+    Format(String),
+    Argument(String),
+    Consistency(String),
+}
+
+impl Gfa2binError {
+    /// Exit code of the binary (1 is left for everything unexpected)
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Gfa2binError::Argument(_) => 2,
+            Gfa2binError::Io(_) => 3,
+            Gfa2binError::Format(_) => 4,
+            Gfa2binError::Consistency(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for Gfa2binError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gfa2binError::Io(e) => write!(f, "I/O error: {}", e),
+            Gfa2binError::Format(e) => write!(f, "Format error: {}", e),
+            Gfa2binError::Argument(e) => write!(f, "Argument error: {}", e),
+            Gfa2binError::Consistency(e) => write!(f, "Consistency error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Gfa2binError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Gfa2binError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Gfa2binError {
+    /// Invalid data (e.g. unparsable bim line) is a format error
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::InvalidData => Gfa2binError::Format(e.to_string()),
+            _ => Gfa2binError::Io(e),
+        }
+    }
+}
+
+/// Parse a command line value, the error names the argument
+pub fn parse_arg<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, Gfa2binError> {
+    value
+        .parse::<T>()
+        .map_err(|_| Gfa2binError::Argument(format!("Can not parse --{}: {}", name, value)))
+}
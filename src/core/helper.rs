@@ -1,7 +1,11 @@
+use crate::core::bed::{BED_ABSENT, BED_PRESENT};
+use crate::core::error::Gfa2binError;
+
 use bitvec::order::Lsb0;
 use bitvec::prelude::BitVec;
 
-use std::fmt::Display;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
 pub enum Feature {
@@ -12,47 +16,30 @@ pub enum Feature {
     MWindow,
     PWindow,
     Block,
+    Bubble,
+    Segment,
 }
 
-impl Feature {
-    pub fn from_str(s: &str) -> Self {
-        match s {
-            "node" => Feature::Node,
-            "dirnode" => Feature::DirNode,
-            "edge" => Feature::Edge,
-            "cov" => Feature::Alignment,
-            "mwindow" => Feature::MWindow,
-            "pwindow" => Feature::PWindow,
-            "block" => Feature::Block,
-            _ => panic!("Not implemented"),
-        }
-    }
-
-    pub fn string2u128(s: &str, feature: Feature, _feature2: Option<Feature>) -> u128 {
-        match feature {
-            Feature::Node => s.parse().unwrap(),
-            Feature::DirNode => {
-                let s1 = s.ends_with('+');
+impl FromStr for Feature {
+    type Err = Gfa2binError;
 
-                s[..s.len() - 1].parse::<u128>().unwrap() * 2 + s1 as u128
-            }
-            Feature::Edge => {
-                let ss = 0;
-                let s1 = &s[..ss];
-                let s2 = &s[ss..ss + 1];
-                let s3 = &s[ss + 1..s.len() - 1];
-                let s4 = &s.chars().last().unwrap();
-                let ss1 = s1.parse::<u128>().unwrap() * 2 + (s2 == "+") as u128;
-                let ss2 = s3.parse::<u128>().unwrap() * 2 + (*s4 == '+') as u128;
-                merge_u64_to_u128(ss1 as u64, ss2 as u64)
-            }
-            Feature::Alignment => 1,
-            Feature::PWindow => 1,
-            Feature::Block => 1,
-            Feature::MWindow => 1,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" => Ok(Feature::Node),
+            "dirnode" => Ok(Feature::DirNode),
+            "edge" => Ok(Feature::Edge),
+            "cov" => Ok(Feature::Alignment),
+            "mwindow" => Ok(Feature::MWindow),
+            "pwindow" => Ok(Feature::PWindow),
+            "block" => Ok(Feature::Block),
+            "bubble" => Ok(Feature::Bubble),
+            "segment" => Ok(Feature::Segment),
+            _ => Err(Gfa2binError::Argument(format!("Unknown feature: {}", s))),
         }
     }
+}
 
+impl Feature {
     pub fn to_string1(&self) -> String {
         match self {
             Feature::Node => "node".to_string(),
@@ -62,184 +49,293 @@ impl Feature {
             Feature::MWindow => "mwindow".to_string(),
             Feature::PWindow => "pwindow".to_string(),
             Feature::Block => "block".to_string(),
+            Feature::Bubble => "bubble".to_string(),
+            Feature::Segment => "segment".to_string(),
         }
     }
 
-    /// Convert the "index"-u64 to a String
+    /// Convert the "index"-u64 to a String (see FeatureId)
     pub fn to_string_u64(&self, input: u64) -> String {
-        if *self == Feature::PWindow {
-            let (left, right) = split_u64_to_u32s(input);
-
-            "P".to_string() + &format_unsigned_as_string(left) + &format_unsigned_as_string(right)
-        } else if *self == Feature::MWindow {
-            let (left, right) = split_u64_to_u32s(input);
-            return "M".to_string()
-                + &format_unsigned_as_string(left)
-                + &format_unsigned_as_string(right);
-        } else if *self == Feature::Block {
-            let (left, right) = split_u64_to_u32s(input);
-            return "B".to_string()
-                + &format_unsigned_as_string(left)
-                + &format_unsigned_as_string(right);
-        } else if Feature::Node == *self {
-            input.to_owned().to_string()
-        } else if *self == Feature::DirNode {
-            return format_unsigned_as_string(input);
-        } else if *self == Feature::Edge {
-            let (left, right) = split_u64_to_u32s(input);
-
-            return format_unsigned_as_string(left) + &format_unsigned_as_string(right);
-        } else {
-            let (left, right) = split_u64_to_u32s(input);
-            return "A".to_string() + &left.to_string() + "-" + right.to_string().as_str();
-        }
+        FeatureId::new(*self, input).to_string()
     }
 
-    /// Convert the "index"-u64 to a String
+    /// Convert the "index"-u64 to a numeric position (node id)
+    ///
+    /// Used as base-pair coordinate in the bim file, therefore only digits
     pub fn to_string_u64_numeric(&self, input: u64) -> String {
-        if *self == Feature::PWindow {
-            let (left, right) = split_u64_to_u32s(input);
-
-            format_unsigned_as_string(left) + &format_unsigned_as_string(right)
-        } else if *self == Feature::MWindow {
-            let (left, right) = split_u64_to_u32s(input);
-            return format_unsigned_as_string(left) + &format_unsigned_as_string(right);
-        } else if *self == Feature::Block {
-            let (left, right) = split_u64_to_u32s(input);
-            return format_unsigned_as_string(left) + &format_unsigned_as_string(right);
-        } else if Feature::Node == *self {
-            input.to_owned().to_string()
-        } else if *self == Feature::DirNode {
-            return format_unsigned_as_string(input);
-        } else if *self == Feature::Edge {
-            let (left, _right) = split_u64_to_u32s(input);
-
-            return format_unsigned_as_string(left);
-        } else {
-            let (left, _right) = split_u64_to_u32s(input);
-            return left.to_string();
-        }
+        FeatureId::new(*self, input).position().to_string()
     }
 
+    /// Feature of a variant identifier, windows also return the inner feature
     pub fn identify_feature(pp: &str) -> (Feature, Option<Feature>) {
-        let parts: Vec<&str> = pp
-            .split(|c| c == '+' || c == '-')
-            .filter(|s| !s.is_empty())
-            .collect();
-        let last_letter = pp.chars().last().unwrap();
-        let parts2 = pp.split('_').collect::<Vec<&str>>();
-        if pp.starts_with('P') {
-            (Feature::PWindow, None)
-        } else if pp.starts_with('M') {
-            (
-                Feature::MWindow,
-                Some(Feature::identify_feature(pp[1..].split('_').next().unwrap()).0),
-            )
-        } else if pp.starts_with('B') {
-            (Feature::Block, None)
-        } else if parts2.len() == 2 {
-            (Feature::Alignment, None)
-        } else if last_letter == '+' || last_letter == '-' {
-            if parts.len() == 1 {
-                (Feature::DirNode, None)
-            } else {
-                (Feature::Edge, None)
-            }
+        let feature = FeatureId::identify(pp);
+        if feature == Feature::MWindow {
+            (feature, Some(FeatureId::identify(&pp[1..])))
         } else {
-            (Feature::Node, None)
+            (feature, None)
         }
     }
 }
 
-pub fn read1(input: &str, f: Feature) -> (u64, u64) {
-    match f {
-        Feature::Node => (input.parse().unwrap(), 0),
-        Feature::DirNode => {
-            let last_char = &input[input.len() - 1..];
-            let rest = &input[..input.len() - 1];
-
-            (
-                rest.parse::<u64>().unwrap() * 2 + (last_char == "+") as u64,
-                0,
-            )
-        }
-        Feature::Edge => {
-            let ff = input.find(|c| c == '+' || c == '-').unwrap();
-            let dir1 = &input[ff..ff];
-            let dir2 = &input[input.len() - 1..input.len() - 1];
+/// Largest node id of an edge (both sides are a u32 with direction)
+pub const MAX_EDGE_NODE: u32 = u32::MAX >> 1;
 
-            let number1 = &input[..ff];
-            let number2 = &input[ff + 1..];
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Canonical feature identifier
+///
+/// Internal value (u64) and variant identifier (bim, pvar, VCF) of a feature
+/// - Node: node id (12)
+/// - DirNode: node * 2 + direction, + is 1 (12+, 12-)
+/// - Edge: two dirnodes (u32 each) merged (12+13-), node ids up to MAX_EDGE_NODE
+/// - Alignment: node and sequence position merged (A12-0)
+/// - MWindow, PWindow, Block: same as edge, with prefix (M12+13-, P12+13-, B12+13-)
+/// - Bubble: source dirnode (u32) and allele index merged (S12+_0)
+/// - Segment: first and last node merged (V12-15)
+pub struct FeatureId {
+    pub feature: Feature,
+    pub value: u64,
+}
 
-            let numb1: u32 = number1.parse::<u32>().unwrap() * 2 + (dir1 == "+") as u32;
-            let numb2: u32 = number2.parse::<u32>().unwrap() * 2 + (dir2 == "+") as u32;
+impl FeatureId {
+    pub fn new(feature: Feature, value: u64) -> Self {
+        Self { feature, value }
+    }
 
-            (merge_u32_to_u64(numb1, numb2), 0)
-        }
-        Feature::Alignment => {
-            let ff: Vec<u32> = input.split('_').map(|a| a.parse().unwrap()).collect();
+    pub fn node(node: u32) -> Self {
+        Self::new(Feature::Node, node as u64)
+    }
 
-            (merge_u32_to_u64(ff[0], ff[1]), 0)
-        }
-        Feature::MWindow => {
-            let ff: Vec<u32> = input.split('_').map(|a| a.parse().unwrap()).collect();
-            (merge_u32_to_u64(ff[0], ff[2]), 0)
+    pub fn dirnode(node: u32, forward: bool) -> Self {
+        Self::new(Feature::DirNode, node as u64 * 2 + forward as u64)
+    }
+
+    /// Edge between two dirnodes (node ids up to MAX_EDGE_NODE)
+    pub fn edge(from: u32, from_forward: bool, to: u32, to_forward: bool) -> Self {
+        Self::new(
+            Feature::Edge,
+            merge_u32_to_u64(from * 2 + from_forward as u32, to * 2 + to_forward as u32),
+        )
+    }
+
+    /// Coverage of a node (position 0) or a single position of the node (sequence)
+    pub fn alignment(node: u32, position: u32) -> Self {
+        Self::new(Feature::Alignment, merge_u32_to_u64(node, position))
+    }
+
+    /// Allele of a bubble, the bubble is identified by its source (node ids up to MAX_EDGE_NODE)
+    pub fn bubble(source: u32, source_forward: bool, allele: u32) -> Self {
+        Self::new(
+            Feature::Bubble,
+            merge_u32_to_u64(source * 2 + source_forward as u32, allele),
+        )
+    }
+
+    /// Presence/absence segment of consecutive nodes
+    pub fn segment(first: u32, last: u32) -> Self {
+        Self::new(Feature::Segment, merge_u32_to_u64(first, last))
+    }
+
+    /// Node id, used as position in the bim file (edge and windows: first node, bubble: source)
+    pub fn position(&self) -> u64 {
+        let (left, _right) = split_u64_to_u32s(self.value);
+        match self.feature {
+            Feature::Node => self.value,
+            Feature::DirNode => self.value / 2,
+            Feature::Alignment | Feature::Segment => left as u64,
+            _ => (left / 2) as u64,
         }
-        Feature::PWindow => {
-            let ff: Vec<u32> = input.split('_').map(|a| a.parse().unwrap()).collect();
-            (merge_u32_to_u64(ff[0], ff[2]), 0)
+    }
+
+    /// Feature of a variant identifier (without parsing it)
+    pub fn identify(id: &str) -> Feature {
+        if id.starts_with('P') {
+            Feature::PWindow
+        } else if id.starts_with('M') {
+            Feature::MWindow
+        } else if id.starts_with('B') {
+            Feature::Block
+        } else if id.starts_with('V') {
+            Feature::Segment
+        } else if id.starts_with('S') {
+            Feature::Bubble
+        } else if id.starts_with('A') {
+            Feature::Alignment
+        } else if id.ends_with('+') || id.ends_with('-') {
+            if id.matches(['+', '-']).count() == 1 {
+                Feature::DirNode
+            } else {
+                Feature::Edge
+            }
+        } else {
+            Feature::Node
         }
-        Feature::Block => {
-            let ff: Vec<u32> = input.split('_').map(|a| a.parse().unwrap()).collect();
-            (merge_u32_to_u64(ff[0], ff[2]), 0)
+    }
+
+    /// Parse a variant identifier, the feature is identified from the identifier
+    pub fn parse(id: &str) -> Result<Self, Gfa2binError> {
+        Self::decode(id, Self::identify(id))
+    }
+
+    /// Parse a variant identifier of a known feature
+    pub fn decode(id: &str, feature: Feature) -> Result<Self, Gfa2binError> {
+        let value = match feature {
+            Feature::Node => parse_digits::<u64>(id),
+            Feature::DirNode => {
+                parse_dirnode(id).map(|(node, forward)| node as u64 * 2 + forward as u64)
+            }
+            Feature::Edge => parse_edge(id),
+            Feature::Alignment => id.strip_prefix('A').and_then(parse_range),
+            Feature::Segment => id.strip_prefix('V').and_then(parse_range),
+            Feature::MWindow => id.strip_prefix('M').and_then(parse_edge),
+            Feature::PWindow => id.strip_prefix('P').and_then(parse_edge),
+            Feature::Block => id.strip_prefix('B').and_then(parse_edge),
+            Feature::Bubble => id.strip_prefix('S').and_then(|x| {
+                let (source, allele) = x.split_at(x.find('_')?);
+                let (source, source_forward) = parse_dirnode(source)?;
+                if source > MAX_EDGE_NODE {
+                    return None;
+                }
+                Some(Self::bubble(source, source_forward, parse_digits(&allele[1..])?).value)
+            }),
+        };
+        value.map(|x| Self::new(feature, x)).ok_or_else(|| {
+            Gfa2binError::Format(format!(
+                "'{}' is not a valid {} identifier",
+                id,
+                feature.to_string1()
+            ))
+        })
+    }
+}
+
+impl fmt::Display for FeatureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, right) = split_u64_to_u32s(self.value);
+        match self.feature {
+            Feature::Node => write!(f, "{}", self.value),
+            Feature::DirNode => write!(f, "{}", format_dirnode(self.value)),
+            Feature::Edge => write!(f, "{}{}", format_dirnode(left), format_dirnode(right)),
+            Feature::Alignment => write!(f, "A{}-{}", left, right),
+            Feature::MWindow => write!(f, "M{}{}", format_dirnode(left), format_dirnode(right)),
+            Feature::PWindow => write!(f, "P{}{}", format_dirnode(left), format_dirnode(right)),
+            Feature::Block => write!(f, "B{}{}", format_dirnode(left), format_dirnode(right)),
+            Feature::Bubble => write!(f, "S{}_{}", format_dirnode(left), right),
+            Feature::Segment => write!(f, "V{}-{}", left, right),
         }
     }
 }
-pub fn from_string(name_input: &str, ftype: Feature) -> u64 {
-    if ftype == Feature::Node {
-        name_input.parse().unwrap()
-    } else if ftype == Feature::DirNode {
-        let last_char = &name_input[name_input.len() - 1..];
-        let rest = &name_input[..name_input.len() - 1];
-
-        rest.parse::<u64>().unwrap() * 2 + (last_char == "+") as u64
-    } else {
-        let ff = name_input.find(|c| c == '+' || c == '-').unwrap();
-        let dir1 = &name_input[ff..ff];
-        let dir2 = &name_input[name_input.len() - 1..name_input.len() - 1];
 
-        let number1 = &name_input[..ff];
-        let number2 = &name_input[ff + 1..];
+/// Parse an unsigned number (digits only, no sign)
+fn parse_digits<T: FromStr>(s: &str) -> Option<T> {
+    if s.is_empty() || !s.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
 
-        let numb1: u32 = number1.parse::<u32>().unwrap() * 2 + (dir1 == "+") as u32;
-        let numb2: u32 = number2.parse::<u32>().unwrap() * 2 + (dir2 == "+") as u32;
+/// Parse two numbers (12-0) to the merged u64
+fn parse_range(s: &str) -> Option<u64> {
+    let (left, right) = s.split_at(s.find('-')?);
+    Some(merge_u32_to_u64(
+        parse_digits(left)?,
+        parse_digits(&right[1..])?,
+    ))
+}
 
-        merge_u32_to_u64(numb1, numb2)
+/// Parse a node with direction (12+)
+fn parse_dirnode(s: &str) -> Option<(u32, bool)> {
+    let forward = match s.chars().last()? {
+        '+' => true,
+        '-' => false,
+        _ => return None,
+    };
+    Some((parse_digits(&s[..s.len() - 1])?, forward))
+}
+
+/// Parse two nodes with direction (12+13-) to the merged u64
+fn parse_edge(s: &str) -> Option<u64> {
+    let ff = s.find(['+', '-'])?;
+    let (from, from_forward) = parse_dirnode(&s[..ff + 1])?;
+    let (to, to_forward) = parse_dirnode(&s[ff + 1..])?;
+    if from > MAX_EDGE_NODE || to > MAX_EDGE_NODE {
+        return None;
     }
+    Some(FeatureId::edge(from, from_forward, to, to_forward).value)
 }
 
-pub fn to_string1(input: u64, ftype: &Feature) -> String {
-    if Feature::Node == *ftype {
-        input.to_owned().to_string()
-    } else if *ftype == Feature::DirNode {
-        return format_unsigned_as_string(input);
-    } else if *ftype == Feature::Edge {
-        let (left, right) = split_u64_to_u32s(input);
+/// Node with direction (+ is 1)
+fn format_dirnode<T: Into<u64>>(value: T) -> String {
+    let value = value.into();
+    format!("{}{}", value / 2, if value % 2 == 1 { "+" } else { "-" })
+}
 
-        return format_unsigned_as_string(left) + &format_unsigned_as_string(right);
-    } else {
-        let (left, right) = split_u64_to_u32s(input);
-        return left.to_string() + "_" + right.to_string().as_str();
+#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+/// # Collapse policy for polyploid samples (more than two haplotypes)
+///
+/// PLINK 1 only represents two alleles per sample, therefore the number of haplotypes
+/// carrying a feature (count) is collapsed to a diploid genotype
+/// - Het: none -> 00, all -> 11, otherwise heterozygous (01)
+/// - Any: at least one -> 11, otherwise 00
+/// - Majority: more than half -> 11, otherwise 00
+pub enum CollapsePolicy {
+    Het,
+    Any,
+    Majority,
+}
+
+impl FromStr for CollapsePolicy {
+    type Err = Gfa2binError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "het" => Ok(CollapsePolicy::Het),
+            "any" => Ok(CollapsePolicy::Any),
+            "majority" => Ok(CollapsePolicy::Majority),
+            _ => Err(Gfa2binError::Argument(format!(
+                "Unknown collapse policy: {}",
+                s
+            ))),
+        }
     }
 }
 
-fn format_unsigned_as_string<T: Display + Into<u64>>(name: T) -> String {
-    let name_u64 = name.into();
-    format!(
-        "{}{}",
-        name_u64 / 2,
-        if name_u64 % 2 == 1 { "+" } else { "-" }
-    )
+impl CollapsePolicy {
+    pub fn to_string1(&self) -> String {
+        match self {
+            CollapsePolicy::Het => "het".to_string(),
+            CollapsePolicy::Any => "any".to_string(),
+            CollapsePolicy::Majority => "majority".to_string(),
+        }
+    }
+
+    /// Two bits (PLINK 1) for a sample with "count" of "ploidy" haplotypes
+    pub fn collapse(&self, count: usize, ploidy: usize) -> (bool, bool) {
+        let present = match self {
+            CollapsePolicy::Het => {
+                if count == 0 {
+                    return (false, false);
+                } else if count < ploidy {
+                    return (false, true);
+                }
+                true
+            }
+            CollapsePolicy::Any => count > 0,
+            CollapsePolicy::Majority => count * 2 > ploidy,
+        };
+        (present, present)
+    }
+
+    /// Two bits (PLINK 1) for a sample of any ploidy
+    ///
+    /// Haploid: present or absent, diploid: heterozygous if only one haplotype, polyploid: collapse
+    pub fn genotype(&self, count: usize, ploidy: usize) -> (bool, bool) {
+        match ploidy {
+            1 if count > 0 => BED_PRESENT,
+            1 => BED_ABSENT,
+            2 => CollapsePolicy::Het.collapse(count, ploidy),
+            _ => self.collapse(count, ploidy),
+        }
+    }
 }
 
 pub fn split_u64_to_u32s(value: u64) -> (u32, u32) {
@@ -261,17 +357,16 @@ pub fn index2node_seq(vector: &[u32]) -> Vec<u64> {
     }
     let mut node_id = vector[0];
     let mut seq_count = 0;
-    result.push(merge_u32_to_u64(node_id, seq_count));
+    result.push(FeatureId::alignment(node_id, seq_count).value);
 
     for &value in vector.iter().skip(1) {
         if value == node_id {
             seq_count += 1;
-            result.push(merge_u32_to_u64(node_id, seq_count))
         } else {
             node_id = value;
             seq_count = 0;
-            result.push(merge_u32_to_u64(node_id, seq_count))
         }
+        result.push(FeatureId::alignment(node_id, seq_count).value)
     }
 
     result
@@ -286,18 +381,12 @@ pub fn merge_u32_to_u64(high: u32, low: u32) -> u64 {
     result
 }
 
-pub fn merge_u64_to_u128(high: u64, low: u64) -> u128 {
-    let high_u128 = (high as u128) << 64;
-    let low_u128 = low as u128;
-    high_u128 | low_u128
-}
-
 pub fn is_all_zeros(bitvector: &BitVec<u8, Lsb0>) -> bool {
-    return bitvector.iter().all(|byte| !byte);
+    bitvector.iter().all(|byte| !byte)
 }
 
 pub fn is_all_ones(bitvector: &BitVec<u8, Lsb0>) -> bool {
-    return bitvector.iter().all(|byte| *byte);
+    bitvector.iter().all(|byte| *byte)
 }
 
 pub fn average_vec_u16(vector: &[u16], rval: f32) -> f64 {
@@ -324,7 +413,7 @@ pub fn median(data: &[u16], rval: u16) -> f64 {
     let n = sorted_data.len();
 
     // Check if the number of elements is odd or even
-    if n % 2 == 0 {
+    if n.is_multiple_of(2) {
         // If even, return the average of the middle two elements
         let middle_index_1 = (n / 2) - 1;
         let middle_index_2 = n / 2;
@@ -0,0 +1,206 @@
+use crate::core::bed::{allele_count, bytes_per_variant, BED_ABSENT};
+
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Genotype matrix (two bits per genotype)
+///
+/// All variants in one contiguous buffer, each variant (row) has the same layout as in a
+/// SNP-major bed file (4 samples per byte, unused bits of the last byte are zero)
+/// Variants, samples and single genotypes are accessed in constant time
+pub struct GenotypeMatrix {
+    data: Vec<u8>,
+    variants_number: usize,
+    samples_number: usize,
+    bytes_per_variant: usize,
+}
+
+impl GenotypeMatrix {
+    /// Matrix with all genotypes absent (00)
+    pub fn new(variants_number: usize, samples_number: usize) -> Self {
+        let bytes_per_variant = bytes_per_variant(samples_number);
+        Self {
+            data: vec![0; variants_number * bytes_per_variant],
+            variants_number,
+            samples_number,
+            bytes_per_variant,
+        }
+    }
+
+    /// Matrix from raw bed data (without header)
+    pub fn from_raw(data: Vec<u8>, variants_number: usize, samples_number: usize) -> Self {
+        let bytes_per_variant = bytes_per_variant(samples_number);
+        assert_eq!(data.len(), variants_number * bytes_per_variant);
+        Self {
+            data,
+            variants_number,
+            samples_number,
+            bytes_per_variant,
+        }
+    }
+
+    pub fn variants_number(&self) -> usize {
+        self.variants_number
+    }
+
+    pub fn samples_number(&self) -> usize {
+        self.samples_number
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants_number == 0
+    }
+
+    pub fn bytes_per_variant(&self) -> usize {
+        self.bytes_per_variant
+    }
+
+    /// Raw data of all variants (bed file without header)
+    pub fn as_raw_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Raw data of a range of variants
+    pub fn raw_variants(&self, range: Range<usize>) -> &[u8] {
+        &self.data[range.start * self.bytes_per_variant..range.end * self.bytes_per_variant]
+    }
+
+    /// Row view: genotypes of a single variant (two bits per sample)
+    pub fn variant(&self, index: usize) -> &BitSlice<u8, Lsb0> {
+        let bits = self.samples_number * 2;
+        &BitSlice::from_slice(self.raw_variants(index..index + 1))[..bits]
+    }
+
+    pub fn variant_mut(&mut self, index: usize) -> &mut BitSlice<u8, Lsb0> {
+        let bits = self.samples_number * 2;
+        let start = index * self.bytes_per_variant;
+        let end = start + self.bytes_per_variant;
+        &mut BitSlice::from_slice_mut(&mut self.data[start..end])[..bits]
+    }
+
+    /// Iterate over all variants (rows)
+    pub fn variants(&self) -> impl Iterator<Item = &BitSlice<u8, Lsb0>> + '_ {
+        (0..self.variants_number).map(move |index| self.variant(index))
+    }
+
+    /// Iterate over all variants in parallel (mutable), index is the variant index
+    pub fn par_variants_mut(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = (usize, &mut BitSlice<u8, Lsb0>)> + '_ {
+        let bits = self.samples_number * 2;
+        self.data
+            .par_chunks_exact_mut(self.bytes_per_variant.max(1))
+            .map(move |x| &mut BitSlice::from_slice_mut(x)[..bits])
+            .enumerate()
+    }
+
+    /// Column view: genotypes of a single sample
+    pub fn sample(&self, index: usize) -> SampleView<'_> {
+        assert!(index < self.samples_number);
+        SampleView {
+            matrix: self,
+            sample: index,
+        }
+    }
+
+    /// Number of present alleles (0, 1, 2), None if missing
+    pub fn genotype(&self, variant: usize, sample: usize) -> Option<u8> {
+        allele_count(self.variant(variant), sample)
+    }
+
+    /// Set the genotype (BED code) of a single sample
+    pub fn set_genotype(&mut self, variant: usize, sample: usize, genotype: (bool, bool)) {
+        let bit = variant * self.bytes_per_variant * 8 + sample * 2;
+        let bits = BitSlice::<u8, Lsb0>::from_slice_mut(&mut self.data);
+        bits.set(bit, genotype.0);
+        bits.set(bit + 1, genotype.1);
+    }
+
+    /// Swap variants and samples (e.g. sample-major to SNP-major)
+    pub fn transpose(&self) -> GenotypeMatrix {
+        let mut matrix = GenotypeMatrix::new(self.samples_number, self.variants_number);
+        for (variant, row) in self.variants().enumerate() {
+            for sample in 0..self.samples_number {
+                let genotype = (row[sample * 2], row[sample * 2 + 1]);
+                if genotype != BED_ABSENT {
+                    matrix.set_genotype(sample, variant, genotype);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Matrix without some samples (sorted indices)
+    pub fn remove_samples(&self, samples: &[usize]) -> GenotypeMatrix {
+        if samples.is_empty() {
+            return self.clone();
+        }
+        let kept = (0..self.samples_number)
+            .filter(|x| samples.binary_search(x).is_err())
+            .collect::<Vec<usize>>();
+        let mut matrix = GenotypeMatrix::new(self.variants_number, kept.len());
+        for (variant, row) in self.variants().enumerate() {
+            for (sample, old) in kept.iter().enumerate() {
+                let genotype = (row[old * 2], row[old * 2 + 1]);
+                if genotype != BED_ABSENT {
+                    matrix.set_genotype(variant, sample, genotype);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Append a variant (two bits per sample)
+    pub fn push_variant(&mut self, variant: &BitSlice<u8, Lsb0>) {
+        assert_eq!(variant.len(), self.samples_number * 2);
+        let start = self.data.len();
+        self.data.resize(start + self.bytes_per_variant, 0);
+        BitSlice::<u8, Lsb0>::from_slice_mut(&mut self.data[start..])[..variant.len()]
+            .copy_from_bitslice(variant);
+        self.variants_number += 1;
+    }
+
+    /// Append all variants of another matrix (same samples), an empty matrix takes the samples of the other
+    pub fn append(&mut self, other: &GenotypeMatrix) {
+        if self.variants_number == 0 {
+            self.samples_number = other.samples_number;
+            self.bytes_per_variant = other.bytes_per_variant;
+        }
+        assert_eq!(self.samples_number, other.samples_number);
+        self.data.extend_from_slice(&other.data);
+        self.variants_number += other.variants_number;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// # Column view of a GenotypeMatrix (single sample)
+pub struct SampleView<'a> {
+    matrix: &'a GenotypeMatrix,
+    sample: usize,
+}
+
+impl<'a> SampleView<'a> {
+    pub fn len(&self) -> usize {
+        self.matrix.variants_number
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matrix.variants_number == 0
+    }
+
+    /// Number of present alleles of a variant (0, 1, 2), None if missing
+    pub fn get(&self, variant: usize) -> Option<u8> {
+        self.matrix.genotype(variant, self.sample)
+    }
+
+    /// Iterate over the genotypes of all variants
+    pub fn iter(&self) -> impl Iterator<Item = Option<u8>> + 'a {
+        let matrix = self.matrix;
+        let sample = self.sample;
+        (0..matrix.variants_number).map(move |variant| matrix.genotype(variant, sample))
+    }
+}
@@ -0,0 +1,201 @@
+use crate::core::bfile::FamEntry;
+use crate::core::compression::open_reader;
+use crate::core::core::MatrixWrapper;
+use crate::core::error::Gfa2binError;
+
+use hashbrown::HashMap;
+use log::{info, warn};
+use std::io::BufRead;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Column of a sample metadata table
+enum MetadataColumn {
+    FamilyId,
+    IndividualId,
+    Father,
+    Mother,
+    Sex,
+    Other,
+}
+
+impl MetadataColumn {
+    fn from_str(s: &str) -> Self {
+        match s.trim_start_matches('#').to_lowercase().as_str() {
+            "fid" | "family" => MetadataColumn::FamilyId,
+            "iid" | "sample" | "id" => MetadataColumn::IndividualId,
+            "pat" | "father" => MetadataColumn::Father,
+            "mat" | "mother" => MetadataColumn::Mother,
+            "sex" => MetadataColumn::Sex,
+            _ => MetadataColumn::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Sample metadata (FID, IID, father, mother, sex)
+///
+/// Tab separated table with header, columns are identified by name (case insensitive):
+/// FID/family, IID/sample/id, PAT/father, MAT/mother, SEX
+/// IID is required (sample name), all other columns are optional
+pub struct SampleMetadata {
+    pub entries: Vec<FamEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl SampleMetadata {
+    /// Read a sample metadata table (plain-text or compressed)
+    pub fn from_file(file_path: &str) -> Result<Self, Gfa2binError> {
+        let reader = open_reader(file_path)?;
+        let mut lines = reader.lines();
+        let header = match lines.next() {
+            Some(line) => line?,
+            None => return Err(Gfa2binError::Format(format!("{} is empty", file_path))),
+        };
+        let columns = header
+            .split('\t')
+            .map(MetadataColumn::from_str)
+            .collect::<Vec<MetadataColumn>>();
+        if !columns.contains(&MetadataColumn::IndividualId) {
+            return Err(Gfa2binError::Format(format!(
+                "{}: no IID (sample) column in the header",
+                file_path
+            )));
+        }
+
+        let mut metadata = Self::default();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = line.split('\t').map(|x| x.trim()).collect::<Vec<&str>>();
+            if fields.len() != columns.len() {
+                return Err(Gfa2binError::Format(format!(
+                    "{}: line has {} columns (expected {}): {}",
+                    file_path,
+                    fields.len(),
+                    columns.len(),
+                    line
+                )));
+            }
+            let mut entry = FamEntry::new("");
+            let mut family_id = None;
+            for (column, value) in columns.iter().zip(fields.iter()) {
+                match column {
+                    MetadataColumn::FamilyId => family_id = Some(value.to_string()),
+                    MetadataColumn::IndividualId => entry.individual_id = value.to_string(),
+                    MetadataColumn::Father => entry.father_id = parent(value),
+                    MetadataColumn::Mother => entry.mother_id = parent(value),
+                    MetadataColumn::Sex => {
+                        entry.sex = parse_sex(value).ok_or_else(|| {
+                            Gfa2binError::Format(format!(
+                                "{}: unknown sex '{}' (1/M/male, 2/F/female, 0/NA): {}",
+                                file_path, value, line
+                            ))
+                        })?
+                    }
+                    MetadataColumn::Other => {}
+                }
+            }
+            entry.family_id = family_id.unwrap_or_else(|| entry.individual_id.clone());
+            if metadata.index.contains_key(&entry.individual_id) {
+                return Err(Gfa2binError::Format(format!(
+                    "{}: sample {} is duplicated",
+                    file_path, entry.individual_id
+                )));
+            }
+            metadata
+                .index
+                .insert(entry.individual_id.clone(), metadata.entries.len());
+            metadata.entries.push(entry);
+        }
+        Ok(metadata)
+    }
+
+    /// Metadata of a sample (by IID), None if the sample is not in the table
+    pub fn get(&self, sample: &str) -> Option<&FamEntry> {
+        self.index.get(sample).map(|x| &self.entries[*x])
+    }
+
+    /// Fam entries of the samples (same order), phenotype is missing (-9)
+    ///
+    /// Samples which are not in the table get the default entry (FID=IID, parents and sex unknown)
+    /// Returns the entries and the samples which are not in the table
+    pub fn fam_entries(&self, samples: &[String]) -> (Vec<FamEntry>, Vec<String>) {
+        let mut missing = Vec::new();
+        let entries = samples
+            .iter()
+            .map(|x| match self.get(x) {
+                Some(entry) => entry.clone(),
+                None => {
+                    missing.push(x.clone());
+                    FamEntry::new(x)
+                }
+            })
+            .collect();
+        (entries, missing)
+    }
+
+    /// Report the samples which are missing on either side
+    pub fn report(&self, samples: &[String], not_in_table: &[String]) {
+        info!(
+            "Sample metadata: {} of {} samples matched",
+            samples.len() - not_in_table.len(),
+            samples.len()
+        );
+        if !not_in_table.is_empty() {
+            warn!(
+                "{} samples are not in the sample metadata (FID=IID, parents and sex unknown): {}",
+                not_in_table.len(),
+                not_in_table.join(", ")
+            );
+        }
+        let not_in_samples = self
+            .entries
+            .iter()
+            .filter(|x| !samples.contains(&x.individual_id))
+            .map(|x| x.individual_id.clone())
+            .collect::<Vec<String>>();
+        if !not_in_samples.is_empty() {
+            warn!(
+                "{} samples of the sample metadata are not in the input (ignored): {}",
+                not_in_samples.len(),
+                not_in_samples.join(", ")
+            );
+        }
+    }
+}
+
+/// Parent id, missing parents are 0
+fn parent(value: &str) -> String {
+    if ["", "NA", "na", "."].contains(&value) {
+        "0".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Sex code of PLINK (1: male, 2: female, 0: unknown)
+fn parse_sex(value: &str) -> Option<u8> {
+    match value.to_lowercase().as_str() {
+        "1" | "m" | "male" => Some(1),
+        "2" | "f" | "female" => Some(2),
+        "0" | "" | "na" | "." | "u" | "unknown" => Some(0),
+        _ => None,
+    }
+}
+
+impl MatrixWrapper {
+    /// # Apply the sample metadata to the fam entries
+    ///
+    /// - Existing fam entries (e.g. from a phenotype table) keep their phenotype
+    /// - Samples are matched by IID (sample name)
+    pub fn apply_metadata(&mut self, metadata: &SampleMetadata) {
+        let (mut entries, missing) = metadata.fam_entries(&self.sample_names);
+        metadata.report(&self.sample_names, &missing);
+        for (entry, old) in entries.iter_mut().zip(self.fam_entries.iter()) {
+            entry.phenotype = old.phenotype.clone();
+        }
+        self.fam_entries = entries;
+    }
+}
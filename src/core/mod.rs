@@ -1,3 +1,17 @@
+pub mod annotation;
+pub mod bed;
 pub mod bfile;
+pub mod bgen;
+pub mod bgzf;
+pub mod compression;
+pub mod copy_number;
+#[allow(clippy::module_inception)]
 pub mod core;
+pub mod dedup;
+pub mod error;
 pub mod helper;
+pub mod matrix;
+pub mod metadata;
+pub mod pgen;
+pub mod phenotype;
+pub mod tabix;
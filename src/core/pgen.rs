@@ -0,0 +1,536 @@
+use crate::core::bed::{allele_count, bytes_per_variant};
+use crate::core::core::MatrixWrapper;
+use crate::core::helper::Feature;
+
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+/// Magic bytes of a PLINK 2 pgen file
+pub const PGEN_MAGIC: [u8; 2] = [108, 27];
+
+/// Storage mode: fixed-width, hardcalls only
+pub const PGEN_MODE_HARDCALL: u8 = 0x02;
+
+/// Storage mode: fixed-width, hardcalls + unphased dosages
+pub const PGEN_MODE_DOSAGE: u8 = 0x03;
+
+/// Dosage of one alternative allele (two alt alleles = 32768)
+pub const PGEN_DOSAGE_ONE: f64 = 16384.0;
+
+/// Hardcall of a missing genotype
+pub const PGEN_MISSING: u8 = 3;
+
+/// Dosage of a missing genotype
+pub const PGEN_DOSAGE_MISSING: u16 = 65535;
+
+/// Storage mode: variable-width (multiallelic hardcalls)
+pub const PGEN_MODE_VARIABLE: u8 = 0x10;
+
+/// Number of variants in each block of the variable-width index
+pub const PGEN_VBLOCK_SIZE: usize = 65536;
+
+/// Record type: hardcalls with multiallelic patch (aux track 1)
+pub const PGEN_VRTYPE_MULTIALLELIC: u8 = 0x08;
+
+/// Maximum number of alleles (REF + ALT) of one variant
+pub const PGEN_MAX_ALLELES: usize = 255;
+
+/// Header flag (bits 6-7 of the 12th byte): all REF alleles are provisional
+pub const PGEN_NONREF_PROVISIONAL: u8 = 2 << 6;
+
+/// Allele indices (0 = REF) of the two haplotypes of each sample, None is missing
+pub type Genotypes = Vec<Option<[usize; 2]>>;
+
+/// Convert a PLINK 1 bed variant to PLINK 2 hardcalls
+///
+/// PLINK 2: 0 hom ref, 1 het, 2 hom alt, 3 missing
+/// Set bits (A2, "T") are the alternative allele
+pub fn bed2pgen_hardcalls(variant: &BitSlice<u8, Lsb0>, samples_number: usize) -> Vec<u8> {
+    let mut result = vec![0; bytes_per_variant(samples_number)];
+    for index in 0..samples_number {
+        let code = allele_count(variant, index).unwrap_or(PGEN_MISSING);
+        result[index / 4] |= code << ((index % 4) * 2);
+    }
+    result
+}
+
+/// Convert dosages (0.0 - 2.0) to PLINK 2 hardcalls (rounded)
+pub fn dosage2pgen_hardcalls(dosages: &[Option<f64>]) -> Vec<u8> {
+    let mut result = vec![0; bytes_per_variant(dosages.len())];
+    for (index, dosage) in dosages.iter().enumerate() {
+        let code = dosage.map_or(PGEN_MISSING, dosage2hardcall);
+        result[index / 4] |= code << ((index % 4) * 2);
+    }
+    result
+}
+
+/// Rounded dosage
+fn dosage2hardcall(dosage: f64) -> u8 {
+    if dosage < 0.5 {
+        0
+    } else if dosage < 1.5 {
+        1
+    } else {
+        2
+    }
+}
+
+/// # Writer for PLINK 2 pgen files
+///
+/// Fixed-width storage (mode 0x02 or 0x03), the number of variants must be known beforehand
+/// More information: https://github.com/chrchang/plink-ng/tree/master/pgen_spec
+pub struct PgenWriter {
+    writer: BufWriter<File>,
+    samples_number: usize,
+    dosage: bool,
+}
+
+impl PgenWriter {
+    pub fn new(
+        filename: &str,
+        samples_number: usize,
+        snp_number: usize,
+        dosage: bool,
+    ) -> Result<Self, io::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(&PGEN_MAGIC)?;
+        writer.write_u8(if dosage {
+            PGEN_MODE_DOSAGE
+        } else {
+            PGEN_MODE_HARDCALL
+        })?;
+        writer.write_u32::<LittleEndian>(snp_number as u32)?;
+        writer.write_u32::<LittleEndian>(samples_number as u32)?;
+        // REF alleles are placeholders, therefore all provisional
+        writer.write_u8(PGEN_NONREF_PROVISIONAL)?;
+        Ok(Self {
+            writer,
+            samples_number,
+            dosage,
+        })
+    }
+
+    /// Write a PLINK 1 bed variant (hardcalls only)
+    pub fn write_bed_variant(&mut self, variant: &BitSlice<u8, Lsb0>) -> Result<(), io::Error> {
+        self.writer
+            .write_all(&bed2pgen_hardcalls(variant, self.samples_number))?;
+        if self.dosage {
+            // Dosages follow directly from the hardcalls
+            for index in 0..self.samples_number {
+                let dosage = allele_count(variant, index)
+                    .map_or(PGEN_DOSAGE_MISSING, |x| x as u16 * PGEN_DOSAGE_ONE as u16);
+                self.writer.write_u16::<LittleEndian>(dosage)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a variant based on dosages (0.0 - 2.0), None is missing
+    pub fn write_dosage_variant(&mut self, dosages: &[Option<f64>]) -> Result<(), io::Error> {
+        self.writer.write_all(&dosage2pgen_hardcalls(dosages))?;
+        if self.dosage {
+            for dosage in dosages.iter() {
+                let value = dosage.map_or(PGEN_DOSAGE_MISSING, |x| {
+                    (x.clamp(0.0, 2.0) * PGEN_DOSAGE_ONE).round() as u16
+                });
+                self.writer.write_u16::<LittleEndian>(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the buffer
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+}
+
+/// Encode a multiallelic hardcall record (variable-width storage)
+///
+/// Genotypes are pairs of allele indices (0 = REF), None is missing
+/// Returns the record type and the record: 2-bit main track (REF/ALT1 codes),
+/// followed by the multiallelic patch if any genotype has another ALT allele
+pub fn multiallelic_record(
+    genotypes: &[Option<[usize; 2]>],
+    allele_count: usize,
+) -> Result<(u8, Vec<u8>), io::Error> {
+    if allele_count > PGEN_MAX_ALLELES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "A pgen variant has at most {} alleles, found {}",
+                PGEN_MAX_ALLELES, allele_count
+            ),
+        ));
+    }
+    let mut record = vec![0; bytes_per_variant(genotypes.len())];
+    // ALT of the REF/ALT genotypes, ALT pair of the ALT/ALT genotypes
+    let mut patch_01 = Vec::new();
+    let mut patch_10 = Vec::new();
+    for (index, genotype) in genotypes.iter().enumerate() {
+        let code = match genotype {
+            None => PGEN_MISSING,
+            Some([allele1, allele2]) => {
+                let (low, high) = (*allele1.min(allele2), *allele1.max(allele2));
+                if high == 0 {
+                    0
+                } else if low == 0 {
+                    patch_01.push(high);
+                    1
+                } else {
+                    patch_10.push([low, high]);
+                    2
+                }
+            }
+        };
+        record[index / 4] |= code << ((index % 4) * 2);
+    }
+    match multiallelic_patch(&patch_01, &patch_10, allele_count) {
+        Some(patch) => {
+            record.extend(patch);
+            Ok((PGEN_VRTYPE_MULTIALLELIC, record))
+        }
+        None => Ok((0, record)),
+    }
+}
+
+/// Multiallelic patch (aux track 1), None if all ALT alleles are ALT1
+///
+/// Format byte (low: REF/ALT patch, high: ALT/ALT patch, 0 = bitarray, 15 = empty),
+/// then for each patch a bitarray of the affected genotypes and their allele codes
+fn multiallelic_patch(
+    patch_01: &[usize],
+    patch_10: &[[usize; 2]],
+    allele_count: usize,
+) -> Option<Vec<u8>> {
+    let rare_01 = patch_01.iter().map(|x| *x > 1).collect::<Vec<bool>>();
+    let rare_10 = patch_10.iter().map(|x| *x != [1, 1]).collect::<Vec<bool>>();
+    let has_01 = rare_01.contains(&true);
+    let has_10 = rare_10.contains(&true);
+    if !has_01 && !has_10 {
+        return None;
+    }
+
+    let format_01: u8 = if has_01 { 0 } else { 15 };
+    let format_10: u8 = if has_10 { 0 } else { 15 };
+    let mut result = vec![format_01 | format_10 << 4];
+    if has_01 {
+        result.extend(bitarray(&rare_01));
+        let codes = patch_01
+            .iter()
+            .filter(|x| **x > 1)
+            .map(|x| x - 2)
+            .collect::<Vec<usize>>();
+        let width = match allele_count {
+            0..=3 => 0,
+            4 => 1,
+            5..=6 => 2,
+            7..=18 => 4,
+            _ => 8,
+        };
+        result.extend(pack_codes(&codes, width));
+    }
+    if has_10 {
+        result.extend(bitarray(&rare_10));
+        let rare = patch_10.iter().filter(|x| **x != [1, 1]);
+        if allele_count == 3 {
+            // Only ALT1/ALT2 (0) or ALT2/ALT2 (1)
+            result.extend(bitarray(&rare.map(|x| x[0] == 2).collect::<Vec<bool>>()));
+        } else {
+            let mut codes = Vec::new();
+            for [low, high] in rare {
+                codes.push(low - 1);
+                codes.push(high - 1);
+            }
+            let width = match allele_count {
+                0..=5 => 2,
+                6..=17 => 4,
+                _ => 8,
+            };
+            result.extend(pack_codes(&codes, width));
+        }
+    }
+    Some(result)
+}
+
+/// Pack codes of 0, 1, 2, 4 or 8 bits (least significant bits first)
+fn pack_codes(codes: &[usize], width: usize) -> Vec<u8> {
+    let mut result = vec![0; (codes.len() * width).div_ceil(8)];
+    if width == 0 {
+        return result;
+    }
+    for (index, code) in codes.iter().enumerate() {
+        let bit = index * width;
+        result[bit / 8] |= (*code as u8) << (bit % 8);
+    }
+    result
+}
+
+/// Pack bits (least significant bit first)
+fn bitarray(bits: &[bool]) -> Vec<u8> {
+    pack_codes(&bits.iter().map(|x| *x as usize).collect::<Vec<usize>>(), 1)
+}
+
+/// Number of bytes needed to store a value (at least one)
+fn byte_count(value: usize) -> usize {
+    let mut count = 1;
+    while count < 8 && value >> (count * 8) != 0 {
+        count += 1;
+    }
+    count
+}
+
+#[derive(Debug, Clone, Default)]
+/// # Index of a variable-width pgen file
+///
+/// Record type, record length and number of alleles of each variant
+pub struct PgenIndex {
+    vrtypes: Vec<u8>,
+    vrec_lens: Vec<usize>,
+    allele_counts: Vec<usize>,
+}
+
+impl PgenIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record
+    pub fn push(&mut self, vrtype: u8, vrec_len: usize, allele_count: usize) {
+        self.vrtypes.push(vrtype);
+        self.vrec_lens.push(vrec_len);
+        self.allele_counts.push(allele_count);
+    }
+
+    /// Append the records of another index
+    pub fn extend(&mut self, other: PgenIndex) {
+        self.vrtypes.extend(other.vrtypes);
+        self.vrec_lens.extend(other.vrec_lens);
+        self.allele_counts.extend(other.allele_counts);
+    }
+
+    /// Number of variants
+    pub fn len(&self) -> usize {
+        self.vrtypes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vrtypes.is_empty()
+    }
+
+    /// Header of a variable-width pgen file (mode 0x10), the records follow directly
+    ///
+    /// Magic, mode, number of variants and samples, control byte (4-bit record types,
+    /// byte widths of the record lengths and allele counts, provisional REF alleles),
+    /// file offset of each block of 65536 variants, then for each block:
+    /// record types (4 bits), record lengths and allele counts (little endian)
+    pub fn header(&self, samples_number: usize) -> Vec<u8> {
+        let vrec_len_bytes = byte_count(self.vrec_lens.iter().copied().max().unwrap_or(0)).min(4);
+        let allele_count_bytes = match self.allele_counts.iter().copied().max() {
+            Some(max) if max > 2 => byte_count(max),
+            _ => 0,
+        };
+
+        let mut header = PGEN_MAGIC.to_vec();
+        header.push(PGEN_MODE_VARIABLE);
+        header.extend(&(self.len() as u32).to_le_bytes());
+        header.extend(&(samples_number as u32).to_le_bytes());
+        header.push(
+            (vrec_len_bytes - 1) as u8 | (allele_count_bytes as u8) << 4 | PGEN_NONREF_PROVISIONAL,
+        );
+
+        let blocks = (0..self.len())
+            .step_by(PGEN_VBLOCK_SIZE)
+            .map(|start| start..self.len().min(start + PGEN_VBLOCK_SIZE))
+            .collect::<Vec<_>>();
+        let index_size: usize = blocks
+            .iter()
+            .map(|x| x.len().div_ceil(2) + x.len() * (vrec_len_bytes + allele_count_bytes))
+            .sum();
+        let mut offset = header.len() + blocks.len() * 8 + index_size;
+        for block in blocks.iter() {
+            header.extend(&(offset as u64).to_le_bytes());
+            offset += self.vrec_lens[block.clone()].iter().sum::<usize>();
+        }
+        for block in blocks {
+            for vrtypes in self.vrtypes[block.clone()].chunks(2) {
+                header.push(vrtypes[0] | vrtypes.get(1).unwrap_or(&0) << 4);
+            }
+            for vrec_len in self.vrec_lens[block.clone()].iter() {
+                header.extend(&vrec_len.to_le_bytes()[..vrec_len_bytes]);
+            }
+            for allele_count in self.allele_counts[block].iter() {
+                header.extend(&allele_count.to_le_bytes()[..allele_count_bytes]);
+            }
+        }
+        header
+    }
+}
+
+/// # Writer for multiallelic PLINK 2 pgen files
+///
+/// Variable-width storage (mode 0x10), the index is only known after the last variant,
+/// therefore the records are written to a temporary file (filename.tmp) first
+pub struct MultiallelicPgenWriter {
+    filename: String,
+    writer: BufWriter<File>,
+    samples_number: usize,
+    index: PgenIndex,
+}
+
+impl MultiallelicPgenWriter {
+    pub fn new(filename: &str, samples_number: usize) -> Result<Self, io::Error> {
+        Ok(Self {
+            filename: filename.to_string(),
+            writer: BufWriter::new(File::create(format!("{}.tmp", filename))?),
+            samples_number,
+            index: PgenIndex::new(),
+        })
+    }
+
+    /// Write a variant, genotypes are pairs of allele indices (0 = REF), None is missing
+    pub fn write_variant(
+        &mut self,
+        genotypes: &[Option<[usize; 2]>],
+        allele_count: usize,
+    ) -> Result<(), io::Error> {
+        let (vrtype, record) = multiallelic_record(genotypes, allele_count)?;
+        self.writer.write_all(&record)?;
+        self.index.push(vrtype, record.len(), allele_count);
+        Ok(())
+    }
+
+    /// Write the header and index, followed by the records
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        self.writer.flush()?;
+        drop(self.writer);
+        let tmp = format!("{}.tmp", self.filename);
+        let mut writer = BufWriter::new(File::create(&self.filename)?);
+        writer.write_all(&self.index.header(self.samples_number))?;
+        io::copy(&mut File::open(&tmp)?, &mut writer)?;
+        writer.flush()?;
+        fs::remove_file(tmp)
+    }
+}
+
+/// Allele codes of a variant with one allele for each group (REF: <G0>, ALT: <G1>,<G2>,...)
+///
+/// Returns REF and ALT, ALT is missing (.) if there is only one group
+pub fn group_alleles(groups: usize) -> (String, String) {
+    let alt = (1..groups)
+        .map(|x| format!("<G{}>", x))
+        .collect::<Vec<String>>()
+        .join(",");
+    (
+        "<G0>".to_string(),
+        if alt.is_empty() { ".".to_string() } else { alt },
+    )
+}
+
+impl MatrixWrapper {
+    /// Write pgen file
+    ///
+    /// Dosages are written if there are raw (u16) or normalized (f32) values, otherwise hardcalls
+    pub fn write_pgen(&self, out_prefix: &str, thresh: &[f32]) -> Result<(), io::Error> {
+        let output = [out_prefix, "pgen"].join(".");
+        let samples_number = self.sample_index_u16.len();
+        let dosage = !self.matrix_f32.is_empty() || !self.matrix_u16.is_empty();
+
+        let variants_number = if !self.matrix_f32.is_empty() {
+            self.matrix_f32.len()
+        } else if !self.matrix_u16.is_empty() {
+            self.matrix_u16.len()
+        } else {
+            self.matrix_bit.variants_number()
+        };
+
+        let mut writer = PgenWriter::new(&output, samples_number, variants_number, dosage)?;
+        if !self.matrix_f32.is_empty() {
+            for (i, (values, thresh)) in self.matrix_f32.iter().zip(thresh.iter()).enumerate() {
+                writer.write_dosage_variant(&self.sample_dosage(i, values, *thresh))?;
+            }
+        } else if !self.matrix_u16.is_empty() {
+            for (i, (values, thresh)) in self.matrix_u16.iter().zip(thresh.iter()).enumerate() {
+                writer.write_dosage_variant(&self.sample_dosage(i, values, *thresh))?;
+            }
+        } else {
+            for variant in self.matrix_bit.variants() {
+                writer.write_bed_variant(variant)?;
+            }
+        }
+        writer.finish()
+    }
+
+    /// Write pvar file
+    ///
+    /// https://www.cog-genomics.org/plink/2.0/formats#pvar
+    /// Same information as the bim file: REF is A (absent), ALT is T (present)
+    /// Multiallelic entries have a comma-separated list of ALT alleles (allele2)
+    pub fn write_pvar(&self, out_prefix: &str, feature: &Feature) -> Result<(), io::Error> {
+        let output = [out_prefix, "pvar"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        writeln!(f, "#CHROM\tPOS\tID\tREF\tALT")?;
+        if self.bim_entries.is_empty() {
+            for x in self.geno_names.iter() {
+                writeln!(
+                    f,
+                    "graph\t{}\t{}\tA\tT",
+                    feature.to_string_u64_numeric(*x),
+                    feature.to_string_u64(*x),
+                )?;
+            }
+        } else {
+            for x in self.bim_entries.iter() {
+                writeln!(
+                    f,
+                    "{}\t{}\t{}\t{}\t{}",
+                    x.chromosome, x.bp_position, x.variant_id, x.allele1, x.allele2
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write psam file
+    ///
+    /// https://www.cog-genomics.org/plink/2.0/formats#psam
+    /// Same information as the fam file, missing phenotype is NA
+    pub fn write_psam(&self, out_prefix: &str, pheno: f64) -> Result<(), io::Error> {
+        let output = [out_prefix, "psam"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        writeln!(f, "#FID\tIID\tPAT\tMAT\tSEX\tPHENO1")?;
+        if self.fam_entries.is_empty() {
+            let pheno = if pheno == f64::MAX {
+                "NA".to_string()
+            } else {
+                pheno.to_string()
+            };
+            for x in self.sample_names.iter() {
+                writeln!(f, "{}\t{}\t0\t0\tNA\t{}", x, x, pheno)?;
+            }
+        } else {
+            for x in self.fam_entries.iter() {
+                let sex = if x.sex == 0 {
+                    "NA".to_string()
+                } else {
+                    x.sex.to_string()
+                };
+                let phenotype = if x.phenotype == "-9" {
+                    "NA"
+                } else {
+                    &x.phenotype
+                };
+                writeln!(
+                    f,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    x.family_id, x.individual_id, x.father_id, x.mother_id, sex, phenotype
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::core::bfile::FamEntry;
+use crate::core::compression::open_reader;
+use crate::core::core::{MatrixWrapper, OutputFormat};
+use crate::core::error::Gfa2binError;
+
+use hashbrown::HashMap;
+use log::{info, warn};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+
+/// Values which are treated as missing in a phenotype table
+const MISSING_VALUES: [&str; 6] = ["", "NA", "na", "NaN", "nan", "."];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Phenotype table
+///
+/// First column is the sample name, all other columns are numeric (phenotypes or covariates)
+/// Columns are separated by tabs, commas or semicolons (same as in the workflow)
+pub struct PhenotypeTable {
+    /// Column names (without sample column)
+    pub names: Vec<String>,
+    pub samples: Vec<String>,
+    /// Values of each sample (same order as samples), None if missing
+    pub values: Vec<Vec<Option<f64>>>,
+    index: HashMap<String, usize>,
+}
+
+impl PhenotypeTable {
+    /// Read a phenotype table (plain-text or compressed)
+    ///
+    /// Header line is required, separator is detected from the header
+    pub fn from_file(file_path: &str) -> Result<Self, Gfa2binError> {
+        let reader = open_reader(file_path)?;
+        let mut lines = reader.lines();
+        let header = loop {
+            match lines.next() {
+                Some(line) => {
+                    let line = line?;
+                    if !line.trim().is_empty() {
+                        break line;
+                    }
+                }
+                None => return Err(Gfa2binError::Format(format!("{} is empty", file_path))),
+            }
+        };
+        let sep = ['\t', ',', ';']
+            .iter()
+            .copied()
+            .find(|x| header.contains(*x))
+            .unwrap_or('\t');
+
+        let mut table = Self {
+            names: header
+                .split(sep)
+                .skip(1)
+                .map(|x| x.trim().to_string())
+                .collect(),
+            ..Self::default()
+        };
+        if table.names.is_empty() {
+            return Err(Gfa2binError::Format(format!(
+                "{}: no phenotype column in the header",
+                file_path
+            )));
+        }
+
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = line.split(sep).map(|x| x.trim()).collect::<Vec<&str>>();
+            if fields.len() != table.names.len() + 1 {
+                return Err(Gfa2binError::Format(format!(
+                    "{}: line has {} columns (expected {}): {}",
+                    file_path,
+                    fields.len(),
+                    table.names.len() + 1,
+                    line
+                )));
+            }
+            let values = fields[1..]
+                .iter()
+                .map(|x| parse_value(x))
+                .collect::<Option<Vec<Option<f64>>>>()
+                .ok_or_else(|| {
+                    Gfa2binError::Format(format!("{}: value is not a number: {}", file_path, line))
+                })?;
+            if !table.push(fields[0], values) {
+                return Err(Gfa2binError::Format(format!(
+                    "{}: sample {} is duplicated",
+                    file_path, fields[0]
+                )));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Add a sample, false if the sample is already in the table
+    fn push(&mut self, sample: &str, values: Vec<Option<f64>>) -> bool {
+        if self.index.contains_key(sample) {
+            return false;
+        }
+        self.index.insert(sample.to_string(), self.samples.len());
+        self.samples.push(sample.to_string());
+        self.values.push(values);
+        true
+    }
+
+    /// Values of a sample, None if the sample is not in the table
+    pub fn get(&self, sample: &str) -> Option<&[Option<f64>]> {
+        self.index.get(sample).map(|x| self.values[*x].as_slice())
+    }
+
+    /// Split the table into phenotypes and covariates (by column name)
+    ///
+    /// Covariates are None if no column is given
+    pub fn split_covariates(
+        &self,
+        covariates: &[String],
+    ) -> Result<(PhenotypeTable, Option<PhenotypeTable>), Gfa2binError> {
+        for x in covariates.iter() {
+            if !self.names.contains(x) {
+                return Err(Gfa2binError::Argument(format!(
+                    "Covariate {} is not a column of the phenotype table",
+                    x
+                )));
+            }
+        }
+        let phenotypes = self.select(|x| !covariates.contains(x));
+        if phenotypes.names.is_empty() {
+            return Err(Gfa2binError::Argument(
+                "All columns of the phenotype table are covariates".to_string(),
+            ));
+        }
+        if covariates.is_empty() {
+            Ok((phenotypes, None))
+        } else {
+            Ok((phenotypes, Some(self.select(|x| covariates.contains(x)))))
+        }
+    }
+
+    /// Table with a subset of the columns
+    fn select<F: Fn(&String) -> bool>(&self, keep: F) -> PhenotypeTable {
+        let columns = (0..self.names.len())
+            .filter(|x| keep(&self.names[*x]))
+            .collect::<Vec<usize>>();
+        PhenotypeTable {
+            names: columns.iter().map(|x| self.names[*x].clone()).collect(),
+            samples: self.samples.clone(),
+            values: self
+                .values
+                .iter()
+                .map(|row| columns.iter().map(|x| row[*x]).collect())
+                .collect(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+/// Parse a single value, Some(None) if missing and None if not a number
+fn parse_value(value: &str) -> Option<Option<f64>> {
+    if MISSING_VALUES.contains(&value) {
+        Some(None)
+    } else {
+        value.parse::<f64>().ok().map(Some)
+    }
+}
+
+/// Format a single value, missing values are replaced
+fn format_value(value: Option<f64>, missing: &str) -> String {
+    value.map_or(missing.to_string(), |x| x.to_string())
+}
+
+impl MatrixWrapper {
+    /// # Match the phenotype table to the samples
+    ///
+    /// - Samples are matched by name (after PanSN grouping)
+    /// - Fam entries are created, phenotype is the first column (missing: -9)
+    /// - Samples which are missing on either side are reported
+    pub fn match_phenotypes(&mut self, phenotypes: &PhenotypeTable) -> Result<(), Gfa2binError> {
+        let not_in_table = self
+            .sample_names
+            .iter()
+            .filter(|x| phenotypes.get(x).is_none())
+            .cloned()
+            .collect::<Vec<String>>();
+        let not_in_samples = phenotypes
+            .samples
+            .iter()
+            .filter(|x| !self.sample_names.contains(x))
+            .cloned()
+            .collect::<Vec<String>>();
+        if not_in_table.len() == self.sample_names.len() {
+            return Err(Gfa2binError::Consistency(
+                "No sample is in the phenotype table".to_string(),
+            ));
+        }
+        info!(
+            "Phenotypes: {} of {} samples matched ({} columns)",
+            self.sample_names.len() - not_in_table.len(),
+            self.sample_names.len(),
+            phenotypes.names.len()
+        );
+        if !not_in_table.is_empty() {
+            warn!(
+                "{} samples are not in the phenotype table (missing phenotype): {}",
+                not_in_table.len(),
+                not_in_table.join(", ")
+            );
+        }
+        if !not_in_samples.is_empty() {
+            warn!(
+                "{} samples of the phenotype table are not in the input (ignored): {}",
+                not_in_samples.len(),
+                not_in_samples.join(", ")
+            );
+        }
+
+        self.fam_entries = self
+            .sample_names
+            .iter()
+            .map(|x| FamEntry {
+                phenotype: format_value(phenotypes.get(x).and_then(|y| y[0]), "-9"),
+                ..FamEntry::new(x)
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// # Write phenotypes and covariates
+    ///
+    /// - PLINK/PLINK 2/BGEN: prefix.pheno and prefix.cov (FID, IID, one column each, missing: NA)
+    /// - BIMBAM (GEMMA): prefix.pheno (one column each) and prefix.cov (intercept + one column each)
+    ///
+    /// Replaces the single column BIMBAM pheno file of write_phenotype_bimbam
+    pub fn write_phenotypes(
+        &self,
+        out_prefix: &str,
+        output_format: OutputFormat,
+        phenotypes: &PhenotypeTable,
+        covariates: Option<&PhenotypeTable>,
+    ) -> Result<(), io::Error> {
+        let bimbam = output_format == OutputFormat::Bimbam;
+        self.write_table(&[out_prefix, "pheno"].join("."), phenotypes, bimbam, false)?;
+        if let Some(covariates) = covariates {
+            self.write_table(&[out_prefix, "cov"].join("."), covariates, bimbam, true)?;
+        }
+        Ok(())
+    }
+
+    /// Write a table (sample order of the matrix)
+    ///
+    /// GEMMA: no header and sample names, covariates start with the intercept
+    fn write_table(
+        &self,
+        output: &str,
+        table: &PhenotypeTable,
+        gemma: bool,
+        intercept: bool,
+    ) -> Result<(), io::Error> {
+        let mut f = BufWriter::new(File::create(output)?);
+        if !gemma {
+            writeln!(f, "FID\tIID\t{}", table.names.join("\t"))?;
+        }
+        for (i, x) in self.sample_names.iter().enumerate() {
+            // Family id of the sample metadata (if given)
+            let family_id = self.fam_entries.get(i).map_or(x, |y| &y.family_id);
+            let values = match table.get(x) {
+                Some(values) => values.iter().map(|y| format_value(*y, "NA")).collect(),
+                None => vec!["NA".to_string(); table.names.len()],
+            };
+            if gemma && intercept {
+                writeln!(f, "1\t{}", values.join("\t"))?;
+            } else if gemma {
+                writeln!(f, "{}", values.join("\t"))?;
+            } else {
+                writeln!(f, "{}\t{}\t{}", family_id, x, values.join("\t"))?;
+            }
+        }
+        f.flush()
+    }
+}
@@ -0,0 +1,220 @@
+use crate::core::bgzf::BgzfWriter;
+use crate::core::error::Gfa2binError;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+/// Size of a linear index window (tbi)
+const TBI_MIN_SHIFT: u32 = 14;
+
+/// Number of bin levels (tbi)
+const TBI_DEPTH: u32 = 5;
+
+/// Number of bin levels (csi), covers coordinates up to 2^32
+const CSI_DEPTH: u32 = 6;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IndexType {
+    Tbi,
+    Csi,
+}
+
+impl FromStr for IndexType {
+    type Err = Gfa2binError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tbi" => Ok(IndexType::Tbi),
+            "csi" => Ok(IndexType::Csi),
+            _ => Err(Gfa2binError::Argument(format!("Unknown index type: {}", s))),
+        }
+    }
+}
+
+impl IndexType {
+    /// File extension
+    pub fn to_string1(&self) -> String {
+        match self {
+            IndexType::Tbi => "tbi".to_string(),
+            IndexType::Csi => "csi".to_string(),
+        }
+    }
+
+    fn depth(&self) -> u32 {
+        match self {
+            IndexType::Tbi => TBI_DEPTH,
+            IndexType::Csi => CSI_DEPTH,
+        }
+    }
+}
+
+/// Bin of a region [beg, end) - CSI specification
+pub fn reg2bin(beg: u64, end: u64, min_shift: u32, depth: u32) -> u32 {
+    let end = end.max(beg + 1) - 1;
+    let mut level = depth;
+    let mut shift = min_shift;
+    let mut offset = ((1u64 << (depth * 3)) - 1) / 7;
+    while level > 0 {
+        if beg >> shift == end >> shift {
+            return (offset + (beg >> shift)) as u32;
+        }
+        level -= 1;
+        shift += 3;
+        offset -= 1 << (level * 3);
+    }
+    0
+}
+
+#[derive(Debug, Clone, Default)]
+/// Index of a single reference sequence (contig)
+struct ReferenceIndex {
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    loffset: BTreeMap<u32, u64>,
+    linear: Vec<u64>,
+}
+
+/// # Tabix (tbi) and csi index for bgzip compressed VCF files
+///
+/// Records must be added sorted by contig and position
+/// More information: https://samtools.github.io/hts-specs/tabix.pdf and https://samtools.github.io/hts-specs/CSIv1.pdf
+pub struct IndexBuilder {
+    index_type: IndexType,
+    names: Vec<String>,
+    references: Vec<ReferenceIndex>,
+    last_position: u64,
+}
+
+impl IndexBuilder {
+    pub fn new(index_type: IndexType) -> Self {
+        Self {
+            index_type,
+            names: Vec::new(),
+            references: Vec::new(),
+            last_position: 0,
+        }
+    }
+
+    /// Add a record (0-based, half-open region) and its virtual offsets
+    pub fn add_record(
+        &mut self,
+        chromosome: &str,
+        beg: u64,
+        end: u64,
+        virtual_start: u64,
+        virtual_end: u64,
+    ) -> Result<(), io::Error> {
+        if self.names.last().map(|x| x.as_str()) != Some(chromosome) {
+            if self.names.iter().any(|x| x == chromosome) {
+                return Err(unsorted_error(chromosome, beg));
+            }
+            self.names.push(chromosome.to_string());
+            self.references.push(ReferenceIndex::default());
+            self.last_position = 0;
+        }
+        if beg < self.last_position {
+            return Err(unsorted_error(chromosome, beg));
+        }
+        self.last_position = beg;
+
+        let reference = self.references.last_mut().unwrap();
+        let bin = reg2bin(beg, end, TBI_MIN_SHIFT, self.index_type.depth());
+        let chunks = reference.bins.entry(bin).or_default();
+        match chunks.last_mut() {
+            Some(chunk) if chunk.1 == virtual_start => chunk.1 = virtual_end,
+            _ => chunks.push((virtual_start, virtual_end)),
+        }
+        reference.loffset.entry(bin).or_insert(virtual_start);
+
+        let first_window = (beg >> TBI_MIN_SHIFT) as usize;
+        let last_window = ((end.max(beg + 1) - 1) >> TBI_MIN_SHIFT) as usize;
+        if reference.linear.len() <= last_window {
+            reference.linear.resize(last_window + 1, u64::MAX);
+        }
+        for window in reference.linear[first_window..=last_window].iter_mut() {
+            if *window == u64::MAX {
+                *window = virtual_start;
+            }
+        }
+        Ok(())
+    }
+
+    /// Header shared by tbi and csi (VCF preset)
+    fn write_header<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        let names = self
+            .names
+            .iter()
+            .flat_map(|x| x.bytes().chain(std::iter::once(0)))
+            .collect::<Vec<u8>>();
+        // Format (VCF), sequence/begin/end column, meta character, skipped lines
+        for value in [2, 1, 2, 0, b'#' as i32, 0, names.len() as i32].iter() {
+            writer.write_i32::<LittleEndian>(*value)?;
+        }
+        writer.write_all(&names)
+    }
+
+    /// Write the index (bgzip compressed)
+    pub fn write(&self, filename: &str) -> Result<(), io::Error> {
+        let mut writer = BgzfWriter::new(BufWriter::new(File::create(filename)?));
+        match self.index_type {
+            IndexType::Tbi => {
+                writer.write_all(b"TBI\x01")?;
+                writer.write_i32::<LittleEndian>(self.names.len() as i32)?;
+                self.write_header(&mut writer)?;
+            }
+            IndexType::Csi => {
+                let mut aux = Vec::new();
+                self.write_header(&mut aux)?;
+                writer.write_all(b"CSI\x01")?;
+                writer.write_i32::<LittleEndian>(TBI_MIN_SHIFT as i32)?;
+                writer.write_i32::<LittleEndian>(CSI_DEPTH as i32)?;
+                writer.write_i32::<LittleEndian>(aux.len() as i32)?;
+                writer.write_all(&aux)?;
+                writer.write_i32::<LittleEndian>(self.names.len() as i32)?;
+            }
+        }
+
+        for reference in self.references.iter() {
+            writer.write_i32::<LittleEndian>(reference.bins.len() as i32)?;
+            for (bin, chunks) in reference.bins.iter() {
+                writer.write_u32::<LittleEndian>(*bin)?;
+                if self.index_type == IndexType::Csi {
+                    writer.write_u64::<LittleEndian>(reference.loffset[bin])?;
+                }
+                writer.write_i32::<LittleEndian>(chunks.len() as i32)?;
+                for (start, end) in chunks.iter() {
+                    writer.write_u64::<LittleEndian>(*start)?;
+                    writer.write_u64::<LittleEndian>(*end)?;
+                }
+            }
+
+            if self.index_type == IndexType::Tbi {
+                // Empty windows get the offset of the previous window
+                writer.write_i32::<LittleEndian>(reference.linear.len() as i32)?;
+                let mut previous = 0;
+                for offset in reference.linear.iter() {
+                    if *offset != u64::MAX {
+                        previous = *offset;
+                    }
+                    writer.write_u64::<LittleEndian>(previous)?;
+                }
+            }
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+fn unsorted_error(chromosome: &str, position: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Records are not sorted, can not index {}:{}",
+            chromosome,
+            position + 1
+        ),
+    )
+}
@@ -1,179 +1,426 @@
-use crate::core::core::MatrixWrapper;
+use crate::core::annotation::write_annotation;
+use crate::core::bed::{transpose_bed, BedReader, SampleMajorWriter};
+use crate::core::compression::{open_reader, Compression, PlainFile};
+use crate::core::core::{MatrixWrapper, OutputFormat};
+use crate::core::dedup::{dedup_bed, write_dedup_mapping};
+use crate::core::error::{parse_arg, Gfa2binError};
 use crate::core::helper::Feature;
+use crate::core::metadata::SampleMetadata;
+use crate::core::phenotype::PhenotypeTable;
 use crate::cov::pack::{
     init_geno_names, init_matrix, matrick_pack_wrapper, read_pack_wrapper, wrapper_reader123,
 };
+use crate::graph::samples::{sample_haplotypes, walks_to_paths};
+use crate::nearest::nearest_main::reference_positions;
+use bitvec::order::Lsb0;
+use bitvec::vec::BitVec;
 use clap::ArgMatches;
-use log::{info};
+use gfa_reader::{Gfa, Pansn};
+use log::info;
 
 use crate::core::helper::Feature::Alignment;
 
 use packing_lib::core::core::PackCompact;
 use packing_lib::core::reader::{read_index, unpack_zstd_to_byte};
 use packing_lib::normalize::convert_helper::Method;
-use std::fs::File;
+use std::fs;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::str::FromStr;
 
-pub fn cov_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin cov'");
-    // You have either a list of packs (plain-text) or a compressed pack (cat or list), but you need to provide an index
-    if matches.is_present("pack")
-        || (matches.is_present("pack compressed") && matches.is_present("index"))
-        || (matches.is_present("pc-list") && matches.is_present("index"))
-    {
-        info!("Files provided");
-    } else {
-        if matches.is_present("pack compressed") {
-            panic!("You need to provide an index file");
+pub fn cov_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    CovConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Coverage input of 'gfa2bin cov'
+pub enum PackInput {
+    /// List of plain-text pack files (sample name, file)
+    PackList(String),
+    /// Concatenated compressed pack file and index
+    Compressed(String, String),
+    /// List of compressed pack files (sample name, file) and index
+    CompressedList(String, String),
+}
+
+impl PackInput {
+    /// Index file (only compressed input)
+    pub fn index(&self) -> Option<&str> {
+        match self {
+            PackInput::PackList(_) => None,
+            PackInput::Compressed(_, index) | PackInput::CompressedList(_, index) => Some(index),
         }
-        if matches.is_present("pc-list") {
-            panic!("You need to provide an index file");
-        } else {
-            panic!("You need to provide a pack file");
+    }
+}
+
+#[derive(Clone)]
+/// # Options of 'gfa2bin cov'
+///
+/// Create with CovConfig::new and the builder methods, convert with CovConfig::run
+pub struct CovConfig {
+    pub input: PackInput,
+    pub output_prefix: String,
+    /// Graph file, only needed for reference coordinates and the feature annotation
+    pub graph_file: Option<String>,
+    /// PanSN-spec separator of the path names in the graph (None: path name is the sample)
+    pub pansn: Option<String>,
+    pub reference: Option<String>,
+    /// Write the feature annotation (length, GC, paths, samples), needs the graph
+    pub annotation: bool,
+    pub compression: Compression,
+    /// Absolute threshold (0: dynamic threshold based on method and fraction)
+    pub absolute_threshold: u32,
+    pub method: Method,
+    pub fraction: f32,
+    pub keep_zeros: bool,
+    /// Number of consecutive uncovered entries which are missing (0: off)
+    pub missing_window: usize,
+    /// Use sequence instead of nodes
+    pub sequence: bool,
+    pub output_format: OutputFormat,
+    /// Dummy phenotype (None: NA)
+    pub pheno: Option<f64>,
+    /// Phenotype table (sample name + phenotype columns)
+    pub pheno_file: Option<String>,
+    /// Columns of the phenotype table which are covariates
+    pub covariates: Vec<String>,
+    /// Sample metadata (FID, IID, father, mother, sex)
+    pub metadata: Option<String>,
+    /// Number of variants which are transposed at once (PLINK output)
+    pub block_size: usize,
+    /// Keep one feature per unique genotype pattern (mapping: prefix.dedup.tsv)
+    pub dedup: bool,
+}
+
+impl CovConfig {
+    /// Default options (dynamic threshold: percentile, fraction 0.1, PLINK output)
+    pub fn new(input: PackInput, output_prefix: &str) -> Self {
+        Self {
+            input,
+            output_prefix: output_prefix.to_string(),
+            graph_file: None,
+            pansn: None,
+            reference: None,
+            annotation: false,
+            compression: Compression::None,
+            absolute_threshold: 0,
+            method: Method::from_str("percentile"),
+            fraction: 0.1,
+            keep_zeros: false,
+            missing_window: 0,
+            sequence: false,
+            output_format: OutputFormat::Plink,
+            pheno: None,
+            pheno_file: None,
+            covariates: Vec::new(),
+            metadata: None,
+            block_size: 10000,
+            dedup: false,
         }
     }
 
-    // Read input files
-    let pack_list = matches.value_of("pack");
-    let cpack = matches.value_of("pack compressed");
-    let cpacklist = matches.value_of("pc-list");
-    let output_prefix = matches.value_of("output").unwrap();
-
-    // Normalize the rows
-    let absolute_thresh = matches
-        .value_of("absolute-threshold")
-        .unwrap_or("0")
-        .parse::<u32>()
-        .unwrap();
-    let fraction = matches
-        .value_of("fraction")
-        .unwrap()
-        .parse::<f32>()
-        .expect("Could not parse fraction");
-    let method = Method::from_str(matches.value_of("method").unwrap());
-
-    let keep_zeros = matches.is_present("keep-zeros");
-    let want_node = !matches.is_present("sequence");
-    // Output modification
-
-    // Output
-    let bimbam = matches.is_present("bimbam");
-
-    let mut dyna = true;
-    if absolute_thresh > 0 {
-        dyna = false;
-        // if not, give method and fraction
-    } else if method == Method::Nothing {
-        panic!("You need to provide a method");
-    } else if fraction <= 0.0 {
-        panic!("You need to provide a fraction");
+    /// Reference coordinates (based on the graph)
+    pub fn reference(mut self, graph_file: &str, reference: &str) -> Self {
+        self.graph_file = Some(graph_file.to_string());
+        self.reference = Some(reference.to_string());
+        self
+    }
+
+    /// Feature annotation (prefix.annotation.tsv), based on the graph
+    pub fn annotation(mut self, graph_file: &str) -> Self {
+        self.graph_file = Some(graph_file.to_string());
+        self.annotation = true;
+        self
+    }
+
+    pub fn pansn(mut self, sep: &str) -> Self {
+        self.pansn = Some(sep.to_string());
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn absolute_threshold(mut self, absolute_threshold: u32) -> Self {
+        self.absolute_threshold = absolute_threshold;
+        self
+    }
+
+    /// Dynamic threshold
+    pub fn dynamic_threshold(mut self, method: Method, fraction: f32, keep_zeros: bool) -> Self {
+        self.method = method;
+        self.fraction = fraction;
+        self.keep_zeros = keep_zeros;
+        self
+    }
+
+    pub fn missing_window(mut self, missing_window: usize) -> Self {
+        self.missing_window = missing_window;
+        self
+    }
+
+    pub fn sequence(mut self, sequence: bool) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    pub fn pheno(mut self, pheno: f64) -> Self {
+        self.pheno = Some(pheno);
+        self
     }
 
-    let mut pheno = f64::MAX;
-    if matches.is_present("pheno") {
-        pheno = matches.value_of("pheno").unwrap().parse()?;
+    /// Phenotype table, covariates are columns of the table
+    pub fn pheno_file(mut self, pheno_file: &str, covariates: &[&str]) -> Self {
+        self.pheno_file = Some(pheno_file.to_string());
+        self.covariates = covariates.iter().map(|x| x.to_string()).collect();
+        self
     }
 
-    info!("Feature: {}", Alignment.to_string1());
-    info!(
-        "Absolute threshold: {}",
-        if absolute_thresh == 0 {
-            "None".to_string()
+    /// Sample metadata (FID, IID, father, mother, sex) for the fam file
+    pub fn metadata(mut self, metadata: &str) -> Self {
+        self.metadata = Some(metadata.to_string());
+        self
+    }
+
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Identical genotype patterns are written once (mapping: prefix.dedup.tsv)
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        // You have either a list of packs (plain-text) or a compressed pack (cat or list), but you need to provide an index
+        let index = matches.value_of("index").map(|x| x.to_string());
+        let input = if let Some(pack_list) = matches.value_of("pack") {
+            PackInput::PackList(pack_list.to_string())
+        } else if let (Some(cpack), Some(index)) = (matches.value_of("pack compressed"), &index) {
+            PackInput::Compressed(cpack.to_string(), index.clone())
+        } else if let (Some(cpacklist), Some(index)) = (matches.value_of("pc-list"), &index) {
+            PackInput::CompressedList(cpacklist.to_string(), index.clone())
+        } else if matches.is_present("pack compressed") || matches.is_present("pc-list") {
+            return Err(Gfa2binError::Argument(
+                "You need to provide an index file (--index)".to_string(),
+            ));
         } else {
-            absolute_thresh.to_string()
-        }
-    );
-    info!("Method: {}", method.to_string());
-    info!("Fraction: {}", fraction);
-    info!("Keep zeros: {}", keep_zeros);
-    info!(
-        "Dummy-Pheno: {}",
-        if pheno == f64::MAX {
-            "NA".to_string()
+            return Err(Gfa2binError::Argument(
+                "You need to provide a pack file".to_string(),
+            ));
+        };
+
+        let mut config = Self::new(input, matches.value_of("output").unwrap());
+        config.graph_file = matches.value_of("gfa").map(|x| x.to_string());
+        config.pansn = matches
+            .value_of("PanSN")
+            .filter(|x| *x != "\n")
+            .map(|x| x.to_string());
+        config.reference = matches.value_of("reference").map(|x| x.to_string());
+        config.annotation = matches.is_present("annotation");
+        config.compression = matches
+            .value_of("compress")
+            .map_or(Ok(Compression::None), Compression::from_str)?;
+        config.absolute_threshold = parse_arg::<u32>(
+            matches.value_of("absolute-threshold").unwrap_or("0"),
+            "absolute-threshold",
+        )?;
+        config.fraction = parse_arg::<f32>(matches.value_of("fraction").unwrap(), "fraction")?;
+        config.method = Method::from_str(matches.value_of("method").unwrap());
+        config.keep_zeros = matches.is_present("keep-zeros");
+        config.missing_window = parse_arg::<usize>(
+            matches.value_of("missing-window").unwrap_or("0"),
+            "missing-window",
+        )?;
+        config.sequence = matches.is_present("sequence");
+        config.output_format = if matches.is_present("bimbam") {
+            OutputFormat::Bimbam
+        } else if matches.is_present("plink2") {
+            OutputFormat::Plink2
+        } else if matches.is_present("bgen") {
+            OutputFormat::Bgen
         } else {
-            pheno.to_string()
+            OutputFormat::Plink
+        };
+        if matches.is_present("pheno") {
+            config.pheno = Some(parse_arg(matches.value_of("pheno").unwrap(), "pheno")?);
         }
-    );
-    info!("Type: {}", if want_node { "Node" } else { "Sequence" });
-    info!("Output format: {}", if bimbam { "bimbam" } else { "PLINK" });
-    info!("Output prefix: {}\n", output_prefix);
-
-    // Initialize the matrix wrapper
-    let mut mw = MatrixWrapper::new();
-    mw.feature = Feature::Alignment;
-
-    info!("Reading the input");
-    if matches.is_present("pack") {
-        info!("Reading plain-text pack");
-        // Read the first data
-        let files_list = read_file_lines(pack_list.unwrap()).unwrap();
-        let mut pack_first = read_pack_wrapper(true, &files_list[0][1]);
-        init_geno_names(&mut mw, &mut pack_first, want_node, &vec![]);
-        init_matrix(
-            &mut mw,
-            &mut pack_first,
-            want_node,
-            bimbam,
-            files_list.len(),
-        );
+        config.pheno_file = matches.value_of("pheno-file").map(|x| x.to_string());
+        config.covariates = matches.value_of("covariates").map_or(Vec::new(), |x| {
+            x.split(',').map(|y| y.to_string()).collect()
+        });
+        config.metadata = matches.value_of("sample-metadata").map(|x| x.to_string());
+        config.block_size =
+            parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?;
+        config.dedup = matches.is_present("dedup");
+        Ok(config)
+    }
 
-        for (index, x) in files_list.iter().enumerate() {
-            let mut pc = PackCompact::parse_pack(&x[1]);
+    /// Convert the coverage (pack) files to PLINK (or BIMBAM/PLINK 2/BGEN)
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin cov'");
+        let output_prefix = self.output_prefix.as_str();
+        let graph_file = self.graph_file.as_deref();
+        let reference = self.reference.as_deref();
+        let compression = self.compression;
+        if reference.is_some() && graph_file.is_none() {
+            return Err(Gfa2binError::Argument(
+                "You need to provide a graph (--gfa) for reference coordinates".to_string(),
+            ));
+        } else if self.annotation && graph_file.is_none() {
+            return Err(Gfa2binError::Argument(
+                "You need to provide a graph (--gfa) for the feature annotation".to_string(),
+            ));
+        }
 
-            if pc.node_index != pack_first.node_index {
-                panic!("The pack files are not the same");
-            }
+        // Normalize the rows
+        let absolute_thresh = self.absolute_threshold;
+        let fraction = self.fraction;
+        let method = self.method;
+        let keep_zeros = self.keep_zeros;
+        let missing_window = self.missing_window;
+        let want_node = !self.sequence;
+
+        // Output
+        let output_format = self.output_format;
+        // Keep the normalized values (bimbam values, plink2 or bgen dosages)
+        let bimbam = output_format != OutputFormat::Plink;
+
+        // Without absolute threshold, give method and fraction
+        if absolute_thresh == 0 && method == Method::Nothing {
+            return Err(Gfa2binError::Argument(
+                "You need to provide a method".to_string(),
+            ));
+        } else if absolute_thresh == 0 && fraction <= 0.0 {
+            return Err(Gfa2binError::Argument(
+                "You need to provide a fraction".to_string(),
+            ));
+        }
 
-            matrick_pack_wrapper(
-                &mut mw,
-                &mut pc,
-                want_node,
-                keep_zeros,
-                fraction,
-                method,
-                bimbam,
-                &vec![],
-                index,
-                &x[0],
-                absolute_thresh,
-            );
+        if self.dedup && output_format != OutputFormat::Plink {
+            return Err(Gfa2binError::Argument(
+                "Deduplication (--dedup) is only available for PLINK output".to_string(),
+            ));
         }
-        // Compressed back (bin/u16, seq/node)
-    } else {
-        // Index of the file
-        let index_file = read_index(matches.value_of("index").unwrap());
-        // Compressed pack list
-        if matches.is_present("pc-list") {
-            info!("Reading pc-list");
-            // Read the samples and path
-            let cpack_list = read_file_lines(cpacklist.unwrap()).unwrap();
-
-            // Read the first file
-            let mut pack_first = read_pack_wrapper(false, &cpack_list[0][1]);
-
-            if !pack_first.is_sequence && !want_node {
-                panic!("The first file is not a sequence, but you want a node");
-            }
-            // Geno names based on the index
-            init_geno_names(&mut mw, &mut pack_first, want_node, &index_file);
 
-            //
+        let pheno = self.pheno.unwrap_or(f64::MAX);
+        if self.pheno_file.is_none() && !self.covariates.is_empty() {
+            return Err(Gfa2binError::Argument(
+                "Covariates need a phenotype table (--pheno-file)".to_string(),
+            ));
+        } else if self.pheno_file.is_some() && self.pheno.is_some() {
+            return Err(Gfa2binError::Argument(
+                "Use either a dummy phenotype (--pheno) or a phenotype table (--pheno-file)"
+                    .to_string(),
+            ));
+        }
+        let phenotypes = match &self.pheno_file {
+            Some(pheno_file) => {
+                Some(PhenotypeTable::from_file(pheno_file)?.split_covariates(&self.covariates)?)
+            }
+            None => None,
+        };
+        let metadata = match &self.metadata {
+            Some(metadata) => Some(SampleMetadata::from_file(metadata)?),
+            None => None,
+        };
+
+        info!("Feature: {}", Alignment.to_string1());
+        info!(
+            "Absolute threshold: {}",
+            if absolute_thresh == 0 {
+                "None".to_string()
+            } else {
+                absolute_thresh.to_string()
+            }
+        );
+        info!("Method: {}", method.to_string());
+        info!("Fraction: {}", fraction);
+        info!("Keep zeros: {}", keep_zeros);
+        info!(
+            "Missing window: {}",
+            if missing_window == 0 {
+                "None".to_string()
+            } else {
+                missing_window.to_string()
+            }
+        );
+        info!(
+            "Dummy-Pheno: {}",
+            if pheno == f64::MAX {
+                "NA".to_string()
+            } else {
+                pheno.to_string()
+            }
+        );
+        info!("Type: {}", if want_node { "Node" } else { "Sequence" });
+        info!(
+            "Phenotype table: {}",
+            self.pheno_file.as_deref().unwrap_or("None")
+        );
+        info!(
+            "Covariates: {}",
+            if self.covariates.is_empty() {
+                "None".to_string()
+            } else {
+                self.covariates.join(", ")
+            }
+        );
+        info!(
+            "Sample metadata: {}",
+            self.metadata.as_deref().unwrap_or("None")
+        );
+        info!("Output format: {}", output_format.to_string1());
+        info!("Reference (coordinates): {}", reference.unwrap_or("None"));
+        info!("Compression: {}", compression.to_string1());
+        info!("Feature annotation: {}", self.annotation);
+        info!("Block size: {}", self.block_size);
+        info!("Deduplicate genotype patterns: {}", self.dedup);
+        info!("Output prefix: {}\n", output_prefix);
+
+        // Initialize the matrix wrapper
+        let mut mw = MatrixWrapper::new();
+        mw.feature = Feature::Alignment;
+        mw.compression = compression;
+
+        // Binary samples are streamed to disk (sample-major) and transposed at the end
+        let mut samples = SampleStream::new(output_prefix);
+
+        info!("Reading the input");
+        if let PackInput::PackList(pack_list) = &self.input {
+            info!("Reading plain-text pack");
+            // Read the first data
+            let files_list = read_file_lines(pack_list)?;
+            let mut pack_first = read_pack_wrapper(true, &files_list[0][1]);
+            init_geno_names(&mut mw, &mut pack_first, want_node, &[]);
             init_matrix(
                 &mut mw,
                 &mut pack_first,
                 want_node,
                 bimbam,
-                cpack_list.len(),
+                files_list.len(),
             );
-            let pack_first = read_pack_wrapper(false, &cpack_list[0][1]);
 
-            for (index, x) in cpack_list.iter().enumerate() {
-                let mut pc = PackCompact::read_wrapper(&x[1]);
+            for (index, x) in files_list.iter().enumerate() {
+                let mut pc = PackCompact::parse_pack(&x[1]);
+
                 if pc.node_index != pack_first.node_index {
-                    panic!("The pack files are not the same");
+                    return Err(Gfa2binError::Consistency(
+                        "The pack files are not the same".to_string(),
+                    ));
                 }
-                matrick_pack_wrapper(
+
+                samples.write(matrick_pack_wrapper(
                     &mut mw,
                     &mut pc,
                     want_node,
@@ -181,101 +428,287 @@ pub fn cov_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>>
                     fraction,
                     method,
                     bimbam,
-                    &index_file,
+                    &[],
                     index,
                     &x[0],
                     absolute_thresh,
-                );
+                    missing_window,
+                ))?;
             }
-
-            // Concatenated compressed pack
-        } else if matches.is_present("pack compressed") {
-            info!("Reading 'pack compressed'");
-            let file_pack = cpack.unwrap();
-            let buffer = unpack_zstd_to_byte(file_pack);
-            let (
-                _kind,
-                _include_all,
-                _bin,
-                _method,
-                _relative,
-                _std,
-                _thresh,
-                bytes,
-                _length,
-                _name,
-            ) = PackCompact::get_meta(&buffer);
-
-            // Chunks
-            let mut chunks = buffer.chunks((bytes + 86) as usize);
-            let number_chunks = chunks.len();
-
-            let mut pack_first = wrapper_reader123(chunks.next().unwrap());
-            init_geno_names(&mut mw, &mut pack_first, want_node, &index_file);
-            init_matrix(&mut mw, &mut pack_first, want_node, bimbam, number_chunks);
-            let chunks = buffer.chunks((bytes + 86) as usize);
-            pack_first.node_index = vec![];
-
-            for (index, chunk) in chunks.enumerate() {
-                let mut pc = wrapper_reader123(chunk);
-                if pc.node_index != pack_first.node_index {
-                    panic!("The pack files are not the same");
+            // Compressed back (bin/u16, seq/node)
+        } else {
+            // Index of the file
+            let index_file = read_index(self.input.index().ok_or_else(|| {
+                Gfa2binError::Argument("Compressed pack files need an index".to_string())
+            })?);
+            // Compressed pack list
+            if let PackInput::CompressedList(cpacklist, _) = &self.input {
+                info!("Reading pc-list");
+                // Read the samples and path
+                let cpack_list = read_file_lines(cpacklist)?;
+
+                // Read the first file
+                let mut pack_first = read_pack_wrapper(false, &cpack_list[0][1]);
+
+                if !pack_first.is_sequence && !want_node {
+                    return Err(Gfa2binError::Consistency(
+                        "The first file is not a sequence, but you want a node".to_string(),
+                    ));
                 }
+                // Geno names based on the index
+                init_geno_names(&mut mw, &mut pack_first, want_node, &index_file);
 
-                let name = pc.name.clone();
-                matrick_pack_wrapper(
+                //
+                init_matrix(
                     &mut mw,
-                    &mut pc,
+                    &mut pack_first,
                     want_node,
-                    keep_zeros,
-                    fraction,
-                    method,
                     bimbam,
-                    &index_file,
-                    index,
-                    &name,
-                    absolute_thresh,
+                    cpack_list.len(),
+                );
+                let pack_first = read_pack_wrapper(false, &cpack_list[0][1]);
+
+                for (index, x) in cpack_list.iter().enumerate() {
+                    let mut pc = PackCompact::read_wrapper(&x[1]);
+                    if pc.node_index != pack_first.node_index {
+                        return Err(Gfa2binError::Consistency(
+                            "The pack files are not the same".to_string(),
+                        ));
+                    }
+                    samples.write(matrick_pack_wrapper(
+                        &mut mw,
+                        &mut pc,
+                        want_node,
+                        keep_zeros,
+                        fraction,
+                        method,
+                        bimbam,
+                        &index_file,
+                        index,
+                        &x[0],
+                        absolute_thresh,
+                        missing_window,
+                    ))?;
+                }
+
+                // Concatenated compressed pack
+            } else if let PackInput::Compressed(file_pack, _) = &self.input {
+                info!("Reading 'pack compressed'");
+                let buffer = unpack_zstd_to_byte(file_pack);
+                let (
+                    _kind,
+                    _include_all,
+                    _bin,
+                    _method,
+                    _relative,
+                    _std,
+                    _thresh,
+                    bytes,
+                    _length,
+                    _name,
+                ) = PackCompact::get_meta(&buffer);
+
+                // Chunks
+                let mut chunks = buffer.chunks((bytes + 86) as usize);
+                let number_chunks = chunks.len();
+
+                let mut pack_first = wrapper_reader123(chunks.next().ok_or_else(|| {
+                    Gfa2binError::Format(format!("{}: no pack in the file", file_pack))
+                })?);
+                init_geno_names(&mut mw, &mut pack_first, want_node, &index_file);
+                init_matrix(&mut mw, &mut pack_first, want_node, bimbam, number_chunks);
+                let chunks = buffer.chunks((bytes + 86) as usize);
+                pack_first.node_index = vec![];
+
+                for (index, chunk) in chunks.enumerate() {
+                    let mut pc = wrapper_reader123(chunk);
+                    if pc.node_index != pack_first.node_index {
+                        return Err(Gfa2binError::Consistency(
+                            "The pack files are not the same".to_string(),
+                        ));
+                    }
+
+                    let name = pc.name.clone();
+                    samples.write(matrick_pack_wrapper(
+                        &mut mw,
+                        &mut pc,
+                        want_node,
+                        keep_zeros,
+                        fraction,
+                        method,
+                        bimbam,
+                        &index_file,
+                        index,
+                        &name,
+                        absolute_thresh,
+                        missing_window,
+                    ))?;
+                }
+            }
+        }
+
+        // Sample index
+        mw.sample_index_u16 = mw
+            .sample_names
+            .iter()
+            .enumerate()
+            .map(|x| [x.0, x.0])
+            .collect();
+
+        if let Some((phenotypes, _)) = &phenotypes {
+            mw.match_phenotypes(phenotypes)?;
+        }
+        if let Some(metadata) = &metadata {
+            mw.apply_metadata(metadata);
+        }
+
+        // Set feature style
+        let feature_enum = Feature::Alignment;
+
+        if let Some(graph_file) = graph_file.filter(|_| reference.is_some() || self.annotation) {
+            let graph_input = PlainFile::new(graph_file)?;
+            let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file(graph_input.path());
+            let origins = walks_to_paths(&mut graph, self.pansn.as_deref());
+            if let Some(reference) = reference {
+                info!("Project the features on the reference");
+                let reference_positions = reference_positions(reference, &graph, &origins)?;
+                mw.project_bim(&reference_positions, feature_enum);
+            }
+            if self.annotation {
+                info!("Writing the feature annotation");
+                let wrapper: Pansn<u32, (), ()> = sample_haplotypes(&graph.paths, &origins);
+                write_annotation(
+                    output_prefix,
+                    &mw.feature_annotation(&graph, &wrapper, feature_enum, !want_node),
+                )?;
+            }
+        }
+
+        if let Some((bed_file, samples_number, snp_number)) = samples.finish()? {
+            if output_format == OutputFormat::Plink {
+                info!(
+                    "Matrix [Genotypes X Samples]: {}, {}",
+                    snp_number,
+                    samples_number * 2
                 );
+                info!("Writing the plink bed/bim/fam");
+                if self.dedup {
+                    info!("Deduplicate the genotype patterns");
+                    let representatives = dedup_bed(
+                        &bed_file,
+                        &format!("{}.bed", output_prefix),
+                        samples_number,
+                        snp_number,
+                        self.block_size,
+                    )?;
+                    write_dedup_mapping(
+                        output_prefix,
+                        &mw.retain_representatives(&representatives),
+                    )?;
+                } else {
+                    transpose_bed(
+                        &bed_file,
+                        &format!("{}.bed", output_prefix),
+                        samples_number,
+                        snp_number,
+                        self.block_size,
+                    )?;
+                }
+                fs::remove_file(&bed_file)?;
+                mw.write_fam(0, output_prefix, feature_enum, 1, pheno)?;
+                mw.write_bim(0, output_prefix, &feature_enum, 1)?;
+                if let Some((phenotypes, covariates)) = &phenotypes {
+                    mw.write_phenotypes(
+                        output_prefix,
+                        output_format,
+                        phenotypes,
+                        covariates.as_ref(),
+                    )?;
+                }
+                return Ok(());
+            }
+            // Other formats need the whole matrix
+            let mut reader =
+                BedReader::new(&bed_file, samples_number, snp_number, self.block_size)?;
+            mw.matrix_bit = reader.read_matrix()?;
+            fs::remove_file(&bed_file)?;
+        }
+
+        let thresh = mw
+            .matrix_f32
+            .iter()
+            .map(|x| x.iter().cloned().fold(0.0, f32::max))
+            .collect();
+
+        mw.write_wrapper(
+            output_format,
+            1,
+            output_prefix,
+            thresh,
+            feature_enum,
+            pheno,
+            !keep_zeros,
+        )?;
+        if let Some((phenotypes, covariates)) = &phenotypes {
+            mw.write_phenotypes(
+                output_prefix,
+                output_format,
+                phenotypes,
+                covariates.as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// # Binary samples of 'gfa2bin cov'
+///
+/// Written to a temporary sample-major bed file, which is created with the first sample
+struct SampleStream {
+    path: String,
+    writer: Option<SampleMajorWriter>,
+}
+
+impl SampleStream {
+    fn new(output_prefix: &str) -> Self {
+        Self {
+            path: format!("{}.bed.tmp", output_prefix),
+            writer: None,
+        }
+    }
+
+    /// Write a sample (nothing if the values are kept in the wrapper)
+    fn write(&mut self, sample: Option<BitVec<u8, Lsb0>>) -> Result<(), Gfa2binError> {
+        if let Some(sample) = sample {
+            if self.writer.is_none() {
+                self.writer = Some(SampleMajorWriter::new(&self.path, sample.len() / 2)?);
+            }
+            if let Some(writer) = self.writer.as_mut() {
+                writer.write_sample(&sample)?;
             }
         }
+        Ok(())
     }
 
-    // Sample index
-    mw.sample_index_u16 = mw
-        .sample_names
-        .iter()
-        .enumerate()
-        .map(|x| [x.0, x.0])
-        .collect();
-
-    // Set feature style
-    let feature_enum = Feature::Alignment;
-
-    let thresh = mw
-        .matrix_f32
-        .iter()
-        .map(|x| x.iter().cloned().fold(0.0, f32::max))
-        .collect();
-
-    mw.write_wrapper(
-        bimbam,
-        1,
-        output_prefix,
-        thresh,
-        feature_enum,
-        pheno,
-        !keep_zeros,
-    );
-    Ok(())
+    /// Finish the file, returns file name, number of samples and variants (None if nothing was written)
+    fn finish(self) -> Result<Option<(String, usize, usize)>, io::Error> {
+        match self.writer {
+            Some(writer) => {
+                let shape = (writer.samples_number, writer.snp_number);
+                writer.finish()?;
+                Ok(Some((self.path, shape.0, shape.1)))
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 /// Read a file and return each line in a vector
 ///
 /// Check if entry is a path
-fn read_file_lines(file_path: &str) -> io::Result<Vec<[String; 2]>> {
+fn read_file_lines(file_path: &str) -> Result<Vec<[String; 2]>, Gfa2binError> {
     // Open the file
-    let file = File::open(file_path).expect("Can not open file");
-    let reader = io::BufReader::new(file);
+    let reader = open_reader(file_path)?;
 
     // Create a vector to store the entries
     let mut entries: Vec<[String; 2]> = Vec::new();
@@ -283,17 +716,25 @@ fn read_file_lines(file_path: &str) -> io::Result<Vec<[String; 2]>> {
     // Iterate over each line in the file
     for line in reader.lines() {
         // Add the line to the vector
-        if let Ok(entry) = line {
-            let entry_split = entry.split_whitespace().collect::<Vec<&str>>();
-            if Path::is_file(Path::new(&entry_split[1])) {
-                entries.push([entry_split[0].to_string(), entry_split[1].to_string()]);
-            } else {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("{} is not a file", entry),
-                ));
-            }
+        let entry = line?;
+        let entry_split = entry.split_whitespace().collect::<Vec<&str>>();
+        if entry_split.len() < 2 {
+            return Err(Gfa2binError::Format(format!(
+                "{}: expected sample name and file, got '{}'",
+                file_path, entry
+            )));
         }
+        if Path::is_file(Path::new(&entry_split[1])) {
+            entries.push([entry_split[0].to_string(), entry_split[1].to_string()]);
+        } else {
+            return Err(Gfa2binError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a file", entry_split[1]),
+            )));
+        }
+    }
+    if entries.is_empty() {
+        return Err(Gfa2binError::Format(format!("{} is empty", file_path)));
     }
 
     // Return the vector of entries
@@ -1,47 +1,84 @@
+use crate::core::bed::{set_genotype, BED_MISSING, BED_PRESENT};
 use crate::core::core::MatrixWrapper;
-use crate::core::helper::{index2node_seq, merge_u32_to_u64};
+use crate::core::helper::{index2node_seq, FeatureId};
+use crate::core::matrix::GenotypeMatrix;
 use bitvec::order::Lsb0;
 use bitvec::prelude::BitVec;
 
-
 use packing_lib::core::core::{DataType, PackCompact};
 use packing_lib::normalize::convert_helper::Method;
 
-/// # Binary Pack to Binary Matrix
-pub fn bin2bin(matrix_w: &mut MatrixWrapper, input: &PackCompact, index: usize) {
+/// # Binary Pack to a binary sample (two bits per entry)
+pub fn bin2bin(input: &PackCompact, missing: &BitVec<u8, Lsb0>) -> BitVec<u8, Lsb0> {
+    let mut sample = BitVec::<u8, Lsb0>::repeat(false, input.bin_coverage.len() * 2);
     for (i, y) in input.bin_coverage.iter().enumerate() {
-        if y == &true {
-            matrix_w.matrix_bit[i].get_mut(index * 2).unwrap().set(true);
-            matrix_w.matrix_bit[i]
-                .get_mut(index * 2 + 1)
-                .unwrap()
-                .set(true);
+        if missing[i] {
+            set_genotype(&mut sample, i, BED_MISSING);
+        } else if y == &true {
+            set_genotype(&mut sample, i, BED_PRESENT);
         }
     }
+    sample
 }
 
-/// # f32 Pack to Binary Matrix
-pub fn f32_to_bin(matrix_w: &mut MatrixWrapper, input: &PackCompact, thresh: f32, index: usize) {
+/// # f32 Pack to a binary sample (two bits per entry)
+pub fn f32_to_bin(
+    input: &PackCompact,
+    thresh: f32,
+    missing: &BitVec<u8, Lsb0>,
+) -> BitVec<u8, Lsb0> {
+    let mut sample = BitVec::<u8, Lsb0>::repeat(false, input.normalized_coverage.len() * 2);
     for (i, y) in input.normalized_coverage.iter().enumerate() {
-        if y > &thresh {
-            matrix_w.matrix_bit[i].get_mut(index * 2).unwrap().set(true);
-            matrix_w.matrix_bit[i]
-                .get_mut(index * 2 + 1)
-                .unwrap()
-                .set(true);
+        if missing[i] {
+            set_genotype(&mut sample, i, BED_MISSING);
+        } else if y > &thresh {
+            set_genotype(&mut sample, i, BED_PRESENT);
         }
     }
+    sample
 }
 
 /// # f32 Pack to f32 Matrix
-pub fn f32_to_f32(matrix_w: &mut MatrixWrapper, input: &PackCompact, thresh: f32, index: usize) {
+pub fn f32_to_f32(
+    matrix_w: &mut MatrixWrapper,
+    input: &PackCompact,
+    thresh: f32,
+    index: usize,
+    missing: &BitVec<u8, Lsb0>,
+) {
+    if missing.any() && matrix_w.matrix_missing.is_empty() {
+        matrix_w.matrix_missing =
+            vec![
+                BitVec::<u8, Lsb0>::repeat(false, matrix_w.matrix_f32[0].len());
+                matrix_w.matrix_f32.len()
+            ];
+    }
     for (i, y) in input.normalized_coverage.iter().enumerate() {
-        if y > &thresh {
+        if missing[i] {
+            matrix_w.matrix_missing[i].set(index, true);
+        } else if y > &thresh {
             matrix_w.matrix_f32[i][index] = *y;
         }
     }
 }
 
+/// # Missing windows
+///
+/// Split the entries in windows (of "window" entries), all entries of a window without coverage are missing
+/// Window size 0: nothing is missing
+pub fn missing_windows(covered: &[bool], window: usize) -> BitVec<u8, Lsb0> {
+    let mut missing = BitVec::<u8, Lsb0>::repeat(false, covered.len());
+    if window == 0 {
+        return missing;
+    }
+    for (i, chunk) in covered.chunks(window).enumerate() {
+        if !chunk.contains(&true) {
+            missing[i * window..i * window + chunk.len()].fill(true);
+        }
+    }
+    missing
+}
+
 /// # Read pack wrapper
 ///
 /// - If the pack is plain, parse it
@@ -76,11 +113,11 @@ pub fn init_geno_names(
     mw: &mut MatrixWrapper,
     pc: &mut PackCompact,
     want_node: bool,
-    index: &Vec<u32>,
+    index: &[u32],
 ) {
-    /// Check the index
+    // Check the index
     if pc.node_index.is_empty() {
-        pc.node_index = index.clone();
+        pc.node_index = index.to_vec();
     }
 
     if want_node {
@@ -93,6 +130,7 @@ pub fn init_geno_names(
 /// # Init matrix
 ///
 /// Based on the output
+/// Binary samples are not stored in the wrapper, but streamed (see matrick_pack_wrapper)
 pub fn init_matrix(
     mw: &mut MatrixWrapper,
     pc: &mut PackCompact,
@@ -114,19 +152,19 @@ pub fn init_matrix(
 
         // if bin_coverage is there
     } else {
-        mw.matrix_bit = vec![BitVec::<u8, Lsb0>::repeat(false, len1 * 2); pc.bin_coverage.len()];
         return;
     }
 
     // This is the important part
     if bimbam {
         mw.matrix_f32 = vec![vec![0.0; len1]; pc.normalized_coverage.len()];
-    } else {
-        mw.matrix_bit =
-            vec![BitVec::<u8, Lsb0>::repeat(false, len1 * 2); pc.normalized_coverage.len()];
     }
 }
 
+/// # Add a single pack to the matrix
+///
+/// Returns the binary sample (two bits per entry), if the values are not kept (bimbam)
+#[allow(clippy::too_many_arguments)]
 pub fn matrick_pack_wrapper(
     mw: &mut MatrixWrapper,
     pc: &mut PackCompact,
@@ -135,16 +173,17 @@ pub fn matrick_pack_wrapper(
     fraction: f32,
     method: Method,
     bimbam: bool,
-    index_file: &Vec<u32>,
+    index_file: &[u32],
     index: usize,
-    name: &String,
+    name: &str,
     absolute: u32,
-) {
+    missing_window: usize,
+) -> Option<BitVec<u8, Lsb0>> {
     // Add samples to matrix
-    mw.sample_names.push(name.clone());
+    mw.sample_names.push(name.to_string());
 
     if !index_file.is_empty() {
-        pc.node_index = index_file.clone();
+        pc.node_index = index_file.to_vec();
     }
 
     if pc.bin_coverage.is_empty() {
@@ -154,47 +193,49 @@ pub fn matrick_pack_wrapper(
             pc.normalized_coverage = pc.coverage.iter().map(|x| *x as f32).collect();
         }
 
-        let thresh;
-        if absolute > 0 {
-            thresh = absolute as f32;
+        let thresh = if absolute > 0 {
+            absolute as f32
         } else {
-            thresh = PackCompact::get_threshold(pc, keep_zeros, fraction, 0.0, method);
-        }
+            PackCompact::get_threshold(pc, keep_zeros, fraction, 0.0, method)
+        };
 
+        let covered = pc
+            .normalized_coverage
+            .iter()
+            .map(|x| *x > 0.0)
+            .collect::<Vec<bool>>();
+        let missing = missing_windows(&covered, missing_window);
         if bimbam {
-            f32_to_f32(mw, pc, thresh, index);
+            f32_to_f32(mw, pc, thresh, index, &missing);
+            None
         } else {
-            f32_to_bin(mw, pc, thresh, index);
+            Some(f32_to_bin(pc, thresh, &missing))
         }
     } else {
-        bin2bin(mw, pc, index);
+        // No coverage information, only covered (above threshold) or not
+        let missing = missing_windows(&pc.bin_coverage, missing_window);
+        Some(bin2bin(pc, &missing))
     }
 }
 
-pub fn matrix_pack_wrapper(
-    matrix_w: &mut MatrixWrapper,
-    input: &Vec<PackCompact>,
-    index: &Vec<u32>,
-) {
+pub fn matrix_pack_wrapper(matrix_w: &mut MatrixWrapper, input: &[PackCompact], index: &[u32]) {
     let first_entry = &input[0];
 
     if first_entry.data_type == DataType::TypeBit {
-        matrix_w.matrix_bit =
-            vec![BitVec::<u8, Lsb0>::repeat(false, input.len() * 2); first_entry.length as usize];
+        matrix_w.matrix_bit = GenotypeMatrix::new(first_entry.length as usize, input.len());
 
         for (i2, x) in input.iter().enumerate() {
             matrix_w.sample_names.push(x.name.clone());
             for (i, y) in x.bin_coverage.iter().enumerate() {
                 if y == &true {
-                    matrix_w.matrix_bit[i].get_mut(i2 * 2).unwrap().set(true);
-                    matrix_w.matrix_bit[i]
-                        .get_mut(i2 * 2 + 1)
-                        .unwrap()
-                        .set(true);
+                    matrix_w.matrix_bit.set_genotype(i, i2, BED_PRESENT);
                 }
             }
         }
-        matrix_w.shape = (matrix_w.matrix_bit.len(), matrix_w.matrix_bit[0].len());
+        matrix_w.shape = (
+            matrix_w.matrix_bit.variants_number(),
+            matrix_w.matrix_bit.samples_number() * 2,
+        );
         matrix_w.sample_names = input.iter().map(|x| x.name.clone()).collect();
         if first_entry.is_sequence {
             matrix_w.geno_names = index2node_seq(index);
@@ -240,8 +281,8 @@ pub fn matrix_pack_wrapper(
     }
 }
 
-///
-pub fn remove_duplicates(sorted_vec: &Vec<u32>) -> Vec<u64> {
+/// Remove consecutive duplicates from a sorted index
+pub fn remove_duplicates(sorted_vec: &[u32]) -> Vec<u64> {
     let mut unique_vec: Vec<u32> = sorted_vec.to_vec();
     let mut result = Vec::new();
 
@@ -254,7 +295,7 @@ pub fn remove_duplicates(sorted_vec: &Vec<u32>) -> Vec<u64> {
     unique_vec.dedup();
 
     for x in unique_vec.iter() {
-        result.push(merge_u32_to_u64(*x, 0));
+        result.push(FeatureId::alignment(*x, 0).value);
     }
 
     result
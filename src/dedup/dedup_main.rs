@@ -0,0 +1,97 @@
+use crate::core::compression::Compression;
+use crate::core::dedup::{dedup_fileset, expand_results, read_dedup_mapping};
+use crate::core::error::Gfa2binError;
+
+use clap::ArgMatches;
+use log::info;
+use std::str::FromStr;
+
+/// Function for 'gfa2bin dedup'
+///
+/// Keep one genotype per unique genotype pattern of a PLINK fileset (bed, bim, fam)
+pub fn dedup_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    DedupConfig::from_matches(matches)?.run()
+}
+
+/// Function for 'gfa2bin expand'
+///
+/// Expand association results of the representatives to all features (dedup mapping)
+pub fn expand_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    ExpandConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin dedup'
+pub struct DedupConfig {
+    pub plink_file: String,
+    pub output_prefix: String,
+    pub compression: Compression,
+}
+
+impl DedupConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            compression: matches
+                .value_of("compress")
+                .map_or(Ok(Compression::None), Compression::from_str)?,
+        })
+    }
+
+    /// Deduplicate the genotypes, writes the fileset and the mapping (prefix.dedup.tsv)
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin dedup'");
+        info!("Plink file: {}", self.plink_file);
+        info!("Compression: {}", self.compression.to_string1());
+        info!("Output prefix: {}\n", self.output_prefix);
+
+        dedup_fileset(&self.plink_file, &self.output_prefix, self.compression)?;
+        info!("Done");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin expand'
+pub struct ExpandConfig {
+    /// Association results (header line, whitespace-separated)
+    pub results: String,
+    /// Deduplication mapping (prefix.dedup.tsv)
+    pub mapping: String,
+    pub output: String,
+    /// Name of the ID column (None: ID, SNP or rs)
+    pub column: Option<String>,
+}
+
+impl ExpandConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            results: matches.value_of("results").unwrap().to_string(),
+            mapping: matches.value_of("mapping").unwrap().to_string(),
+            output: matches.value_of("output").unwrap().to_string(),
+            column: matches.value_of("column").map(|x| x.to_string()),
+        })
+    }
+
+    /// Expand the results, one line for each feature of a representative
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin expand'");
+        info!("Results: {}", self.results);
+        info!("Mapping: {}", self.mapping);
+        info!("ID column: {}", self.column.as_deref().unwrap_or("auto"));
+        info!("Output: {}\n", self.output);
+
+        let mapping = read_dedup_mapping(&self.mapping)?;
+        expand_results(
+            &self.results,
+            &mapping,
+            self.column.as_deref(),
+            &self.output,
+        )?;
+        info!("Done");
+        Ok(())
+    }
+}
@@ -0,0 +1 @@
+pub mod dedup_main;
@@ -1,215 +1,246 @@
+use crate::core::bed::{allele_count, is_missing, BedReader, BedWriter};
+use crate::core::compression::Compression;
 use crate::core::core::MatrixWrapper;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::matrix::GenotypeMatrix;
 
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
 use clap::ArgMatches;
 
-use crate::core::bfile::count_lines;
-use crate::core::helper::Feature;
-use crate::remove::remove_main::read_write_filter_index;
+use crate::remove::remove_main::retain_by_index;
 use log::info;
+use std::str::FromStr;
 
 /// # Filter main function
 ///
-/// Filter plink by MAF/maf and path MAF/maf
+/// Filter plink by MAF/maf and samples by missing rate
 ///
-pub fn filter_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    // Read the arguments from the command line
-    let plink_file = matches.value_of("plink").unwrap();
-    let output_prefix = matches.value_of("output").unwrap();
-
-    let mut maf = matches
-        .value_of("maf")
-        .unwrap()
-        .parse::<f64>()
-        .expect("Error parsing maf");
-
-    let mut MAF = matches
-        .value_of("MAF")
-        .unwrap()
-        .parse::<f64>()
-        .expect("Error parsing MAF");
-    let mut missing_rate = matches
-        .value_of("missing-rate")
-        .unwrap()
-        .parse::<f64>()
-        .expect("Error parsing missing_rate");
-
-    info!("Input file: {}", plink_file);
-    info!("maf: {}", maf);
-    info!("MAF: {}", MAF);
-    info!(
-        "mac: {}",
-        if matches.is_present("mac") {
-            matches
-                .value_of("mac")
-                .unwrap()
-                .parse::<usize>()
-                .expect("mac is not a number")
-                .to_string()
-        } else {
-            "None".to_string()
-        }
-    );
-    info!(
-        "MAC: {}",
-        if matches.is_present("MAC") {
-            matches
-                .value_of("MAC")
-                .unwrap()
-                .parse::<usize>()
-                .expect("MAC is not a number")
-                .to_string()
-        } else {
-            "None".to_string()
+pub fn filter_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    FilterConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin filter'
+///
+/// Allele counts (mac, MAC) and missing count replace the frequency/rate if set
+pub struct FilterConfig {
+    pub plink_file: String,
+    pub output_prefix: String,
+    pub compression: Compression,
+    /// Minimum and maximum allele frequency
+    pub maf: f64,
+    pub max_maf: f64,
+    /// Minimum and maximum allele count
+    pub mac: Option<usize>,
+    pub max_mac: Option<usize>,
+    /// Maximum missing rate of a sample
+    pub missing_rate: f64,
+    pub missing_count: Option<usize>,
+    pub block_size: usize,
+}
+
+impl FilterConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            compression: matches
+                .value_of("compress")
+                .map_or(Ok(Compression::None), Compression::from_str)?,
+            maf: parse_arg::<f64>(matches.value_of("maf").unwrap(), "maf")?,
+            max_maf: parse_arg::<f64>(matches.value_of("MAF").unwrap(), "MAF")?,
+            mac: optional_count(matches, "mac")?,
+            max_mac: optional_count(matches, "MAC")?,
+            missing_rate: parse_arg::<f64>(
+                matches.value_of("missing-rate").unwrap(),
+                "missing-rate",
+            )?,
+            missing_count: optional_count(matches, "missing-count")?,
+            block_size: parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?,
+        })
+    }
+
+    /// Filter the samples (missing rate) and variants (allele frequency)
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        let plink_file = self.plink_file.as_str();
+        let output_prefix = self.output_prefix.as_str();
+        let compression = self.compression;
+        let mut maf = self.maf;
+        let mut max_maf = self.max_maf;
+        let mut missing_rate = self.missing_rate;
+        let block_size = self.block_size;
+        let mac = self.mac;
+        let max_mac = self.max_mac;
+        let missing_count = self.missing_count;
+
+        info!("Input file: {}", plink_file);
+        info!("maf: {}", maf);
+        info!("MAF: {}", max_maf);
+        info!("mac: {}", mac.map_or("None".to_string(), |x| x.to_string()));
+        info!(
+            "MAC: {}",
+            max_mac.map_or("None".to_string(), |x| x.to_string())
+        );
+        info!("Missing-rate: {}", missing_rate);
+        info!(
+            "Missing-count: {}",
+            missing_count.map_or("None".to_string(), |x| x.to_string())
+        );
+        info!("Block size: {}", block_size);
+        info!("Compression: {}", compression.to_string1());
+        info!("Output prefix: {}", output_prefix);
+
+        let mut mw = MatrixWrapper::new();
+        mw.compression = compression;
+        mw.read_bim_fam(plink_file)?;
+        let bim_count = mw.bim_entries.len();
+        let fam_count = mw.fam_entries.len();
+        let bed_file = format!("{}{}", plink_file, ".bed");
+
+        if let Some(mac) = mac {
+            maf = mac as f64 / fam_count as f64;
         }
-    );
-    info!("Missing-rate: {}", missing_rate);
-    info!(
-        "Missing-count: {}",
-        if matches.is_present("missing_count") {
-            matches
-                .value_of("missing_count")
-                .unwrap()
-                .parse::<usize>()
-                .expect("missing_count is not a number")
-                .to_string()
-        } else {
-            "None".to_string()
+        if let Some(max_mac) = max_mac {
+            max_maf = max_mac as f64 / fam_count as f64;
         }
-    );
-    info!("Output prefix: {}", output_prefix);
-
-    let mut mw = MatrixWrapper::new();
-    let bim_count = count_lines(&format!("{}{}", plink_file, ".bim"))?;
-    let fam_count = count_lines(&format!("{}{}", plink_file, ".fam"))?;
-    mw.read_bed(&format!("{}{}", plink_file, ".bed"), fam_count, bim_count)?;
-
-    if matches.is_present("mac") {
-        maf = matches
-            .value_of("mac")
-            .unwrap()
-            .parse::<f64>()
-            .expect("Error parsing mac")
-            / fam_count as f64;
-    }
-    if matches.is_present("MAC") {
-        MAF = matches
-            .value_of("MAC")
-            .unwrap()
-            .parse::<f64>()
-            .expect("Error parsing MAC")
-            / fam_count as f64;
-    }
 
-    if matches.is_present("missing-count") {
-        missing_rate = matches
-            .value_of("missing_count")
-            .unwrap()
-            .parse::<f64>()
-            .expect("Error parsing missing_count")
-            / bim_count as f64;
-    }
+        if let Some(missing_count) = missing_count {
+            missing_rate = missing_count as f64 / bim_count as f64;
+        }
 
-    info!(
-        "Matrix size (SNPs X Samples): {} {}",
-        mw.matrix_bit.len(),
-        mw.matrix_bit[0].len()
-    );
+        info!(
+            "Matrix size (SNPs X Samples): {} {}",
+            bim_count,
+            fam_count * 2
+        );
 
-    let mut remove_index_samples = Vec::new();
-    if missing_rate != 0.0 {
         info!(
             "Filtering by missing rate or missing count: {}",
             missing_rate
         );
-        remove_index_samples = mw.filter_path(missing_rate, 1.0);
-    }
+        let reader = BedReader::new(&bed_file, fam_count, bim_count, block_size)?;
+        let remove_index_samples = filter_missing(reader, missing_rate)?;
+        let remove_hashset = remove_index_samples.iter().cloned().collect();
+        retain_by_index(&mut mw.sample_names, &remove_hashset);
+        retain_by_index(&mut mw.fam_entries, &remove_hashset);
+        if mw.fam_entries.is_empty() {
+            return Err(Gfa2binError::Consistency(
+                "Matrix is now empty after path removal".to_string(),
+            ));
+        }
 
-    info!(
-        "Matrix size (SNPs X Samples) - After sample filtering: {} {}",
-        mw.matrix_bit.len(),
-        mw.matrix_bit
-            .first()
-            .ok_or("Matrix is now empty after path removal")?
-            .len()
-    );
-
-    let mut remove_index_genotypes = Vec::new();
-    if maf != 0.0 || MAF != 1.0 {
-        info!("Filtering by MAF/maf: {} {}", maf, MAF);
-        remove_index_genotypes = mw.filet_maf(maf, MAF);
-    }
+        info!(
+            "Matrix size (SNPs X Samples) - After sample filtering: {} {}",
+            bim_count,
+            mw.fam_entries.len() * 2
+        );
 
-    info!(
-        "Matrix size (SNPs X Samples) - after maf: {} {}",
-        mw.matrix_bit.len(),
-        mw.matrix_bit
-            .first()
-            .ok_or("Matrix is now empty after MAF/maf removal ")?
-            .len()
-    );
-
-    info!("Writing BED file");
-    mw.write_bed(0, output_prefix, Feature::Node, 1);
-
-    info!("Writing BIM file");
-    read_write_filter_index(
-        &format!("{}{}", plink_file, ".bim"),
-        &format!("{}{}", output_prefix, ".bim"),
-        &remove_index_genotypes,
-    )
-    .expect("Error writing BIM file");
-
-    info!("Writing FAM file");
-    read_write_filter_index(
-        &format!("{}{}", plink_file, ".fam"),
-        &format!("{}{}", output_prefix, ".fam"),
-        &remove_index_samples,
-    )
-    .expect("Error writing FAM file");
-
-    Ok(())
-}
+        let filter_maf = maf != 0.0 || max_maf != 1.0;
+        if filter_maf {
+            info!("Filtering by MAF/maf: {} {}", maf, max_maf);
+        }
 
-impl MatrixWrapper {
-    /// # Filter bit matrix by MAF/maf
-    pub fn filet_maf(&mut self, maf: f64, MAF: f64) -> Vec<usize> {
-        let mut remove_index_vec = Vec::new();
-        for (index, bitvec) in self.matrix_bit.iter().enumerate() {
-            let mut count = 0;
-            for y in bitvec.iter().step_by(2) {
-                if y == true {
-                    count += 1;
+        info!("Writing BED file");
+        let reader = BedReader::new(&bed_file, fam_count, bim_count, block_size)?;
+        let mut writer = BedWriter::new(&format!("{}{}", output_prefix, ".bed"))?;
+        let mut remove_index_genotypes = Vec::new();
+        let mut index = 0;
+        for block in reader {
+            let block = block?.remove_samples(&remove_index_samples);
+            let mut kept = GenotypeMatrix::new(0, block.samples_number());
+            for variant in block.variants() {
+                if filter_maf && !maf_in_range(variant, maf, max_maf) {
+                    remove_index_genotypes.push(index);
+                } else {
+                    kept.push_variant(variant);
                 }
+                index += 1;
             }
-            let maf1 = count as f64 / bitvec.len() as f64;
-            if maf1 < MAF || maf1 > maf {
-                remove_index_vec.push(index);
-            }
+            writer.write_matrix(&kept)?;
         }
-        info!("Removing {} SNPs", remove_index_vec.len());
-        self.remove_by_index(&remove_index_vec);
-        remove_index_vec
+        let snp_number = writer.snp_number;
+        writer.finish()?;
+        info!("Removing {} SNPs", remove_index_genotypes.len());
+        if snp_number == 0 {
+            return Err(Gfa2binError::Consistency(
+                "Matrix is now empty after MAF/maf removal".to_string(),
+            ));
+        }
+
+        let remove_hashset = remove_index_genotypes.iter().cloned().collect();
+        retain_by_index(&mut mw.bim_entries, &remove_hashset);
+        retain_by_index(&mut mw.geno_names, &remove_hashset);
+
+        info!(
+            "Matrix size (SNPs X Samples) - after maf: {} {}",
+            mw.bim_entries.len(),
+            mw.fam_entries.len() * 2
+        );
+
+        let feature = mw.feature;
+        info!("Writing BIM file");
+        mw.write_bim(0, output_prefix, &feature, 1)?;
+
+        info!("Writing FAM file");
+        mw.write_fam(0, output_prefix, feature, 1, f64::MAX)?;
+
+        Ok(())
     }
+}
 
-    /// # Filter matrix by path MAF/maf
-    pub fn filter_path(&mut self, lower_limit: f64, upper_limit: f64) -> Vec<usize> {
-        let mut remove_index_vec = Vec::new();
+/// Optional count argument (e.g. mac), None if not set
+fn optional_count(matches: &ArgMatches, name: &str) -> Result<Option<usize>, Gfa2binError> {
+    matches
+        .value_of(name)
+        .map(|x| parse_arg::<usize>(x, name))
+        .transpose()
+}
 
-        for i in 0..self.matrix_bit[0].len() / 2 {
-            let mut c = 0;
+/// # Check if the allele frequency of a variant is in range
+///
+/// Frequency of the present allele (T), missing genotypes are ignored
+pub fn maf_in_range(bitvec: &BitSlice<u8, Lsb0>, maf: f64, max_maf: f64) -> bool {
+    let mut count = 0;
+    let mut total = 0;
+    for sample in 0..bitvec.len() / 2 {
+        if let Some(c) = allele_count(bitvec, sample) {
+            count += c as usize;
+            total += 2;
+        }
+    }
+    if total == 0 {
+        return false;
+    }
+    let maf1 = count as f64 / total as f64;
+    !(maf1 < max_maf || maf1 > maf)
+}
 
-            for x in self.matrix_bit.iter() {
-                if x[i * 2] || x[i * 2 + 1] {
-                    c += 1;
+/// # Filter samples by missing rate
+///
+/// Streams over the bed file once and returns the index of the samples which should be removed
+/// (more missing genotypes than the missing rate)
+pub fn filter_missing(reader: BedReader, missing_rate: f64) -> Result<Vec<usize>, std::io::Error> {
+    let mut counts = vec![0; reader.samples_number];
+    let snp_number = reader.snp_number;
+    for block in reader {
+        for x in block?.variants() {
+            for (i, c) in counts.iter_mut().enumerate() {
+                if is_missing(x, i) {
+                    *c += 1;
                 }
             }
-            let a = c as f64 / self.matrix_bit.len() as f64;
-            if a < lower_limit || a >= upper_limit {
-                remove_index_vec.push(i);
-            }
         }
+    }
 
-        self.remove_index_samples(&remove_index_vec);
-        remove_index_vec
+    let mut remove_index_vec = Vec::new();
+    for (i, c) in counts.iter().enumerate() {
+        let a = *c as f64 / snp_number as f64;
+        if a > missing_rate {
+            remove_index_vec.push(i);
+        }
     }
+    info!("Removing {} samples", remove_index_vec.len());
+    Ok(remove_index_vec)
 }
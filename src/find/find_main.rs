@@ -1,34 +1,58 @@
-use crate::core::helper::merge_u32_to_u64;
+use crate::core::compression::{open_reader, PlainFile};
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::helper::{Feature, FeatureId};
 use clap::ArgMatches;
 use gfa_reader::Gfa;
 use std::cmp::PartialEq;
 use std::fs::File;
-use std::io::Write;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, Write};
 
 /// Main function for find subcommand
-pub fn find_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let graph_file = matches.value_of("gfa").unwrap();
-    let feature_file = matches.value_of("features").unwrap();
-    let output = matches.value_of("output").unwrap();
-    let _length = matches
-        .value_of("length")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-
-    let a = determine_type(feature_file)?;
-    find_easy(
-        &Gfa::parse_gfa_file(graph_file),
-        &a,
-        read_file_lines(feature_file, &a)?,
-        output,
-    )?;
-    Ok(())
+pub fn find_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    FindConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin find'
+pub struct FindConfig {
+    pub graph_file: String,
+    /// Features (one per line)
+    pub feature_file: String,
+    pub output: String,
+    pub length: usize,
+}
+
+impl FindConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            graph_file: matches.value_of("gfa").unwrap().to_string(),
+            feature_file: matches.value_of("features").unwrap().to_string(),
+            output: matches.value_of("output").unwrap().to_string(),
+            length: parse_arg::<usize>(matches.value_of("length").unwrap(), "length")?,
+        })
+    }
+
+    /// Find the features in the graph
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        let graph_file = self.graph_file.as_str();
+        let feature_file = self.feature_file.as_str();
+        let output = self.output.as_str();
+
+        let a = determine_type(feature_file)?;
+        let graph_input = PlainFile::new(graph_file)?;
+        find_easy(
+            &Gfa::parse_gfa_file(graph_input.path()),
+            &a,
+            read_file_lines(feature_file, &a)?,
+            output,
+        )?;
+        Ok(())
+    }
 }
 
 #[derive(PartialEq, Eq, Hash)]
-enum InputType {
+pub enum InputType {
     Segment,
     DirSegment,
     Link,
@@ -36,49 +60,42 @@ enum InputType {
     Block,
 }
 
+impl InputType {
+    /// Feature of the input type (None: subgraph and block)
+    pub fn feature(&self) -> Option<Feature> {
+        match self {
+            InputType::Segment => Some(Feature::Node),
+            InputType::DirSegment => Some(Feature::DirNode),
+            InputType::Link => Some(Feature::Edge),
+            _ => None,
+        }
+    }
+}
+
+/// Variant identifier of a feature (see FeatureId)
 pub fn to_string(dig: u64, input_type: InputType) -> String {
-    if input_type == InputType::Segment {
-        dig.to_string()
-    } else if input_type == InputType::DirSegment {
-        let dig1 = dig / 2;
-        let dig2 = dig % 2;
-        return format!("{}{}", dig1, if dig2 == 0 { "+" } else { "-" });
-    } else if input_type == InputType::Link {
-        let dig1 = dig / 2;
-        let dig2 = dig % 2;
-        let dig3 = dig1 / 2;
-        let dig4 = dig1 % 2;
-        return format!(
-            "{}{}{}{}",
-            dig3,
-            if dig4 == 0 { "+" } else { "-" },
-            dig2 / 2,
-            if dig2 % 2 == 0 { "+" } else { "-" }
-        );
-    } else {
-        "help".to_string()
+    match input_type.feature() {
+        Some(feature) => FeatureId::new(feature, dig).to_string(),
+        None => "help".to_string(),
     }
 }
 
 /// Determine the type of input
-pub fn determine_type(input: &str) -> Result<InputType, Box<dyn std::error::Error>> {
-    let file = File::open(input).unwrap();
-    // Create a buffered reader for the file
-    let reader = BufReader::new(file);
+pub fn determine_type(input: &str) -> Result<InputType, Gfa2binError> {
+    // Create a buffered reader for the file (plain-text or compressed)
+    let reader = open_reader(input)?;
 
     // Read the first line of the file
     let mut lines = reader.lines();
     if let Some(line) = lines.next() {
-        let first_line = line.unwrap();
+        let first_line = line?;
         if first_line.starts_with('A') {
             return Ok(InputType::Segment);
         } else if first_line.starts_with('G') {
             // Count + and - in the firstline
             let mut pm = 0;
             for c in first_line.chars() {
-                if c == '+' {
-                    pm += 1;
-                } else if c == '-' {
+                if c == '+' || c == '-' {
                     pm += 1;
                 }
             }
@@ -98,51 +115,37 @@ pub fn determine_type(input: &str) -> Result<InputType, Box<dyn std::error::Erro
     Ok(InputType::Block)
 }
 
-pub fn read_file_lines(_file_path: &str, class: &InputType) -> std::io::Result<Vec<u64>> {
-    let file = File::open("example.txt")?;
-
-    // Create a buffered reader for efficient reading.
-    let reader = BufReader::new(file);
+pub fn read_file_lines(file_path: &str, class: &InputType) -> Result<Vec<u64>, Gfa2binError> {
+    // Create a buffered reader for the file (plain-text or compressed)
+    let reader = open_reader(file_path)?;
 
     let mut vec_u64 = Vec::new();
 
-    // Iterate over each line in the file.
+    // Iterate over each line in the file, the first character is the type
     for line in reader.lines() {
-        let line = line?[1..].to_string(); // Handle any errors in reading lines.
-        let mut bools = [false; 2];
-        let mut jj = [0; 2];
-        let mut index = 0;
-        for x in line.chars() {
-            if x == '+' || x == '-' {
-                if x == '+' {
-                    bools[0] = true;
-                }
-                jj[0] = line[0..index].parse().unwrap();
-                break;
-            }
-            index += 1;
-        }
-        if line.ends_with('+') || line.ends_with('-') {
-            bools[1] = line.ends_with('+');
-            jj[1] = line[index..line.len() - 2].parse().unwrap();
-        } else {
-            jj[1] = line[index..line.len() - 1].parse().unwrap();
-        }
-
-        if *class == InputType::Segment {
-            vec_u64.push(jj[1] as u64);
-        } else if *class == InputType::DirSegment {
-            vec_u64.push(jj[0] as u64 * 2 + bools[0] as u64);
-        } else if *class == InputType::Link {
-            let u1 = jj[0] * 2 + bools[0] as u32;
-            let u2 = jj[1] * 2 + bools[1] as u32;
-            vec_u64.push(merge_u32_to_u64(u1, u2));
+        let line = line?;
+        if let Some(feature) = class.feature() {
+            vec_u64.push(parse_id(file_path, line.get(1..).unwrap_or(""), feature)?);
         }
     }
 
     Ok(vec_u64)
 }
 
+/// Parse a feature identifier of the feature file
+fn parse_id(file_path: &str, value: &str, feature: Feature) -> Result<u64, Gfa2binError> {
+    FeatureId::decode(value, feature)
+        .map(|x| x.value)
+        .map_err(|_| {
+            Gfa2binError::Format(format!(
+                "{}: '{}' is not a valid {} identifier",
+                file_path,
+                value,
+                feature.to_string1()
+            ))
+        })
+}
+
 /// positional vector
 ///
 /// question. where can I find a node in a path, which position
@@ -160,13 +163,11 @@ pub fn pos(graph: &Gfa<u32, (), ()>, name: String, class: &InputType) -> Vec<(u3
                 let v4 = path.dir[i + 1];
 
                 if *class == InputType::Segment {
-                    vec_u64.push((pos, v1 as u64));
+                    vec_u64.push((pos, FeatureId::node(v1).value));
                 } else if *class == InputType::DirSegment {
-                    vec_u64.push((pos, v1 as u64 * 2 + v2 as u64));
+                    vec_u64.push((pos, FeatureId::dirnode(v1, v2).value));
                 } else if *class == InputType::Link {
-                    let u1 = v1 * 2 + v2 as u32;
-                    let u2 = v3 * 2 + v4 as u32;
-                    vec_u64.push((pos, merge_u32_to_u64(u1, u2)));
+                    vec_u64.push((pos, FeatureId::edge(v1, v2, v3, v4).value));
                 }
             }
             vec_u64.sort();
@@ -0,0 +1,558 @@
+use crate::core::annotation::sequence_gc;
+use crate::core::bfile::{BimEntry, ReferencePositions};
+use crate::core::compression::TextWriter;
+use crate::core::core::MatrixWrapper;
+use crate::core::helper::{split_u64_to_u32s, Feature, FeatureId};
+use crate::core::pgen::{Genotypes, MultiallelicPgenWriter};
+use crate::view::view_main::contigs_bim;
+
+use gfa_reader::{Gfa, Path};
+use hashbrown::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Header of the bubble alleles (TSV)
+pub const ALLELES_HEADER: &str = "#ID\tSOURCE\tSINK\tALLELE\tLENGTH\tTRAVERSAL";
+
+/// Maximum number of dirnodes visited when searching the sink of a bubble (larger bubbles are not found)
+pub const BUBBLE_MAX_NODES: usize = 10000;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Bubble (superbubble) of the graph
+///
+/// Source and sink are dirnodes (node * 2 + direction, + is 1)
+/// Alleles are the distinct traversals (inner dirnodes) of all paths,
+/// the traversal of the reference (if any) first, then the most frequent
+pub struct Bubble {
+    pub source: u32,
+    pub sink: u32,
+    pub alleles: Vec<Vec<u32>>,
+    /// Sequence length of each allele (bp)
+    pub lengths: Vec<u64>,
+}
+
+impl Bubble {
+    /// Variant identifier of an allele (S12+_0)
+    pub fn allele_id(&self, allele: usize) -> FeatureId {
+        FeatureId::bubble(self.source / 2, self.source % 2 == 1, allele as u32)
+    }
+
+    /// Identifier of the bubble, source and sink (S12+15+)
+    pub fn name(&self) -> String {
+        format!(
+            "S{}{}",
+            dirnode_string(self.source),
+            dirnode_string(self.sink)
+        )
+    }
+
+    /// Sequence of an allele (orientation of the bubble)
+    pub fn sequence(&self, graph: &Gfa<u32, (), ()>, allele: usize) -> String {
+        self.alleles[allele]
+            .iter()
+            .map(|x| dirnode_sequence(graph, *x))
+            .collect()
+    }
+
+    /// Length and number of G/C of an allele
+    pub fn gc(&self, graph: &Gfa<u32, (), ()>, allele: usize) -> (u64, u64) {
+        self.alleles[allele]
+            .iter()
+            .map(|x| sequence_gc(graph.get_sequence_by_id(&(x / 2))))
+            .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # All bubbles of a graph
+///
+/// Bubbles are sorted by their source, therefore alleles (geno names) are sorted too
+pub struct BubbleIndex {
+    pub bubbles: Vec<Bubble>,
+    /// Source dirnode -> index of the bubble
+    sources: HashMap<u32, usize>,
+}
+
+impl BubbleIndex {
+    /// # Bubbles of the graph and their alleles
+    ///
+    /// Alleles are the traversals of all paths (forward and reverse)
+    /// The first traversal of a reference path (names) is allele 0, otherwise the most frequent one
+    /// Bubbles which are not traversed by any path are removed
+    pub fn new(graph: &Gfa<u32, (), ()>, reference: Option<&[String]>) -> Self {
+        let mut index = Self::default();
+        for (source, sink) in find_bubbles(graph) {
+            index.bubbles.push(Bubble {
+                source,
+                sink,
+                ..Bubble::default()
+            });
+        }
+        index.update_sources();
+
+        // Number of traversals of each allele
+        let mut traversals: Vec<HashMap<Vec<u32>, usize>> =
+            vec![HashMap::new(); index.bubbles.len()];
+        let mut reference_traversals: Vec<Option<Vec<u32>>> = vec![None; index.bubbles.len()];
+        for path in graph.paths.iter() {
+            let is_reference = reference.is_some_and(|x| x.contains(&path.name));
+            for (bubble, traversal) in index.traversals(path) {
+                if is_reference && reference_traversals[bubble].is_none() {
+                    reference_traversals[bubble] = Some(traversal.clone());
+                }
+                *traversals[bubble].entry(traversal).or_insert(0) += 1;
+            }
+        }
+        for ((bubble, traversals), reference) in index
+            .bubbles
+            .iter_mut()
+            .zip(traversals)
+            .zip(reference_traversals)
+        {
+            let mut alleles = traversals.into_iter().collect::<Vec<(Vec<u32>, usize)>>();
+            let reference = reference.as_ref();
+            alleles.sort_by(|a, b| {
+                (Some(&b.0) == reference)
+                    .cmp(&(Some(&a.0) == reference))
+                    .then_with(|| b.1.cmp(&a.1))
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+            bubble.alleles = alleles.into_iter().map(|x| x.0).collect();
+            bubble.lengths = (0..bubble.alleles.len())
+                .map(|x| bubble.gc(graph, x).0)
+                .collect();
+        }
+        index.bubbles.retain(|x| !x.alleles.is_empty());
+        index.update_sources();
+        index
+    }
+
+    fn update_sources(&mut self) {
+        self.sources = self
+            .bubbles
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.source, i))
+            .collect();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bubbles.is_empty()
+    }
+
+    /// Bubble and allele of a geno name
+    pub fn get(&self, value: u64) -> Option<(&Bubble, usize)> {
+        let (source, allele) = split_u64_to_u32s(value);
+        self.sources
+            .get(&source)
+            .map(|x| (&self.bubbles[*x], allele as usize))
+    }
+
+    /// Geno names of all alleles (sorted)
+    pub fn geno_names(&self) -> Vec<u64> {
+        let mut geno_names = self
+            .bubbles
+            .iter()
+            .flat_map(|x| (0..x.alleles.len()).map(move |y| x.allele_id(y).value))
+            .collect::<Vec<u64>>();
+        geno_names.sort();
+        geno_names
+    }
+
+    /// # Traversals of all bubbles by a path
+    ///
+    /// The path is read in both directions, traversals of the reverse path are bubbles traversed in reverse
+    /// Traversals which do not reach the sink (end of the path) are ignored
+    pub fn traversals(&self, path: &Path<u32, (), ()>) -> Vec<(usize, Vec<u32>)> {
+        let forward = path
+            .nodes
+            .iter()
+            .zip(path.dir.iter())
+            .map(|(node, dir)| FeatureId::dirnode(*node, *dir).value as u32)
+            .collect::<Vec<u32>>();
+        let reverse = forward.iter().rev().map(|x| x ^ 1).collect::<Vec<u32>>();
+
+        let mut result = Vec::new();
+        for dirnodes in [forward, reverse].iter() {
+            for (i, x) in dirnodes.iter().enumerate() {
+                if let Some(bubble) = self.sources.get(x) {
+                    let sink = self.bubbles[*bubble].sink;
+                    // Bubbles are acyclic, the source is not part of a traversal
+                    let end = dirnodes[i + 1..].iter().position(|y| *y == sink || y == x);
+                    if let Some(end) = end.filter(|y| dirnodes[i + 1 + y] == sink) {
+                        result.push((*bubble, dirnodes[i + 1..i + 1 + end].to_vec()));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Geno names of all alleles traversed by a path (sorted, multiple traversals are repeated)
+    pub fn path_alleles(&self, path: &Path<u32, (), ()>) -> Vec<u64> {
+        let mut alleles = self
+            .traversals(path)
+            .into_iter()
+            .filter_map(|(bubble, traversal)| {
+                let bubble = &self.bubbles[bubble];
+                bubble
+                    .alleles
+                    .iter()
+                    .position(|x| *x == traversal)
+                    .map(|x| bubble.allele_id(x).value)
+            })
+            .collect::<Vec<u64>>();
+        alleles.sort();
+        alleles
+    }
+}
+
+/// # Superbubbles of the graph
+///
+/// Each link is an edge between two dirnodes (and its reverse complement)
+/// Every bubble is found in both orientations, the one whose source is smaller than the reversed sink is kept
+/// (the reversed sink is resolved and not searched again)
+/// Nested bubbles are reported as well, each search visits at most BUBBLE_MAX_NODES dirnodes
+pub fn find_bubbles(graph: &Gfa<u32, (), ()>) -> Vec<(u32, u32)> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut parents: HashMap<u32, Vec<u32>> = HashMap::new();
+    for link in graph.links.iter() {
+        let from = FeatureId::dirnode(link.from, link.from_dir).value as u32;
+        let to = FeatureId::dirnode(link.to, link.to_dir).value as u32;
+        for (a, b) in [(from, to), (to ^ 1, from ^ 1)] {
+            children.entry(a).or_insert_with(Vec::new).push(b);
+            parents.entry(b).or_insert_with(Vec::new).push(a);
+        }
+    }
+    for x in children.values_mut().chain(parents.values_mut()) {
+        x.sort();
+        x.dedup();
+    }
+
+    let mut sources = children
+        .iter()
+        .filter(|x| x.1.len() > 1)
+        .map(|x| *x.0)
+        .collect::<Vec<u32>>();
+    sources.sort();
+    let mut resolved = HashSet::new();
+    let mut bubbles = Vec::new();
+    for source in sources.into_iter() {
+        if resolved.contains(&source) {
+            continue;
+        }
+        if let Some(sink) = superbubble(source, &children, &parents) {
+            // Same bubble in reverse (sink ^ 1 -> source ^ 1)
+            resolved.insert(sink ^ 1);
+            if source <= sink ^ 1 {
+                bubbles.push((source, sink));
+            }
+        }
+    }
+    bubbles
+}
+
+/// # Sink of the superbubble starting at the source
+///
+/// Onodera et al. (2013): a node is added once all its parents are visited,
+/// the sink is reached if it is the only node left and not connected to the source.
+/// None if a tip or a cycle containing the source is found or more than BUBBLE_MAX_NODES are visited
+fn superbubble(
+    source: u32,
+    children: &HashMap<u32, Vec<u32>>,
+    parents: &HashMap<u32, Vec<u32>>,
+) -> Option<u32> {
+    let mut visited = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![source];
+    seen.insert(source);
+    while let Some(node) = stack.pop() {
+        if visited.len() >= BUBBLE_MAX_NODES {
+            return None;
+        }
+        visited.insert(node);
+        seen.remove(&node);
+        for child in children.get(&node)?.iter() {
+            if *child == source {
+                return None;
+            }
+            seen.insert(*child);
+            if parents[child].iter().all(|x| visited.contains(x)) {
+                stack.push(*child);
+            }
+        }
+        if stack.len() == 1 && seen.len() == 1 {
+            let sink = stack[0];
+            return if children.get(&sink).is_some_and(|x| x.contains(&source)) {
+                None
+            } else {
+                Some(sink)
+            };
+        }
+    }
+    None
+}
+
+/// Node with direction (12+)
+fn dirnode_string(dirnode: u32) -> String {
+    FeatureId::new(Feature::DirNode, dirnode as u64).to_string()
+}
+
+/// Sequence of a dirnode (reverse complement if -)
+pub fn dirnode_sequence(graph: &Gfa<u32, (), ()>, dirnode: u32) -> String {
+    let sequence = graph.get_sequence_by_id(&(dirnode / 2));
+    if dirnode % 2 == 1 {
+        sequence.to_string()
+    } else {
+        reverse_complement(sequence)
+    }
+}
+
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence
+        .chars()
+        .rev()
+        .map(|x| match x {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' => 'A',
+            'a' => 't',
+            'c' => 'g',
+            'g' => 'c',
+            't' => 'a',
+            _ => x,
+        })
+        .collect()
+}
+
+/// # Write the alleles of all bubbles (prefix.alleles.tsv)
+///
+/// Traversal: inner dirnodes of the allele (. if empty)
+pub fn write_alleles(out_prefix: &str, bubbles: &BubbleIndex) -> Result<(), io::Error> {
+    let output = [out_prefix, "alleles", "tsv"].join(".");
+    let mut f = BufWriter::new(File::create(output)?);
+    writeln!(f, "{}", ALLELES_HEADER)?;
+    for bubble in bubbles.bubbles.iter() {
+        for (i, (traversal, length)) in bubble.alleles.iter().zip(bubble.lengths.iter()).enumerate()
+        {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                bubble.allele_id(i),
+                dirnode_string(bubble.source),
+                dirnode_string(bubble.sink),
+                i,
+                length,
+                if traversal.is_empty() {
+                    ".".to_string()
+                } else {
+                    traversal
+                        .iter()
+                        .map(|x| dirnode_string(*x))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                }
+            )?;
+        }
+    }
+    f.flush()
+}
+
+impl MatrixWrapper {
+    /// # Write the bubbles as multiallelic VCF (v4.3)
+    ///
+    /// One record for each bubble, allele 0 is REF (traversal of the reference path, otherwise the most frequent)
+    /// - Alleles: last base of the source and the sequence of the traversal (orientation of the bubble)
+    /// - Position: last base of the source (reference) or the source node id (graph)
+    /// - Genotypes are phased (haplotypes in order), haplotypes without traversal (or with different traversals) are missing
+    /// - Based on the counts (matrix_u16), compressed if a compression is set (extension is added)
+    pub fn write_bubble_vcf(
+        &self,
+        out_prefix: &str,
+        graph: &Gfa<u32, (), ()>,
+        reference: Option<&ReferencePositions>,
+    ) -> Result<(), io::Error> {
+        let records = self.bubble_records(graph, reference)?;
+
+        let output = [out_prefix, "vcf"].join(".") + self.compression.extension();
+        let mut f = TextWriter::new(&output, self.compression)?;
+        writeln!(f, "##fileformat=VCFv4.3")?;
+        writeln!(f, "##source=gfa2bin")?;
+        let bim_entries = records.iter().map(|x| x.2.clone()).collect::<Vec<_>>();
+        for (name, length) in contigs_bim(&bim_entries).iter() {
+            writeln!(f, "##contig=<ID={},length={}>", name, length)?;
+        }
+        writeln!(
+            f,
+            "##INFO=<ID=FEATURE,Number=1,Type=String,Description=\"Graph feature type (bubble)\">"
+        )?;
+        writeln!(
+            f,
+            "##INFO=<ID=ALEN,Number=R,Type=Integer,Description=\"Sequence length of each allele (traversal without the source base)\">"
+        )?;
+        writeln!(
+            f,
+            "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
+        )?;
+        let sample_names = if self.fam_entries.is_empty() {
+            self.sample_names.clone()
+        } else {
+            self.fam_entries
+                .iter()
+                .map(|x| x.individual_id.clone())
+                .collect()
+        };
+        writeln!(
+            f,
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+            sample_names.join("\t")
+        )?;
+
+        for (first, bubble, entry) in records.iter() {
+            let genotypes = self
+                .sample_index_u16
+                .iter()
+                .map(|x| {
+                    (x[0]..=x[1])
+                        .map(|column| self.haplotype_allele(*first, bubble.alleles.len(), column))
+                        .map(|y| y.map_or(".".to_string(), |z| z.to_string()))
+                        .collect::<Vec<String>>()
+                        .join("|")
+                })
+                .collect::<Vec<String>>();
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t.\tPASS\tFEATURE=bubble;ALEN={}\tGT\t{}",
+                entry.chromosome,
+                entry.bp_position,
+                entry.variant_id,
+                entry.allele1,
+                entry.allele2,
+                bubble
+                    .lengths
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+                genotypes.join("\t")
+            )?;
+        }
+        f.finish()
+    }
+
+    /// # Write the bubbles as multiallelic PLINK 2 files (pgen, pvar, psam)
+    ///
+    /// One variant for each bubble, same alleles and positions as the VCF (see write_bubble_vcf)
+    /// - Haploid samples are homozygous, diploid samples have one allele for each haplotype
+    /// - Samples with a missing haplotype are missing, only first and last haplotype are used (no polyploid samples)
+    pub fn write_bubble_pgen(
+        &self,
+        out_prefix: &str,
+        graph: &Gfa<u32, (), ()>,
+        reference: Option<&ReferencePositions>,
+        pheno: f64,
+    ) -> Result<(), io::Error> {
+        let records = self.bubble_records(graph, reference)?;
+
+        let mut writer = MultiallelicPgenWriter::new(
+            &[out_prefix, "pgen"].join("."),
+            self.sample_index_u16.len(),
+        )?;
+        for (first, bubble, _) in records.iter() {
+            let genotypes = self
+                .sample_index_u16
+                .iter()
+                .map(|x| {
+                    let allele1 = self.haplotype_allele(*first, bubble.alleles.len(), x[0])?;
+                    let allele2 = self.haplotype_allele(*first, bubble.alleles.len(), x[1])?;
+                    Some([allele1, allele2])
+                })
+                .collect::<Genotypes>();
+            writer.write_variant(&genotypes, bubble.alleles.len().max(2))?;
+        }
+        writer.finish()?;
+
+        let mut f = BufWriter::new(File::create([out_prefix, "pvar"].join("."))?);
+        writeln!(f, "#CHROM\tPOS\tID\tREF\tALT")?;
+        for (_, _, x) in records.iter() {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}",
+                x.chromosome, x.bp_position, x.variant_id, x.allele1, x.allele2
+            )?;
+        }
+        f.flush()?;
+        self.write_psam(out_prefix, pheno)
+    }
+
+    /// First genotype index, bubble and entry (REF, comma-separated ALT) of each bubble
+    fn bubble_records(
+        &self,
+        graph: &Gfa<u32, (), ()>,
+        reference: Option<&ReferencePositions>,
+    ) -> Result<Vec<(usize, &Bubble, BimEntry)>, io::Error> {
+        // First geno name of each bubble
+        let mut records = Vec::new();
+        let mut i = 0;
+        while i < self.geno_names.len() {
+            let (bubble, _) = self.bubbles.get(self.geno_names[i]).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Allele without bubble")
+            })?;
+            let source = bubble.source / 2;
+            let (chromosome, bp_position) = match reference {
+                Some(reference) => {
+                    let offset = if bubble.source % 2 == 1 {
+                        graph.get_sequence_by_id(&source).len().max(1) as u64 - 1
+                    } else {
+                        0
+                    };
+                    reference.get(source, offset)
+                }
+                None => ("graph".to_string(), source as u64),
+            };
+            let anchor = dirnode_sequence(graph, bubble.source)
+                .chars()
+                .last()
+                .unwrap_or('N');
+            let alleles = (0..bubble.alleles.len())
+                .map(|x| format!("{}{}", anchor, bubble.sequence(graph, x)))
+                .collect::<Vec<String>>();
+            records.push((
+                i,
+                bubble,
+                BimEntry {
+                    chromosome,
+                    variant_id: bubble.name(),
+                    cm_position: 0.0,
+                    bp_position,
+                    allele1: alleles[0].clone(),
+                    allele2: if alleles.len() == 1 {
+                        ".".to_string()
+                    } else {
+                        alleles[1..].join(",")
+                    },
+                },
+            ));
+            i += bubble.alleles.len();
+        }
+        Ok(records)
+    }
+
+    /// Allele of a haplotype (column), None if missing, not traversed or more than one allele
+    fn haplotype_allele(&self, first: usize, alleles: usize, column: usize) -> Option<usize> {
+        let mut result = None;
+        for allele in 0..alleles {
+            if !self.matrix_missing.is_empty() && self.matrix_missing[first + allele][column] {
+                return None;
+            }
+            if self.matrix_u16[first + allele][column] > 0 {
+                if result.is_some() {
+                    return None;
+                }
+                result = Some(allele);
+            }
+        }
+        result
+    }
+}
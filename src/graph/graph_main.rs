@@ -1,209 +1,823 @@
-use crate::core::core::MatrixWrapper;
-use crate::core::helper::Feature;
-
-
+use crate::core::annotation::{write_annotation, FeatureAnnotation};
+use crate::core::bfile::ReferencePositions;
+use crate::core::compression::{Compression, PlainFile};
+use crate::core::copy_number::CopyNumberFormat;
+use crate::core::core::{MatrixWrapper, OutputFormat};
+use crate::core::dedup::write_dedup_mapping;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::helper::{CollapsePolicy, Feature};
+use crate::core::metadata::SampleMetadata;
+use crate::core::phenotype::PhenotypeTable;
 
+use crate::graph::bubble::{write_alleles, BubbleIndex};
 use crate::graph::parser::{diploid_adder, gfa_reader};
+use crate::graph::samples::{sample_haplotypes, walks_to_paths};
+use crate::graph::segment::write_segments;
+use crate::nearest::nearest_main::{reference_paths, reference_positions};
 
 use clap::ArgMatches;
 use gfa_reader::{Gfa, Pansn};
-use log::{info, warn};
+use log::info;
 use packing_lib::core::core::PackCompact;
 use packing_lib::normalize::convert_helper::Method;
-
-use std::process;
+use std::str::FromStr;
 
 /// # Main function for 'gfa2bin graph'
-pub fn graph_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin graph'");
-
-    // Check graph file
-    let graph_file: &str = matches.value_of("gfa").unwrap();
-
-    // Input parameters
-    let feature1 = matches.value_of("feature").unwrap_or("node");
-    let output_feature = if ["node", "dirnode", "edge"].contains(&feature1) {
-        feature1
-    } else {
-        warn!("Feature {} is not supported", feature1);
-        warn!("Only node, dirnode and edge are supported");
-        process::exit(1);
-    };
-
-    let feature_enum = Feature::from_str(output_feature);
-    let mut sep = matches.value_of("PanSN").unwrap();
-
-    let bimbam_output = matches.is_present("bimbam");
-    let output_prefix = matches.value_of("output").unwrap();
-
-    // Threshold
-    let absolute_thresh = matches
-        .value_of("absolute-threshold")
-        .unwrap()
-        .parse::<u32>()
-        .expect("Error: Absolute threshold is not a number");
-
-    let method = Method::from_str(matches.value_of("method").unwrap_or("nothing"));
-    let keep_zeros = matches.is_present("keep-zeros");
-
-    // Bin is for faster computation
-    let mut bin = false;
-    if absolute_thresh == 1 {
-        bin = true;
-    }
-
-    let max_scale = matches.is_present("max-scale");
-
-    // Dummy phenotype
-    let mut pheno = f64::MAX;
-    if matches.is_present("pheno") {
-        pheno = matches.value_of("pheno").unwrap().parse()?;
-    }
-
-    let threads = matches
-        .value_of("threads")
-        .unwrap_or("1")
-        .parse::<usize>()
-        .unwrap();
-
-    info!("Input parameters");
-    info!("Graph file: {}", graph_file);
-    info!("Feature: {} -> {}", feature1, output_feature);
-    info!(
-        "Pan-SN: {}",
-        if sep == "\n" {
-            "None".to_string()
-        } else {
-            format!("{:?}", sep)
+pub fn graph_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    GraphConfig::from_matches(matches)?.run()
+}
+
+#[derive(Clone)]
+/// # Options of 'gfa2bin graph'
+///
+/// Create with GraphConfig::new and the builder methods, convert with GraphConfig::run
+pub struct GraphConfig {
+    pub graph_file: String,
+    pub output_prefix: String,
+    /// Node, dirnode, edge, bubble and/or segment (one graph parse for all)
+    pub features: Vec<Feature>,
+    /// Write all features into one fileset (otherwise: prefix.feature if more than one)
+    pub combined: bool,
+    /// Bubbles: one multiallelic VCF (PLINK 2) record per bubble (otherwise: one biallelic record per allele)
+    pub multiallelic: bool,
+    /// Segments: minimum length (bp), shorter segments are removed
+    pub min_bp: u64,
+    /// Keep one feature per unique genotype pattern (mapping: prefix.dedup.tsv)
+    pub dedup: bool,
+    /// Copy numbers (traversals summed per sample) instead of genotypes
+    pub copy_number: Option<CopyNumberFormat>,
+    /// Copy numbers: maximum value
+    pub cn_cap: Option<u16>,
+    /// Copy numbers: write the most common copy number of each feature (prefix.cn_mode.tsv)
+    pub cn_mode: bool,
+    /// PanSN-spec separator (None: every path is a sample)
+    pub pansn: Option<String>,
+    pub output_format: OutputFormat,
+    /// Reference path (or prefix) for coordinates
+    pub reference: Option<String>,
+    /// Features outside of the reference region are missing
+    pub missing: bool,
+    pub compression: Compression,
+    pub absolute_threshold: u32,
+    /// Dynamic threshold: method and fraction (0.0 is no dynamic threshold)
+    pub method: Method,
+    pub fraction: f32,
+    pub keep_zeros: bool,
+    pub max_scale: bool,
+    pub collapse: CollapsePolicy,
+    /// Dummy phenotype (None: NA)
+    pub pheno: Option<f64>,
+    /// Phenotype table (sample name + phenotype columns)
+    pub pheno_file: Option<String>,
+    /// Columns of the phenotype table which are covariates
+    pub covariates: Vec<String>,
+    /// Sample metadata (FID, IID, father, mother, sex)
+    pub metadata: Option<String>,
+    /// Write the feature annotation (length, GC, paths, samples)
+    pub annotation: bool,
+    pub threads: usize,
+}
+
+impl GraphConfig {
+    /// Default options (node feature, PLINK output, absolute threshold 1)
+    pub fn new(graph_file: &str, output_prefix: &str) -> Self {
+        Self {
+            graph_file: graph_file.to_string(),
+            output_prefix: output_prefix.to_string(),
+            features: vec![Feature::Node],
+            combined: false,
+            multiallelic: false,
+            min_bp: 0,
+            dedup: false,
+            copy_number: None,
+            cn_cap: None,
+            cn_mode: false,
+            pansn: None,
+            output_format: OutputFormat::Plink,
+            reference: None,
+            missing: false,
+            compression: Compression::None,
+            absolute_threshold: 1,
+            method: Method::Nothing,
+            fraction: 0.0,
+            keep_zeros: false,
+            max_scale: false,
+            collapse: CollapsePolicy::Het,
+            pheno: None,
+            pheno_file: None,
+            covariates: Vec::new(),
+            metadata: None,
+            annotation: false,
+            threads: 1,
         }
-    );
-    info!("Absolute threshold: {}", absolute_thresh);
-    info!("Method: {}", method.to_string());
-    info!(
-        "Fraction: {}",
-        if matches.is_present("fraction") {
-            matches.value_of("fraction").unwrap()
-        } else {
-            "None"
-        }
-    );
-    info!("Binary: {}", bin);
-    info!("Keep zeros: {}", keep_zeros);
-    info!("Max value scaling (only bimbam): {}", max_scale);
-    info!("Threads: {}", threads);
-    info!(
-        "Dummy-Pheno: {}",
-        if pheno == f64::MAX {
-            "NA".to_string()
+    }
+
+    pub fn feature(mut self, feature: Feature) -> Self {
+        self.features = vec![feature];
+        self
+    }
+
+    /// Multiple features, combined: one fileset for all features
+    pub fn features(mut self, features: &[Feature], combined: bool) -> Self {
+        self.features = features.to_vec();
+        self.combined = combined;
+        self
+    }
+
+    /// Bubbles are written as multiallelic VCF (prefix.vcf) or PLINK 2 (output format)
+    pub fn multiallelic(mut self, multiallelic: bool) -> Self {
+        self.multiallelic = multiallelic;
+        self
+    }
+
+    /// PAV segments with less than min_bp are removed
+    pub fn min_bp(mut self, min_bp: u64) -> Self {
+        self.min_bp = min_bp;
+        self
+    }
+
+    /// Identical genotype patterns are written once (mapping: prefix.dedup.tsv)
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Copy numbers instead of genotypes, capped at cap, mode: prefix.cn_mode.tsv
+    pub fn copy_number(mut self, format: CopyNumberFormat, cap: Option<u16>, mode: bool) -> Self {
+        self.copy_number = Some(format);
+        self.cn_cap = cap;
+        self.cn_mode = mode;
+        self
+    }
+
+    pub fn pansn(mut self, sep: &str) -> Self {
+        self.pansn = Some(sep.to_string());
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Reference coordinates, missing genotypes outside of the reference region
+    pub fn reference(mut self, reference: &str, missing: bool) -> Self {
+        self.reference = Some(reference.to_string());
+        self.missing = missing;
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn absolute_threshold(mut self, absolute_threshold: u32) -> Self {
+        self.absolute_threshold = absolute_threshold;
+        self
+    }
+
+    /// Dynamic threshold
+    pub fn dynamic_threshold(mut self, method: Method, fraction: f32, keep_zeros: bool) -> Self {
+        self.method = method;
+        self.fraction = fraction;
+        self.keep_zeros = keep_zeros;
+        self
+    }
+
+    pub fn max_scale(mut self, max_scale: bool) -> Self {
+        self.max_scale = max_scale;
+        self
+    }
+
+    pub fn collapse(mut self, collapse: CollapsePolicy) -> Self {
+        self.collapse = collapse;
+        self
+    }
+
+    pub fn pheno(mut self, pheno: f64) -> Self {
+        self.pheno = Some(pheno);
+        self
+    }
+
+    /// Phenotype table, covariates are columns of the table
+    pub fn pheno_file(mut self, pheno_file: &str, covariates: &[&str]) -> Self {
+        self.pheno_file = Some(pheno_file.to_string());
+        self.covariates = covariates.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Sample metadata (FID, IID, father, mother, sex) for the fam file
+    pub fn metadata(mut self, metadata: &str) -> Self {
+        self.metadata = Some(metadata.to_string());
+        self
+    }
+
+    /// Feature annotation (prefix.annotation.tsv)
+    pub fn annotation(mut self, annotation: bool) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        let mut config = Self::new(
+            matches.value_of("gfa").unwrap(),
+            matches.value_of("output").unwrap(),
+        );
+        config.features = matches
+            .value_of("feature")
+            .unwrap_or("node")
+            .split(',')
+            .map(|x| Feature::from_str(x.trim()))
+            .collect::<Result<Vec<Feature>, Gfa2binError>>()?;
+        config.combined = matches.is_present("combined");
+        config.multiallelic = matches.is_present("multiallelic");
+        if matches.is_present("min-bp") {
+            config.min_bp = parse_arg(matches.value_of("min-bp").unwrap(), "min-bp")?;
+        }
+        config.dedup = matches.is_present("dedup");
+        config.copy_number = matches
+            .value_of("copy-number")
+            .map(CopyNumberFormat::from_str)
+            .transpose()?;
+        if matches.is_present("cn-cap") {
+            config.cn_cap = Some(parse_arg(matches.value_of("cn-cap").unwrap(), "cn-cap")?);
+        }
+        config.cn_mode = matches.is_present("cn-mode");
+        config.pansn = matches
+            .value_of("PanSN")
+            .filter(|x| *x != "\n")
+            .map(|x| x.to_string());
+        config.output_format = if matches.is_present("bimbam") {
+            OutputFormat::Bimbam
+        } else if matches.is_present("plink2") {
+            OutputFormat::Plink2
         } else {
-            pheno.to_string()
-        }
-    );
-    info!(
-        "Output format: {}",
-        if bimbam_output { "bimbam" } else { "PLINK" }
-    );
-    info!("Output prefix: {}\n", output_prefix);
-
-    let mut fraction = 0.0;
-    if matches.is_present("fraction") {
-        fraction = matches
-            .value_of("fraction")
-            .unwrap()
-            .parse::<f32>()
-            .expect("Error: Fraction is not a number");
-    }
-    let mut dynamic = false;
-    if !(0.0..=1.0).contains(&fraction) {
-        panic!("Fraction is not between 0 and 1");
-    }
-    if method != Method::Nothing && fraction == 0.0 {
-        panic!("Method is given but fraction is not.");
-    } else if method == Method::Nothing && fraction != 0.0 {
-        panic!("Fraction is given but method is not.");
-    } else if method != Method::Nothing && fraction != 0.0 {
-        dynamic = true;
-    }
-    info!("Dynamic threshold: {}", dynamic);
-
-    info!("Read the graph");
-    // Read the graph and wrapper
-    let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file_multi(graph_file, threads);
-    if graph.paths.is_empty() && sep == "\n" {
-        sep = "#"
-    }
-
-    graph.walk_to_path(sep);
-
-    // Wrapper on PanSN
-    let wrapper: Pansn<u32, (), ()> = Pansn::from_graph(&graph.paths, sep);
-
-    // Check diploid
-    let mut is_diploid = false;
-    for x in wrapper.genomes.iter() {
-        if x.haplotypes.len() == 2 {
-            is_diploid = true;
-        }
-        if x.haplotypes.len() > 2 {
-            warn!("More than 2 haplotypes");
-            warn!(
-                "Haplotypes are {}",
-                x.haplotypes
-                    .iter()
-                    .map(|x| x.name.clone())
-                    .collect::<Vec<String>>()
-                    .join(", ")
-            );
-            warn!("Will only take the first 2 haplotypes")
+            OutputFormat::Plink
+        };
+        config.reference = matches.value_of("reference").map(|x| x.to_string());
+        config.missing = matches.is_present("missing");
+        config.compression = matches
+            .value_of("compress")
+            .map_or(Ok(Compression::None), Compression::from_str)?;
+        config.absolute_threshold = parse_arg::<u32>(
+            matches.value_of("absolute-threshold").unwrap(),
+            "absolute-threshold",
+        )?;
+        config.method = Method::from_str(matches.value_of("method").unwrap_or("nothing"));
+        if matches.is_present("fraction") {
+            config.fraction = parse_arg::<f32>(matches.value_of("fraction").unwrap(), "fraction")?;
+        }
+        config.keep_zeros = matches.is_present("keep-zeros");
+        config.max_scale = matches.is_present("max-scale");
+        config.collapse = CollapsePolicy::from_str(matches.value_of("collapse").unwrap())?;
+        if matches.is_present("pheno") {
+            config.pheno = Some(parse_arg(matches.value_of("pheno").unwrap(), "pheno")?);
         }
+        config.pheno_file = matches.value_of("pheno-file").map(|x| x.to_string());
+        config.covariates = matches.value_of("covariates").map_or(Vec::new(), |x| {
+            x.split(',').map(|y| y.to_string()).collect()
+        });
+        config.metadata = matches.value_of("sample-metadata").map(|x| x.to_string());
+        config.annotation = matches.is_present("annotation");
+        config.threads = parse_arg::<usize>(matches.value_of("threads").unwrap_or("1"), "threads")?;
+        Ok(config)
     }
 
-    info!("Diploid: {}", is_diploid);
-    info!("Number of samples: {}", wrapper.genomes.len());
-    info!("Number of paths: {}", graph.paths.len());
+    /// Convert the graph to PLINK (or BIMBAM/PLINK 2)
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin graph'");
+        if self.features.is_empty() {
+            return Err(Gfa2binError::Argument("No feature is given".to_string()));
+        }
+        for (i, feature) in self.features.iter().enumerate() {
+            if ![
+                Feature::Node,
+                Feature::DirNode,
+                Feature::Edge,
+                Feature::Bubble,
+                Feature::Segment,
+            ]
+            .contains(feature)
+            {
+                return Err(Gfa2binError::Argument(format!(
+                    "Feature {} is not supported (only node, dirnode, edge, bubble and segment)",
+                    feature.to_string1()
+                )));
+            } else if self.features[..i].contains(feature) {
+                return Err(Gfa2binError::Argument(format!(
+                    "Feature {} is given twice",
+                    feature.to_string1()
+                )));
+            }
+        }
+        if self.multiallelic && !self.features.contains(&Feature::Bubble) {
+            return Err(Gfa2binError::Argument(
+                "Multiallelic output is only available for bubbles (-f bubble)".to_string(),
+            ));
+        } else if self.multiallelic && self.combined {
+            return Err(Gfa2binError::Argument(
+                "Multiallelic bubbles can not be combined with other features".to_string(),
+            ));
+        } else if self.multiallelic
+            && (self.output_format == OutputFormat::Bimbam
+                || self.output_format == OutputFormat::Bgen)
+        {
+            return Err(Gfa2binError::Argument(
+                "Multiallelic bubbles are only available as VCF (default) or PLINK 2 (--plink2)"
+                    .to_string(),
+            ));
+        }
+        if self.features.contains(&Feature::Segment) {
+            if self.output_format == OutputFormat::Bimbam
+                || self.output_format == OutputFormat::Bgen
+            {
+                return Err(Gfa2binError::Argument(
+                    "Segments (-f segment) are only available for PLINK and PLINK 2 output"
+                        .to_string(),
+                ));
+            }
+        } else if self.min_bp > 0 {
+            return Err(Gfa2binError::Argument(
+                "Minimum length (--min-bp) is only used for segments (-f segment)".to_string(),
+            ));
+        }
+        if self.dedup {
+            if self.output_format == OutputFormat::Bimbam
+                || self.output_format == OutputFormat::Bgen
+            {
+                return Err(Gfa2binError::Argument(
+                    "Deduplication (--dedup) is only available for PLINK and PLINK 2 output"
+                        .to_string(),
+                ));
+            } else if self.multiallelic {
+                return Err(Gfa2binError::Argument(
+                    "Deduplication (--dedup) can not be combined with multiallelic bubbles"
+                        .to_string(),
+                ));
+            }
+        }
+        match self.copy_number {
+            Some(format) => {
+                if self
+                    .features
+                    .iter()
+                    .any(|x| ![Feature::Node, Feature::DirNode, Feature::Edge].contains(x))
+                {
+                    return Err(Gfa2binError::Argument(
+                        "Copy numbers (--copy-number) are only available for node, dirnode and edge"
+                            .to_string(),
+                    ));
+                } else if self.output_format != OutputFormat::Plink {
+                    return Err(Gfa2binError::Argument(
+                        "The format of copy numbers is given by --copy-number".to_string(),
+                    ));
+                } else if self.dedup {
+                    return Err(Gfa2binError::Argument(
+                        "Deduplication (--dedup) can not be combined with copy numbers".to_string(),
+                    ));
+                } else if format == CopyNumberFormat::Plink2 && self.cn_cap.unwrap_or(0) > 2 {
+                    return Err(Gfa2binError::Argument(
+                        "PLINK 2 dosages are limited to 2 (--cn-cap)".to_string(),
+                    ));
+                }
+            }
+            None => {
+                if self.cn_cap.is_some() || self.cn_mode {
+                    return Err(Gfa2binError::Argument(
+                        "Cap (--cn-cap) and mode (--cn-mode) are only used for copy numbers (--copy-number)"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        let graph_file = self.graph_file.as_str();
+        let output_prefix = self.output_prefix.as_str();
+        let features = &self.features;
+        let sep = self.pansn.as_deref().unwrap_or("\n");
+        let output_format = self.output_format;
+        let reference = self.reference.as_deref();
+        let missing = self.missing;
+        let compression = self.compression;
+        let absolute_thresh = self.absolute_threshold;
+        let method = self.method;
+        let keep_zeros = self.keep_zeros;
+        let max_scale = self.max_scale;
+        let collapse = self.collapse;
+        let pheno = self.pheno.unwrap_or(f64::MAX);
+        if self.pheno_file.is_none() && !self.covariates.is_empty() {
+            return Err(Gfa2binError::Argument(
+                "Covariates need a phenotype table (--pheno-file)".to_string(),
+            ));
+        } else if self.pheno_file.is_some() && self.pheno.is_some() {
+            return Err(Gfa2binError::Argument(
+                "Use either a dummy phenotype (--pheno) or a phenotype table (--pheno-file)"
+                    .to_string(),
+            ));
+        }
+        let phenotypes = match &self.pheno_file {
+            Some(pheno_file) => {
+                Some(PhenotypeTable::from_file(pheno_file)?.split_covariates(&self.covariates)?)
+            }
+            None => None,
+        };
+        let metadata = match &self.metadata {
+            Some(metadata) => Some(SampleMetadata::from_file(metadata)?),
+            None => None,
+        };
+        let threads = self.threads;
+        let fraction = self.fraction;
+
+        // Bin is for faster computation
+        let mut bin = false;
+        if absolute_thresh == 1 && self.copy_number.is_none() {
+            bin = true;
+        }
+
+        info!("Input parameters");
+        info!("Graph file: {}", graph_file);
+        info!(
+            "Features: {}",
+            features
+                .iter()
+                .map(|x| x.to_string1())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        info!("Combined output: {}", self.combined);
+        info!("Multiallelic bubbles: {}", self.multiallelic);
+        info!("Minimum segment length (bp): {}", self.min_bp);
+        info!("Deduplicate genotype patterns: {}", self.dedup);
+        info!(
+            "Copy numbers: {}",
+            self.copy_number
+                .map_or("None".to_string(), |x| x.to_string1())
+        );
+        info!(
+            "Copy number cap: {}",
+            self.cn_cap.map_or("None".to_string(), |x| x.to_string())
+        );
+        info!("Copy number mode: {}", self.cn_mode);
+        info!(
+            "Pan-SN: {}",
+            if sep == "\n" {
+                "None".to_string()
+            } else {
+                format!("{:?}", sep)
+            }
+        );
+        info!("Absolute threshold: {}", absolute_thresh);
+        info!("Method: {}", method.to_string());
+        info!(
+            "Fraction: {}",
+            if fraction == 0.0 {
+                "None".to_string()
+            } else {
+                fraction.to_string()
+            }
+        );
+        info!("Binary: {}", bin);
+        info!("Keep zeros: {}", keep_zeros);
+        info!("Max value scaling (only bimbam): {}", max_scale);
+        info!(
+            "Collapse policy (polyploid, PLINK): {}",
+            collapse.to_string1()
+        );
+        info!("Threads: {}", threads);
+        info!(
+            "Dummy-Pheno: {}",
+            if pheno == f64::MAX {
+                "NA".to_string()
+            } else {
+                pheno.to_string()
+            }
+        );
+        info!(
+            "Phenotype table: {}",
+            self.pheno_file.as_deref().unwrap_or("None")
+        );
+        info!(
+            "Covariates: {}",
+            if self.covariates.is_empty() {
+                "None".to_string()
+            } else {
+                self.covariates.join(", ")
+            }
+        );
+        info!(
+            "Sample metadata: {}",
+            self.metadata.as_deref().unwrap_or("None")
+        );
+        info!("Output format: {}", output_format.to_string1());
+        info!("Reference (coordinates): {}", reference.unwrap_or("None"));
+        info!("Missing (outside of reference region): {}", missing);
+        info!("Compression: {}", compression.to_string1());
+        info!("Feature annotation: {}", self.annotation);
+        info!("Output prefix: {}\n", output_prefix);
+
+        let mut dynamic = false;
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(Gfa2binError::Argument(
+                "Fraction is not between 0 and 1".to_string(),
+            ));
+        }
+        if method != Method::Nothing && fraction == 0.0 {
+            return Err(Gfa2binError::Argument(
+                "Method is given but fraction is not".to_string(),
+            ));
+        } else if method == Method::Nothing && fraction != 0.0 {
+            return Err(Gfa2binError::Argument(
+                "Fraction is given but method is not".to_string(),
+            ));
+        } else if method != Method::Nothing && fraction != 0.0 {
+            dynamic = true;
+        }
+        info!("Dynamic threshold: {}", dynamic);
+
+        info!("Read the graph");
+        // Read the graph and wrapper
+        let graph_input = PlainFile::new(graph_file)?;
+        let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file_multi(graph_input.path(), threads);
+
+        // Walks keep their sample and haplotype, paths are split by the PanSN separator
+        let origins = walks_to_paths(&mut graph, self.pansn.as_deref());
+
+        // Wrapper on PanSN
+        let wrapper: Pansn<u32, (), ()> = sample_haplotypes(&graph.paths, &origins);
+
+        // Check ploidy
+        let max_ploidy = wrapper
+            .genomes
+            .iter()
+            .map(|x| x.haplotypes.len())
+            .max()
+            .unwrap_or(1);
+        info!("Diploid: {}", max_ploidy == 2);
+        info!("Polyploid: {}", max_ploidy > 2);
+        if max_ploidy > 2 {
+            if output_format == OutputFormat::Plink {
+                info!(
+                    "Polyploid samples are collapsed to two alleles (policy: {})",
+                    collapse.to_string1()
+                );
+            } else {
+                // Dosage output is based on the number of haplotypes
+                bin = false;
+            }
+        }
+        info!("Number of samples: {}", wrapper.genomes.len());
+        info!("Number of paths: {}", graph.paths.len());
+
+        let reference_positions = match reference {
+            Some(reference) => Some(reference_positions(reference, &graph, &origins)?),
+            None => None,
+        };
+
+        let mut bubbles = if features.contains(&Feature::Bubble) {
+            info!("Find the bubbles");
+            let reference_names = match reference {
+                Some(reference) => Some(reference_paths(reference, &graph)?.0),
+                None => None,
+            };
+            let bubbles = BubbleIndex::new(&graph, reference_names.as_deref());
+            info!("Number of bubbles: {}", bubbles.bubbles.len());
+            Some(bubbles)
+        } else {
+            None
+        };
+
+        // All features are based on the same graph, combined output is collected here
+        let mut combined: Option<(MatrixWrapper, Vec<f32>, Vec<FeatureAnnotation>)> = None;
+        let mut fam_entries = None;
+        for feature_enum in features.iter().copied() {
+            info!("Feature: {}", feature_enum.to_string1());
+            // This is the matrix
+            let mut mw = MatrixWrapper::new();
+            mw.feature = feature_enum;
+            mw.collapse = collapse;
+            mw.compression = compression;
+            if feature_enum == Feature::Bubble {
+                mw.bubbles = bubbles.take().unwrap_or_default();
+            }
+            // Multiallelic records need the allele of each haplotype (counts)
+            let counts = self.multiallelic && feature_enum == Feature::Bubble;
+            // Segments are collapsed from the node genotypes
+            let index_feature = if feature_enum == Feature::Segment {
+                Feature::Node
+            } else {
+                feature_enum
+            };
+
+            info!("Create the index");
+            mw.create_index(&graph, index_feature);
 
-    // This is the matrix
-    let mut mw = MatrixWrapper::new();
+            info!("Read the graph into matrix");
+            gfa_reader(
+                &mut mw,
+                &wrapper,
+                bin && !counts,
+                index_feature,
+                reference_positions.as_ref().filter(|_| missing),
+                threads,
+            )?;
 
-    info!("Create the index");
-    mw.create_index(&graph, feature_enum);
+            // Samples are the same for all features
+            match &fam_entries {
+                Some(fam_entries) => mw.fam_entries = Vec::clone(fam_entries),
+                None => {
+                    if let Some((phenotypes, _)) = &phenotypes {
+                        mw.match_phenotypes(phenotypes)?;
+                    }
+                    if let Some(metadata) = &metadata {
+                        mw.apply_metadata(metadata);
+                    }
+                    fam_entries = Some(mw.fam_entries.clone());
+                }
+            }
 
-    info!("Read the graph into matrix");
-    gfa_reader(&mut mw, &wrapper, bin, feature_enum);
+            // Threshold calculation
+            let mut thresh = Vec::new();
 
-    // Threshold calculation
-    let mut thresh = Vec::new();
+            // If max_scale is true, threshold needs to be adjusted
+            if max_scale && output_format == OutputFormat::Bimbam {
+                for x in mw.matrix_u16.iter() {
+                    thresh.push(*x.iter().max().ok_or_else(|| {
+                        Gfa2binError::Consistency("No samples in the graph".to_string())
+                    })? as f32)
+                }
+            } else if !dynamic {
+                thresh = vec![absolute_thresh as f32; mw.geno_names.len()];
+            } else {
+                for x in mw.matrix_u16.iter() {
+                    let mut count_vec = x.clone();
+                    diploid_adder(&mw.sample_index_u16, &mut count_vec);
 
-    // If max_scale is true, threshold needs to be adjusted
-    if max_scale && bimbam_output {
-        for x in mw.matrix_u16.iter() {
-            thresh.push(*x.iter().max().ok_or("Error: Empty vector")? as f32)
+                    thresh.push(PackCompact::threshold(
+                        &mut count_vec,
+                        keep_zeros,
+                        fraction,
+                        0.0,
+                        method,
+                    ));
+                }
+            }
+
+            if feature_enum == Feature::Segment {
+                mw.fill_genotypes(&thresh);
+                info!("Collapse the nodes into segments");
+                mw.pav_segments(&graph, reference_positions.as_ref(), self.min_bp);
+                info!("Number of segments: {}", mw.segments.len());
+                thresh = vec![1.0; mw.geno_names.len()];
+            }
+
+            if let Some(reference_positions) = &reference_positions {
+                info!("Project the features on the reference");
+                mw.project_bim(reference_positions, feature_enum);
+            }
+
+            let annotation = if self.annotation {
+                mw.feature_annotation(&graph, &wrapper, feature_enum, false)
+            } else {
+                Vec::new()
+            };
+
+            if self.combined {
+                match &mut combined {
+                    Some((combined_mw, combined_thresh, combined_annotation)) => {
+                        combined_mw.append_features(mw);
+                        combined_thresh.extend(thresh);
+                        combined_annotation.extend(annotation);
+                    }
+                    None => combined = Some((mw, thresh, annotation)),
+                }
+            } else {
+                // One fileset for each feature (prefix.feature)
+                let prefix = if features.len() == 1 {
+                    output_prefix.to_string()
+                } else {
+                    format!("{}.{}", output_prefix, feature_enum.to_string1())
+                };
+                self.write_output(
+                    &mut mw,
+                    &prefix,
+                    thresh,
+                    &annotation,
+                    &phenotypes,
+                    &graph,
+                    reference_positions.as_ref(),
+                )?;
+            }
         }
-    } else if !dynamic {
-        thresh = vec![absolute_thresh as f32; mw.geno_names.len()];
-    } else {
-        for x in mw.matrix_u16.iter() {
-            let mut count_vec = x.clone();
-            diploid_adder(&mw.sample_index_u16, &mut count_vec);
 
-            thresh.push(PackCompact::threshold(
-                &mut count_vec,
-                keep_zeros,
-                fraction,
-                0.0,
-                method,
-            ));
+        if let Some((mut mw, thresh, annotation)) = combined {
+            info!("Writing all features (combined)");
+            self.write_output(
+                &mut mw,
+                output_prefix,
+                thresh,
+                &annotation,
+                &phenotypes,
+                &graph,
+                reference_positions.as_ref(),
+            )?;
         }
+        Ok(())
     }
 
-    mw.write_wrapper(
-        bimbam_output,
-        1,
-        output_prefix,
-        thresh,
-        feature_enum,
-        pheno,
-        !keep_zeros,
-    );
-    Ok(())
+    /// Write genotypes, phenotypes and the feature annotation of one fileset
+    ///
+    /// Bubbles: alleles (prefix.alleles.tsv), multiallelic VCF (PLINK 2) instead of the genotypes if requested
+    /// Segments: prefix.segments.tsv
+    /// Deduplication: genotypes of the representatives, mapping in prefix.dedup.tsv
+    /// Copy numbers: instead of the genotypes, modes in prefix.cn_mode.tsv
+    #[allow(clippy::too_many_arguments)]
+    fn write_output(
+        &self,
+        mw: &mut MatrixWrapper,
+        output_prefix: &str,
+        thresh: Vec<f32>,
+        annotation: &[FeatureAnnotation],
+        phenotypes: &Option<(PhenotypeTable, Option<PhenotypeTable>)>,
+        graph: &Gfa<u32, (), ()>,
+        reference: Option<&ReferencePositions>,
+    ) -> Result<(), Gfa2binError> {
+        let feature_enum = mw.feature;
+        let pheno = self.pheno.unwrap_or(f64::MAX);
+        if let Some(format) = self.copy_number {
+            // PLINK 2 dosages are limited to 2
+            let cap = match format {
+                CopyNumberFormat::Plink2 => Some(self.cn_cap.unwrap_or(2)),
+                _ => self.cn_cap,
+            };
+            info!("Writing the copy numbers ({})", format.to_string1());
+            mw.write_copy_numbers(output_prefix, format, cap, pheno)?;
+            if self.cn_mode {
+                info!("Writing the copy number modes");
+                mw.write_cn_modes(output_prefix, cap)?;
+            }
+        } else if self.multiallelic
+            && feature_enum == Feature::Bubble
+            && self.output_format == OutputFormat::Plink2
+        {
+            if mw.sample_index_u16.iter().any(|x| x[1] > x[0] + 1) {
+                return Err(Gfa2binError::Argument(
+                    "Multiallelic PLINK 2 output supports haploid and diploid samples only"
+                        .to_string(),
+                ));
+            }
+            info!("Writing the bubbles (multiallelic pgen/pvar/psam)");
+            mw.write_bubble_pgen(output_prefix, graph, reference, pheno)?;
+        } else if self.multiallelic && feature_enum == Feature::Bubble {
+            info!("Writing the bubbles (multiallelic VCF)");
+            mw.write_bubble_vcf(output_prefix, graph, reference)?;
+        } else {
+            if self.dedup {
+                info!("Deduplicate the genotype patterns");
+                mw.fill_genotypes(&thresh);
+                write_dedup_mapping(output_prefix, &mw.dedup_patterns())?;
+            }
+            mw.write_wrapper(
+                self.output_format,
+                1,
+                output_prefix,
+                thresh,
+                feature_enum,
+                pheno,
+                !self.keep_zeros,
+            )?;
+        }
+        if !mw.bubbles.is_empty() {
+            info!("Writing the bubble alleles");
+            write_alleles(output_prefix, &mw.bubbles)?;
+        }
+        if !mw.segments.is_empty() {
+            info!("Writing the segments");
+            write_segments(output_prefix, &mw.segments)?;
+        }
+        if let Some((phenotypes, covariates)) = phenotypes {
+            let output_format = match self.copy_number {
+                Some(CopyNumberFormat::Bimbam) => OutputFormat::Bimbam,
+                Some(_) => OutputFormat::Plink2,
+                None => self.output_format,
+            };
+            mw.write_phenotypes(
+                output_prefix,
+                output_format,
+                phenotypes,
+                covariates.as_ref(),
+            )?;
+        }
+        if self.annotation {
+            info!("Writing the feature annotation");
+            write_annotation(output_prefix, annotation)?;
+        }
+        Ok(())
+    }
 }
@@ -1,2 +1,5 @@
+pub mod bubble;
 pub mod graph_main;
 pub mod parser;
+pub mod samples;
+pub mod segment;
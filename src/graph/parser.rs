@@ -1,176 +1,290 @@
+use crate::core::bed::{set_genotype, BED_MISSING};
+use crate::core::bfile::{feature2node, ReferencePositions};
 use crate::core::core::MatrixWrapper;
-use crate::core::helper::{merge_u32_to_u64, Feature};
+use crate::core::error::Gfa2binError;
+use crate::core::helper::{CollapsePolicy, Feature, FeatureId};
+use crate::core::matrix::GenotypeMatrix;
+use crate::graph::bubble::BubbleIndex;
 
 use bitvec::order::Lsb0;
 use bitvec::vec::BitVec;
-use gfa_reader::{Pansn, Path};
+use gfa_reader::{Pansn, Path, Sample};
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 
 /// Read a gfa file and convert it to a matrix (bit or u16)
 ///
-/// Read the graph sample by
+/// Samples are read in chunks, the samples of a chunk in parallel
+/// If a reference is provided, features outside the region covered by a haplotype are missing
 pub fn gfa_reader(
     matrix: &mut MatrixWrapper,
     graph_wrapper: &Pansn<u32, (), ()>,
     want_bool: bool,
     feature: Feature,
-) {
-    // Don't count
+    reference: Option<&ReferencePositions>,
+    threads: usize,
+) -> Result<(), Gfa2binError> {
+    let feature_number = matrix.geno_names.len();
+    let haplotype_number = graph_wrapper
+        .genomes
+        .iter()
+        .map(|x| x.haplotypes.len())
+        .sum::<usize>();
+
     if want_bool {
-        // Matrix bit
-        matrix.matrix_bit = vec![
-            BitVec::<u8, Lsb0>::repeat(false, graph_wrapper.genomes.len() * 2);
-            matrix.geno_names.len()
-        ];
-        matrix.shape = (matrix.matrix_bit.len(), matrix.matrix_bit[0].len());
-
-        let index2geno = &matrix.geno_names;
-        for (path_index, nn) in graph_wrapper.genomes.iter().enumerate() {
-            matrix.sample_names.push(nn.name.clone());
-
-            // "haploid"
-            if nn.haplotypes.len() == 1 {
-                for ncpath in nn.haplotypes[0].paths.iter() {
-                    let path_geno_vec = paths_to_u64vec(ncpath, feature);
-                    let mut i = 0;
-                    let mut j = 0;
-
-                    while i < path_geno_vec.len() && j < index2geno.len() {
-                        if path_geno_vec[i] == index2geno[j] {
-                            matrix.matrix_bit[j]
-                                .get_mut(path_index * 2 + 1)
-                                .unwrap()
-                                .set(true);
-
-                            matrix.matrix_bit[j]
-                                .get_mut(path_index * 2)
-                                .unwrap()
-                                .set(true);
-
-                            i += 1;
-                            j += 1;
-                        } else if path_geno_vec[i] < index2geno[j] {
-                            i += 1;
-                        } else {
-                            j += 1;
-                        }
-                    }
-                }
+        // Matrix bit (missing features are genotypes)
+        matrix.matrix_bit = GenotypeMatrix::new(feature_number, graph_wrapper.genomes.len());
+        matrix.shape = (feature_number, graph_wrapper.genomes.len() * 2);
+    } else {
+        // Now count
+        matrix.matrix_u16 = vec![vec![0; haplotype_number]; feature_number];
+        matrix.shape = (matrix.matrix_u16.len(), matrix.matrix_u16[0].len());
+        if reference.is_some() {
+            matrix.matrix_missing =
+                vec![BitVec::<u8, Lsb0>::repeat(false, haplotype_number); feature_number];
+        }
+    }
+    matrix.sample_names = graph_wrapper
+        .genomes
+        .iter()
+        .map(|x| x.name.clone())
+        .collect();
+    matrix.sample_index_u16 = what_together(graph_wrapper);
 
-                // "diploid"
-            } else if nn.haplotypes.len() == 2 {
-                // First haplotypes
-                for ncpath in nn.haplotypes[0].paths.iter() {
-                    let iter1 = paths_to_u64vec(ncpath, feature);
-                    let mut i = 0;
-                    let mut j = 0;
-
-                    while i < iter1.len() && j < index2geno.len() {
-                        if iter1[i] == index2geno[j] {
-                            matrix.matrix_bit[j]
-                                .get_mut(path_index * 2)
-                                .unwrap()
-                                .set(true);
-                            i += 1;
-                            j += 1;
-                        } else if iter1[i] < index2geno[j] {
-                            i += 1;
-                        } else {
-                            j += 1;
-                        }
-                    }
-                }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| Gfa2binError::Argument(format!("Can not create the thread pool: {}", e)))?;
+    let collapse = matrix.collapse;
+    let geno_names = &matrix.geno_names;
+    let bubbles = &matrix.bubbles;
+    let sample_index = &matrix.sample_index_u16;
+    let matrix_bit = &mut matrix.matrix_bit;
+    let matrix_u16 = &mut matrix.matrix_u16;
+    let matrix_missing = &mut matrix.matrix_missing;
+    let chunk_size = threads.max(1) * 4;
 
-                // Second haplotypes
-                for ncpath in nn.haplotypes[1].paths.iter() {
-                    let iter1 = paths_to_u64vec(ncpath, feature);
-                    let mut i = 0;
-                    let mut j = 0;
-
-                    while i < iter1.len() && j < index2geno.len() {
-                        if iter1[i] == index2geno[j] {
-                            let check = matrix.matrix_bit[j][path_index * 2];
-                            if check {
-                                matrix.matrix_bit[j]
-                                    .get_mut(path_index * 2 + 1)
-                                    .unwrap()
-                                    .set(true);
-                            } else {
-                                matrix.matrix_bit[j]
-                                    .get_mut(path_index * 2)
-                                    .unwrap()
-                                    .set(true);
-                            }
-                            i += 1;
-                            j += 1;
-                        } else if iter1[i] < index2geno[j] {
-                            i += 1;
-                        } else {
-                            j += 1;
-                        }
+    for (chunk_index, samples) in graph_wrapper.genomes.chunks(chunk_size).enumerate() {
+        let first_sample = chunk_index * chunk_size;
+        if want_bool {
+            // Genotypes of each sample
+            let columns = pool.install(|| {
+                samples
+                    .par_iter()
+                    .map(|sample| {
+                        sample_genotypes(sample, geno_names, feature, bubbles, reference, collapse)
+                    })
+                    .collect::<Vec<BitVec<u8, Lsb0>>>()
+            });
+
+            // Write the columns of the chunk (features in parallel)
+            pool.install(|| {
+                matrix_bit.par_variants_mut().for_each(|(j, row)| {
+                    for (i, column) in columns.iter().enumerate() {
+                        set_genotype(row, first_sample + i, (column[2 * j], column[2 * j + 1]));
                     }
+                });
+            });
+            continue;
+        }
+
+        // Counts and missing features of each haplotype
+        let columns = pool.install(|| {
+            samples
+                .par_iter()
+                .map(|sample| {
+                    sample
+                        .haplotypes
+                        .iter()
+                        .map(|haplotype| {
+                            let counts =
+                                haplotype_counts(&haplotype.paths, geno_names, feature, bubbles);
+                            let missing = match reference {
+                                Some(reference) => {
+                                    let present = counts.iter().map(|x| *x > 0).collect();
+                                    haplotype_missing(
+                                        &haplotype.paths,
+                                        geno_names,
+                                        feature,
+                                        &present,
+                                        reference,
+                                    )
+                                }
+                                None => BitVec::new(),
+                            };
+                            (counts, missing)
+                        })
+                        .collect::<Vec<(Vec<u16>, BitVec<u8, Lsb0>)>>()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // Write the columns of the chunk (features in parallel)
+        let first_column = sample_index[first_sample][0];
+        pool.install(|| {
+            matrix_u16.par_iter_mut().enumerate().for_each(|(j, row)| {
+                for (c, (counts, _)) in columns.iter().flatten().enumerate() {
+                    row[first_column + c] = counts[j];
                 }
-                // If you have more than 3 haplotypes
-            } else {
-                panic!("Not implemented");
+            });
+            if reference.is_some() {
+                matrix_missing
+                    .par_iter_mut()
+                    .enumerate()
+                    .for_each(|(j, row)| {
+                        for (c, (_, missing)) in columns.iter().flatten().enumerate() {
+                            row.set(first_column + c, missing[j]);
+                        }
+                    });
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Genotypes of a sample (two bits per feature, same as a bed variant)
+///
+/// Only the presence of each haplotype is kept, features missing in any haplotype are missing
+fn sample_genotypes(
+    sample: &Sample<u32, (), ()>,
+    geno_names: &[u64],
+    feature: Feature,
+    bubbles: &BubbleIndex,
+    reference: Option<&ReferencePositions>,
+    collapse: CollapsePolicy,
+) -> BitVec<u8, Lsb0> {
+    let mut presence = Vec::with_capacity(sample.haplotypes.len());
+    let mut missing = BitVec::<u8, Lsb0>::repeat(false, geno_names.len());
+    for haplotype in sample.haplotypes.iter() {
+        let present = haplotype_presence(&haplotype.paths, geno_names, feature, bubbles);
+        if let Some(reference) = reference {
+            let absent =
+                haplotype_missing(&haplotype.paths, geno_names, feature, &present, reference);
+            for j in absent.iter_ones() {
+                missing.set(j, true);
             }
         }
-        // Now count
-    } else {
-        matrix.matrix_u16 =
-            vec![vec![0; graph_wrapper.get_haplo_path().len()]; matrix.geno_names.len()];
-        matrix.shape = (matrix.matrix_u16.len(), matrix.matrix_u16[0].len());
-        let hm = &matrix.geno_names;
-        let mut c = 0;
-        for nn in graph_wrapper.genomes.iter() {
-            matrix.sample_names.push(nn.name.clone());
-            for haplotype in nn.haplotypes.iter() {
-                for path in haplotype.paths.iter() {
-                    let iter1 = paths_to_u64vec(path, feature);
-                    let mut i = 0;
-                    let mut j = 0;
-
-                    let mut lastentry = 0;
-
-                    while i < iter1.len() && j < hm.len() {
-                        if iter1[i] == hm[j] {
-                            matrix.matrix_u16[j][c] += 1;
-                            lastentry = iter1[i];
-                            i += 1;
-                            j += 1;
-                        } else if iter1[i] < hm[j] {
-                            if iter1[i] == lastentry {
-                                matrix.matrix_u16[j][c] += 1;
-                            }
-                            lastentry = iter1[i];
-                            i += 1;
-                        } else {
-                            j += 1;
-                        }
-                    }
-                }
+        presence.push(present);
+    }
+
+    let mut genotypes = BitVec::<u8, Lsb0>::repeat(false, geno_names.len() * 2);
+    for j in 0..geno_names.len() {
+        let genotype = if missing[j] {
+            BED_MISSING
+        } else {
+            let count = presence.iter().filter(|x| x[j]).count();
+            collapse.genotype(count, presence.len())
+        };
+        set_genotype(&mut genotypes, j, genotype);
+    }
+    genotypes
+}
+
+/// Number of occurrences of each feature (sorted geno names) in a haplotype (all its paths)
+///
+/// Bubble alleles are the traversals of the bubbles (index)
+pub fn haplotype_counts(
+    paths: &[&Path<u32, (), ()>],
+    geno_names: &[u64],
+    feature: Feature,
+    bubbles: &BubbleIndex,
+) -> Vec<u16> {
+    let mut counts = vec![0; geno_names.len()];
+    for_each_feature(paths, geno_names, feature, bubbles, |j| counts[j] += 1);
+    counts
+}
+
+/// Presence of each feature (sorted geno names) in a haplotype (all its paths)
+pub fn haplotype_presence(
+    paths: &[&Path<u32, (), ()>],
+    geno_names: &[u64],
+    feature: Feature,
+    bubbles: &BubbleIndex,
+) -> BitVec<u8, Lsb0> {
+    let mut present = BitVec::<u8, Lsb0>::repeat(false, geno_names.len());
+    for_each_feature(paths, geno_names, feature, bubbles, |j| {
+        present.set(j, true)
+    });
+    present
+}
+
+/// Call f with the index (in the sorted geno names) of each feature occurrence in the paths
+fn for_each_feature<F: FnMut(usize)>(
+    paths: &[&Path<u32, (), ()>],
+    geno_names: &[u64],
+    feature: Feature,
+    bubbles: &BubbleIndex,
+    mut f: F,
+) {
+    for path in paths.iter() {
+        let path_geno_vec = if feature == Feature::Bubble {
+            bubbles.path_alleles(path)
+        } else {
+            paths_to_u64vec(path, feature)
+        };
+        let mut j = 0;
+        for x in path_geno_vec.iter() {
+            while j < geno_names.len() && geno_names[j] < *x {
+                j += 1;
+            }
+            if j == geno_names.len() {
+                break;
+            }
+            if geno_names[j] == *x {
+                f(j);
             }
-            c += 1;
         }
     }
-    matrix.sample_index_u16 = what_together(graph_wrapper);
+}
+
+/// Missing features of a haplotype
+///
+/// The region covered by a haplotype is spanned by the first and last reference node (on each chromosome) of its paths
+/// Features which are absent and outside this region are missing, features without reference position are never missing
+pub fn haplotype_missing(
+    paths: &[&Path<u32, (), ()>],
+    geno_names: &[u64],
+    feature: Feature,
+    present: &BitVec<u8, Lsb0>,
+    reference: &ReferencePositions,
+) -> BitVec<u8, Lsb0> {
+    let mut covered: HashMap<usize, (u64, u64)> = HashMap::new();
+    for path in paths.iter() {
+        for node in path.nodes.iter() {
+            if let Some((chromosome, position, true)) = reference.positions.get(node) {
+                let region = covered.entry(*chromosome).or_insert((*position, *position));
+                region.0 = region.0.min(*position);
+                region.1 = region.1.max(*position);
+            }
+        }
+    }
+
+    let mut missing = BitVec::<u8, Lsb0>::repeat(false, geno_names.len());
+    for (j, x) in geno_names.iter().enumerate() {
+        if present[j] {
+            continue;
+        }
+        let (node, _offset) = feature2node(*x, feature);
+        if let Some((chromosome, position, _)) = reference.positions.get(&node) {
+            match covered.get(chromosome) {
+                Some((start, end)) if start <= position && position <= end => {}
+                _ => missing.set(j, true),
+            }
+        }
+    }
+    missing
 }
 
 /// Index for merge
 ///
-/// C-Index
+/// C-Index: [first, last] haplotype (column) of each sample
 pub fn what_together(graph_wrapper: &Pansn<u32, (), ()>) -> Vec<[usize; 2]> {
     let mut c = Vec::new();
     let mut count = 0;
     for x in graph_wrapper.genomes.iter() {
-        if x.haplotypes.len() == 1 {
-            c.push([count, count]);
-            count += 1;
-        } else if x.haplotypes.len() == 2 {
-            c.push([count, count + 1]);
-            count += 2
-        } else {
-            panic!("Not implemented");
-        }
+        c.push([count, count + x.haplotypes.len() - 1]);
+        count += x.haplotypes.len();
     }
     c
 }
@@ -198,23 +312,19 @@ pub fn paths_to_u64vec(path: &Path<u32, (), ()>, feature: Feature) -> Vec<u64> {
         let d2 = path.dir[i + 1];
 
         if feature == Feature::Node {
-            vec_u64.push(n1 as u64);
+            vec_u64.push(FeatureId::node(n1).value);
         } else if feature == Feature::DirNode {
-            vec_u64.push(n1 as u64 * 2 + d1 as u64);
+            vec_u64.push(FeatureId::dirnode(n1, d1).value);
         } else if feature == Feature::Edge {
-            let u1 = n1 * 2 + d1 as u32;
-            let u2 = n2 * 2 + d2 as u32;
-            // Merge u32 to u64
-            vec_u64.push(merge_u32_to_u64(u1, u2));
+            vec_u64.push(FeatureId::edge(n1, d1, n2, d2).value);
         }
     }
     // Add the last entry
+    let last = path.nodes.len() - 1;
     if feature == Feature::Node {
-        vec_u64.push(path.nodes[path.nodes.len() - 1] as u64);
+        vec_u64.push(FeatureId::node(path.nodes[last]).value);
     } else if feature == Feature::DirNode {
-        vec_u64.push(
-            path.nodes[path.nodes.len() - 1] as u64 * 2 + path.dir[path.nodes.len() - 1] as u64,
-        );
+        vec_u64.push(FeatureId::dirnode(path.nodes[last], path.dir[last]).value);
     }
     vec_u64.sort();
     vec_u64
@@ -0,0 +1,112 @@
+use gfa_reader::{Gfa, Haplotype, Pansn, Path, Sample};
+use hashbrown::HashMap;
+use log::info;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Sample, haplotype and coordinates of a path (P-line or W-line)
+///
+/// - W-line: sample, haplotype index, sequence and start column
+/// - P-line: PanSN name (sample#haplotype#sequence) if a separator is given, otherwise the name is the sample
+pub struct PathOrigin {
+    pub sample: String,
+    pub haplotype: String,
+    /// Sequence name (chromosome)
+    pub sequence: String,
+    /// Start of the path on the sequence (0-based, P-lines: 0)
+    pub start: u64,
+    pub walk: bool,
+}
+
+impl PathOrigin {
+    /// Origin of a P-line, PanSN-spec name if a separator is given
+    pub fn from_path_name(name: &str, sep: Option<&str>) -> Self {
+        let parts = match sep {
+            Some(sep) => name.splitn(3, sep).collect::<Vec<&str>>(),
+            None => vec![name],
+        };
+        Self {
+            sample: parts[0].to_string(),
+            haplotype: parts.get(1).unwrap_or(&"").to_string(),
+            sequence: parts.last().unwrap().to_string(),
+            start: 0,
+            walk: false,
+        }
+    }
+
+    /// PanSN-spec name (sample#haplotype#sequence)
+    pub fn pansn_name(&self) -> String {
+        format!("{}#{}#{}", self.sample, self.haplotype, self.sequence)
+    }
+}
+
+/// # Convert walks to paths and keep their origin
+///
+/// - Walks are appended to the paths and renamed to sample#haplotype#sequence (for --reference)
+/// - Returns the origin of each path (same order as graph.paths)
+pub fn walks_to_paths(graph: &mut Gfa<u32, (), ()>, sep: Option<&str>) -> Vec<PathOrigin> {
+    let mut origins = graph
+        .paths
+        .iter()
+        .map(|x| PathOrigin::from_path_name(&x.name, sep))
+        .collect::<Vec<PathOrigin>>();
+    let walks = graph
+        .walk
+        .iter()
+        .map(|x| PathOrigin {
+            sample: x.sample_id.clone(),
+            haplotype: x.hap_index.to_string(),
+            sequence: x.seq_id.clone(),
+            start: x.seq_start.max(0) as u64,
+            walk: true,
+        })
+        .collect::<Vec<PathOrigin>>();
+    if !walks.is_empty() {
+        info!(
+            "Number of walks: {} (sample and haplotype from the W-lines)",
+            walks.len()
+        );
+    }
+
+    let number_paths = graph.paths.len();
+    graph.walk_to_path("#");
+    for (path, origin) in graph.paths[number_paths..].iter_mut().zip(walks.iter()) {
+        path.name = origin.pansn_name();
+    }
+    origins.extend(walks);
+    origins
+}
+
+/// # Group the paths by sample and haplotype
+///
+/// Samples and haplotypes are in order of their first path
+pub fn sample_haplotypes<'a>(
+    paths: &'a [Path<u32, (), ()>],
+    origins: &[PathOrigin],
+) -> Pansn<'a, u32, (), ()> {
+    let mut genomes: Vec<Sample<'a, u32, (), ()>> = Vec::new();
+    let mut genome_index: HashMap<&str, usize> = HashMap::new();
+    for (path, origin) in paths.iter().zip(origins.iter()) {
+        let index = *genome_index
+            .entry(origin.sample.as_str())
+            .or_insert_with(|| {
+                genomes.push(Sample {
+                    name: origin.sample.clone(),
+                    haplotypes: Vec::new(),
+                });
+                genomes.len() - 1
+            });
+        let genome = &mut genomes[index];
+        match genome
+            .haplotypes
+            .iter_mut()
+            .find(|x| x.name == origin.haplotype)
+        {
+            Some(haplotype) => haplotype.paths.push(path),
+            None => genome.haplotypes.push(Haplotype {
+                name: origin.haplotype.clone(),
+                paths: vec![path],
+            }),
+        }
+    }
+    Pansn { genomes }
+}
@@ -0,0 +1,157 @@
+use crate::core::bfile::ReferencePositions;
+use crate::core::core::MatrixWrapper;
+use crate::core::helper::{Feature, FeatureId};
+use crate::core::matrix::GenotypeMatrix;
+
+use gfa_reader::Gfa;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Header of the PAV segments (TSV)
+pub const SEGMENTS_HEADER: &str = "#ID\tFIRST\tLAST\tNODES\tBP\tCHROM\tSTART\tEND";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// # Presence/absence (PAV) segment
+///
+/// Consecutive nodes (node id order) with the same genotype in all samples
+pub struct PavSegment {
+    pub first: u32,
+    pub last: u32,
+    pub nodes: usize,
+    /// Total sequence length (bp)
+    pub bp: u64,
+    /// Reference span of the nodes on the reference (chromosome, first and last bp)
+    pub span: Option<(String, u64, u64)>,
+}
+
+impl PavSegment {
+    pub fn id(&self) -> FeatureId {
+        FeatureId::segment(self.first, self.last)
+    }
+
+    /// Add a node (and its reference span) to the segment
+    fn add(&mut self, node: u32, length: u64, span: Option<(&str, u64, u64)>) {
+        self.last = node;
+        self.nodes += 1;
+        self.bp += length;
+        if let Some((chromosome, start, end)) = span {
+            match &mut self.span {
+                Some(x) if x.0 == chromosome => {
+                    x.1 = x.1.min(start);
+                    x.2 = x.2.max(end);
+                }
+                Some(_) => {}
+                None => self.span = Some((chromosome.to_string(), start, end)),
+            }
+        }
+    }
+}
+
+impl fmt::Display for PavSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (chromosome, start, end) = match &self.span {
+            Some((chromosome, start, end)) => {
+                (chromosome.clone(), start.to_string(), end.to_string())
+            }
+            None => ("NA".to_string(), "NA".to_string(), "NA".to_string()),
+        };
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id(),
+            self.first,
+            self.last,
+            self.nodes,
+            self.bp,
+            chromosome,
+            start,
+            end
+        )
+    }
+}
+
+/// Write the PAV segments (prefix.segments.tsv)
+pub fn write_segments(out_prefix: &str, segments: &[PavSegment]) -> Result<(), io::Error> {
+    let output = [out_prefix, "segments", "tsv"].join(".");
+    let mut f = BufWriter::new(File::create(output)?);
+    writeln!(f, "{}", SEGMENTS_HEADER)?;
+    for x in segments.iter() {
+        writeln!(f, "{}", x)?;
+    }
+    f.flush()
+}
+
+impl MatrixWrapper {
+    /// # Collapse the nodes into PAV segments
+    ///
+    /// Based on the node genotypes (matrix_bit): consecutive nodes with identical genotypes are merged
+    /// - Node ids must be consecutive (a gap, e.g. a filtered node, starts a new segment)
+    /// - Segments do not span two chromosomes of the reference
+    /// - Segments with less than min_bp are removed
+    /// - Raw values (u16) are removed, the segments are written from the genotypes
+    pub fn pav_segments(
+        &mut self,
+        graph: &Gfa<u32, (), ()>,
+        reference: Option<&ReferencePositions>,
+        min_bp: u64,
+    ) {
+        let mut segments: Vec<(usize, PavSegment)> = Vec::new();
+        let mut chromosome = None;
+        for (i, node) in self.geno_names.iter().enumerate() {
+            let node = *node as u32;
+            let length = graph.get_sequence_by_id(&node).len() as u64;
+            let position = reference.and_then(|x| x.positions.get(&node));
+            let span = match (reference, position) {
+                (Some(reference), Some((index, start, true))) => Some((
+                    reference.chromosomes[*index].as_str(),
+                    *start,
+                    start + length.max(1) - 1,
+                )),
+                _ => None,
+            };
+
+            let same_chromosome = match (chromosome, position) {
+                (Some(x), Some(y)) => x == y.0,
+                _ => true,
+            };
+            match segments.last_mut() {
+                Some((row, segment))
+                    if same_chromosome
+                        && node == segment.last + 1
+                        && self.matrix_bit.raw_variants(*row..*row + 1)
+                            == self.matrix_bit.raw_variants(i..i + 1) =>
+                {
+                    segment.add(node, length, span)
+                }
+                _ => {
+                    let mut segment = PavSegment {
+                        first: node,
+                        ..PavSegment::default()
+                    };
+                    segment.add(node, length, span);
+                    segments.push((i, segment));
+                    chromosome = None;
+                }
+            }
+            if let Some(position) = position {
+                chromosome = Some(position.0);
+            }
+        }
+        segments.retain(|x| x.1.bp >= min_bp);
+
+        let mut matrix_bit = GenotypeMatrix::new(0, self.matrix_bit.samples_number());
+        for (row, _) in segments.iter() {
+            matrix_bit.push_variant(self.matrix_bit.variant(*row));
+        }
+        self.matrix_bit = matrix_bit;
+        self.matrix_u16 = Vec::new();
+        self.matrix_f32 = Vec::new();
+        self.matrix_missing = Vec::new();
+        self.bim_entries = Vec::new();
+        self.feature = Feature::Segment;
+        self.geno_names = segments.iter().map(|x| x.1.id().value).collect();
+        self.shape = (segments.len(), self.matrix_bit.samples_number() * 2);
+        self.segments = segments.into_iter().map(|x| x.1).collect();
+    }
+}
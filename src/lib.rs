@@ -0,0 +1,54 @@
+//! # gfa2bin
+//!
+//! Convert variation graphs (GFA) and coverage (pack) files to PLINK (and BIMBAM, PLINK 2, BGEN)
+//! and modify the resulting PLINK files.
+//!
+//! Each subcommand has a config struct (e.g. [GraphConfig], [CovConfig]), which is either built
+//! in code or parsed from the command line, and converted using `run`.
+//!
+//! ```no_run
+//! use gfa2bin::{Feature, GraphConfig, OutputFormat};
+//!
+//! GraphConfig::new("graph.gfa", "output")
+//!     .feature(Feature::Edge)
+//!     .pansn("#")
+//!     .output_format(OutputFormat::Plink2)
+//!     .threads(4)
+//!     .run()
+//!     .unwrap();
+//! ```
+pub mod core;
+pub mod cov;
+pub mod dedup;
+pub mod filter;
+pub mod find;
+pub mod graph;
+mod helper;
+pub mod merge;
+pub mod nearest;
+pub mod remove;
+pub mod split;
+pub mod stats;
+pub mod subpath;
+pub mod view;
+pub mod window;
+
+pub use crate::core::compression::Compression;
+pub use crate::core::copy_number::CopyNumberFormat;
+pub use crate::core::core::{MatrixWrapper, OutputFormat};
+pub use crate::core::error::Gfa2binError;
+pub use crate::core::helper::{CollapsePolicy, Feature};
+pub use crate::cov::cov_main::{CovConfig, PackInput};
+pub use crate::dedup::dedup_main::{DedupConfig, ExpandConfig};
+pub use crate::filter::filter_main::FilterConfig;
+pub use crate::find::find_main::FindConfig;
+pub use crate::graph::graph_main::GraphConfig;
+pub use crate::merge::merge_main::MergeConfig;
+pub use crate::nearest::nearest_main::NearestConfig;
+pub use crate::remove::remove_main::{RemoveConfig, RemoveList};
+pub use crate::split::split_main::SplitConfig;
+pub use crate::stats::stats_main::StatsConfig;
+pub use crate::subpath::subpath_main::SubpathConfig;
+pub use crate::view::view_main::ViewConfig;
+pub use crate::window::window_main::WindowConfig;
+pub use packing_lib::normalize::convert_helper::Method;
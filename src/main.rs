@@ -1,35 +1,23 @@
-mod core;
-mod cov;
-mod filter;
-mod find;
-mod graph;
-mod helper;
 mod logging;
-mod merge;
-mod nearest;
-mod remove;
-mod split;
-mod subpath;
-mod view;
-mod window;
-
-use crate::filter::filter_main::filter_main;
-use crate::find::find_main::find_main;
-use crate::graph::graph_main::graph_main;
+
 use crate::logging::newbuilder;
-use crate::merge::merge_main::merge_main;
-use crate::remove::remove_main::remove_main;
-use crate::split::split_main::split_main;
-use crate::subpath::subpath_main::subpath_main;
-use crate::view::view_main::view_main;
-use crate::window::window_main::window_main;
 use clap::{App, AppSettings, Arg};
-
-use crate::cov::cov_main::cov_main;
-use crate::nearest::nearest_main::nearest_main;
-use std::error::Error;
-
-fn main() -> Result<(), Box<dyn Error>> {
+use gfa2bin::cov::cov_main::cov_main;
+use gfa2bin::dedup::dedup_main::{dedup_main, expand_main};
+use gfa2bin::filter::filter_main::filter_main;
+use gfa2bin::find::find_main::find_main;
+use gfa2bin::graph::graph_main::graph_main;
+use gfa2bin::merge::merge_main::merge_main;
+use gfa2bin::nearest::nearest_main::nearest_main;
+use gfa2bin::remove::remove_main::remove_main;
+use gfa2bin::split::split_main::split_main;
+use gfa2bin::stats::stats_main::stats_main;
+use gfa2bin::subpath::subpath_main::subpath_main;
+use gfa2bin::view::view_main::view_main;
+use gfa2bin::window::window_main::window_main;
+use std::process;
+
+fn main() {
     let matches = App::new("gfa2bin")
         .version("0.1.0")
         .author("Sebastian V")
@@ -41,8 +29,6 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("verbose")
                 .about("verbose "),
         )
-
-
         .subcommand(
             App::new("graph")
                 .about("Convert GFA file (v1) to PLINK (bed, bim, fam). \n \
@@ -50,7 +36,6 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 .setting(AppSettings::ArgRequiredElseHelp)
 
-
                 .help_heading("Input options")
                 .arg(
                     Arg::new("gfa")
@@ -65,18 +50,46 @@ fn main() -> Result<(), Box<dyn Error>> {
                     Arg::new("feature")
                         .short('f')
                         .long("feature")
-                        .about("Specify the feature you want to count. Examples: node, dirnode, edge")
+                        .about("Specify the feature you want to count, comma separated for multiple features (one fileset each: prefix.feature). Examples: node, dirnode, edge, bubble, segment, node,edge")
                         .takes_value(true)
                         .default_value("node"),
                 )
+                .arg(
+                    Arg::new("combined")
+                        .long("combined")
+                        .about("Write all features (-f node,edge) into one fileset, the feature type is part of the variant identifier [default: off]"),
+                )
+                .arg(
+                    Arg::new("multiallelic")
+                        .long("multiallelic")
+                        .about("Write bubbles (-f bubble) as multiallelic VCF (prefix.vcf) or PLINK 2 (--plink2), one record per bubble instead of one biallelic record per allele [default: off]"),
+                )
+                .arg(
+                    Arg::new("min-bp")
+                        .long("min-bp")
+                        .about("Remove PAV segments (-f segment: consecutive nodes with the same genotypes) with less sequence (bp) [default: 0]")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::new("PanSN")
                         .display_order(1)
                         .long("pansn")
-                        .about("PanSN-spec separator")
+                        .about("PanSN-spec separator for paths (P-lines), walks (W-lines) always use their sample and haplotype")
                         .takes_value(true)
                         .default_value("\n")
                 )
+                .arg(
+                    Arg::new("reference")
+                        .long("reference")
+                        .about("Reference path or PanSN prefix (e.g. 'HG38#0#') for chromosome and position in the bim file [default: graph]")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("missing")
+                        .long("missing")
+                        .about("Absent features outside the region covered by a haplotype (first to last reference node) are missing")
+                        .requires("reference")
+                )
 
 
                 .help_heading("Absolute thresholds")
@@ -124,16 +137,88 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .about("Output prefix for all files")
                         .required(true),
                 )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam, bimbam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
                 .arg(Arg::new("pheno")
                     .long("pheno")
                          .about("Phenotype value")
                          .takes_value(true)
                 )
+                .arg(
+                    Arg::new("pheno-file")
+                        .long("pheno-file")
+                        .about("Phenotype table (sample name and one column per phenotype/covariate, tab, comma or semicolon separated)")
+                        .takes_value(true)
+                        .conflicts_with("pheno"),
+                )
+                .arg(
+                    Arg::new("covariates")
+                        .long("covariates")
+                        .about("Columns of the phenotype table which are covariates (comma separated)")
+                        .takes_value(true)
+                        .requires("pheno-file"),
+                )
+                .arg(
+                    Arg::new("sample-metadata")
+                        .long("sample-metadata")
+                        .about("Sample metadata for the fam file (TSV with header: FID, IID, PAT, MAT, SEX) [default: FID=IID=sample]")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::new("bimbam")
                         .long("bimbam")
                         .about("Output in BIMBAM format [default: off] -> PLINK"),
                 )
+                .arg(
+                    Arg::new("plink2")
+                        .long("plink2")
+                        .about("Output in PLINK 2 format (pgen, pvar, psam) with dosages [default: off] -> PLINK")
+                        .conflicts_with("bimbam"),
+                )
+                .arg(
+                    Arg::new("copy-number")
+                        .long("copy-number")
+                        .about("Output copy numbers (traversals of all haplotypes of a sample) instead of genotypes: BIMBAM, PLINK 2 dosages (max 2), dense TSV (prefix.cn.tsv) or NPY (prefix.cn.npy) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["bimbam", "plink2", "tsv", "npy"])
+                        .conflicts_with_all(&["bimbam", "plink2"]),
+                )
+                .arg(
+                    Arg::new("cn-cap")
+                        .long("cn-cap")
+                        .about("Maximum copy number, higher values are capped [default: off, PLINK 2: 2]")
+                        .takes_value(true)
+                        .requires("copy-number"),
+                )
+                .arg(
+                    Arg::new("cn-mode")
+                        .long("cn-mode")
+                        .about("Write the most common copy number of each feature (prefix.cn_mode.tsv) [default: off]")
+                        .requires("copy-number"),
+                )
+                .arg(
+                    Arg::new("collapse")
+                        .long("collapse")
+                        .about("PLINK genotype of polyploid samples (>2 haplotypes): het (some haplotypes -> heterozygous), any (at least one -> present), majority (more than half -> present)")
+                        .takes_value(true)
+                        .possible_values(&["het", "any", "majority"])
+                        .default_value("het"),
+                )
+                .arg(
+                    Arg::new("annotation")
+                        .long("annotation")
+                        .about("Write a feature annotation (prefix.annotation.tsv): length, GC content, number of paths and samples [default: off]"),
+                )
+                .arg(
+                    Arg::new("dedup")
+                        .long("dedup")
+                        .about("Write identical genotype patterns only once, the mapping of the representatives to all features is written to prefix.dedup.tsv [default: off]"),
+                )
                 .help_heading("Performance")
                 .arg(
                     Arg::new("threads")
@@ -184,6 +269,33 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .about("Index file is needed for compressed pack files. This includes 'pc-list' and 'pack compressed'")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::new("gfa")
+                        .short('g')
+                        .long("gfa")
+                        .about("Graph file (GFA), only needed for reference coordinates (--reference) and the feature annotation (--annotation)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("PanSN")
+                        .long("pansn")
+                        .about("PanSN-spec separator for paths (P-lines) of the graph, walks (W-lines) always use their sample and haplotype")
+                        .takes_value(true)
+                        .default_value("\n")
+                )
+                .arg(
+                    Arg::new("reference")
+                        .long("reference")
+                        .about("Reference path or PanSN prefix (e.g. 'HG38#0#') for chromosome and position in the bim file [default: graph]")
+                        .takes_value(true)
+                        .requires("gfa"),
+                )
+                .arg(
+                    Arg::new("missing-window")
+                        .long("missing-window")
+                        .about("Features are missing if a sample has no coverage in a window of this many features [default: off]")
+                        .takes_value(true)
+                )
 
 
                 .help_heading("Absolute thresholds")
@@ -239,15 +351,73 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam, bimbam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
                 .arg(Arg::new("pheno")
                     .long("pheno")
                     .about("Phenotype value")
                     .takes_value(true)
                 )
+                .arg(
+                    Arg::new("pheno-file")
+                        .long("pheno-file")
+                        .about("Phenotype table (sample name and one column per phenotype/covariate, tab, comma or semicolon separated)")
+                        .takes_value(true)
+                        .conflicts_with("pheno"),
+                )
+                .arg(
+                    Arg::new("covariates")
+                        .long("covariates")
+                        .about("Columns of the phenotype table which are covariates (comma separated)")
+                        .takes_value(true)
+                        .requires("pheno-file"),
+                )
+                .arg(
+                    Arg::new("sample-metadata")
+                        .long("sample-metadata")
+                        .about("Sample metadata for the fam file (TSV with header: FID, IID, PAT, MAT, SEX) [default: FID=IID=sample]")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::new("bimbam")
                         .long("bimbam")
                         .about("Output bimbam format [default: plink]"),
+                )
+                .arg(
+                    Arg::new("plink2")
+                        .long("plink2")
+                        .about("Output PLINK 2 format (pgen, pvar, psam) with dosages [default: plink]")
+                        .conflicts_with("bimbam"),
+                )
+                .arg(
+                    Arg::new("bgen")
+                        .long("bgen")
+                        .about("Output BGEN 1.2 format (bgen, sample) with dosages [default: plink]")
+                        .conflicts_with_all(&["bimbam", "plink2"]),
+                )
+                .arg(
+                    Arg::new("annotation")
+                        .long("annotation")
+                        .about("Write a feature annotation (prefix.annotation.tsv): length, GC content, number of paths and samples [default: off]")
+                        .requires("gfa"),
+                )
+                .arg(
+                    Arg::new("dedup")
+                        .long("dedup")
+                        .about("Write identical genotype patterns only once (PLINK), the mapping of the representatives to all features is written to prefix.dedup.tsv [default: off]"),
+                )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are transposed at once (PLINK output)")
+                        .takes_value(true)
+                        .default_value("10000")
                 ),
         )
 
@@ -271,7 +441,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     Arg::new("genotypes")
                         .short('g')
                         .long("genotypes")
-                        .about("List of genotypes to remove (one per line)")
+                        .about("List of genotypes to remove (one per line), matched with the variant id (second column of the bim file)")
                         .takes_value(true),
                 )
                 .arg(
@@ -302,6 +472,21 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read and written at once")
+                        .takes_value(true)
+                        .default_value("10000")
+                )
         )
         // Will work on this later
         .subcommand(
@@ -381,6 +566,26 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .about("Output prefix for the new plink file")
                         .takes_value(true)
                         .required(true),
+                )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
+                .arg(
+                    Arg::new("plink2")
+                        .long("plink2")
+                        .about("Output in PLINK 2 format (pgen, pvar, psam) with one multiallelic variant per window, each group of samples is one allele (<G0>, <G1>, ...) [default: off]"),
+                )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read and written at once")
+                        .takes_value(true)
+                        .default_value("10000")
                 ),
         )
         .subcommand(
@@ -398,10 +603,23 @@ fn main() -> Result<(), Box<dyn Error>> {
                 )
                 .arg(Arg::new("PanSN")
                     .long("pansn")
-                    .about("PanSN-spec separator")
+                    .about("PanSN-spec separator for paths (P-lines), walks (W-lines) always use their sample and haplotype")
                     .takes_value(true)
                     .default_value("\n")
                 )
+                .arg(
+                    Arg::new("reference")
+                        .long("reference")
+                        .about("Reference path or PanSN prefix (e.g. 'HG38#0#') for chromosome and position in the bim file [default: graph]")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("sample-metadata")
+                        .long("sample-metadata")
+                        .about("Sample metadata for the fam file (TSV with header: FID, IID, PAT, MAT, SEX) [default: FID=IID=sample]")
+                        .takes_value(true),
+                )
+
 
                 .help_heading("Subpath options")
                 .arg(
@@ -412,6 +630,14 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .default_value("5"),
                 )
+                .arg(
+                    Arg::new("collapse")
+                        .long("collapse")
+                        .about("Genotype of polyploid samples (>2 haplotypes): het (some haplotypes -> heterozygous), any (at least one -> present), majority (more than half -> present)")
+                        .takes_value(true)
+                        .possible_values(&["het", "any", "majority"])
+                        .default_value("het"),
+                )
                 .help_heading("Performance options")
                 .arg(
                     Arg::new("threads")
@@ -431,6 +657,22 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("annotation")
+                        .long("annotation")
+                        .about("Write a feature annotation (prefix.annotation.tsv): length, GC content, number of paths and samples, spanned bp [default: off]"),
+                )
+                .arg(
+                    Arg::new("dedup")
+                        .long("dedup")
+                        .about("Write identical genotype patterns only once, the mapping of the representatives to all subpaths is written to prefix.dedup.tsv [default: off]"),
+                )
+                .arg(
+                    Arg::new("plink2")
+                        .long("plink2")
+                        .about("Output in PLINK 2 format (pgen, pvar, psam) with one multiallelic variant per node, each group of traversals is one allele (<G0>, <G1>, ...) [default: off]")
+                        .conflicts_with("dedup"),
+                )
         )
 
 
@@ -440,7 +682,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         // This is fine
         .subcommand(
             App::new("view")
-                .about("Convert BED to VCF")
+                .about("Convert BED to VCF (v4.3)")
 
                 .help_heading("Input options")
                 .arg(
@@ -451,22 +693,97 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("gfa")
+                        .short('g')
+                        .long("gfa")
+                        .about("Graph file (GFA) to build the contig header lines and get the ploidy of the samples [default: from bim, diploid]")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("PanSN")
+                        .long("pansn")
+                        .about("PanSN-spec separator for paths (P-lines) of the graph, walks (W-lines) always use their sample and haplotype")
+                        .takes_value(true)
+                        .default_value("\n")
+                )
 
                 .help_heading("Output options")
                 .arg(
                     Arg::new("output")
                         .short('o')
                         .long("output")
-                        .about("Output prefix for the new plink file")
+                        .about("Output VCF file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (VCF) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
+                .arg(
+                    Arg::new("bgzip")
+                        .long("bgzip")
+                        .about("Compress the output with bgzip (same as --compress bgzip)")
+                        .conflicts_with("compress"),
+                )
+                .arg(
+                    Arg::new("index")
+                        .long("index")
+                        .about("Index the bgzip compressed output (tbi or csi)")
+                        .takes_value(true)
+                        .possible_values(&["tbi", "csi"]),
+                )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read and written at once")
+                        .takes_value(true)
+                        .default_value("10000")
+                ),
+        )
+
+
+        .subcommand(
+            App::new("stats")
+                .about("Summary statistics of a PLINK file (allele frequency, call rate, heterozygosity, missingness)")
+
+                .help_heading("Input options")
+                .arg(
+                    Arg::new("plink")
+                        .short('p')
+                        .long("plink")
+                        .about("PLINK input file")
+                        .takes_value(true)
+                        .required(true),
+                )
+
+                .help_heading("Output options")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .about("Output prefix (variants.tsv, samples.tsv, summary.json)")
                         .takes_value(true)
                         .required(true),
+                )
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read at once")
+                        .takes_value(true)
+                        .default_value("10000")
                 ),
         )
 
 
         .subcommand(
             App::new("filter")
-                .about("Filter a PLINK file. (1) 'SNPs' by allele frequency or (2) path/samples by matrix coverage")
+                .about("Filter a PLINK file. (1) 'SNPs' by allele frequency or (2) path/samples by missing genotypes")
 
                 .help_heading("Input options")
                 .arg(
@@ -515,14 +832,14 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .arg(
                     Arg::new("missing-rate")
                         .long("missing-rate")
-                        .about("Filter sample which have less relative genotypes than this value. Value between 0.0 and 1.0")
+                        .about("Filter samples which have more missing genotypes (relative) than this value. Value between 0.0 and 1.0")
                         .takes_value(true)
                         .default_value("0.2"),
                 )
                 .arg(
                     Arg::new("missing-count")
                         .long("missing-count")
-                        .about("Filter sample which have less genotypes than this value")
+                        .about("Filter samples which have more missing genotypes than this value")
                         .takes_value(true)
                 )
 
@@ -535,6 +852,21 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read and written at once")
+                        .takes_value(true)
+                        .default_value("10000")
+                )
         )
 
 
@@ -551,6 +883,12 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .required(true)
 
                 )
+                .arg(
+                    Arg::new("sample-metadata")
+                        .long("sample-metadata")
+                        .about("Sample metadata for the fam file (TSV with header: FID, IID, PAT, MAT, SEX) [default: FID=IID=sample]")
+                        .takes_value(true),
+                )
 
                 .help_heading("Output options")
                 .arg(
@@ -561,6 +899,14 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .takes_value(true)
                         .required(true),
                 )
+                .help_heading("Performance")
+                .arg(
+                    Arg::new("block-size")
+                        .long("block-size")
+                        .about("Number of variants which are read and written at once")
+                        .takes_value(true)
+                        .default_value("10000")
+                )
         )
 
         .subcommand(
@@ -660,6 +1006,75 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .default_value("1")
                 )
         )
+        .subcommand(
+            App::new("dedup")
+                .about("Keep one genotype per unique genotype pattern (mapping: prefix.dedup.tsv)")
+                .help_heading("Input options")
+                .arg(
+                    Arg::new("plink")
+                        .short('p')
+                        .long("plink")
+                        .about("Plink input file (prefix)")
+                        .takes_value(true)
+                        .required(true),
+                )
+
+                .help_heading("Output options")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .about("Output prefix for the new plink file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .about("Compress the plain-text output (bim, fam) [default: off]")
+                        .takes_value(true)
+                        .possible_values(&["gzip", "bgzip", "zstd"]),
+                )
+        )
+
+        .subcommand(
+            App::new("expand")
+                .about("Expand association results of deduplicated genotypes to all features")
+                .help_heading("Input options")
+                .arg(
+                    Arg::new("results")
+                        .short('r')
+                        .long("results")
+                        .about("Association results with header (e.g. PLINK, GEMMA)")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("mapping")
+                        .short('m')
+                        .long("mapping")
+                        .about("Deduplication mapping (prefix.dedup.tsv)")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("column")
+                        .short('c')
+                        .long("column")
+                        .about("Name of the variant ID column [default: ID, SNP or rs]")
+                        .takes_value(true),
+                )
+
+                .help_heading("Output options")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .about("Output file (tab-separated)")
+                        .takes_value(true)
+                        .required(true),
+                )
+        )
         .get_matches();
 
     //cargo run -- -g /home/svorbrugg_local/Rust/data/AAA_AAB.cat.gfa
@@ -667,7 +1082,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Checking verbose
     newbuilder(&matches);
 
-    if let Some(matches) = matches.subcommand_matches("graph") {
+    let result = if let Some(matches) = matches.subcommand_matches("graph") {
         graph_main(matches)
     } else if let Some(matches) = matches.subcommand_matches("cov") {
         cov_main(matches)
@@ -689,8 +1104,20 @@ fn main() -> Result<(), Box<dyn Error>> {
         split_main(matches)
     } else if let Some(matches) = matches.subcommand_matches("nearest") {
         nearest_main(matches)
+    } else if let Some(matches) = matches.subcommand_matches("stats") {
+        stats_main(matches)
+    } else if let Some(matches) = matches.subcommand_matches("dedup") {
+        dedup_main(matches)
+    } else if let Some(matches) = matches.subcommand_matches("expand") {
+        expand_main(matches)
     } else {
         println!("No subcommand was used");
         Ok(())
+    };
+
+    // One-line message, exit code depends on the error type
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(e.exit_code());
     }
 }
@@ -1,58 +1,113 @@
+use crate::core::bed::{BedReader, BedWriter};
+use crate::core::bfile::{count_lines, read_fam};
+use crate::core::compression::{open_reader, read_to_string};
+use crate::core::core::MatrixWrapper;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::metadata::SampleMetadata;
 use crate::remove::remove_main::copy_file;
 
-
-
 use clap::ArgMatches;
 use log::info;
 use std::fs;
-use std::fs::File;
-use std::io::{self, BufRead, Read, Write};
+use std::io::{self, BufRead, BufWriter, Write};
 
 /// # Merge main
 ///
 /// Merge multiple PLINK files togther. This includes BED, BIM and FAM files
 ///
 /// Comment: Fam files are only checked if they contain the same content, bim files are simply concatenated, and BED files are trimmed ([3:]) and concatenated
-pub fn merge_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin merge'");
-
-    let plink_list = matches.value_of("bed-list").unwrap();
-    let out_file = matches.value_of("output").unwrap();
-
-    info!("BED file list: {}", plink_list);
-    info!("Output prefix: {}\n", out_file);
-
-    let input_list = read_list(plink_list)?;
-    let names = clear_names(input_list)?;
+pub fn merge_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    MergeConfig::from_matches(matches)?.run()
+}
 
-    let fams = check_fams(&names)?;
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin merge'
+pub struct MergeConfig {
+    /// List of PLINK prefixes (or bed files), one per line
+    pub plink_list: String,
+    pub output_prefix: String,
+    pub block_size: usize,
+    /// Sample metadata (FID, IID, father, mother, sex)
+    pub metadata: Option<String>,
+}
 
-    if !fams {
-        panic!("Fam files are not the same");
+impl MergeConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_list: matches.value_of("bed-list").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            block_size: parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?,
+            metadata: matches.value_of("sample-metadata").map(|x| x.to_string()),
+        })
     }
 
-    info!("Merging FAM files");
-    copy_file(
-        &format!("{}{}", names[0], ".fam"),
-        &format!("{}{}", out_file, ".fam"),
-    )?;
+    /// Merge the PLINK files
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin merge'");
+        let plink_list = self.plink_list.as_str();
+        let out_file = self.output_prefix.as_str();
+        let block_size = self.block_size;
+
+        info!("BED file list: {}", plink_list);
+        info!("Block size: {}", block_size);
+        info!(
+            "Sample metadata: {}",
+            self.metadata.as_deref().unwrap_or("None")
+        );
+        info!("Output prefix: {}\n", out_file);
+
+        let metadata = match &self.metadata {
+            Some(metadata) => Some(SampleMetadata::from_file(metadata)?),
+            None => None,
+        };
+
+        let input_list = read_list(plink_list)?;
+        let names = clear_names(input_list)?;
+
+        let fams = check_fams(&names)?;
+
+        if !fams {
+            return Err(Gfa2binError::Consistency(
+                "Fam files are not the same".to_string(),
+            ));
+        }
+
+        info!("Merging FAM files");
+        if let Some(metadata) = metadata {
+            // Phenotypes are kept, everything else is taken from the metadata
+            let mut mw = MatrixWrapper::new();
+            mw.fam_entries = read_fam(&format!("{}{}", names[0], ".fam"))?;
+            mw.sample_names = mw
+                .fam_entries
+                .iter()
+                .map(|x| x.individual_id.clone())
+                .collect();
+            mw.apply_metadata(&metadata);
+            mw.write_fam(0, out_file, mw.feature, 1, f64::MAX)?;
+        } else {
+            copy_file(
+                &format!("{}{}", names[0], ".fam"),
+                &format!("{}{}", out_file, ".fam"),
+            )?;
+        }
 
-    info!("Merge BIM files");
-    merge_bim(&names, &(out_file.to_string() + ".bim"))?;
+        info!("Merge BIM files");
+        merge_bim(&names, &(out_file.to_string() + ".bim"))?;
 
-    info!("Merge BED files");
-    merge_bed(&names, &(out_file.to_string() + ".bed"))?;
+        info!("Merge BED files");
+        merge_bed(&names, &(out_file.to_string() + ".bed"), block_size)?;
 
-    Ok(())
+        Ok(())
+    }
 }
 
 /// # Read a file (line by line
 ///
 /// Each line one path
-pub fn read_list(file: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn read_list(file: &str) -> Result<Vec<String>, Gfa2binError> {
     let mut list: Vec<String> = Vec::new();
-    let file = fs::File::open(file).expect(format!("Could not open file {}", file).as_str());
-    let reader = io::BufReader::new(file);
+    let reader = open_reader(file)?;
     for line in reader.lines() {
         list.push(line?);
     }
@@ -62,7 +117,7 @@ pub fn read_list(file: &str) -> Result<Vec<String>, Box<dyn std::error::Error>>
 /// # Remove suffix from the string
 ///
 /// Comment: If bed files are the input, remove those to get the prefix name
-pub fn clear_names(names: Vec<String>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn clear_names(names: Vec<String>) -> Result<Vec<String>, Gfa2binError> {
     let mut new_names: Vec<String> = Vec::new();
     for x in names.iter() {
         if x.ends_with(".bed") {
@@ -74,17 +129,16 @@ pub fn clear_names(names: Vec<String>) -> Result<Vec<String>, Box<dyn std::error
 }
 
 /// # FAM file checker
-pub fn check_fams(fams: &Vec<String>) -> Result<bool, Box<dyn std::error::Error>> {
+pub fn check_fams(fams: &[String]) -> Result<bool, Gfa2binError> {
     if fams.is_empty() {
         return Ok(false);
     }
     let mut fam_content_first = "".to_string();
     if !fams.is_empty() {
-        fam_content_first += &fs::read_to_string(fams[0].to_string() + ".fam")
-            .unwrap_or_else(|_| panic!("Could not read FAM file {}", fams[0].to_string() + ".fam"));
+        fam_content_first += &read_to_string(&(fams[0].to_string() + ".fam"))?;
     }
     for fam_entry in fams.iter().skip(1) {
-        let fam_content = fs::read_to_string(fam_entry.to_string() + ".fam")?;
+        let fam_content = read_to_string(&(fam_entry.to_string() + ".fam"))?;
         if fam_content_first != fam_content {
             return Ok(false);
         }
@@ -97,16 +151,18 @@ pub fn check_fams(fams: &Vec<String>) -> Result<bool, Box<dyn std::error::Error>
 /// Here - Merge bim (PLINK) file
 pub fn merge_bim(fams: &Vec<String>, output_file: &str) -> io::Result<()> {
     // Create or truncate the output file
-    let mut output = fs::File::create(output_file).expect("Failed to create output file");
+    let output = fs::File::create(output_file)?;
+    let mut output = BufWriter::new(output);
 
     // Read each file and write its content to the output file
     for file in fams {
-        // Read the content of the current file
-        let content = fs::read_to_string(file.to_string() + ".bim")?;
-
-        // Write the content to the output file
-        writeln!(output, "{}", content)?;
+        // Read the current file line by line
+        let reader = open_reader(&(file.to_string() + ".bim"))?;
+        for line in reader.lines() {
+            writeln!(output, "{}", line?)?;
+        }
     }
+    output.flush()?;
     info!("Files have been concatenated into {}", output_file);
 
     Ok(())
@@ -114,31 +170,31 @@ pub fn merge_bim(fams: &Vec<String>, output_file: &str) -> io::Result<()> {
 
 /// # Merge BED files
 ///
-/// Start with the first entry as a base, then add the others as well. Adding the rest is done bz removing the three header bytes.
-pub fn merge_bed(files: &Vec<String>, output_file: &str) -> io::Result<()> {
-    // Open the output file
-    let mut output = File::create(output_file).expect("Failed to create output file");
+/// Stream all bed files (in blocks of variants) into a single output file. Header is written once.
+pub fn merge_bed(
+    files: &[String],
+    output_file: &str,
+    block_size: usize,
+) -> Result<(), Gfa2binError> {
+    let mut writer = BedWriter::new(output_file)?;
 
-    // Copy the first file as-is
     if let Some(first_file) = files.first() {
-        let mut input = File::open(first_file.to_string() + ".bed")?;
-        io::copy(&mut input, &mut output)?;
-    }
-
-    // Process and append the remaining files
-    for file in files.iter().skip(1) {
-        let mut input = File::open(file.to_string() + ".bed")?;
-        let mut buffer = Vec::new();
-        input.read_to_end(&mut buffer)?;
-
-        // Skip the first 3 bytes
-        if buffer.len() > 3 {
-            let remaining_bytes = &buffer[3..];
-            output
-                .write_all(remaining_bytes)
-                .expect("Failed to write to output file");
+        // Same samples in all files
+        let samples_number = count_lines(&(first_file.to_string() + ".fam"))?;
+        for file in files.iter() {
+            let snp_number = count_lines(&(file.to_string() + ".bim"))?;
+            let reader = BedReader::new(
+                &(file.to_string() + ".bed"),
+                samples_number,
+                snp_number,
+                block_size,
+            )?;
+            for block in reader {
+                writer.write_matrix(&block?)?;
+            }
         }
     }
+    writer.finish()?;
 
     Ok(())
 }
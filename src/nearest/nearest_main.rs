@@ -1,105 +1,114 @@
+use crate::core::bfile::ReferencePositions;
+use crate::core::compression::{open_reader, PlainFile};
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::graph::samples::{walks_to_paths, PathOrigin};
 use clap::ArgMatches;
 use gfa_reader::Gfa;
 use hashbrown::{HashMap, HashSet};
 use log::info;
 use std::fs::File;
-
-use std::i64;
 use std::io::{self, BufWriter};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, Write};
 use std::str::FromStr;
 
 /// Nearest node main function
 ///
 /// Find the closest reference node for each node in the graph
-pub fn nearest_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin nearest'");
+pub fn nearest_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    NearestConfig::from_matches(matches)?.run()
+}
 
-    // Graph file
-    let graph_file = matches.value_of("gfa").unwrap();
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin nearest'
+///
+/// Reference paths are either a list (references) or all paths with a prefix
+pub struct NearestConfig {
+    pub graph_file: String,
+    pub output: String,
+    /// Requested nodes (None: all nodes)
+    pub nodes: Option<String>,
+    pub references: Option<String>,
+    pub prefix: Option<String>,
+    pub threads: usize,
+}
 
-    // Output file
-    let output_file = matches.value_of("output").unwrap();
+impl NearestConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            graph_file: matches.value_of("gfa").unwrap().to_string(),
+            output: matches.value_of("output").unwrap().to_string(),
+            nodes: matches.value_of("nodes").map(|x| x.to_string()),
+            references: matches.value_of("references").map(|x| x.to_string()),
+            prefix: matches.value_of("prefix").map(|x| x.to_string()),
+            threads: parse_arg(matches.value_of("threads").unwrap(), "threads")?,
+        })
+    }
 
-    let threads = matches.value_of("threads").unwrap();
+    /// Find the closest reference node for each node
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin nearest'");
+        let graph_file = self.graph_file.as_str();
+        let output_file = self.output.as_str();
 
-    info!("GFA file: {}", graph_file);
-    info!(
-        "Node file: {}",
-        if matches.is_present("nodes") {
-            matches.value_of("nodes").unwrap()
-        } else {
-            "All nodes"
-        }
-    );
-    info!(
-        "Reference file: {}",
-        if matches.is_present("references") {
-            matches.value_of("references").unwrap()
-        } else {
-            "None"
-        }
-    );
-    info!(
-        "Prefix: {}",
-        if matches.is_present("prefix") {
-            matches.value_of("prefix").unwrap()
-        } else {
-            "None"
-        }
-    );
-    info!("Threads: {}", threads);
-    info!("Output file: {}\n", output_file);
+        info!("GFA file: {}", graph_file);
+        info!(
+            "Node file: {}",
+            self.nodes.as_deref().unwrap_or("All nodes")
+        );
+        info!(
+            "Reference file: {}",
+            self.references.as_deref().unwrap_or("None")
+        );
+        info!("Prefix: {}", self.prefix.as_deref().unwrap_or("None"));
+        info!("Threads: {}", self.threads);
+        info!("Output file: {}\n", output_file);
 
-    info!("Read GFA file");
-    let mut graph = Gfa::parse_gfa_file_multi(graph_file, threads.parse().unwrap());
+        info!("Read GFA file");
+        let graph_input = PlainFile::new(graph_file)?;
+        let mut graph = Gfa::parse_gfa_file_multi(graph_input.path(), self.threads);
 
-    info!("Convert walks to path with '#' separator");
-    graph.walk_to_path("#");
+        info!("Convert walks to path (sample#haplotype#sequence)");
+        walks_to_paths(&mut graph, None);
 
-    // Requested nodes
-    let requested_nodes: Vec<u32>;
-    if matches.is_present("nodes") {
-        info!(
-            "Reading requested nodes from {}",
-            matches.value_of("nodes").unwrap()
-        );
-        requested_nodes = read_input(matches.value_of("nodes").unwrap()).unwrap();
-    } else {
-        info!("No nodes provided, all nodes will be considered");
-        requested_nodes = graph.segments.iter().map(|x| x.id).collect();
-    }
+        // Requested nodes
+        let requested_nodes: Vec<u32> = if let Some(nodes) = &self.nodes {
+            info!("Reading requested nodes from {}", nodes);
+            read_input(nodes)?
+        } else {
+            info!("No nodes provided, all nodes will be considered");
+            graph.segments.iter().map(|x| x.id).collect()
+        };
 
-    // Which path are "reference" paths
-    let mut ref_list = Vec::new();
-    if matches.is_present("references") {
-        ref_list = read_input(matches.value_of("references").unwrap())?;
-    } else if matches.is_present("prefix") {
-        ref_list = by_prefix(matches.value_of("prefix").unwrap(), &graph)?;
-    } else {
-        panic!("You need to provide either a reference list or a prefix")
-    }
+        // Which path are "reference" paths
+        let ref_list = if let Some(references) = &self.references {
+            read_input(references)?
+        } else if let Some(prefix) = &self.prefix {
+            by_prefix(prefix, &graph)?
+        } else {
+            return Err(Gfa2binError::Argument(
+                "You need to provide either a reference list or a prefix".to_string(),
+            ));
+        };
 
-    info!("Finding closest reference node for each node");
-    let closest_node_vec = read_nodes(
-        &graph,
-        &ref_list,
-        &requested_nodes.iter().cloned().collect::<HashSet<u32>>(),
-    );
+        info!("Finding closest reference node for each node");
+        let closest_node_vec = read_nodes(
+            &graph,
+            &ref_list,
+            &requested_nodes.iter().cloned().collect::<HashSet<u32>>(),
+        );
 
-    info!("Writing output to {}", output_file);
-    write_file(closest_node_vec, output_file, &graph, &ref_list).unwrap();
+        info!("Writing output to {}", output_file);
+        write_file(closest_node_vec, output_file, &graph, &ref_list)?;
 
-    Ok(())
+        Ok(())
+    }
 }
 
 /// # Extract reference paths by prefix
 ///
 /// - .startswith(prefix) on all path
-pub fn by_prefix(
-    prefix: &str,
-    graph: &Gfa<u32, (), ()>,
-) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn by_prefix(prefix: &str, graph: &Gfa<u32, (), ()>) -> Result<Vec<String>, Gfa2binError> {
     let reference_paths = graph
         .paths
         .iter()
@@ -107,9 +116,9 @@ pub fn by_prefix(
         .filter(|x| x.starts_with(prefix))
         .collect::<Vec<_>>();
     if reference_paths.is_empty() {
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "No reference paths found",
+        Err(Gfa2binError::Consistency(format!(
+            "No reference paths found (prefix: {})",
+            prefix
         )))
     } else {
         info!("{:?}", reference_paths);
@@ -117,12 +126,84 @@ pub fn by_prefix(
     }
 }
 
+/// # Select reference paths by name or PanSN prefix
+///
+/// Returns the paths and the prefix which is removed from the chromosome name
+pub fn reference_paths(
+    reference: &str,
+    graph: &Gfa<u32, (), ()>,
+) -> Result<(Vec<String>, String), Gfa2binError> {
+    if graph.paths.iter().any(|x| x.name == reference) {
+        Ok((vec![reference.to_string()], String::new()))
+    } else {
+        Ok((by_prefix(reference, graph)?, reference.to_string()))
+    }
+}
+
+/// # Reference coordinates for all nodes
+///
+/// - Nodes on the reference: first position in the reference path
+/// - Nodes off the reference: position of the closest reference node (see read_nodes)
+/// - Walks (W-lines): chromosome is the sequence name, positions start at the walk start
+pub fn reference_positions(
+    reference: &str,
+    graph: &Gfa<u32, (), ()>,
+    origins: &[PathOrigin],
+) -> Result<ReferencePositions, Gfa2binError> {
+    let (names, prefix) = reference_paths(reference, graph)?;
+
+    let mut result = ReferencePositions::default();
+    for (path, origin) in graph.paths.iter().zip(origins.iter()) {
+        if !names.contains(&path.name) {
+            continue;
+        }
+        let chromosome = if origin.walk {
+            origin.sequence.clone()
+        } else {
+            path.name
+                .strip_prefix(prefix.as_str())
+                .unwrap_or(&path.name)
+                .to_string()
+        };
+        // Walks of the same sequence share the chromosome
+        let index = match result.chromosomes.iter().position(|x| *x == chromosome) {
+            Some(index) => index,
+            None => {
+                result.chromosomes.push(chromosome);
+                result.chromosomes.len() - 1
+            }
+        };
+        let mut position = origin.start;
+        for node in path.nodes.iter() {
+            result
+                .positions
+                .entry(*node)
+                .or_insert((index, position + 1, true));
+            position += graph.get_sequence_by_id(node).len() as u64;
+        }
+    }
+
+    let all_nodes = graph
+        .segments
+        .iter()
+        .map(|x| x.id)
+        .collect::<HashSet<u32>>();
+    for (node, ref_node, distance) in read_nodes(graph, &names, &all_nodes) {
+        if distance == i64::MAX || result.positions.contains_key(&node) {
+            continue;
+        }
+        if let Some((chromosome, position, _)) = result.positions.get(&(ref_node as u32)).cloned() {
+            result.positions.insert(node, (chromosome, position, false));
+        }
+    }
+    Ok(result)
+}
+
 /// Read input by line
 ///
 /// Read input by line and return a vector of the type T
 pub fn read_input<T: FromStr>(input: &str) -> Result<Vec<T>, io::Error> {
-    let file = File::open(input).unwrap_or_else(|_| panic!("Can not open file: {}", input));
-    let reader = BufReader::new(file);
+    let reader = open_reader(input)?;
 
     let mut lines_vec: Vec<T> = Vec::new();
 
@@ -152,7 +233,7 @@ pub fn pos(graph: &Gfa<u32, (), ()>, name: String) -> Vec<(u32, usize)> {
                 bb.push((*node, pos1));
                 pos1 += graph.get_sequence_by_id(node).len();
             }
-            bb.sort_by(|a, b| a.0.cmp(&b.0));
+            bb.sort_by_key(|a| a.0);
             return bb;
         }
     }
@@ -163,7 +244,7 @@ pub fn pos(graph: &Gfa<u32, (), ()>, name: String) -> Vec<(u32, usize)> {
 ///
 /// - All nodes in reference paths
 /// - Collection is a hashset for better testing
-pub fn get_ref_nodes(graph: &Gfa<u32, (), ()>, names: &Vec<String>) -> HashSet<u32> {
+pub fn get_ref_nodes(graph: &Gfa<u32, (), ()>, names: &[String]) -> HashSet<u32> {
     let mut nodes_hashset = HashSet::new();
     for path in graph.paths.iter() {
         if names.contains(&path.name) {
@@ -195,7 +276,7 @@ pub fn init_hm(graph: &Gfa<u32, (), ()>, checked_nodes: &HashSet<u32>) -> HashMa
 /// Return a vector [(node, ref_node, distance)]
 pub fn read_nodes(
     graph: &Gfa<u32, (), ()>,
-    names: &Vec<String>,
+    names: &[String],
     checked_nodes: &HashSet<u32>,
 ) -> Vec<(u32, i64, i64)> {
     let mut distance = 0;
@@ -245,7 +326,7 @@ pub fn read_nodes(
         .iter()
         .map(|(k, v)| (*k, v[0], v[1]))
         .collect::<Vec<_>>();
-    result_vec.sort_by(|a, b| a.1.cmp(&b.1));
+    result_vec.sort_by_key(|a| a.1);
     result_vec
 }
 
@@ -256,10 +337,10 @@ pub fn write_file(
     result: Vec<(u32, i64, i64)>,
     output: &str,
     graph: &Gfa<u32, (), ()>,
-    names: &Vec<String>,
+    names: &[String],
 ) -> Result<(), std::io::Error> {
     // Create file
-    let file_out = File::create(output).expect("Unable to create file");
+    let file_out = File::create(output)?;
     let mut output_reader = BufWriter::new(file_out);
     writeln!(output_reader, "node\tref_node\tdistance\tposition\tpath")?;
 
@@ -286,16 +367,12 @@ pub fn write_file(
                 }
 
                 // Directly generate combinations of indices for the matching value
-                for index1 in start_i..i {
-                    for index2 in start_j..j {
+                for entry in &result[start_i..i] {
+                    for reference_pos in &pos1[start_j..j] {
                         writeln!(
                             output_reader,
                             "{}\t{}\t{}\t{}\t{}",
-                            result[index1].0,
-                            result[index1].1,
-                            result[index1].2,
-                            pos1[index2].1,
-                            reference_name
+                            entry.0, entry.1, entry.2, reference_pos.1, reference_name
                         )?;
                     }
                 }
@@ -1,4 +1,7 @@
+use crate::core::bed::{transpose_bed, BED_MODE_SAMPLE_MAJOR};
 use crate::core::bfile::count_lines;
+use crate::core::compression::PlainFile;
+use crate::core::error::{parse_arg, Gfa2binError};
 
 use crate::remove::remove_main::copy_file;
 
@@ -7,7 +10,7 @@ use log::info;
 
 use rayon::iter::*;
 use rayon::ThreadPoolBuilder;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead, Read, Seek, Write};
 use std::io::{BufReader, BufWriter};
 
@@ -15,61 +18,72 @@ use std::io::{BufReader, BufWriter};
 ///
 /// Reading a ped file return "genotypes" which reflect windows over the entries
 /// We assume that the entries that in variation graphs we have some kind of pan-genomic order in the order of the entries which reflect haplotypes
-pub fn split_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin split'");
-
-    let plink_file = matches.value_of("plink").unwrap();
-    let out_file = matches.value_of("output").unwrap();
-    let number_splits = matches
-        .value_of("splits")
-        .unwrap()
-        .parse::<usize>()
-        .expect("Error parsing splits");
-    let threads = matches
-        .value_of("threads")
-        .unwrap()
-        .parse::<usize>()
-        .expect("Error parsing threads");
-
-    info!("Splitting file: {}", plink_file);
-    info!("Number of splits: {}", number_splits);
-    info!("Output prefix: {}\n", out_file);
-
-    let lines = count_lines(&format!("{}.bim", plink_file))?;
-    let fam_lines = count_lines(&format!("{}.fam", plink_file))?;
-    info!("Number of samples: {}", fam_lines);
-    info!("Number of variants: {}", lines);
-
-    info!(
-        "Splitting PLINK BIM file: {}",
-        format!("{}.bim", plink_file)
-    );
-    split_file(
-        &format!("{}.bim", plink_file),
-        out_file,
-        number_splits,
-        "bim",
-        threads,
-    )?;
-    info!(
-        "Splitting PLINK FAM file: {}",
-        format!("{}.fam", plink_file)
-    );
-    split_fam(&format!("{}.fam", plink_file), number_splits, out_file)
-        .expect("Error splitting FAM file");
-    info!(
-        "Splitting PLINK BED file: {}",
-        format!("{}.bed", plink_file)
-    );
-    split_bed(
-        &format!("{}.bed", plink_file),
-        number_splits,
-        fam_lines,
-        lines,
-        out_file,
-    )?;
-    info!("Done");
-    Ok(())
+pub fn split_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    SplitConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin split'
+pub struct SplitConfig {
+    pub plink_file: String,
+    pub output_prefix: String,
+    /// Number of output files
+    pub splits: usize,
+    pub threads: usize,
+}
+
+impl SplitConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            splits: parse_arg::<usize>(matches.value_of("splits").unwrap(), "splits")?,
+            threads: parse_arg::<usize>(matches.value_of("threads").unwrap(), "threads")?,
+        })
+    }
+
+    /// Split the PLINK files
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin split'");
+        let plink_file = self.plink_file.as_str();
+        let out_file = self.output_prefix.as_str();
+        let number_splits = self.splits;
+        let threads = self.threads;
+
+        info!("Splitting file: {}", plink_file);
+        info!("Number of splits: {}", number_splits);
+        info!("Output prefix: {}\n", out_file);
+
+        let lines = count_lines(&format!("{}.bim", plink_file))?;
+        let fam_lines = count_lines(&format!("{}.fam", plink_file))?;
+        info!("Number of samples: {}", fam_lines);
+        info!("Number of variants: {}", lines);
+
+        info!("Splitting PLINK BIM file: {}.bim", plink_file);
+        // Split by byte positions, therefore compressed files are decompressed first
+        let bim_file = PlainFile::new(&format!("{}.bim", plink_file))?;
+        split_file(bim_file.path(), out_file, number_splits, "bim", threads)?;
+        info!("Splitting PLINK FAM file: {}.fam", plink_file);
+        split_fam(&format!("{}.fam", plink_file), number_splits, out_file)?;
+        info!("Splitting PLINK BED file: {}.bed", plink_file);
+        // Split by byte positions, therefore individual-major files are transposed first
+        let mut bed_file = format!("{}.bed", plink_file);
+        let mut header = [0; 3];
+        File::open(&bed_file)?.read_exact(&mut header)?;
+        if header[2] == BED_MODE_SAMPLE_MAJOR {
+            info!("Transposing the individual-major bed file");
+            let transposed = format!("{}.bed.tmp", out_file);
+            transpose_bed(&bed_file, &transposed, fam_lines, lines, 10000)?;
+            bed_file = transposed;
+        }
+        split_bed(&bed_file, number_splits, fam_lines, lines, out_file)?;
+        if header[2] == BED_MODE_SAMPLE_MAJOR {
+            fs::remove_file(&bed_file)?;
+        }
+        info!("Done");
+        Ok(())
+    }
 }
 
 /// # Split a plain-text file
@@ -83,40 +97,39 @@ fn split_file(
     output_suffix: &str,
     threads: usize,
 ) -> io::Result<()> {
-    let index = index_file(filename, splits);
+    let index = index_file(filename, splits)?;
 
     // rayon number of threads pool
     let pool = ThreadPoolBuilder::new()
         .num_threads(threads) // Limit to 4 threads
         .build()
-        .unwrap();
+        .map_err(io::Error::other)?;
 
     pool.install(|| {
-        index.par_iter().enumerate().for_each(|(i, x)| {
-            let file_name = format!("{}.{}.{}", output_prefix, i + 1, output_suffix);
-            let mut output_file =
-                BufWriter::new(File::create(file_name).expect("Error creating file"));
-
-            let input_file = File::open(filename).expect("Error opening file");
-            let mut buffer_input = BufReader::new(input_file);
-            // Seek to the correct position in the input file
-            buffer_input
-                .seek(std::io::SeekFrom::Start(x[0] as u64))
-                .expect("Error seeking file");
-
-            let mut pos = x[0];
-            for line in buffer_input.lines() {
-                let l = line.unwrap();
-                pos += l.len() + 1;
-                if pos > x[1] {
-                    break;
+        index
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, x)| -> io::Result<()> {
+                let file_name = format!("{}.{}.{}", output_prefix, i + 1, output_suffix);
+                let mut output_file = BufWriter::new(File::create(file_name)?);
+
+                let input_file = File::open(filename)?;
+                let mut buffer_input = BufReader::new(input_file);
+                // Seek to the correct position in the input file
+                buffer_input.seek(std::io::SeekFrom::Start(x[0] as u64))?;
+
+                let mut pos = x[0];
+                for line in buffer_input.lines() {
+                    let l = line?;
+                    pos += l.len() + 1;
+                    if pos > x[1] {
+                        break;
+                    }
+                    writeln!(output_file, "{}", l)?;
                 }
-                writeln!(output_file, "{}", l).expect("Error writing to file");
-            }
-        });
-    });
-
-    Ok(())
+                output_file.flush()
+            })
+    })
 }
 
 /// # Split plink fam file
@@ -157,20 +170,14 @@ fn split_bed(
     let sample_size = (sample_size as f64 / 4_f64).ceil() as usize;
     let mut bytes_per_file = sample_size * (var_size as f64 / n as f64).ceil() as usize;
     let mut start = 0;
-    let total_len = File::open(filename)
-        .expect("Not able to open file")
-        .metadata()
-        .expect("Metadata now working")
-        .len()
-        - 3;
+    let total_len = input_file.metadata()?.len() - 3;
     // Read from the input file and write to the output files
-    for x in 0..n {
+    for output_file in output_files.iter_mut() {
         if (total_len - start) < bytes_per_file as u64 {
             bytes_per_file = (total_len - start) as usize;
         }
         let mut buffer = vec![0; bytes_per_file];
         input_file.read_exact(&mut buffer)?;
-        let mut output_file = &output_files[x];
         output_file.write_all(&buffer)?;
         start += bytes_per_file as u64;
     }
@@ -182,14 +189,14 @@ fn split_bed(
 ///
 /// - By lines
 /// - In Bytes
-pub fn index_file(file: &str, number: usize) -> Vec<[usize; 2]> {
-    let file = File::open(file).expect("Error opening file");
+pub fn index_file(file: &str, number: usize) -> Result<Vec<[usize; 2]>, io::Error> {
+    let file = File::open(file)?;
     let buffreader = BufReader::new(file);
 
     let mut result = vec![0];
     let mut pos = 0;
     for line in buffreader.lines() {
-        pos += line.unwrap().len() + 1;
+        pos += line?.len() + 1;
         result.push(pos);
     }
     let step_size = result.len() / number;
@@ -198,7 +205,7 @@ pub fn index_file(file: &str, number: usize) -> Vec<[usize; 2]> {
         .map(|x| result[x * step_size])
         .collect::<Vec<usize>>();
 
-    (1..oo.len())
+    Ok((1..oo.len())
         .map(|x| [oo[x - 1], oo[x]])
-        .collect::<Vec<[usize; 2]>>()
+        .collect::<Vec<[usize; 2]>>())
 }
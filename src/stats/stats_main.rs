@@ -0,0 +1,300 @@
+use crate::core::bed::{allele_count, BedReader};
+use crate::core::bfile::get_type_bim;
+use crate::core::core::MatrixWrapper;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::helper::Feature;
+
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
+use clap::ArgMatches;
+use log::info;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// # Stats main function
+///
+/// Summary of a PLINK file (bed, bim, fam)
+/// - Variants: allele frequency, heterozygosity, call rate and feature type
+/// - Samples: present features, heterozygosity and missingness
+pub fn stats_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    StatsConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin stats'
+pub struct StatsConfig {
+    pub plink_file: String,
+    pub output_prefix: String,
+    /// Number of variants which are read at once
+    pub block_size: usize,
+}
+
+impl StatsConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            block_size: parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?,
+        })
+    }
+
+    /// Compute and write the statistics
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin stats'");
+        let plink_file = self.plink_file.as_str();
+        let output_prefix = self.output_prefix.as_str();
+
+        info!("Plink file: {}", plink_file);
+        info!("Output prefix: {}", output_prefix);
+
+        info!("Reading the plink file (bim + fam), bed is streamed");
+        let mut mw = MatrixWrapper::new();
+        mw.read_bim_fam(plink_file)?;
+        let feature = if mw.bim_entries.is_empty() {
+            (mw.feature, None)
+        } else {
+            get_type_bim(&format!("{}{}", plink_file, ".bim"))?
+        };
+
+        info!("Computing the statistics");
+        let samples_number = mw.fam_entries.len();
+        let reader = BedReader::new(
+            &format!("{}{}", plink_file, ".bed"),
+            samples_number,
+            mw.bim_entries.len(),
+            self.block_size,
+        )?;
+        let mut variant_stats = Vec::with_capacity(mw.bim_entries.len());
+        let mut sample_stats = vec![GenotypeCounts::default(); samples_number];
+        for block in reader {
+            for variant in block?.variants() {
+                variant_stats.push(GenotypeCounts::from_variant(variant, samples_number));
+                for (sample, counts) in sample_stats.iter_mut().enumerate() {
+                    counts.add(allele_count(variant, sample));
+                }
+            }
+        }
+
+        info!("Writing the output");
+        mw.write_variant_stats(output_prefix, &variant_stats)?;
+        mw.write_sample_stats(output_prefix, &sample_stats)?;
+        write_summary_json(output_prefix, feature, &variant_stats, &sample_stats)?;
+        info!("Done");
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Number of genotypes (absent, heterozygous, present, missing)
+pub struct GenotypeCounts {
+    pub absent: usize,
+    pub het: usize,
+    pub present: usize,
+    pub missing: usize,
+}
+
+impl GenotypeCounts {
+    /// Count the genotypes of a single variant
+    pub fn from_variant(variant: &BitSlice<u8, Lsb0>, samples_number: usize) -> Self {
+        let mut counts = Self::default();
+        for sample in 0..samples_number {
+            counts.add(allele_count(variant, sample));
+        }
+        counts
+    }
+
+    /// Add a single genotype (number of present alleles, None if missing)
+    pub fn add(&mut self, genotype: Option<u8>) {
+        match genotype {
+            Some(0) => self.absent += 1,
+            Some(1) => self.het += 1,
+            Some(_) => self.present += 1,
+            None => self.missing += 1,
+        }
+    }
+
+    /// Number of called (non-missing) genotypes
+    pub fn called(&self) -> usize {
+        self.absent + self.het + self.present
+    }
+
+    pub fn total(&self) -> usize {
+        self.called() + self.missing
+    }
+
+    /// Frequency of the present allele (T), missing genotypes are ignored
+    pub fn allele_frequency(&self) -> Option<f64> {
+        ratio(self.het + self.present * 2, self.called() * 2)
+    }
+
+    /// Fraction of heterozygous genotypes (of all called genotypes)
+    pub fn het_rate(&self) -> Option<f64> {
+        ratio(self.het, self.called())
+    }
+
+    pub fn call_rate(&self) -> Option<f64> {
+        ratio(self.called(), self.total())
+    }
+
+    pub fn missing_rate(&self) -> Option<f64> {
+        ratio(self.missing, self.total())
+    }
+}
+
+/// Ratio, None if the denominator is zero
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator as f64 / denominator as f64)
+    }
+}
+
+/// Format a rate (4 digits), NA if not available
+fn format_rate(value: Option<f64>) -> String {
+    value.map_or("NA".to_string(), |x| format!("{:.4}", x))
+}
+
+/// Format a rate for JSON, null if not available
+fn format_rate_json(value: Option<f64>) -> String {
+    value.map_or("null".to_string(), |x| format!("{:.6}", x))
+}
+
+impl MatrixWrapper {
+    /// Write the variant statistics (TSV)
+    ///
+    /// Columns: chromosome, position, identifier, feature type, allele frequency (present allele),
+    /// heterozygosity, call rate
+    pub fn write_variant_stats(
+        &self,
+        out_prefix: &str,
+        variant_stats: &[GenotypeCounts],
+    ) -> Result<(), io::Error> {
+        let output = [out_prefix, "variants", "tsv"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        writeln!(f, "#CHROM\tPOS\tID\tFEATURE\tAF\tHET_RATE\tCALL_RATE")?;
+        for (entry, counts) in self.bim_entries.iter().zip(variant_stats.iter()) {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                entry.chromosome,
+                entry.bp_position,
+                entry.variant_id,
+                Feature::identify_feature(&entry.variant_id).0.to_string1(),
+                format_rate(counts.allele_frequency()),
+                format_rate(counts.het_rate()),
+                format_rate(counts.call_rate())
+            )?;
+        }
+        f.flush()
+    }
+
+    /// Write the sample statistics (TSV)
+    ///
+    /// Columns: family and individual id, number of present features (heterozygous or homozygous),
+    /// number of heterozygous features, heterozygosity, number of missing features, missing rate
+    pub fn write_sample_stats(
+        &self,
+        out_prefix: &str,
+        sample_stats: &[GenotypeCounts],
+    ) -> Result<(), io::Error> {
+        let output = [out_prefix, "samples", "tsv"].join(".");
+        let mut f = BufWriter::new(File::create(output)?);
+        writeln!(
+            f,
+            "#FID\tIID\tPRESENT\tHET\tHET_RATE\tMISSING\tMISSING_RATE"
+        )?;
+        for (entry, counts) in self.fam_entries.iter().zip(sample_stats.iter()) {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                entry.family_id,
+                entry.individual_id,
+                counts.het + counts.present,
+                counts.het,
+                format_rate(counts.het_rate()),
+                counts.missing,
+                format_rate(counts.missing_rate())
+            )?;
+        }
+        f.flush()
+    }
+}
+
+/// Write the summary of the whole file set (JSON)
+pub fn write_summary_json(
+    out_prefix: &str,
+    feature: (Feature, Option<Feature>),
+    variant_stats: &[GenotypeCounts],
+    sample_stats: &[GenotypeCounts],
+) -> Result<(), io::Error> {
+    let output = [out_prefix, "summary", "json"].join(".");
+    let mut f = BufWriter::new(File::create(output)?);
+
+    let mut total = GenotypeCounts::default();
+    for counts in variant_stats.iter() {
+        total.absent += counts.absent;
+        total.het += counts.het;
+        total.present += counts.present;
+        total.missing += counts.missing;
+    }
+    let frequencies = variant_stats
+        .iter()
+        .filter_map(|x| x.allele_frequency())
+        .collect::<Vec<f64>>();
+    let monomorphic = frequencies
+        .iter()
+        .filter(|x| **x == 0.0 || **x == 1.0)
+        .count();
+    let sample_call_rates = sample_stats
+        .iter()
+        .filter_map(|x| x.call_rate())
+        .collect::<Vec<f64>>();
+
+    writeln!(f, "{{")?;
+    writeln!(f, "  \"variants\": {},", variant_stats.len())?;
+    writeln!(f, "  \"samples\": {},", sample_stats.len())?;
+    writeln!(f, "  \"feature\": \"{}\",", feature.0.to_string1())?;
+    writeln!(
+        f,
+        "  \"sub_feature\": {},",
+        feature
+            .1
+            .map_or("null".to_string(), |x| format!("\"{}\"", x.to_string1()))
+    )?;
+    writeln!(
+        f,
+        "  \"genotypes\": {{\"absent\": {}, \"het\": {}, \"present\": {}, \"missing\": {}}},",
+        total.absent, total.het, total.present, total.missing
+    )?;
+    writeln!(
+        f,
+        "  \"call_rate\": {},",
+        format_rate_json(total.call_rate())
+    )?;
+    writeln!(
+        f,
+        "  \"mean_allele_frequency\": {},",
+        format_rate_json(mean(&frequencies))
+    )?;
+    writeln!(f, "  \"monomorphic_variants\": {},", monomorphic)?;
+    writeln!(
+        f,
+        "  \"sample_call_rate\": {{\"min\": {}, \"mean\": {}, \"max\": {}}}",
+        format_rate_json(sample_call_rates.iter().cloned().reduce(f64::min)),
+        format_rate_json(mean(&sample_call_rates)),
+        format_rate_json(sample_call_rates.iter().cloned().reduce(f64::max))
+    )?;
+    writeln!(f, "}}")?;
+    f.flush()
+}
+
+/// Mean of a vector, None if empty
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
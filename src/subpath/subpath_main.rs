@@ -1,4 +1,14 @@
-use crate::core::bfile::write_dummy_fam;
+use crate::core::annotation::{traversal_annotation, ANNOTATION_HEADER};
+use crate::core::bed::set_genotype;
+use crate::core::bfile::{write_dummy_fam, ReferencePositions};
+use crate::core::compression::{Compression, PlainFile};
+use crate::core::dedup::dedup_fileset;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::helper::CollapsePolicy;
+use crate::core::metadata::SampleMetadata;
+use crate::core::pgen::{group_alleles, multiallelic_record, Genotypes, PgenIndex};
+use crate::graph::samples::{sample_haplotypes, walks_to_paths};
+use crate::nearest::nearest_main::reference_positions;
 use bitvec::order::Lsb0;
 use bitvec::prelude::BitVec;
 use clap::ArgMatches;
@@ -10,66 +20,168 @@ use std::fs::File;
 use std::io::{self, BufReader, Read};
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::str::FromStr;
 
 /// Subpath main function
 ///
 /// Extract the subpath from a graph for each node
-pub fn subpath_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin subpath'");
-
-    // Read the arguments from the command line
-    let graph_file = matches.value_of("gfa").unwrap();
-    let output_prefix = matches.value_of("output").unwrap();
-    let window: usize = matches.value_of("step").unwrap().parse().unwrap();
-    let threads: usize = matches.value_of("threads").unwrap().parse().unwrap();
-    let mut pansn = matches.value_of("PanSN").unwrap();
-    // Check the arguments
-    info!("Graph file: {}", graph_file);
-    info!("PanSN: {}", pansn);
-    info!("Window length: {}", window);
-    info!("Threads: {}", threads);
-    info!("Output prefix: {}\n", output_prefix);
-
-    info!("Read graph file");
-    let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file_multi(graph_file, threads);
-
-    info!("Convert walks to paths");
-    if graph.paths.is_empty() && pansn == "\n" {
-        pansn = "#";
+pub fn subpath_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    SubpathConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin subpath'
+pub struct SubpathConfig {
+    pub graph_file: String,
+    pub output_prefix: String,
+    /// Number of steps in each direction
+    pub step: usize,
+    pub threads: usize,
+    /// PanSN-spec separator (None: every path is a sample)
+    pub pansn: Option<String>,
+    /// Reference path (or prefix) for coordinates
+    pub reference: Option<String>,
+    pub collapse: CollapsePolicy,
+    /// Write the subpaths (blocks)
+    pub blocks: bool,
+    /// Sample metadata (FID, IID, father, mother, sex)
+    pub metadata: Option<String>,
+    /// Write the feature annotation (length, GC, paths, samples, span)
+    pub annotation: bool,
+    /// Keep one subpath per unique genotype pattern (mapping: prefix.dedup.tsv)
+    pub dedup: bool,
+    /// Write one multiallelic PLINK 2 variant per node (pgen, pvar, psam)
+    pub plink2: bool,
+}
+
+impl SubpathConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            graph_file: matches.value_of("gfa").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            step: parse_arg(matches.value_of("step").unwrap(), "step")?,
+            threads: parse_arg(matches.value_of("threads").unwrap(), "threads")?,
+            pansn: matches
+                .value_of("PanSN")
+                .filter(|x| *x != "\n")
+                .map(|x| x.to_string()),
+            reference: matches.value_of("reference").map(|x| x.to_string()),
+            collapse: CollapsePolicy::from_str(matches.value_of("collapse").unwrap())?,
+            blocks: matches.is_present("blocks"),
+            metadata: matches.value_of("sample-metadata").map(|x| x.to_string()),
+            annotation: matches.is_present("annotation"),
+            dedup: matches.is_present("dedup"),
+            plink2: matches.is_present("plink2"),
+        })
+    }
+
+    /// Extract the subpaths of the graph
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin subpath'");
+        let graph_file = self.graph_file.as_str();
+        let output_prefix = self.output_prefix.as_str();
+        let window = self.step;
+        let threads = self.threads;
+        let pansn = self.pansn.as_deref().unwrap_or("\n");
+        let reference = self.reference.as_deref();
+        let collapse = self.collapse;
+
+        // Check the arguments
+        info!("Graph file: {}", graph_file);
+        info!("PanSN: {}", pansn);
+        info!("Window length: {}", window);
+        info!("Threads: {}", threads);
+        info!("Collapse policy (polyploid): {}", collapse.to_string1());
+        info!("Reference (coordinates): {}", reference.unwrap_or("None"));
+        info!(
+            "Sample metadata: {}",
+            self.metadata.as_deref().unwrap_or("None")
+        );
+        info!("Feature annotation: {}", self.annotation);
+        info!("Deduplicate genotype patterns: {}", self.dedup);
+        info!("PLINK 2 (multiallelic): {}", self.plink2);
+        info!("Output prefix: {}\n", output_prefix);
+
+        let metadata = match &self.metadata {
+            Some(metadata) => Some(SampleMetadata::from_file(metadata)?),
+            None => None,
+        };
+
+        info!("Read graph file");
+        let graph_input = PlainFile::new(graph_file)?;
+        let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file_multi(graph_input.path(), threads);
+
+        info!("Convert walks to paths");
+        let origins = walks_to_paths(&mut graph, self.pansn.as_deref());
+
+        let wrapper: Pansn<u32, (), ()> = sample_haplotypes(&graph.paths, &origins);
+        if self.plink2 {
+            if let Some(genome) = wrapper.genomes.iter().find(|x| x.haplotypes.len() > 2) {
+                return Err(Gfa2binError::Argument(format!(
+                    "PLINK 2 output supports haploid and diploid samples only, {} has {} haplotypes",
+                    genome.name,
+                    genome.haplotypes.len()
+                )));
+            }
+        }
+
+        let reference_positions = match reference {
+            Some(reference) => {
+                info!("Project the nodes on the reference");
+                Some(reference_positions(reference, &graph, &origins)?)
+            }
+            None => None,
+        };
+
+        info!("Indexing graph");
+        let index_gfa_pos = gfa_index(&wrapper);
+
+        info!("Extracting subpath");
+        subpath_wrapper(
+            &wrapper,
+            &graph,
+            window,
+            index_gfa_pos,
+            self.blocks,
+            self.annotation,
+            output_prefix,
+            threads,
+            reference_positions.as_ref(),
+            collapse,
+            self.plink2,
+        )?;
+        // The psam file has the same layout as the fam file (no header line)
+        write_dummy_fam(
+            &wrapper,
+            &format!(
+                "{}.{}",
+                output_prefix,
+                if self.plink2 { "psam" } else { "fam" }
+            ),
+            metadata.as_ref(),
+        )?;
+        if self.dedup {
+            info!("Deduplicate the genotype patterns");
+            dedup_fileset(output_prefix, output_prefix, Compression::None)?;
+        }
+        info!("Done");
+        Ok(())
     }
-    graph.walk_to_path(pansn);
-
-    let wrapper: Pansn<u32, (), ()> = Pansn::from_graph(&graph.paths, pansn);
-
-    info!("Indexing graph");
-    let index_gfa_pos = gfa_index(&wrapper);
-
-    info!("Extracting subpath");
-    subpath_wrapper(
-        &wrapper,
-        &graph,
-        window,
-        index_gfa_pos,
-        matches.is_present("blocks"),
-        output_prefix,
-        threads,
-    )?;
-    write_dummy_fam(&wrapper, &format!("{}.fam", output_prefix))?;
-    info!("Done");
-    Ok(())
 }
 
 /// # Node to position index (hashmap)
 ///
+/// Node positions of a single path: (genome_id, haplotype_id, path_id, total_haplo, node -> Vec<index>)
+pub type PathIndex = (usize, usize, usize, usize, HashMap<u32, Vec<usize>>);
+
 /// For each path: Iterate over all nodes and store the index of each node
 ///
 /// For each path:
 ///     node -> Vec<index>
 ///
 /// Comment: Saved genome_id, haplotype_id, path_id, total_haplo, node2index
-pub fn gfa_index(
-    graph: &Pansn<u32, (), ()>,
-) -> Vec<(usize, usize, usize, usize, HashMap<u32, Vec<usize>>)> {
+pub fn gfa_index(graph: &Pansn<u32, (), ()>) -> Vec<PathIndex> {
     // Index (genome_id, haplotype_id, path_id, {node -> Vec<index>})
     let mut index = Vec::new();
     for (genome_id, path) in graph.genomes.iter().enumerate() {
@@ -95,36 +207,56 @@ pub fn gfa_index(
 
 /// Extract all subpath for each node
 ///
+/// PLINK 1: one biallelic entry for each group of traversals
+/// PLINK 2: one multiallelic variant for each node (one allele for each group)
+#[allow(clippy::too_many_arguments)]
 pub fn subpath_wrapper(
     graph2: &Pansn<u32, (), ()>,
     graph: &Gfa<u32, (), ()>,
     window: usize,
-    node2index_hm: Vec<(usize, usize, usize, usize, HashMap<u32, Vec<usize>>)>,
+    node2index_hm: Vec<PathIndex>,
     _blocks: bool,
+    annotation: bool,
     out_prefix: &str,
     threads: usize,
-) -> Result<(), Box<dyn std::error::Error>> {
+    reference: Option<&ReferencePositions>,
+    collapse: CollapsePolicy,
+    plink2: bool,
+) -> Result<(), Gfa2binError> {
     // Sample size
     let sample_size = graph2.genomes.len();
-    let is_diploid = diploid_or_not(graph2);
+    let ploidy = sample_ploidy(graph2);
+    let (genotype_suffix, variant_suffix) = if plink2 {
+        ("pgen", "pvar")
+    } else {
+        ("bed", "bim")
+    };
     let segment_id = graph.segments.iter().map(|x| x.id).collect::<Vec<u32>>();
-    segment_id
+    let pgen_index = segment_id
         .par_chunks(segment_id.len() / threads + 1)
         .enumerate()
-        .for_each(|(i, chunks)| {
-            let mut file_bed = BufWriter::new(
-                File::create(format!("{}_{}.bed", out_prefix, i))
-                    .expect("Not able to create bed file"),
-            );
-            let mut file_bim = BufWriter::new(
-                File::create(format!("{}_{}.bim", out_prefix, i))
-                    .expect("Not able to create bim file"),
-            );
-            let mut block = None;
-            block = Some(BufWriter::new(
-                File::create(format!("{}_{}.block", out_prefix, i))
-                    .expect("Not able to create block file"),
-            ));
+        .map(|(i, chunks)| -> Result<PgenIndex, io::Error> {
+            let mut file_bed = BufWriter::new(File::create(format!(
+                "{}_{}.{}",
+                out_prefix, i, genotype_suffix
+            ))?);
+            let mut file_bim = BufWriter::new(File::create(format!(
+                "{}_{}.{}",
+                out_prefix, i, variant_suffix
+            ))?);
+            let mut pgen_index = PgenIndex::new();
+            let mut block = Some(BufWriter::new(File::create(format!(
+                "{}_{}.block",
+                out_prefix, i
+            ))?));
+            let mut file_annotation = if annotation {
+                Some(BufWriter::new(File::create(format!(
+                    "{}_{}.annotation",
+                    out_prefix, i
+                ))?))
+            } else {
+                None
+            };
 
             for node_id in chunks {
                 // Result vec
@@ -137,42 +269,91 @@ pub fn subpath_wrapper(
                 if result_vec.is_empty() {
                     continue;
                 }
+                if let Some(file_annotation) = file_annotation.as_mut() {
+                    for x in traversal_annotation(graph, *node_id, window, &result_vec) {
+                        writeln!(file_annotation, "{}", x)?;
+                    }
+                }
+
+                let (chromosome, position) = match reference {
+                    Some(reference) => reference.get(*node_id, 0),
+                    None => ("graph".to_string(), *node_id as u64),
+                };
+                if plink2 {
+                    let (genotypes, groups) =
+                        traversal2genotypes(result_vec, sample_size, &ploidy, &mut block, node_id)?;
+                    let (vrtype, record) = multiallelic_record(&genotypes, groups.max(2))?;
+                    file_bed.write_all(&record)?;
+                    pgen_index.push(vrtype, record.len(), groups.max(2));
+                    let (allele_ref, allele_alt) = group_alleles(groups);
+                    writeln!(
+                        file_bim,
+                        "{}\t{}\t{}_{}\t{}\t{}",
+                        chromosome, position, node_id, window, allele_ref, allele_alt
+                    )?;
+                    continue;
+                }
 
                 // !Thiis mmight be wring
-                let vec_bitvec =
-                    traversal2bitvec(result_vec, sample_size, &is_diploid, &mut block, node_id);
+                let vec_bitvec = traversal2bitvec(
+                    result_vec,
+                    sample_size,
+                    &ploidy,
+                    collapse,
+                    &mut block,
+                    node_id,
+                )?;
                 for (x, _item) in vec_bitvec.iter().enumerate() {
                     writeln!(
                         file_bim,
-                        "graph\t{}\t0\t{}\tA\tT",
+                        "{}\t{}\t0\t{}\tA\tT",
+                        chromosome,
                         node_id.to_string() + "_" + &window.to_string() + "_" + &x.to_string(),
-                        node_id,
-                    )
-                    .unwrap();
+                        position,
+                    )?;
 
                     let buff = vec_bitvec[x].as_raw_slice();
-                    file_bed.write_all(buff).expect("Not able to write ")
+                    file_bed.write_all(buff)?;
                 }
             }
-        });
+            if let Some(mut file_annotation) = file_annotation {
+                file_annotation.flush()?;
+            }
+            file_bed.flush()?;
+            file_bim.flush()?;
+            Ok(pgen_index)
+        })
+        .collect::<Result<Vec<PgenIndex>, io::Error>>()?;
 
     info!("Concatenating files");
     let filenames1 = make_filename(out_prefix, threads);
+    let (genotype_header, variant_header) = if plink2 {
+        let mut index = PgenIndex::new();
+        for chunk in pgen_index {
+            index.extend(chunk);
+        }
+        (
+            index.header(sample_size),
+            "#CHROM\tPOS\tID\tREF\tALT\n".as_bytes().to_vec(),
+        )
+    } else {
+        (vec![108, 27, 1], Vec::new())
+    };
     concatenate_files_and_cleanup(
         &filenames1
             .iter()
-            .map(|a1| format!("{}.bim", a1))
+            .map(|a1| format!("{}.{}", a1, variant_suffix))
             .collect::<Vec<String>>(),
-        format!("{}.bim", out_prefix),
-        &Vec::new(),
+        format!("{}.{}", out_prefix, variant_suffix),
+        &variant_header,
     )?;
     concatenate_files_and_cleanup(
         &filenames1
             .iter()
-            .map(|a1| format!("{}.bed", a1))
+            .map(|a1| format!("{}.{}", a1, genotype_suffix))
             .collect::<Vec<String>>(),
-        format!("{}.bed", out_prefix),
-        &vec![108, 27, 1],
+        format!("{}.{}", out_prefix, genotype_suffix),
+        &genotype_header,
     )?;
     concatenate_files_and_cleanup(
         &filenames1
@@ -182,6 +363,16 @@ pub fn subpath_wrapper(
         format!("{}.block", out_prefix),
         &Vec::new(),
     )?;
+    if annotation {
+        concatenate_files_and_cleanup(
+            &filenames1
+                .iter()
+                .map(|a1| format!("{}.annotation", a1))
+                .collect::<Vec<String>>(),
+            format!("{}.annotation.tsv", out_prefix),
+            &format!("{}\n", ANNOTATION_HEADER).into_bytes(),
+        )?;
+    }
 
     Ok(())
 }
@@ -196,7 +387,7 @@ pub fn make_filename(output_prefix: &str, num: usize) -> Vec<String> {
 /// # Get traversals for each node
 pub fn get_traversals<'a>(
     node_id: u32,
-    node2index_hm: &Vec<(usize, usize, usize, usize, HashMap<u32, Vec<usize>>)>,
+    node2index_hm: &[PathIndex],
     graph: &'a Pansn<u32, (), ()>,
     window: usize,
 ) -> Vec<(usize, usize, &'a [u32])> {
@@ -208,9 +399,9 @@ pub fn get_traversals<'a>(
             .len();
 
         // If node is in path
-        if node2index.contains_key(&node_id) {
+        if let Some(occurrences) = node2index.get(&node_id) {
             // Iterate over all occurrences
-            for z in node2index.get(&node_id).unwrap() {
+            for z in occurrences {
                 // If window is within the path (does not exceed)
                 // Push to vector with slice
                 if window <= *z && *z + window < max_index + 1 {
@@ -235,17 +426,47 @@ pub fn get_traversals<'a>(
 pub fn traversal2bitvec(
     traversals: Vec<(usize, usize, &[u32])>,
     number_of_samples: usize,
-    is_diploid: &Vec<bool>,
+    ploidy: &[usize],
+    collapse: CollapsePolicy,
     blocks: &mut Option<BufWriter<File>>,
     node_id: &u32,
-) -> Vec<BitVec<u8>> {
+) -> Result<Vec<BitVec<u8>>, io::Error> {
     let mut sample_list: Vec<Vec<[usize; 2]>> = group_traversal(traversals);
 
     // If you want blocks written into extra
     if let Some(bufw) = blocks {
-        writeln!(bufw, "{}\t{:?}", node_id, sample_list).unwrap();
+        writeln!(bufw, "{}\t{:?}", node_id, sample_list)?;
+    }
+    Ok(get_bitvector(
+        &mut sample_list,
+        number_of_samples,
+        ploidy,
+        collapse,
+    ))
+}
+
+/// # Convert collection of traversals to multiallelic genotypes
+///
+/// Each group of similar traversals is one allele (group 0 is REF)
+/// Returns the genotype of each sample and the number of groups
+pub fn traversal2genotypes(
+    mut traversals: Vec<(usize, usize, &[u32])>,
+    number_of_samples: usize,
+    ploidy: &[usize],
+    blocks: &mut Option<BufWriter<File>>,
+    node_id: &u32,
+) -> Result<(Genotypes, usize), io::Error> {
+    // Sort by traversal, each allele is one distinct traversal
+    traversals.sort_by(|a, b| (a.2, a.0, a.1).cmp(&(b.2, b.0, b.1)));
+    let sample_list: Vec<Vec<[usize; 2]>> = group_traversal(traversals);
+
+    if let Some(bufw) = blocks {
+        writeln!(bufw, "{}\t{:?}", node_id, sample_list)?;
     }
-    get_bitvector(&mut sample_list, number_of_samples, is_diploid)
+    Ok((
+        get_genotypes(&sample_list, number_of_samples, ploidy),
+        sample_list.len(),
+    ))
 }
 
 /// # Group traversals with similar traversals
@@ -254,17 +475,14 @@ pub fn traversal2bitvec(
 /// Collected into one vector
 pub fn group_traversal(traversals: Vec<(usize, usize, &[u32])>) -> Vec<Vec<[usize; 2]>> {
     let mut sample_list: Vec<Vec<[usize; 2]>> = Vec::new();
-    let mut previous = traversals[0].2;
-    sample_list.push(vec![[traversals[0].0, traversals[0].1]]);
-    for traversal in traversals.iter().skip(1) {
-        if traversal.2 != previous {
-            previous = traversal.2;
-            sample_list.push(vec![[traversal.0, traversal.1]]);
-        } else {
-            sample_list
-                .last_mut()
-                .unwrap()
-                .push([traversal.0, traversal.1]);
+    let mut previous: Option<&[u32]> = None;
+    for traversal in traversals.iter() {
+        match sample_list.last_mut() {
+            Some(group) if previous == Some(traversal.2) => group.push([traversal.0, traversal.1]),
+            _ => {
+                previous = Some(traversal.2);
+                sample_list.push(vec![[traversal.0, traversal.1]]);
+            }
         }
     }
     sample_list
@@ -273,43 +491,70 @@ pub fn group_traversal(traversals: Vec<(usize, usize, &[u32])>) -> Vec<Vec<[usiz
 /// Each group is one genotype.
 ///
 /// Iterate over one genotype and setup a bitvector
-///
+/// - haploid: both bits
+/// - diploid: one bit for each haplotype
+/// - polyploid: number of haplotypes is collapsed (see CollapsePolicy)
 pub fn get_bitvector(
-    present_sample_collection: &mut Vec<Vec<[usize; 2]>>,
+    present_sample_collection: &mut [Vec<[usize; 2]>],
     len: usize,
-    is_diploid: &Vec<bool>,
+    ploidy: &[usize],
+    collapse: CollapsePolicy,
 ) -> Vec<BitVec<u8>> {
     let mut bitvec_collection = Vec::new();
     for samples in present_sample_collection.iter_mut() {
-        let mut bitvec_tmp: BitVec<u8, Lsb0> = BitVec::<u8, Lsb0>::repeat(false, len * 2 + 1);
-        let mut index = 0;
-        while index < samples.len() - 1 {
-            if samples[index][0] == samples[index + 1][0] {
-                bitvec_tmp.set(samples[index][0] * 2, true);
-                bitvec_tmp.set(samples[index][0] * 2 + 1, true);
-                index += 2;
-            } else if !is_diploid[samples[index][0]] {
-                bitvec_tmp.set(samples[index][0] * 2, true);
-                bitvec_tmp.set(samples[index][0] * 2 + 1, true);
-                index += 1;
-            } else {
-                bitvec_tmp.set(samples[index][0] * 2 + 1, true);
-                index += 1;
-            }
+        let mut bitvec_tmp: BitVec<u8, Lsb0> = BitVec::<u8, Lsb0>::repeat(false, len * 2);
+        // Each haplotype is counted once
+        samples.sort();
+        samples.dedup();
+        let mut counts = vec![0; len];
+        for [genome_id, _haplo_id] in samples.iter() {
+            counts[*genome_id] += 1;
         }
-        if index == samples.len() - 1 {
-            bitvec_tmp.set(samples[index][0] * 2 + 1, true);
+        for (genome_id, count) in counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let genotype = collapse.genotype(*count, ploidy[genome_id]);
+            set_genotype(&mut bitvec_tmp, genome_id, genotype);
         }
         bitvec_collection.push(bitvec_tmp);
     }
     bitvec_collection
 }
 
+/// Allele (group) of each haplotype, combined to one genotype per sample
+///
+/// - haploid: the allele twice
+/// - diploid: one allele for each haplotype
+/// - missing: a haplotype without traversal, in multiple groups (loops) or polyploid samples
+pub fn get_genotypes(sample_list: &[Vec<[usize; 2]>], len: usize, ploidy: &[usize]) -> Genotypes {
+    let mut alleles: Vec<Vec<Option<usize>>> = ploidy.iter().map(|x| vec![None; *x]).collect();
+    let mut ambiguous = vec![false; len];
+    for (group, samples) in sample_list.iter().enumerate() {
+        for [genome_id, haplo_id] in samples.iter() {
+            match alleles[*genome_id][*haplo_id] {
+                Some(allele) if allele != group => ambiguous[*genome_id] = true,
+                _ => alleles[*genome_id][*haplo_id] = Some(group),
+            }
+        }
+    }
+    alleles
+        .iter()
+        .zip(ambiguous.iter())
+        .map(|(haplotypes, ambiguous)| match haplotypes.as_slice() {
+            _ if *ambiguous => None,
+            [Some(allele)] => Some([*allele, *allele]),
+            [Some(allele1), Some(allele2)] => Some([*allele1, *allele2]),
+            _ => None,
+        })
+        .collect()
+}
+
 /// Concatenate files and cleanup
 pub fn concatenate_files_and_cleanup<P: AsRef<Path>>(
     input_files: &[P],
     output_file: P,
-    buffer: &Vec<u8>,
+    buffer: &[u8],
 ) -> io::Result<()> {
     // Open the output file for writing
     let output = File::create(output_file)?;
@@ -343,14 +588,7 @@ pub fn concatenate_files_and_cleanup<P: AsRef<Path>>(
     Ok(())
 }
 
-pub fn diploid_or_not(gr: &Pansn<u32, (), ()>) -> Vec<bool> {
-    let mut diploid_vec = Vec::new();
-    for sample in gr.genomes.iter() {
-        if sample.haplotypes.len() > 1 {
-            diploid_vec.push(true);
-        } else {
-            diploid_vec.push(false);
-        }
-    }
-    diploid_vec
+/// Number of haplotypes of each sample
+pub fn sample_ploidy(gr: &Pansn<u32, (), ()>) -> Vec<usize> {
+    gr.genomes.iter().map(|x| x.haplotypes.len()).collect()
 }
@@ -1,178 +1,392 @@
-use crate::core::bfile::count_lines;
+use crate::core::bed::{allele_count, BedReader};
+use crate::core::bfile::BimEntry;
+use crate::core::bgzf::BgzfWriter;
+use crate::core::compression::{Compression, PlainFile, TextWriter};
 use crate::core::core::MatrixWrapper;
-use bitvec::vec::BitVec;
+use crate::core::error::{parse_arg, Gfa2binError};
+use crate::core::helper::Feature;
+use crate::core::tabix::{IndexBuilder, IndexType};
+use crate::graph::samples::{sample_haplotypes, walks_to_paths};
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
 use clap::ArgMatches;
+use gfa_reader::{Gfa, Pansn};
+use hashbrown::HashMap;
 use log::info;
 use std::fs::File;
-use std::io::Write;
-use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
 
 /// # View main function
 ///
 /// Convert a bed file to VCF file
 /// Vcf is bim + bed
-pub fn view_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Running 'gfa2bin view'");
-    let plink_file = matches.value_of("plink").unwrap();
-    let output_prefix = matches.value_of("output").unwrap();
-    // Read the bed file
-
-    info!("Plink file: {}", plink_file);
-    info!("Output file: {}", output_prefix);
-
-    info!("Writing output (vcf)");
-    write_vcf(plink_file, output_prefix).expect("Error writing to file");
-    info!("Done");
-    Ok(())
+pub fn view_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    ViewConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin view'
+pub struct ViewConfig {
+    pub plink_file: String,
+    /// Output file (VCF)
+    pub output: String,
+    /// Graph file, used for the contig lengths and the ploidy of the samples
+    pub graph_file: Option<String>,
+    /// PanSN-spec separator of the path names in the graph (None: path name is the sample)
+    pub pansn: Option<String>,
+    pub compression: Compression,
+    /// Index of the VCF (only bgzip compressed)
+    pub index_type: Option<IndexType>,
+    pub block_size: usize,
+}
+
+impl ViewConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output: matches.value_of("output").unwrap().to_string(),
+            graph_file: matches.value_of("gfa").map(|x| x.to_string()),
+            pansn: matches
+                .value_of("PanSN")
+                .filter(|x| *x != "\n")
+                .map(|x| x.to_string()),
+            compression: if matches.is_present("bgzip") {
+                Compression::Bgzip
+            } else {
+                matches
+                    .value_of("compress")
+                    .map_or(Ok(Compression::None), Compression::from_str)?
+            },
+            index_type: matches
+                .value_of("index")
+                .map(IndexType::from_str)
+                .transpose()?,
+            block_size: parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?,
+        })
+    }
+
+    /// Convert the PLINK files to VCF
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        info!("Running 'gfa2bin view'");
+        let plink_file = self.plink_file.as_str();
+        let output_prefix = self.output.as_str();
+        let graph_file = self.graph_file.as_deref();
+        let pansn = self.pansn.as_deref();
+        let compression = self.compression;
+        let index_type = self.index_type;
+        let block_size = self.block_size;
+
+        // Read the bed file
+
+        if index_type.is_some() && compression != Compression::Bgzip {
+            return Err(Gfa2binError::Argument(
+                "Index (--index) can only be built for bgzip compressed output (--bgzip)"
+                    .to_string(),
+            ));
+        }
+
+        info!("Plink file: {}", plink_file);
+        info!(
+            "Graph file (contigs, ploidy): {}",
+            graph_file.unwrap_or("None")
+        );
+        info!("PanSN: {}", pansn.unwrap_or("None"));
+        info!("Output file: {}", output_prefix);
+        info!("Compression: {}", compression.to_string1());
+        info!(
+            "Index: {}",
+            index_type.map_or("None".to_string(), |x| x.to_string1())
+        );
+        info!("Block size: {}", block_size);
+
+        info!("Writing output (vcf)");
+        write_vcf(
+            plink_file,
+            output_prefix,
+            graph_file,
+            pansn,
+            compression,
+            index_type,
+            block_size,
+        )?;
+        info!("Done");
+        Ok(())
+    }
+}
+
+/// Output of the VCF (plain text, gzip/zstd compressed or bgzip compressed + index)
+enum VcfOutput {
+    Plain(TextWriter),
+    Bgzf(BgzfWriter<BufWriter<File>>, Option<IndexBuilder>),
 }
 
-/// Read the plink and write a vcf-like file
+impl VcfOutput {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            VcfOutput::Plain(writer) => writer,
+            VcfOutput::Bgzf(writer, _) => writer,
+        }
+    }
+}
+
+/// Read the plink and write a VCF (v4.3) file
 ///
-/// All-in-one function
-/// Does represent haplotypes
-/// Not sure of version vcf format 4.2
+/// Absence is the reference allele (N), presence the symbolic allele <PRESENT>
+/// The ploidy of the samples is taken from the graph (if provided), otherwise all samples are diploid
+/// Genotypes are unphased, the bed file does not store which haplotype carries the feature
+/// The bed file is streamed in blocks of variants
 pub fn write_vcf(
     filename_prefix: &str,
-    output_prefix: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Write the header
-    let file = File::create(output_prefix).unwrap();
-    let mut writer = std::io::BufWriter::new(file);
-    writeln!(writer, "##fileformat=VCFv4.2").expect("Error writing to file");
-    writeln!(
-        writer,
-        "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
-    )
-    .expect("Error writing to file");
-
-    // Read the bim file
-    let fam = read_first_column_from_tsv(&format!("{}{}", filename_prefix, ".fam"))?;
-    write!(
-        writer,
-        "{}",
-        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t".to_string()
-            + &fam.join("\t")
-            + "\n"
-    )
-    .expect("Error writing to file");
-
+    output: &str,
+    graph_file: Option<&str>,
+    pansn: Option<&str>,
+    compression: Compression,
+    index_type: Option<IndexType>,
+    block_size: usize,
+) -> Result<(), Gfa2binError> {
     let mut mw = MatrixWrapper::new();
-    let bim_count = count_lines(&format!("{}{}", filename_prefix, ".bim"))?;
-    let fam_count = count_lines(&format!("{}{}", filename_prefix, ".fam"))?;
-    mw.read_bed(
+    mw.read_bim_fam(filename_prefix)?;
+    let reader = BedReader::new(
         &format!("{}{}", filename_prefix, ".bed"),
-        fam_count,
-        bim_count,
-    )
-    .expect("Error reading bed file");
-
-    let file_bim = File::open(format!("{}{}", filename_prefix, ".bim"))?;
-    let reader_bim = BufReader::new(file_bim);
-    let lines_bim = reader_bim.lines();
-
-    for (index, line_bim) in lines_bim.enumerate() {
-        let line1 = line_bim?;
-        let b = &mw.matrix_bit[index];
-        writeln!(
-            writer,
-            "graph\t{}\t.\t{}\t-\t{}\tPASS\t{}\tGT\t{}",
-            line1.split_whitespace().nth(3).unwrap(),
-            line1.split_whitespace().nth(3).unwrap(),
-            mw.feature.to_string1(),
-            "GT=".to_string() + &(b.len() / 2).to_string(),
-            bitvec2vcf_string(b)
-        )?;
+        mw.fam_entries.len(),
+        mw.bim_entries.len(),
+        block_size,
+    )?;
+
+    let contigs = match graph_file {
+        Some(graph_file) => {
+            let graph_input = PlainFile::new(graph_file)?;
+            let mut graph: Gfa<u32, (), ()> = Gfa::parse_gfa_file(graph_input.path());
+            let origins = walks_to_paths(&mut graph, pansn);
+            mw.sample_index_u16 =
+                sample_index_graph(&mw.sample_names, &sample_haplotypes(&graph.paths, &origins));
+            contigs_graph(&mw.bim_entries, &graph)
+        }
+        None => contigs_bim(&mw.bim_entries),
+    };
+
+    let mut output_writer = if compression == Compression::Bgzip {
+        let writer = BufWriter::new(File::create(output)?);
+        VcfOutput::Bgzf(BgzfWriter::new(writer), index_type.map(IndexBuilder::new))
+    } else {
+        VcfOutput::Plain(TextWriter::new(output, compression)?)
+    };
+    mw.write_vcf_header(output_writer.writer(), &contigs)?;
+
+    let mut bim_iter = mw.bim_entries.iter();
+    let mut record = Vec::new();
+    for block in reader {
+        for x in block?.variants() {
+            let y = bim_iter.next().ok_or_else(|| {
+                Gfa2binError::Consistency("Bim file is shorter than bed file".to_string())
+            })?;
+            record.clear();
+            mw.write_vcf_record(&mut record, y, x)?;
+            match &mut output_writer {
+                VcfOutput::Plain(writer) => writer.write_all(&record)?,
+                VcfOutput::Bgzf(writer, index) => {
+                    let virtual_start = writer.virtual_offset();
+                    writer.write_all(&record)?;
+                    if let Some(index) = index {
+                        index.add_record(
+                            &y.chromosome,
+                            y.bp_position.saturating_sub(1),
+                            y.bp_position,
+                            virtual_start,
+                            writer.virtual_offset(),
+                        )?;
+                    }
+                }
+            }
+        }
     }
 
+    match output_writer {
+        VcfOutput::Plain(writer) => writer.finish()?,
+        VcfOutput::Bgzf(writer, index) => {
+            writer.finish()?;
+            if let Some(index) = index {
+                index.write(&format!("{}.{}", output, index_type.unwrap().to_string1()))?;
+            }
+        }
+    }
     Ok(())
 }
 
-/// # Read the first column from a tsv file
+/// Contigs (name, length) in order of appearance in the bim file
+///
+/// Length is the largest position of each contig
+pub fn contigs_bim(bim_entries: &[BimEntry]) -> Vec<(String, u64)> {
+    let mut contigs: Vec<(String, u64)> = Vec::new();
+    // Unplaced entries (PLINK chromosome code 0) have no contig
+    for x in bim_entries.iter().filter(|x| x.chromosome != "0") {
+        match contigs.iter_mut().find(|c| c.0 == x.chromosome) {
+            Some(contig) => contig.1 = contig.1.max(x.bp_position),
+            None => contigs.push((x.chromosome.clone(), x.bp_position)),
+        }
+    }
+    contigs
+}
+
+/// Contigs (name, length) based on the graph
 ///
-/// - Samples of a fam file
-fn read_first_column_from_tsv(file_path: &str) -> Result<Vec<String>, std::io::Error> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
+/// - Path names: sequence length of the path
+/// - Everything else (e.g. graph): positions are node ids, length is the largest node id
+pub fn contigs_graph(bim_entries: &[BimEntry], graph: &Gfa<u32, (), ()>) -> Vec<(String, u64)> {
+    let max_node = graph
+        .segments
+        .iter()
+        .map(|x| x.id as u64)
+        .max()
+        .unwrap_or(0);
+    let path_length = graph
+        .paths
+        .iter()
+        .map(|path| {
+            (
+                path.name.clone(),
+                path.nodes
+                    .iter()
+                    .map(|node| graph.get_sequence_by_id(node).len() as u64)
+                    .sum::<u64>(),
+            )
+        })
+        .collect::<HashMap<String, u64>>();
 
-    let mut first_columns = Vec::new();
+    contigs_bim(bim_entries)
+        .into_iter()
+        .map(|(name, length)| {
+            let graph_length = *path_length.get(&name).unwrap_or(&max_node);
+            (name, graph_length.max(length))
+        })
+        .collect()
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        let columns: Vec<&str> = line.split_whitespace().collect();
+/// # Haplotypes of each sample (sample index, see what_together)
+///
+/// Samples are matched by name with the genomes of the graph (after PanSN grouping)
+/// Samples which are not in the graph are diploid
+pub fn sample_index_graph(
+    sample_names: &[String],
+    graph_wrapper: &Pansn<u32, (), ()>,
+) -> Vec<[usize; 2]> {
+    let ploidy = graph_wrapper
+        .genomes
+        .iter()
+        .map(|x| (x.name.as_str(), x.haplotypes.len()))
+        .collect::<HashMap<&str, usize>>();
 
-        if let Some(first_column) = columns.first() {
-            first_columns.push((*first_column).to_string());
-        }
+    let mut sample_index = Vec::with_capacity(sample_names.len());
+    let mut missing = 0;
+    let mut count = 0;
+    for name in sample_names.iter() {
+        let haplotypes = match ploidy.get(name.as_str()) {
+            Some(haplotypes) => *haplotypes,
+            None => {
+                missing += 1;
+                2
+            }
+        };
+        sample_index.push([count, count + haplotypes - 1]);
+        count += haplotypes;
     }
-
-    Ok(first_columns)
+    if missing > 0 {
+        info!("Samples which are not in the graph (diploid): {}", missing);
+    }
+    info!(
+        "Haploid samples: {}",
+        sample_index.iter().filter(|x| x[0] == x[1]).count()
+    );
+    sample_index
 }
 
 impl MatrixWrapper {
-    /// # Write a VCF file with dumb header
-    pub fn write_vcf(&self, filename: &str) {
-        let file = File::create(filename).unwrap();
-        let mut writer = std::io::BufWriter::new(file);
-        writeln!(writer, "##fileformat=VCFv4.2").expect("Error writing to file");
+    /// # Write a VCF (v4.3) header
+    pub fn write_vcf_header<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        contigs: &[(String, u64)],
+    ) -> Result<(), std::io::Error> {
+        writeln!(writer, "##fileformat=VCFv4.3")?;
+        writeln!(writer, "##source=gfa2bin")?;
+        for (name, length) in contigs.iter() {
+            writeln!(writer, "##contig=<ID={},length={}>", name, length)?;
+        }
+        // REF has to be a base (VCF 4.3, 1.6.1), therefore absence is N and not a symbolic allele
+        writeln!(
+            writer,
+            "##ALT=<ID=PRESENT,Description=\"Graph feature is present (reference allele N: feature is absent)\">"
+        )?;
+        writeln!(
+            writer,
+            "##INFO=<ID=FEATURE,Number=1,Type=String,Description=\"Graph feature type (node, dirnode, edge, cov, mwindow, pwindow, block, bubble)\">"
+        )?;
         writeln!(
             writer,
             "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
-        )
-        .expect("Error writing to file");
+        )?;
 
-        let fam_entries = self
+        let sample_names = self
             .fam_entries
             .iter()
-            .map(|x| x.split_whitespace().next().unwrap().to_string())
-            .collect::<Vec<String>>();
-        let bims = self
-            .bim_entries
-            .iter()
-            .map(|x| x.split_whitespace().nth(3).unwrap().to_string())
+            .map(|x| x.individual_id.clone())
             .collect::<Vec<String>>();
 
-        write!(
+        writeln!(
             writer,
-            "{}",
-            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t".to_string()
-                + &fam_entries.join("\t")
-                + "\n"
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+            sample_names.join("\t")
         )
-        .expect("Test");
-
-        for (x, y) in self.matrix_bit.iter().zip(bims.iter()) {
-            writeln!(
-                writer,
-                "graph\t{}\t.\t{}\t-\t{}\tPASS\t{}\tGT\t{}",
-                y,
-                y,
-                self.feature.to_string1(),
-                "GT=".to_string() + &(x.len() / 2).to_string(),
-                bitvec2vcf_string(x)
-            )
-            .expect("Error writing to file");
-        }
     }
-}
 
-/// # Convert bitvector to string to 0 and 1 string
-///
-/// - Using chunks in pairs of two
-// Convert bitvec to vcf string
-pub fn bitvec2vcf_string(bitvec: &BitVec<u8>) -> String {
-    let mut s = String::new();
-    let chunks = bitvec.chunks(2);
-    for x in chunks {
-        if !x[0] && !x[1] {
-            s.push_str("0/0");
-        } else if !x[0] && x[1] {
-            s.push_str("0/1");
-        } else if x[0] && !x[1] {
-            s.push_str("1/0");
-        } else if x[0] && x[1] {
-            s.push_str("1/1");
-        }
-        s.push('\t');
+    /// # Write a single VCF record (one variant)
+    ///
+    /// Feature type is identified from the variant identifier (ID column)
+    pub fn write_vcf_record<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        bim_entry: &BimEntry,
+        bitvec: &BitSlice<u8, Lsb0>,
+    ) -> Result<(), std::io::Error> {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\tN\t<PRESENT>\t.\tPASS\tFEATURE={}\tGT\t{}",
+            bim_entry.chromosome,
+            bim_entry.bp_position,
+            bim_entry.variant_id,
+            Feature::identify_feature(&bim_entry.variant_id)
+                .0
+                .to_string1(),
+            self.bitvec2vcf_string(bitvec)
+        )
+    }
+
+    /// # Convert bitvector to VCF genotypes (tab separated)
+    ///
+    /// - Genotypes are unphased (0/0, 0/1, 1/1), the bed file has no phase
+    /// - Haploid samples (based on sample index) have a single allele (0, 1)
+    /// - Heterozygous calls of haploid samples are kept as diploid (0/1)
+    /// - Missing genotypes are ./. (haploid: .)
+    pub fn bitvec2vcf_string(&self, bitvec: &BitSlice<u8, Lsb0>) -> String {
+        (0..bitvec.len() / 2)
+            .map(|index| {
+                let haploid = self
+                    .sample_index_u16
+                    .get(index)
+                    .is_some_and(|y| y[0] == y[1]);
+                match (allele_count(bitvec, index), haploid) {
+                    (None, true) => ".".to_string(),
+                    (None, false) => "./.".to_string(),
+                    (Some(0), true) => "0".to_string(),
+                    (Some(2), true) => "1".to_string(),
+                    (Some(0), _) => "0/0".to_string(),
+                    (Some(1), _) => "0/1".to_string(),
+                    (Some(_), _) => "1/1".to_string(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\t")
     }
-    s
 }
@@ -1,96 +1,213 @@
-use crate::core::bfile::count_lines;
+use crate::core::bed::{BedReader, BedWriter};
+use crate::core::bfile::BimEntry;
+use crate::core::compression::Compression;
 use crate::core::core::MatrixWrapper;
+use crate::core::error::{parse_arg, Gfa2binError};
 use crate::core::helper::Feature;
-use crate::remove::remove_main::copy_file;
+use crate::core::pgen::{group_alleles, MultiallelicPgenWriter};
 use bitvec::order::Lsb0;
 use bitvec::vec::BitVec;
 use clap::ArgMatches;
 
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufRead, BufWriter, Write};
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
 
 /// Window function
 ///
 /// Reading a bed file and return "genotypes" which reflect windows over multiple entries
 /// We assume that the entries that are present in variation graphs have some kind of pan-genomic order. Otherwise, this makes not that much sense.
-pub fn window_main(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let plink_file = matches.value_of("plink").unwrap();
-    let out_file = matches.value_of("output").unwrap();
-    let window: usize = matches.value_of("length").unwrap().parse().unwrap();
-    let mut block = None;
-    if let Some(blocks_path) = matches.value_of("blocks") {
-        let file = File::create(blocks_path)?;
-        block = Some(BufWriter::new(file));
+pub fn window_main(matches: &ArgMatches) -> Result<(), Gfa2binError> {
+    WindowConfig::from_matches(matches)?.run()
+}
+
+#[derive(Debug, Clone)]
+/// # Options of 'gfa2bin window'
+pub struct WindowConfig {
+    pub plink_file: String,
+    pub output_prefix: String,
+    pub compression: Compression,
+    /// Number of entries in each window
+    pub length: usize,
+    /// Output file of the blocks (optional)
+    pub blocks: Option<String>,
+    pub block_size: usize,
+    /// Write one multiallelic PLINK 2 variant per window (pgen, pvar, psam)
+    pub plink2: bool,
+}
+
+impl WindowConfig {
+    /// Options from the command line
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Gfa2binError> {
+        Ok(Self {
+            plink_file: matches.value_of("plink").unwrap().to_string(),
+            output_prefix: matches.value_of("output").unwrap().to_string(),
+            compression: matches
+                .value_of("compress")
+                .map_or(Ok(Compression::None), Compression::from_str)?,
+            length: parse_arg(matches.value_of("length").unwrap(), "length")?,
+            blocks: matches.value_of("blocks").map(|x| x.to_string()),
+            block_size: parse_arg::<usize>(matches.value_of("block-size").unwrap(), "block-size")?,
+            plink2: matches.is_present("plink2"),
+        })
     }
 
-    // Read the bed file
-    let mut mw = MatrixWrapper::new();
-    let bim_count = count_lines(&format!("{}{}", plink_file, ".bim"))?;
-    let fam_count = count_lines(&format!("{}{}", plink_file, ".fam"))?;
-    mw.read_bed(&format!("{}{}", plink_file, ".bed"), fam_count, bim_count)?;
-    let (mw, index) = iterate_test(&mw, window, &mut block)?;
-
-    mw.write_bed(0, out_file, Feature::Node, 1);
-    read_write_bim(
-        &mw,
-        &index,
-        &format!("{}{}", plink_file, ".bim"),
-        &format!("{}{}", out_file, ".bim"),
-        window,
-    )
-    .unwrap();
-    copy_file(
-        &format!("{}{}", plink_file, ".fam"),
-        &format!("{}{}", out_file, ".fam"),
-    )
-    .unwrap();
-
-    Ok(())
+    /// Sliding window over the entries of the bed file
+    pub fn run(&self) -> Result<(), Gfa2binError> {
+        let plink_file = self.plink_file.as_str();
+        let out_file = self.output_prefix.as_str();
+        let compression = self.compression;
+        let window = self.length;
+        let mut block = None;
+        if let Some(blocks_path) = &self.blocks {
+            let file = File::create(blocks_path)?;
+            block = Some(BufWriter::new(file));
+        }
+        let block_size = self.block_size;
+
+        // Read the bim and fam file, the bed file is streamed
+        let mut mw = MatrixWrapper::new();
+        mw.read_bim_fam(plink_file)?;
+        if mw.bim_entries.len() < window {
+            return Err(Gfa2binError::Consistency(format!(
+                "Window size ({}) is larger than the number of entries ({})",
+                window,
+                mw.bim_entries.len()
+            )));
+        }
+        let reader = BedReader::new(
+            &format!("{}{}", plink_file, ".bed"),
+            mw.fam_entries.len(),
+            mw.bim_entries.len(),
+            block_size,
+        )?;
+        let mut writer = if self.plink2 {
+            WindowWriter::Pgen(MultiallelicPgenWriter::new(
+                &format!("{}{}", out_file, ".pgen"),
+                mw.fam_entries.len(),
+            )?)
+        } else {
+            WindowWriter::Bed(BedWriter::new(&format!("{}{}", out_file, ".bed"))?)
+        };
+        let index = iterate_test(reader, window, &mut block, &mut writer)?;
+        writer.finish()?;
+
+        let mut mw_new = MatrixWrapper::new();
+        mw_new.fam_entries = mw.fam_entries.clone();
+        mw_new.sample_names = mw.sample_names.clone();
+        mw_new.compression = compression;
+
+        if self.plink2 {
+            mw_new.bim_entries = window_pvar_entries(&mw, &index, window);
+            mw_new.write_pvar(out_file, &Feature::Node)?;
+            mw_new.write_psam(out_file, f64::MAX)?;
+        } else {
+            mw_new.bim_entries = window_bim_entries(&mw, &index, window);
+            mw_new.write_bim(0, out_file, &Feature::Node, 1)?;
+            mw_new.write_fam(0, out_file, Feature::Node, 1, f64::MAX)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Output of the window "genotypes"
+///
+/// PLINK 1: one biallelic entry for each group of samples
+/// PLINK 2: one multiallelic variant for each window (one allele for each group)
+pub enum WindowWriter {
+    Bed(BedWriter),
+    Pgen(MultiallelicPgenWriter),
+}
+
+impl WindowWriter {
+    /// Write the groups of samples of one window
+    pub fn write_groups(&mut self, groups: &[Vec<usize>], len: usize) -> Result<(), io::Error> {
+        match self {
+            WindowWriter::Bed(writer) => writer.write_block(&getbv(groups, len)),
+            WindowWriter::Pgen(writer) => {
+                let mut genotypes = vec![None; len];
+                for (allele, samples) in groups.iter().enumerate() {
+                    for sample in samples.iter() {
+                        genotypes[*sample] = Some([allele, allele]);
+                    }
+                }
+                writer.write_variant(&genotypes, groups.len().max(2))
+            }
+        }
+    }
+
+    /// Flush the buffer (bed) or write the index (pgen)
+    pub fn finish(self) -> Result<(), io::Error> {
+        match self {
+            WindowWriter::Bed(writer) => writer.finish(),
+            WindowWriter::Pgen(writer) => writer.finish(),
+        }
+    }
 }
 
 /// Wrapper around the matrix in sliding window
 ///
-/// Iterate over the matrix
-/// No bim entries
+/// Stream over the bed file, only the current window is kept in memory
+/// No bim entries, returns the number of entries for each window
 pub fn iterate_test(
-    mw: &MatrixWrapper,
+    reader: BedReader,
     window: usize,
     blocks: &mut Option<BufWriter<File>>,
-) -> Result<(MatrixWrapper, Vec<usize>), std::io::Error> {
-    let num_path = mw.matrix_bit[0].len() / 2;
+    writer: &mut WindowWriter,
+) -> Result<Vec<usize>, std::io::Error> {
+    let num_path = reader.samples_number;
+    let snp_number = reader.snp_number;
     let mut index = Vec::new();
 
-    let mut mw_new = MatrixWrapper::new();
-    if mw.matrix_bit.len() < window {
-        panic!("Window size is larger than the number of entries");
+    if snp_number < window {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Window size is larger than the number of entries",
+        ));
     }
-    for x in window..mw.matrix_bit.len() - window {
-        let mut bv2 = Vec::new();
-        for y in 0..num_path {
-            let mut bv: Vec<[bool; 2]> = Vec::new();
 
-            for z in x - window..x + window {
-                bv.push([mw.matrix_bit[z][y * 2], mw.matrix_bit[z][y * 2 + 1]]);
+    // Holds the entries x - window..x + window
+    let mut buffer: VecDeque<BitVec<u8, Lsb0>> = VecDeque::with_capacity(window * 2 + 1);
+    let mut position = 0;
+    for block in reader {
+        for bitvec in block?.variants() {
+            buffer.push_back(bitvec.to_bitvec());
+            position += 1;
+            if buffer.len() > window * 2 {
+                buffer.pop_front();
+            }
+            if buffer.len() < window * 2 || position >= snp_number {
+                continue;
+            }
+
+            let x = position - window;
+            let mut bv2 = Vec::new();
+            for y in 0..num_path {
+                let mut bv: Vec<[bool; 2]> = Vec::new();
+
+                for z in buffer.iter() {
+                    bv.push([z[y * 2], z[y * 2 + 1]]);
+                }
+                bv2.push((y, bv));
             }
-            bv2.push((y, bv));
+            // sort by the bitvec
+            bv2.sort_by(|a, b| a.1.cmp(&b.1));
+            let groups = get_index(&bv2, blocks, x)?;
+            writer.write_groups(&groups, num_path)?;
+            index.push(groups.len());
         }
-        // sort by the bitvec
-        bv2.sort_by(|a, b| a.1.cmp(&b.1));
-        let f = get_index(&bv2, blocks, x)?;
-        let flen = f.len();
-        mw_new.matrix_bit.extend(f);
-        index.push(flen);
     }
-    mw_new.shape = (mw_new.matrix_bit.len(), mw_new.matrix_bit[0].len());
-    Ok((mw_new, index))
+    Ok(index)
 }
 
-///
+/// Group the samples with the same entries (sorted)
 pub fn get_index(
-    vv: &Vec<(usize, Vec<[bool; 2]>)>,
+    vv: &[(usize, Vec<[bool; 2]>)],
     blocks: &mut Option<BufWriter<File>>,
     index: usize,
-) -> Result<Vec<BitVec<u8>>, std::io::Error> {
+) -> Result<Vec<Vec<usize>>, std::io::Error> {
     let mut pp = Vec::new();
     let mut last = &vv[0].1;
     pp.push(vec![vv[0].0]);
@@ -105,11 +222,11 @@ pub fn get_index(
     if let Some(b) = blocks {
         writeln!(b, "{}\t{:?}", index, pp)?;
     }
-    Ok(getbv(&pp, vv.len()))
+    Ok(pp)
 }
 
 /// Create a bitvector
-pub fn getbv(vv: &Vec<Vec<usize>>, len: usize) -> Vec<BitVec<u8>> {
+pub fn getbv(vv: &[Vec<usize>], len: usize) -> Vec<BitVec<u8>> {
     let mut gg = Vec::new();
     for x in vv {
         let mut oo: BitVec<u8, Lsb0> = BitVec::<u8, Lsb0>::repeat(false, len * 2);
@@ -123,39 +240,41 @@ pub fn getbv(vv: &Vec<Vec<usize>>, len: usize) -> Vec<BitVec<u8>> {
     gg
 }
 
-pub fn read_write_bim(
-    _mw: &MatrixWrapper,
-    index: &Vec<usize>,
-    input: &str,
-    output: &str,
-    window: usize,
-) -> Result<(), std::io::Error> {
-    let input_file = std::fs::File::open(input).unwrap();
-    let input_reader = std::io::BufReader::new(input_file);
-
-    let file_out = File::create(output)?;
-    let mut output_reader = std::io::BufWriter::new(file_out);
-
-    let mut i = 0;
-
-    for line in input_reader.lines().skip(window).enumerate() {
-        let line = line.1?; // unwrap the line or propagate error
-        let line_split = line.split_whitespace().collect::<Vec<&str>>();
-        for x in 0..index[i] {
-            writeln!(
-                output_reader,
-                "graph\t.\t0\tW{}:{}_{}\tA\tT",
-                line_split[3], window, x
-            )?;
-        }
-        i += 1;
-        if i == index.len() {
-            break;
+/// Create the bim entries of the window "genotypes"
+///
+/// Each window (centered on an entry of the input) has multiple entries (one for each group)
+pub fn window_bim_entries(mw: &MatrixWrapper, index: &[usize], window: usize) -> Vec<BimEntry> {
+    let mut bim_entries = Vec::new();
+    for (entry, number) in mw.bim_entries.iter().skip(window).zip(index.iter()) {
+        for x in 0..*number {
+            bim_entries.push(BimEntry {
+                chromosome: entry.chromosome.clone(),
+                variant_id: format!("W{}:{}_{}", entry.variant_id, window, x),
+                cm_position: 0.0,
+                bp_position: entry.bp_position,
+                allele1: "A".to_string(),
+                allele2: "T".to_string(),
+            });
         }
     }
+    bim_entries
+}
 
-    // Flush the buffer to ensure all data is written to file
-    output_reader.flush()?;
-
-    Ok(())
+/// Create the pvar entries of the window "genotypes"
+///
+/// Each window (centered on an entry of the input) is one variant, each group is one allele
+pub fn window_pvar_entries(mw: &MatrixWrapper, index: &[usize], window: usize) -> Vec<BimEntry> {
+    let mut bim_entries = Vec::new();
+    for (entry, number) in mw.bim_entries.iter().skip(window).zip(index.iter()) {
+        let (allele1, allele2) = group_alleles(*number);
+        bim_entries.push(BimEntry {
+            chromosome: entry.chromosome.clone(),
+            variant_id: format!("W{}:{}", entry.variant_id, window),
+            cm_position: 0.0,
+            bp_position: entry.bp_position,
+            allele1,
+            allele2,
+        });
+    }
+    bim_entries
 }
@@ -85,10 +85,12 @@ fn cov_pack_pn() -> Result<(), Box<dyn std::error::Error>> {
     b.read_to_end(&mut buffer).unwrap();
     assert_eq!(buffer.len(), 3 + 67);
 
-    let content = fs::read_to_string("data/output/gfa2bin.cov.list.merge.pn.bim").expect("Could not read BIM file");
+    let content = fs::read_to_string("data/output/gfa2bin.cov.list.merge.pn.bim")
+        .expect("Could not read BIM file");
     assert_eq!(content.lines().count(), 67);
 
-    let content = fs::read_to_string("data/output/gfa2bin.cov.list.merge.pn.fam").expect("Could not read FAM file");
+    let content = fs::read_to_string("data/output/gfa2bin.cov.list.merge.pn.fam")
+        .expect("Could not read FAM file");
     assert_eq!(content.lines().count(), 2);
     // Buffer should be 8 samples + header
     Ok(())
@@ -172,3 +174,39 @@ fn cov_pc2() -> Result<(), Box<dyn std::error::Error>> {
     // Buffer should be 8 samples + header
     Ok(())
 }
+
+#[test]
+/// Test for cov subcommand
+///
+/// Input: pt
+/// Number of samples: 2
+/// Output: bgen
+fn cov_bgen() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("cov")
+        .arg("-c")
+        .arg("data/example_data/packs/9986.1k.a1.a2.merge.pt")
+        .arg("-i")
+        .arg("data/example_data/packs/9986.1k.pi")
+        .arg("-o")
+        .arg("data/output/gfa2bin.cov.merge.bgen")
+        .arg("--bgen");
+    cmd.assert().success();
+    let mut b = File::open("data/output/gfa2bin.cov.merge.bgen.bgen").unwrap();
+    // Read the buffer
+    let mut buffer = Vec::new();
+    b.read_to_end(&mut buffer).unwrap();
+
+    // Header length, number of samples and magic bytes
+    assert_eq!(buffer[4..8], [20, 0, 0, 0]);
+    assert_eq!(buffer[12..16], [2, 0, 0, 0]);
+    assert_eq!(&buffer[16..20], b"bgen");
+
+    let content = fs::read_to_string("data/output/gfa2bin.cov.merge.bgen.sample")?;
+    assert_eq!(content.lines().count(), 4);
+
+    fs::remove_file("data/output/gfa2bin.cov.merge.bgen.bgen")?;
+    fs::remove_file("data/output/gfa2bin.cov.merge.bgen.sample")?;
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use assert_cmd::prelude::*;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+
+#[test]
+/// Deduplicate the node genotypes while converting the graph
+/// Node 1 and 5 are present in all paths, node 2-4 only in a and c
+fn graph_dedup() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_pav.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.dedup")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--dedup");
+    cmd.assert().success();
+
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.dedup.bim")?;
+    let lines = bim.lines().collect::<Vec<&str>>();
+    assert_eq!(lines, vec!["graph\t1\t0\t1\tA\tT", "graph\t2\t0\t2\tA\tT"]);
+
+    let mut buffer = Vec::new();
+    File::open("data/output/gfa2bin.graph.dedup.bed")?.read_to_end(&mut buffer)?;
+    assert_eq!(buffer, vec![108, 27, 1, 255, 51]);
+
+    let mapping = fs::read_to_string("data/output/gfa2bin.graph.dedup.dedup.tsv")?;
+    let lines = mapping.lines().collect::<Vec<&str>>();
+    assert_eq!(
+        lines,
+        vec![
+            "#REPRESENTATIVE\tFEATURE",
+            "1\t1",
+            "2\t2",
+            "2\t3",
+            "2\t4",
+            "1\t5"
+        ]
+    );
+    fs::remove_file("data/output/gfa2bin.graph.dedup.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.dedup.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.dedup.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.dedup.dedup.tsv")?;
+
+    // Only PLINK and PLINK 2 output
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_pav.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.dedup")
+        .arg("--bimbam")
+        .arg("--dedup");
+    cmd.assert().failure().code(2);
+    Ok(())
+}
+
+#[test]
+/// Deduplicate an existing fileset and expand association results through the mapping
+fn dedup_expand() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_pav.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.dedup.input")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("dedup")
+        .arg("-p")
+        .arg("./data/output/gfa2bin.dedup.input")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.dedup.output");
+    cmd.assert().success();
+
+    let bim = fs::read_to_string("data/output/gfa2bin.dedup.output.bim")?;
+    assert_eq!(bim.lines().count(), 2);
+    let fam1 = fs::read_to_string("data/output/gfa2bin.dedup.input.fam")?;
+    let fam2 = fs::read_to_string("data/output/gfa2bin.dedup.output.fam")?;
+    assert_eq!(fam1, fam2);
+
+    // PLINK 2 style results (one line per representative)
+    fs::write(
+        "data/output/gfa2bin.dedup.results.txt",
+        "#CHROM\tPOS\tID\tP\ngraph\t1\t1\t0.5\ngraph\t2\t2\t0.01\n",
+    )?;
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("expand")
+        .arg("-r")
+        .arg("./data/output/gfa2bin.dedup.results.txt")
+        .arg("-m")
+        .arg("./data/output/gfa2bin.dedup.output.dedup.tsv")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.dedup.expanded.txt");
+    cmd.assert().success();
+
+    let expanded = fs::read_to_string("data/output/gfa2bin.dedup.expanded.txt")?;
+    let lines = expanded.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "#CHROM\tPOS\tID\tP");
+    assert_eq!(lines[2], "graph\t1\t5\t0.5");
+    assert_eq!(lines[5], "graph\t2\t4\t0.01");
+
+    // Unknown ID column
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("expand")
+        .arg("-r")
+        .arg("./data/output/gfa2bin.dedup.results.txt")
+        .arg("-m")
+        .arg("./data/output/gfa2bin.dedup.output.dedup.tsv")
+        .arg("-c")
+        .arg("MARKER")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.dedup.expanded.txt");
+    cmd.assert().failure().code(2);
+
+    for file in [
+        "gfa2bin.dedup.input.bed",
+        "gfa2bin.dedup.input.bim",
+        "gfa2bin.dedup.input.fam",
+        "gfa2bin.dedup.output.bed",
+        "gfa2bin.dedup.output.bim",
+        "gfa2bin.dedup.output.fam",
+        "gfa2bin.dedup.output.dedup.tsv",
+        "gfa2bin.dedup.results.txt",
+        "gfa2bin.dedup.expanded.txt",
+    ] {
+        fs::remove_file(format!("data/output/{}", file))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use gfa2bin::core::helper::{FeatureId, MAX_EDGE_NODE};
+use gfa2bin::Feature;
+use proptest::prelude::*;
+
+/// Features which are encoded as two dirnodes (edge and windows)
+const EDGE_LIKE: [Feature; 4] = [
+    Feature::Edge,
+    Feature::MWindow,
+    Feature::PWindow,
+    Feature::Block,
+];
+
+fn edge_like() -> impl Strategy<Value = Feature> {
+    prop::sample::select(EDGE_LIKE.to_vec())
+}
+
+/// Encode, decode and identify a feature
+fn round_trip(id: FeatureId) {
+    let s = id.to_string();
+    assert_eq!(FeatureId::decode(&s, id.feature).unwrap(), id, "{}", s);
+    assert_eq!(FeatureId::parse(&s).unwrap(), id, "{}", s);
+    assert_eq!(id.feature.to_string_u64(id.value), s);
+}
+
+proptest! {
+    #[test]
+    fn node_round_trip(node in any::<u32>()) {
+        let id = FeatureId::node(node);
+        prop_assert_eq!(id.to_string(), node.to_string());
+        prop_assert_eq!(id.position(), node as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn dirnode_round_trip(node in any::<u32>(), forward in any::<bool>()) {
+        let id = FeatureId::dirnode(node, forward);
+        prop_assert_eq!(id.to_string(), format!("{}{}", node, if forward { "+" } else { "-" }));
+        prop_assert_eq!(id.position(), node as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn edge_round_trip(
+        feature in edge_like(),
+        from in 0..=MAX_EDGE_NODE,
+        from_forward in any::<bool>(),
+        to in 0..=MAX_EDGE_NODE,
+        to_forward in any::<bool>(),
+    ) {
+        let id = FeatureId::new(feature, FeatureId::edge(from, from_forward, to, to_forward).value);
+        prop_assert_eq!(id.position(), from as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn alignment_round_trip(node in any::<u32>(), position in any::<u32>()) {
+        let id = FeatureId::alignment(node, position);
+        prop_assert_eq!(id.to_string(), format!("A{}-{}", node, position));
+        prop_assert_eq!(id.position(), node as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn bubble_round_trip(
+        source in 0..=MAX_EDGE_NODE,
+        forward in any::<bool>(),
+        allele in any::<u32>(),
+    ) {
+        let id = FeatureId::bubble(source, forward, allele);
+        let direction = if forward { "+" } else { "-" };
+        prop_assert_eq!(id.to_string(), format!("S{}{}_{}", source, direction, allele));
+        prop_assert_eq!(id.position(), source as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn segment_round_trip(first in any::<u32>(), last in any::<u32>()) {
+        let id = FeatureId::segment(first, last);
+        prop_assert_eq!(id.to_string(), format!("V{}-{}", first, last));
+        prop_assert_eq!(id.position(), first as u64);
+        round_trip(id);
+    }
+
+    #[test]
+    fn edge_value_round_trip(feature in edge_like(), value in any::<u64>()) {
+        // Every u64 is a valid edge (two u32 dirnodes)
+        round_trip(FeatureId::new(feature, value));
+    }
+
+    #[test]
+    fn parse_never_panics(s in "[0-9+-]{0,12}") {
+        // Valid identifiers are parsed again to the same feature (leading zeros are dropped)
+        if let Ok(id) = FeatureId::parse(&s) {
+            prop_assert_eq!(FeatureId::parse(&id.to_string()).unwrap(), id);
+        }
+    }
+}
+
+#[test]
+/// Orientation of dirnodes and edges (+ is 1)
+fn feature_id_orientation() {
+    assert_eq!(FeatureId::dirnode(12, true).value, 25);
+    assert_eq!(FeatureId::dirnode(12, false).value, 24);
+    assert_eq!(FeatureId::parse("12+13-").unwrap().value, (25 << 32) | 26);
+    assert_ne!(
+        FeatureId::edge(12, true, 13, false),
+        FeatureId::edge(13, false, 12, true)
+    );
+    assert_eq!(FeatureId::identify("12+"), Feature::DirNode);
+    assert_eq!(FeatureId::identify("12-13+"), Feature::Edge);
+    assert_eq!(FeatureId::identify("A12-0"), Feature::Alignment);
+    assert_eq!(FeatureId::identify("S12+_0"), Feature::Bubble);
+    assert_eq!(FeatureId::identify("V12-15"), Feature::Segment);
+}
+
+#[test]
+/// Invalid identifiers are errors (no panic)
+fn feature_id_invalid() {
+    for (id, feature) in [
+        ("", Feature::Node),
+        ("+12", Feature::Node),
+        ("12", Feature::DirNode),
+        ("12+", Feature::Edge),
+        ("12+13", Feature::Edge),
+        ("+13-", Feature::Edge),
+        ("A12", Feature::Alignment),
+        ("12-0", Feature::Alignment),
+        ("4294967296+", Feature::DirNode),
+        ("2147483648+1-", Feature::Edge),
+        ("P1+2+", Feature::Block),
+        ("S12_0", Feature::Bubble),
+        ("S12+", Feature::Bubble),
+        ("S12+_", Feature::Bubble),
+        ("V12", Feature::Segment),
+        ("V12+15", Feature::Segment),
+    ]
+    .iter()
+    {
+        assert!(FeatureId::decode(id, *feature).is_err(), "{}", id);
+    }
+}
@@ -1,7 +1,7 @@
 use assert_cmd::prelude::*;
- // Add methods on commands
-
+// Add methods on commands
 
+use std::fs;
 
 use std::process::Command;
 
@@ -37,3 +37,93 @@ fn filter_test() -> Result<(), Box<dyn std::error::Error>> {
     //fs::remove_file("data/output/remove.feature.node.fam")?;
     Ok(())
 }
+
+#[test]
+/// Streaming with a small block size gives the same result
+fn filter_block_size() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.filter.block")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    for block_size in ["1", "10000"].iter() {
+        let mut cmd = Command::cargo_bin("gfa2bin")?;
+        cmd.arg("filter")
+            .arg("-p")
+            .arg("./data/output/gfa2bin.filter.block")
+            .arg("-m")
+            .arg("0.5")
+            .arg("--block-size")
+            .arg(block_size)
+            .arg("-o")
+            .arg(format!("data/output/gfa2bin.filter.block.{}", block_size));
+        cmd.assert().success();
+    }
+
+    assert_eq!(
+        fs::read("data/output/gfa2bin.filter.block.1.bed")?,
+        fs::read("data/output/gfa2bin.filter.block.10000.bed")?
+    );
+    assert_eq!(
+        fs::read_to_string("data/output/gfa2bin.filter.block.1.bim")?,
+        fs::read_to_string("data/output/gfa2bin.filter.block.10000.bim")?
+    );
+
+    for suffix in ["bed", "bim", "fam"].iter() {
+        fs::remove_file(format!("data/output/gfa2bin.filter.block.{}", suffix))?;
+        fs::remove_file(format!("data/output/gfa2bin.filter.block.1.{}", suffix))?;
+        fs::remove_file(format!("data/output/gfa2bin.filter.block.10000.{}", suffix))?;
+    }
+    Ok(())
+}
+
+#[test]
+/// Filter samples by (real) missing rate
+///
+/// d and e do not cover most of the reference (a#1#Chr1) and are removed
+fn filter_missing_rate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.filter.missing")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("a#1#")
+        .arg("--missing");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("filter")
+        .arg("-p")
+        .arg("./data/output/gfa2bin.filter.missing")
+        .arg("--missing-rate")
+        .arg("0.5")
+        .arg("-o")
+        .arg("data/output/gfa2bin.filter.missing.0.5");
+    cmd.assert().success();
+
+    let fam = fs::read_to_string("data/output/gfa2bin.filter.missing.0.5.fam")?;
+    let samples = fam
+        .lines()
+        .map(|x| x.split('\t').next().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(samples, vec!["a", "b", "c"]);
+
+    for suffix in ["bed", "bim", "fam"].iter() {
+        fs::remove_file(format!("data/output/gfa2bin.filter.missing.{}", suffix))?;
+        fs::remove_file(format!("data/output/gfa2bin.filter.missing.0.5.{}", suffix))?;
+    }
+    Ok(())
+}
@@ -29,8 +29,8 @@ fn gfa_nodes() -> Result<(), Box<dyn std::error::Error>> {
     // Buffer should be 8 samples + header
     assert_eq!(buffer.len(), 3 + (9 * 2));
 
-    // First "real" byte is 00
-    assert_eq!(buffer[3], 127);
+    // a, b, c present (11), d heterozygous (01)
+    assert_eq!(buffer[3], 191);
     // Second "real" byte is 000000000
     assert_eq!(buffer[4], 0);
     fs::remove_file("data/output/gfa2bin.graph.node.bed")?;
@@ -65,8 +65,8 @@ fn gfa_dirnodes() -> Result<(), Box<dyn std::error::Error>> {
     // Buffer should be 8 samples + header
     assert_eq!(buffer.len(), 3 + (8 * 2));
 
-    // First "real" byte is 00
-    assert_eq!(buffer[3], 127);
+    // a, b, c present (11), d heterozygous (01)
+    assert_eq!(buffer[3], 191);
     // Second "real" byte is 000000000
     assert_eq!(buffer[4], 0);
     fs::remove_file("data/output/gfa2bin.graph.dirnode.bed")?;
@@ -111,3 +111,780 @@ fn gfa_edges() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+/// Test for "gfa2bin graph --plink2"
+///
+/// Nodes (hardcalls)
+fn gfa_nodes_plink2() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.node.plink2")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--plink2");
+    cmd.assert().success();
+    let mut b = File::open("data/output/gfa2bin.graph.node.plink2.pgen").unwrap();
+
+    // Read the buffer
+    let mut buffer = Vec::new();
+    b.read_to_end(&mut buffer).unwrap();
+
+    // Header (12 bytes) + 9 variants with 5 samples (2 bytes)
+    assert_eq!(buffer.len(), 12 + (9 * 2));
+    // Hardcalls, 9 variants, 5 samples, all REF alleles provisional
+    assert_eq!(buffer[..12], [108, 27, 2, 9, 0, 0, 0, 5, 0, 0, 0, 128]);
+
+    // a, b, c hom alt (2), d het (1)
+    assert_eq!(buffer[12], 106);
+
+    let pvar = fs::read_to_string("data/output/gfa2bin.graph.node.plink2.pvar")?;
+    assert_eq!(pvar.lines().count(), 10);
+    let psam = fs::read_to_string("data/output/gfa2bin.graph.node.plink2.psam")?;
+    assert_eq!(psam.lines().count(), 6);
+
+    fs::remove_file("data/output/gfa2bin.graph.node.plink2.pgen")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.plink2.pvar")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.plink2.psam")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph --reference"
+///
+/// Nodes with reference coordinates (a#1#Chr1: 1, 2, 5, 8, 9)
+fn gfa_nodes_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.node.reference")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("a#1#");
+    cmd.assert().success();
+
+    let content = fs::read_to_string("data/output/gfa2bin.graph.node.reference.bim")?;
+    let lines = content.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[0], "Chr1\t1\t0\t1\tA\tT");
+    assert_eq!(lines[1], "Chr1\t2\t0\t11\tA\tT");
+    assert_eq!(lines[4], "Chr1\t5\t0\t16\tA\tT");
+
+    fs::remove_file("data/output/gfa2bin.graph.node.reference.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.reference.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.reference.fam")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph --threads"
+///
+/// Same output for serial and parallel runs (presence/absence and counts)
+fn gfa_nodes_threads() -> Result<(), Box<dyn std::error::Error>> {
+    for absolute in ["1", "2"].iter() {
+        for threads in ["1", "4"].iter() {
+            let mut cmd = Command::cargo_bin("gfa2bin")?;
+            cmd.arg("graph")
+                .arg("-g")
+                .arg("./data/example_data/gfa/testGraph.gfa")
+                .arg("-o")
+                .arg(format!("./data/output/gfa2bin.graph.threads.{}", threads))
+                .arg("-f")
+                .arg("node")
+                .arg("--pansn")
+                .arg("#")
+                .arg("-a")
+                .arg(absolute)
+                .arg("-t")
+                .arg(threads);
+            cmd.assert().success();
+        }
+        assert_eq!(
+            fs::read("data/output/gfa2bin.graph.threads.1.bed")?,
+            fs::read("data/output/gfa2bin.graph.threads.4.bed")?
+        );
+    }
+
+    for threads in ["1", "4"].iter() {
+        for suffix in ["bed", "bim", "fam"].iter() {
+            fs::remove_file(format!(
+                "data/output/gfa2bin.graph.threads.{}.{}",
+                threads, suffix
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph --missing"
+///
+/// d#2 and e#2 do not cover any node of the reference (a#1#Chr1)
+fn gfa_nodes_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.node.missing")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("a#1#")
+        .arg("--missing");
+    cmd.assert().success();
+    let buffer = fs::read("data/output/gfa2bin.graph.node.missing.bed")?;
+    assert_eq!(buffer.len(), 3 + (9 * 2));
+
+    // Node 1: a, b, c present (11), d and e missing (10)
+    assert_eq!(buffer[3], 127);
+    assert_eq!(buffer[4], 1);
+    // Node 3: a, b, c absent (00), d and e present (11) - present is never missing
+    assert_eq!(buffer[7], 192);
+    assert_eq!(buffer[8], 3);
+
+    fs::remove_file("data/output/gfa2bin.graph.node.missing.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.missing.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.node.missing.fam")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph" with a tetraploid sample
+///
+/// t: 4 haplotypes, u: 1 haplotype
+/// Node 2 (1/4 haplotypes), node 3 (3/4 haplotypes)
+fn gfa_nodes_polyploid() -> Result<(), Box<dyn std::error::Error>> {
+    for (collapse, node2, node3) in [("het", 14, 2), ("majority", 12, 3)].iter() {
+        let mut cmd = Command::cargo_bin("gfa2bin")?;
+        cmd.arg("graph")
+            .arg("-g")
+            .arg("./data/example_data/gfa/tetraploid.gfa")
+            .arg("-o")
+            .arg("./data/output/gfa2bin.graph.polyploid")
+            .arg("-f")
+            .arg("node")
+            .arg("--pansn")
+            .arg("#")
+            .arg("--collapse")
+            .arg(collapse);
+        cmd.assert().success();
+        let buffer = fs::read("data/output/gfa2bin.graph.polyploid.bed")?;
+
+        // Header + 4 nodes with 2 samples (1 byte)
+        assert_eq!(buffer.len(), 3 + 4);
+        assert_eq!(buffer[3], 15);
+        assert_eq!(buffer[4], *node2);
+        assert_eq!(buffer[5], *node3);
+        assert_eq!(buffer[6], 15);
+    }
+
+    fs::remove_file("data/output/gfa2bin.graph.polyploid.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.polyploid.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.polyploid.fam")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph"
+///
+/// Compressed input (gzip) and compressed output (bim, fam)
+fn gfa_nodes_compressed() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa.gz")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.compress")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--compress")
+        .arg("gzip");
+    cmd.assert().success();
+    let mut b = File::open("data/output/gfa2bin.graph.compress.bed").unwrap();
+    let mut buffer = Vec::new();
+    b.read_to_end(&mut buffer).unwrap();
+    assert_eq!(buffer.len(), 3 + (9 * 2));
+    assert_eq!(buffer[3], 191);
+
+    let mut bim = String::new();
+    flate2::read::GzDecoder::new(File::open("data/output/gfa2bin.graph.compress.bim.gz")?)
+        .read_to_string(&mut bim)?;
+    assert_eq!(bim.lines().count(), 9);
+    assert_eq!(bim.lines().next().unwrap(), "graph\t1\t0\t1\tA\tT");
+
+    // Compressed bim and fam are found by the prefix
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.graph.compress")
+        .arg("-o")
+        .arg("data/output/gfa2bin.graph.compress.vcf");
+    cmd.assert().success();
+    let vcf = fs::read_to_string("data/output/gfa2bin.graph.compress.vcf")?;
+    assert_eq!(vcf.lines().filter(|x| !x.starts_with('#')).count(), 9);
+
+    fs::remove_file("data/output/gfa2bin.graph.compress.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.compress.bim.gz")?;
+    fs::remove_file("data/output/gfa2bin.graph.compress.fam.gz")?;
+    fs::remove_file("data/output/gfa2bin.graph.compress.vcf")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph"
+///
+/// Unknown feature is an argument error (exit code 2)
+fn gfa_unknown_feature() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.unknown")
+        .arg("-f")
+        .arg("nodes");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicates::str::contains(
+            "Argument error: Unknown feature: nodes",
+        ));
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph"
+///
+/// Phenotype table (two phenotypes, one covariate), e is not in the table, f is not in the graph
+fn gfa_pheno_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.pheno")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--pheno-file")
+        .arg("./data/example_data/additional_input/phenotypes.tsv")
+        .arg("--covariates")
+        .arg("age");
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "1 samples are not in the phenotype table (missing phenotype): e",
+        ))
+        .stderr(predicates::str::contains(
+            "1 samples of the phenotype table are not in the input (ignored): f",
+        ));
+
+    let fam = fs::read_to_string("data/output/gfa2bin.graph.pheno.fam")?;
+    assert!(fam.lines().any(|x| x == "a\ta\t0\t0\t0\t1.5"));
+    assert!(fam.lines().any(|x| x == "b\tb\t0\t0\t0\t-9"));
+    assert!(fam.lines().any(|x| x == "e\te\t0\t0\t0\t-9"));
+
+    let pheno = fs::read_to_string("data/output/gfa2bin.graph.pheno.pheno")?;
+    assert_eq!(pheno.lines().next(), Some("FID\tIID\theight\tweight"));
+    assert!(pheno.lines().any(|x| x == "d\td\t1.6\tNA"));
+    assert_eq!(pheno.lines().count(), 6);
+
+    let cov = fs::read_to_string("data/output/gfa2bin.graph.pheno.cov")?;
+    assert_eq!(cov.lines().next(), Some("FID\tIID\tage"));
+    assert!(cov.lines().any(|x| x == "e\te\tNA"));
+
+    fs::remove_file("data/output/gfa2bin.graph.pheno.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.pheno.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.pheno.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.pheno.pheno")?;
+    fs::remove_file("data/output/gfa2bin.graph.pheno.cov")?;
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph"
+///
+/// Sample metadata (family, parents, sex) and phenotype table, e is not in the metadata
+fn gfa_sample_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.metadata")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--pheno-file")
+        .arg("./data/example_data/additional_input/phenotypes.tsv")
+        .arg("--sample-metadata")
+        .arg("./data/example_data/additional_input/sample_metadata.tsv");
+    cmd.assert().success().stderr(predicates::str::contains(
+        "1 samples are not in the sample metadata (FID=IID, parents and sex unknown): e",
+    ));
+
+    let fam = fs::read_to_string("data/output/gfa2bin.graph.metadata.fam")?;
+    assert!(fam.lines().any(|x| x == "fam1\ta\t0\t0\t1\t1.5"));
+    assert!(fam.lines().any(|x| x == "fam1\tc\ta\tb\t2\t1.8"));
+    assert!(fam.lines().any(|x| x == "fam2\td\t0\t0\t1\t1.6"));
+    assert!(fam.lines().any(|x| x == "e\te\t0\t0\t0\t-9"));
+
+    let pheno = fs::read_to_string("data/output/gfa2bin.graph.metadata.pheno")?;
+    assert!(pheno.lines().any(|x| x == "fam1\tb\tNA\t72.5\t41"));
+
+    fs::remove_file("data/output/gfa2bin.graph.metadata.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.metadata.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.metadata.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.metadata.pheno")?;
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph"
+///
+/// Feature annotation (length, GC content, paths and samples) of each node
+fn gfa_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.annotation")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--annotation");
+    cmd.assert().success();
+
+    let annotation = fs::read_to_string("data/output/gfa2bin.graph.annotation.annotation.tsv")?;
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.annotation.bim")?;
+    assert_eq!(
+        annotation.lines().next(),
+        Some("#ID\tLENGTH\tGC\tPATHS\tSAMPLES\tSOURCE_LENGTH\tTARGET_LENGTH\tSPAN")
+    );
+    assert_eq!(annotation.lines().count(), bim.lines().count() + 1);
+    assert!(annotation
+        .lines()
+        .any(|x| x == "1\t10\t0.0000\t5\t4\tNA\tNA\tNA"));
+    assert!(annotation
+        .lines()
+        .any(|x| x == "2\t5\t1.0000\t3\t3\tNA\tNA\tNA"));
+
+    fs::remove_file("data/output/gfa2bin.graph.annotation.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.annotation.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.annotation.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.annotation.annotation.tsv")?;
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph" with walks (W-lines)
+///
+/// a is a path (P-line), b-e are walks, d#1#Chr2 starts at 100
+fn gfa_walks() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_walks.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.walks")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("d#1#");
+    cmd.assert().success();
+
+    let fam = fs::read_to_string("data/output/gfa2bin.graph.walks.fam")?;
+    let samples = fam
+        .lines()
+        .map(|x| x.split('\t').nth(1).unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(samples, vec!["a", "b", "c", "d", "e"]);
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.walks.bim")?;
+    let lines = bim.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "Chr1\t1\t0\t1\tA\tT");
+    assert_eq!(lines[4], "Chr2\t5\t0\t111\tA\tT");
+
+    // Walks do not need a separator
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_walks.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.walks");
+    cmd.assert().success();
+    let fam = fs::read_to_string("data/output/gfa2bin.graph.walks.fam")?;
+    assert_eq!(fam.lines().count(), 5);
+    assert!(fam.lines().any(|x| x.starts_with("d\td\t")));
+
+    fs::remove_file("data/output/gfa2bin.graph.walks.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.walks.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.walks.fam")?;
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph -f node,edge"
+///
+/// Multiple features (separate and combined output)
+fn gfa_multi_feature() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.multi")
+        .arg("-f")
+        .arg("node,edge")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+    for feature in ["node", "edge"].iter() {
+        for suffix in ["bed", "bim", "fam"].iter() {
+            let file = format!("data/output/gfa2bin.graph.multi.{}.{}", feature, suffix);
+            assert!(fs::metadata(&file).is_ok(), "{}", file);
+            fs::remove_file(&file)?;
+        }
+    }
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.combined")
+        .arg("-f")
+        .arg("node,dirnode")
+        .arg("--combined")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.combined.bim")?;
+    let ids = bim
+        .lines()
+        .map(|x| x.split('\t').nth(1).unwrap())
+        .collect::<Vec<&str>>();
+    // 9 nodes + 8 dirnodes
+    assert_eq!(ids.len(), 9 + 8);
+    assert!(ids.contains(&"1"));
+    assert!(ids.contains(&"1+"));
+    fs::remove_file("data/output/gfa2bin.graph.combined.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.combined.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.combined.fam")?;
+
+    // Same feature twice
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.twice")
+        .arg("-f")
+        .arg("node,node");
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph -f bubble"
+///
+/// Bubbles (one biallelic record per allele, multiallelic VCF)
+fn gfa_bubbles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.bubble")
+        .arg("-f")
+        .arg("bubble")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    // Two bubbles: 1+ -> 9+ (4 alleles), 5+ -> 8+ (2 alleles)
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.bubble.bim")?;
+    let lines = bim.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "graph\tS1+_0\t0\t1\tA\tT");
+    assert_eq!(lines[4], "graph\tS5+_0\t0\t5\tA\tT");
+
+    // Allele 0 of the first bubble is only present in c
+    let mut buffer = Vec::new();
+    File::open("data/output/gfa2bin.graph.bubble.bed")?.read_to_end(&mut buffer)?;
+    assert_eq!(buffer.len(), 3 + (6 * 2));
+    assert_eq!(buffer[3], 48);
+
+    let alleles = fs::read_to_string("data/output/gfa2bin.graph.bubble.alleles.tsv")?;
+    let lines = alleles.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "#ID\tSOURCE\tSINK\tALLELE\tLENGTH\tTRAVERSAL");
+    assert_eq!(lines[1], "S1+_0\t1+\t9+\t0\t15\t2+,4+");
+    assert_eq!(lines[5], "S5+_0\t5+\t8+\t0\t0\t.");
+    fs::remove_file("data/output/gfa2bin.graph.bubble.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble.alleles.tsv")?;
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.bubble_vcf")
+        .arg("-f")
+        .arg("bubble")
+        .arg("--multiallelic")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+    let vcf = fs::read_to_string("data/output/gfa2bin.graph.bubble_vcf.vcf")?;
+    let records = vcf
+        .lines()
+        .filter(|x| !x.starts_with('#'))
+        .collect::<Vec<&str>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(
+        records[0],
+        "graph\t1\tS1+9+\tACCCCCNNNNNNNNNN\tACCCCCNNNNNNNNNNCNNNNNNNNNN,ACCCCCNNNNNNNNNNNNNNNNNNNN,AGNNNNNNNNNN\t.\tPASS\tFEATURE=bubble;ALEN=15,26,25,11\tGT\t2\t1\t0\t3|.\t."
+    );
+    assert_eq!(
+        records[1],
+        "graph\t5\tS5+8+\tN\tNC\t.\tPASS\tFEATURE=bubble;ALEN=0,1\tGT\t0\t1\t.\t.|.\t."
+    );
+    fs::remove_file("data/output/gfa2bin.graph.bubble_vcf.vcf")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble_vcf.alleles.tsv")?;
+
+    // REF is the traversal of the reference (b), not the most frequent one
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.bubble_ref")
+        .arg("-f")
+        .arg("bubble")
+        .arg("--multiallelic")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("b#1#");
+    cmd.assert().success();
+    let vcf = fs::read_to_string("data/output/gfa2bin.graph.bubble_ref.vcf")?;
+    let records = vcf
+        .lines()
+        .filter(|x| !x.starts_with('#'))
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        records[1],
+        "Chr1\t25\tS5+8+\tNC\tN\t.\tPASS\tFEATURE=bubble;ALEN=1,0\tGT\t1\t0\t.\t.|.\t."
+    );
+    fs::remove_file("data/output/gfa2bin.graph.bubble_ref.vcf")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble_ref.alleles.tsv")?;
+
+    // Multiallelic PLINK 2 (same alleles as the VCF, unphased)
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.bubble_pgen")
+        .arg("-f")
+        .arg("bubble")
+        .arg("--multiallelic")
+        .arg("--plink2")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+    let pvar = fs::read_to_string("data/output/gfa2bin.graph.bubble_pgen.pvar")?;
+    let lines = pvar.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[2], "graph\t5\tS5+8+\tN\tNC");
+    let pgen = fs::read("data/output/gfa2bin.graph.bubble_pgen.pgen")?;
+    // Variable-width, 2 variants, 5 samples, allele counts stored (1 byte)
+    assert_eq!(pgen[..12], [108, 27, 16, 2, 0, 0, 0, 5, 0, 0, 0, 144]);
+    // Offset of the records, record types, lengths and allele counts
+    assert_eq!(pgen[12..25], [25, 0, 0, 0, 0, 0, 0, 0, 8, 5, 2, 4, 2]);
+    // a 2/2, b 1/1, c 0/0 (patch: a is 2/2), then a 0/0, b 1/1
+    assert_eq!(pgen[25..], [202, 3, 15, 1, 5, 248, 3]);
+    fs::remove_file("data/output/gfa2bin.graph.bubble_pgen.pgen")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble_pgen.pvar")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble_pgen.psam")?;
+    fs::remove_file("data/output/gfa2bin.graph.bubble_pgen.alleles.tsv")?;
+
+    // No multiallelic BIMBAM
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.bubble_bimbam")
+        .arg("-f")
+        .arg("bubble")
+        .arg("--multiallelic")
+        .arg("--bimbam")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().failure().code(2);
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin graph -f segment"
+///
+/// PAV segments (consecutive nodes with the same genotypes), minimum length
+fn gfa_segments() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_pav.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.segment")
+        .arg("-f")
+        .arg("segment")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("a#1#")
+        .arg("--min-bp")
+        .arg("5");
+    cmd.assert().success();
+
+    // Node 5 (1 bp) is removed
+    let bim = fs::read_to_string("data/output/gfa2bin.graph.segment.bim")?;
+    let lines = bim.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "chr1\tV1-1\t0\t1\tA\tT");
+    assert_eq!(lines[1], "chr1\tV2-4\t0\t21\tA\tT");
+
+    // Nodes 2-4 are present in a and c
+    let mut buffer = Vec::new();
+    File::open("data/output/gfa2bin.graph.segment.bed")?.read_to_end(&mut buffer)?;
+    assert_eq!(buffer.len(), 3 + 2);
+    assert_eq!(buffer[4], 51);
+
+    let segments = fs::read_to_string("data/output/gfa2bin.graph.segment.segments.tsv")?;
+    let lines = segments.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "#ID\tFIRST\tLAST\tNODES\tBP\tCHROM\tSTART\tEND");
+    assert_eq!(lines[2], "V2-4\t2\t4\t3\t10\tchr1\t21\t30");
+    fs::remove_file("data/output/gfa2bin.graph.segment.bed")?;
+    fs::remove_file("data/output/gfa2bin.graph.segment.bim")?;
+    fs::remove_file("data/output/gfa2bin.graph.segment.fam")?;
+    fs::remove_file("data/output/gfa2bin.graph.segment.segments.tsv")?;
+
+    // Minimum length without segments
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_pav.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.segment")
+        .arg("--min-bp")
+        .arg("5");
+    cmd.assert().failure().code(2);
+    Ok(())
+}
+
+#[test]
+/// Copy numbers: node 2 is traversed once (a), twice (a) and three times (b)
+fn gfa_copy_number() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_cnv.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.cn")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--copy-number")
+        .arg("tsv")
+        .arg("--cn-mode");
+    cmd.assert().success();
+
+    let matrix = fs::read_to_string("data/output/gfa2bin.graph.cn.cn.tsv")?;
+    let lines = matrix.lines().collect::<Vec<&str>>();
+    assert_eq!(
+        lines,
+        vec!["#ID\ta\tb\tc", "1\t2\t2\t1", "2\t3\t3\t1", "3\t2\t2\t1"]
+    );
+
+    let modes = fs::read_to_string("data/output/gfa2bin.graph.cn.cn_mode.tsv")?;
+    let lines = modes.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "#ID\tMODE\tSAMPLES");
+    assert_eq!(lines[2], "2\t3\t2");
+    fs::remove_file("data/output/gfa2bin.graph.cn.cn.tsv")?;
+    fs::remove_file("data/output/gfa2bin.graph.cn.cn_mode.tsv")?;
+
+    // Capped BIMBAM
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_cnv.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.cn")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--copy-number")
+        .arg("bimbam")
+        .arg("--cn-cap")
+        .arg("2");
+    cmd.assert().success();
+    let bimbam = fs::read_to_string("data/output/gfa2bin.graph.cn.bimbam")?;
+    assert_eq!(bimbam.lines().nth(1), Some("2, A, T, 2,  2,  1"));
+    fs::remove_file("data/output/gfa2bin.graph.cn.bimbam")?;
+    fs::remove_file("data/output/gfa2bin.graph.cn.pheno")?;
+
+    // NPY: 128 byte header, 3 x 3 float32
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_cnv.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.cn")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--copy-number")
+        .arg("npy");
+    cmd.assert().success();
+    let mut buffer = Vec::new();
+    File::open("data/output/gfa2bin.graph.cn.cn.npy")?.read_to_end(&mut buffer)?;
+    assert_eq!(&buffer[..6], b"\x93NUMPY");
+    assert_eq!(buffer.len(), 128 + 9 * 4);
+    assert_eq!(&buffer[140..144], &3.0f32.to_le_bytes());
+    let samples = fs::read_to_string("data/output/gfa2bin.graph.cn.cn.samples")?;
+    assert_eq!(samples, "a\nb\nc\n");
+    fs::remove_file("data/output/gfa2bin.graph.cn.cn.npy")?;
+    fs::remove_file("data/output/gfa2bin.graph.cn.cn.features")?;
+    fs::remove_file("data/output/gfa2bin.graph.cn.cn.samples")?;
+
+    // PLINK 2 dosages are limited to 2
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_cnv.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.graph.cn")
+        .arg("--copy-number")
+        .arg("plink2")
+        .arg("--cn-cap")
+        .arg("3");
+    cmd.assert().failure().code(2);
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use gfa2bin::core::annotation::{sequence_gc, FeatureAnnotation};
+use gfa2bin::core::bed::{
+    allele_count, transpose_bed, BedReader, BedWriter, SampleMajorWriter, BED_HET, BED_MISSING,
+    BED_PRESENT,
+};
+use gfa2bin::core::dedup::dedup_bed;
+use gfa2bin::core::matrix::GenotypeMatrix;
+use gfa2bin::core::metadata::SampleMetadata;
+use gfa2bin::core::phenotype::PhenotypeTable;
+use gfa2bin::graph::samples::PathOrigin;
+use gfa2bin::{Feature, Gfa2binError, GraphConfig, StatsConfig};
+use std::fs;
+use std::str::FromStr;
+
+#[test]
+/// Test for the library (graph)
+///
+/// Same output as "gfa2bin graph -f node --pansn #"
+fn lib_graph_nodes() -> Result<(), Box<dyn std::error::Error>> {
+    GraphConfig::new(
+        "./data/example_data/gfa/testGraph.gfa",
+        "./data/output/gfa2bin.lib.node",
+    )
+    .feature(Feature::Node)
+    .pansn("#")
+    .run()?;
+
+    let buffer = fs::read("data/output/gfa2bin.lib.node.bed")?;
+    assert_eq!(buffer.len(), 3 + (9 * 2));
+    assert_eq!(buffer[3], 191);
+    assert_eq!(buffer[4], 0);
+
+    StatsConfig {
+        plink_file: "data/output/gfa2bin.lib.node".to_string(),
+        output_prefix: "data/output/gfa2bin.lib.node".to_string(),
+        block_size: 4,
+    }
+    .run()?;
+    let content = fs::read_to_string("data/output/gfa2bin.lib.node.summary.json")?;
+    assert!(content.contains("\"variants\": 9,"));
+
+    for suffix in [
+        "bed",
+        "bim",
+        "fam",
+        "variants.tsv",
+        "samples.tsv",
+        "summary.json",
+    ] {
+        fs::remove_file(format!("data/output/gfa2bin.lib.node.{}", suffix))?;
+    }
+    Ok(())
+}
+
+#[test]
+/// Test for the library (errors)
+///
+/// Unsupported feature and unknown feature names are argument errors
+fn lib_errors() {
+    let result = GraphConfig::new(
+        "./data/example_data/gfa/testGraph.gfa",
+        "./data/output/gfa2bin.lib.error",
+    )
+    .feature(Feature::Block)
+    .run();
+    assert!(matches!(result, Err(Gfa2binError::Argument(_))));
+
+    let feature = Feature::from_str("nodes");
+    assert!(matches!(feature, Err(Gfa2binError::Argument(_))));
+    assert_eq!(feature.unwrap_err().exit_code(), 2);
+}
+
+#[test]
+/// Test for the library (genotype matrix)
+///
+/// Row and column views, bed round trip without conversion
+fn lib_genotype_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let mut matrix = GenotypeMatrix::new(3, 5);
+    matrix.set_genotype(0, 0, BED_PRESENT);
+    matrix.set_genotype(0, 4, BED_HET);
+    matrix.set_genotype(2, 4, BED_MISSING);
+    assert_eq!(matrix.bytes_per_variant(), 2);
+    assert_eq!(matrix.variant(0).len(), 10);
+    assert_eq!(allele_count(matrix.variant(0), 4), Some(1));
+    assert_eq!(
+        matrix.sample(4).iter().collect::<Vec<Option<u8>>>(),
+        vec![Some(1), Some(0), None]
+    );
+    assert_eq!(matrix.raw_variants(0..1), &[3, 2]);
+
+    let mut writer = BedWriter::new("data/output/gfa2bin.lib.matrix.bed")?;
+    writer.write_matrix(&matrix)?;
+    writer.finish()?;
+    let mut reader = BedReader::new("data/output/gfa2bin.lib.matrix.bed", 5, 3, 1)?;
+    assert_eq!(reader.read_matrix()?, matrix);
+    fs::remove_file("data/output/gfa2bin.lib.matrix.bed")?;
+    Ok(())
+}
+
+#[test]
+/// Test for sample-major (individual-major) bed files
+///
+/// Samples are written one by one, reading and transposing returns the SNP-major matrix
+fn lib_sample_major() -> Result<(), Box<dyn std::error::Error>> {
+    let mut matrix = GenotypeMatrix::new(6, 3);
+    matrix.set_genotype(0, 0, BED_PRESENT);
+    matrix.set_genotype(4, 1, BED_HET);
+    matrix.set_genotype(5, 2, BED_MISSING);
+    matrix.set_genotype(5, 0, BED_PRESENT);
+    let transposed = matrix.transpose();
+    assert_eq!(transposed.variants_number(), 3);
+    assert_eq!(transposed.genotype(1, 4), Some(1));
+    assert_eq!(transposed.transpose(), matrix);
+
+    // Without the second sample: the heterozygous genotype is gone, the third sample moves
+    let removed = matrix.remove_samples(&[1]);
+    assert_eq!(removed.samples_number(), 2);
+    assert_eq!(
+        removed.sample(0).iter().collect::<Vec<_>>(),
+        matrix.sample(0).iter().collect::<Vec<_>>()
+    );
+    assert_eq!(removed.genotype(4, 0), Some(0));
+    assert_eq!(removed.genotype(5, 1), None);
+
+    let mut writer = SampleMajorWriter::new("data/output/gfa2bin.lib.sample_major.bed", 6)?;
+    for sample in transposed.variants() {
+        writer.write_sample(&sample.to_bitvec())?;
+    }
+    writer.finish()?;
+
+    // Block size is rounded up to 4 variants (two blocks)
+    let mut reader = BedReader::new("data/output/gfa2bin.lib.sample_major.bed", 3, 6, 3)?;
+    assert!(reader.sample_major);
+    assert_eq!(reader.read_block()?.variants_number(), 4);
+    assert_eq!(reader.read_matrix()?.variant(1), matrix.variant(5));
+
+    transpose_bed(
+        "data/output/gfa2bin.lib.sample_major.bed",
+        "data/output/gfa2bin.lib.snp_major.bed",
+        3,
+        6,
+        3,
+    )?;
+    let mut reader = BedReader::new("data/output/gfa2bin.lib.snp_major.bed", 3, 6, 3)?;
+    assert!(!reader.sample_major);
+    assert_eq!(reader.read_matrix()?, matrix);
+
+    // Deduplicated while streaming: variants 1-3 have the same pattern (all absent)
+    let representatives = dedup_bed(
+        "data/output/gfa2bin.lib.sample_major.bed",
+        "data/output/gfa2bin.lib.dedup.bed",
+        3,
+        6,
+        3,
+    )?;
+    assert_eq!(representatives, vec![0, 1, 1, 1, 4, 5]);
+    let mut reader = BedReader::new("data/output/gfa2bin.lib.dedup.bed", 3, 4, 3)?;
+    assert_eq!(reader.read_matrix()?.variant(3), matrix.variant(5));
+    fs::remove_file("data/output/gfa2bin.lib.sample_major.bed")?;
+    fs::remove_file("data/output/gfa2bin.lib.snp_major.bed")?;
+    fs::remove_file("data/output/gfa2bin.lib.dedup.bed")?;
+    Ok(())
+}
+
+#[test]
+/// Test for the library (phenotype table)
+fn lib_phenotype_table() -> Result<(), Box<dyn std::error::Error>> {
+    let table = PhenotypeTable::from_file("data/example_data/additional_input/phenotypes.tsv")?;
+    assert_eq!(table.names, vec!["height", "weight", "age"]);
+    assert_eq!(table.samples.len(), 5);
+    assert_eq!(table.get("b"), Some(&[None, Some(72.5), Some(41.0)][..]));
+    assert_eq!(table.get("e"), None);
+
+    let (phenotypes, covariates) = table.split_covariates(&["age".to_string()])?;
+    assert_eq!(phenotypes.names, vec!["height", "weight"]);
+    assert_eq!(covariates.unwrap().get("a"), Some(&[Some(30.0)][..]));
+    assert_eq!(
+        table
+            .split_covariates(&["sex".to_string()])
+            .unwrap_err()
+            .exit_code(),
+        2
+    );
+    Ok(())
+}
+
+#[test]
+/// Test for the library (sample metadata)
+fn lib_sample_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata =
+        SampleMetadata::from_file("data/example_data/additional_input/sample_metadata.tsv")?;
+    assert_eq!(metadata.entries.len(), 4);
+    assert_eq!(metadata.get("b").unwrap().sex, 2);
+    assert_eq!(metadata.get("c").unwrap().father_id, "a");
+
+    let samples = vec!["d".to_string(), "e".to_string()];
+    let (entries, missing) = metadata.fam_entries(&samples);
+    assert_eq!(entries[0].to_string(), "fam2\td\t0\t0\t1\t-9");
+    assert_eq!(entries[1].to_string(), "e\te\t0\t0\t0\t-9");
+    assert_eq!(missing, vec!["e"]);
+    Ok(())
+}
+
+#[test]
+/// Feature annotation rows (GC content, NA for columns which do not apply)
+fn lib_feature_annotation() {
+    assert_eq!(sequence_gc("ACGTgcN"), (7, 4));
+    let (length, gc) = sequence_gc("AACG");
+    let annotation = FeatureAnnotation {
+        id: "3+4+".to_string(),
+        length,
+        gc,
+        paths: 2,
+        samples: 1,
+        source_length: Some(1),
+        target_length: Some(3),
+        ..FeatureAnnotation::default()
+    };
+    assert_eq!(annotation.to_string(), "3+4+\t4\t0.5000\t2\t1\t1\t3\tNA");
+    assert_eq!(FeatureAnnotation::default().gc_content(), None);
+}
+
+#[test]
+/// Sample and haplotype of paths (P-lines)
+fn lib_path_origin() {
+    let origin = PathOrigin::from_path_name("HG002#1#chr1#alt", Some("#"));
+    assert_eq!(origin.sample, "HG002");
+    assert_eq!(origin.haplotype, "1");
+    assert_eq!(origin.sequence, "chr1#alt");
+    assert_eq!(origin.pansn_name(), "HG002#1#chr1#alt");
+    assert!(!origin.walk);
+
+    let origin = PathOrigin::from_path_name("HG002#1#chr1", None);
+    assert_eq!(origin.sample, "HG002#1#chr1");
+    assert_eq!(origin.haplotype, "");
+}
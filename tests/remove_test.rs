@@ -38,8 +38,22 @@ fn remove_genotype() -> Result<(), Box<dyn std::error::Error>> {
     let mut buffer = Vec::new();
     b.read_to_end(&mut buffer).unwrap();
     assert_eq!(buffer.len(), 3 + ((9 - 2) * 2));
-    assert_eq!(buffer[3], 127);
+    // a, b, c present (11), d heterozygous (01)
+    assert_eq!(buffer[3], 191);
     //fs::remove_file("data/output/remove.feature.node.fam")?;
+
+    // Genotypes are matched with the variant id (second column), not the position
+    fs::write("data/output/remove.feature.unknown.txt", "graph\nnode1\n")?;
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("remove")
+        .arg("-p")
+        .arg("./data/output/gfa2bin.graph.remove1")
+        .arg("-o")
+        .arg("data/output/remove.feature.unknown")
+        .arg("--genotypes")
+        .arg("data/output/remove.feature.unknown.txt");
+    cmd.assert().failure().code(5);
+    fs::remove_file("data/output/remove.feature.unknown.txt")?;
     Ok(())
 }
 
@@ -77,7 +91,8 @@ fn remove_samples() -> Result<(), Box<dyn std::error::Error>> {
     let mut buffer = Vec::new();
     b.read_to_end(&mut buffer).unwrap();
     assert_eq!(buffer.len(), 3 + 9);
-    assert_eq!(buffer[3], 31);
+    // Without a: b, c present (11), d heterozygous (01)
+    assert_eq!(buffer[3], 47);
     //fs::remove_file("data/output/remove.feature.node.fam")?;
     fs::remove_file("./data/output/gfa2bin.remove.samples.bed")?;
     fs::remove_file("./data/output/gfa2bin.remove.samples.bim")?;
@@ -85,3 +100,51 @@ fn remove_samples() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+/// Remove samples by index
+///
+/// Fam file is reduced, bim file is unchanged
+fn remove_sample_index() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.remove3")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("remove")
+        .arg("-p")
+        .arg("./data/output/gfa2bin.remove3")
+        .arg("-o")
+        .arg("data/output/gfa2bin.remove.sample_index")
+        .arg("--sample-index")
+        .arg("./data/example_data/additional_input/sample_index.txt");
+    cmd.assert().success();
+
+    let fam = fs::read_to_string("data/output/gfa2bin.remove.sample_index.fam")?;
+    assert_eq!(fam.lines().count(), 4);
+    assert!(!fam.lines().any(|x| x.starts_with("b\t")));
+    let bim = fs::read_to_string("data/output/gfa2bin.remove.sample_index.bim")?;
+    assert_eq!(bim.lines().count(), 9);
+
+    let mut b = File::open("data/output/gfa2bin.remove.sample_index.bed").unwrap();
+    let mut buffer = Vec::new();
+    b.read_to_end(&mut buffer).unwrap();
+    assert_eq!(buffer.len(), 3 + 9);
+
+    fs::remove_file("./data/output/gfa2bin.remove3.bed")?;
+    fs::remove_file("./data/output/gfa2bin.remove3.bim")?;
+    fs::remove_file("./data/output/gfa2bin.remove3.fam")?;
+    fs::remove_file("./data/output/gfa2bin.remove.sample_index.bed")?;
+    fs::remove_file("./data/output/gfa2bin.remove.sample_index.bim")?;
+    fs::remove_file("./data/output/gfa2bin.remove.sample_index.fam")?;
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use assert_cmd::prelude::*; // Add methods on commands
+use std::fs;
+
+use std::process::Command;
+
+#[test]
+/// Test for "gfa2bin stats"
+///
+/// Variant and sample summary (TSV + JSON)
+fn stats_test() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.stats")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("stats")
+        .arg("-p")
+        .arg("data/output/gfa2bin.stats")
+        .arg("-o")
+        .arg("data/output/gfa2bin.stats.out");
+    cmd.assert().success();
+
+    // Node 1: a, b, c present, d heterozygous, e absent
+    let variants = fs::read_to_string("data/output/gfa2bin.stats.out.variants.tsv")?;
+    let lines = variants.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 1 + 9);
+    assert_eq!(lines[1], "graph\t1\t1\tnode\t0.7000\t0.2000\t1.0000");
+
+    // d: nodes 1, 3, 4, 5, 9 (1, 5, 9 heterozygous)
+    let samples = fs::read_to_string("data/output/gfa2bin.stats.out.samples.tsv")?;
+    let lines = samples.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 1 + 5);
+    assert_eq!(lines[4], "d\td\t5\t3\t0.3333\t0\t0.0000");
+
+    let summary = fs::read_to_string("data/output/gfa2bin.stats.out.summary.json")?;
+    assert!(summary.contains("\"variants\": 9,"));
+    assert!(summary.contains("\"samples\": 5,"));
+    assert!(summary.contains("\"feature\": \"node\","));
+
+    for suffix in ["bed", "bim", "fam"].iter() {
+        fs::remove_file(format!("data/output/gfa2bin.stats.{}", suffix))?;
+    }
+    for suffix in ["variants.tsv", "samples.tsv", "summary.json"].iter() {
+        fs::remove_file(format!("data/output/gfa2bin.stats.out.{}", suffix))?;
+    }
+
+    Ok(())
+}
@@ -21,3 +21,70 @@ fn subpath_test() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+/// Feature annotation of the subpaths
+///
+/// Node 2 is traversed by a, b and c (same subpath: 1, 2)
+fn subpath_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("subpath")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.subpath.annotation")
+        .arg("--step")
+        .arg("1")
+        .arg("--annotation");
+    cmd.assert().success();
+
+    let annotation = fs::read_to_string("data/output/gfa2bin.subpath.annotation.annotation.tsv")?;
+    let bim = fs::read_to_string("data/output/gfa2bin.subpath.annotation.bim")?;
+    assert_eq!(annotation.lines().count(), bim.lines().count() + 1);
+    assert!(annotation
+        .lines()
+        .any(|x| x == "2_1_0\t5\t1.0000\t3\t3\tNA\tNA\t15"));
+
+    fs::remove_file("data/output/gfa2bin.subpath.annotation.bed")?;
+    fs::remove_file("data/output/gfa2bin.subpath.annotation.bim")?;
+    fs::remove_file("data/output/gfa2bin.subpath.annotation.fam")?;
+    fs::remove_file("data/output/gfa2bin.subpath.annotation.block")?;
+    fs::remove_file("data/output/gfa2bin.subpath.annotation.annotation.tsv")?;
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin subpath --plink2"
+///
+/// Node 5 has three subpaths (2-5, 3-5, 4-5): A 0/1, B 0/2, C 1/2, D 2/2
+fn subpath_plink2_multiallelic() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("subpath")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph_multiallelic.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.subpath.plink2")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--step")
+        .arg("1")
+        .arg("--plink2");
+    cmd.assert().success();
+
+    let pvar = fs::read_to_string("data/output/gfa2bin.subpath.plink2.pvar")?;
+    assert_eq!(pvar.lines().last(), Some("graph\t5\t5_1\t<G0>\t<G1>,<G2>"));
+
+    let pgen = fs::read("data/output/gfa2bin.subpath.plink2.pgen")?;
+    // Variable-width, 4 variants, 4 samples, allele counts stored (1 byte)
+    assert_eq!(pgen[..12], [108, 27, 16, 4, 0, 0, 0, 4, 0, 0, 0, 144]);
+    // Record types (node 5 with multiallelic patch), record lengths, allele counts
+    assert_eq!(pgen[20..30], [0, 128, 1, 1, 1, 5, 2, 2, 2, 3]);
+    // Main track (1, 1, 2, 2), patches: B is 0/2, C is 1/2 and D is 2/2
+    assert_eq!(pgen[pgen.len() - 5..], [165, 0, 2, 3, 2]);
+
+    fs::remove_file("data/output/gfa2bin.subpath.plink2.pgen")?;
+    fs::remove_file("data/output/gfa2bin.subpath.plink2.pvar")?;
+    fs::remove_file("data/output/gfa2bin.subpath.plink2.psam")?;
+    fs::remove_file("data/output/gfa2bin.subpath.plink2.block")?;
+    Ok(())
+}
@@ -28,9 +28,174 @@ fn view_test() -> Result<(), Box<dyn std::error::Error>> {
         .arg("data/output/gfa2bin.view.vcf");
     cmd.assert().success();
 
+    // Genotypes are unphased (the bed file has no phase)
+    let content = fs::read_to_string("data/output/gfa2bin.view.vcf")?;
+    let lines = content.lines().collect::<Vec<&str>>();
+    assert!(lines[7].ends_with("GT\t1/1\t1/1\t1/1\t0/1\t0/0"));
+
     fs::remove_file("data/output/gfa2bin.view.bed")?;
     fs::remove_file("data/output/gfa2bin.view.bim")?;
     fs::remove_file("data/output/gfa2bin.view.fam")?;
 
     Ok(())
 }
+
+#[test]
+/// Convert plink to VCF (bgzip + tabix index)
+///
+/// Header, contig and symbolic alleles
+fn view_bgzip_test() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.view.bgzip")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.view.bgzip")
+        .arg("-o")
+        .arg("data/output/gfa2bin.view.bgzip.vcf");
+    cmd.assert().success();
+
+    let content = fs::read_to_string("data/output/gfa2bin.view.bgzip.vcf")?;
+    let lines = content.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "##fileformat=VCFv4.3");
+    assert!(lines.contains(&"##contig=<ID=graph,length=9>"));
+    assert_eq!(lines.len(), 7 + 9);
+    assert!(lines[7].starts_with("graph\t1\t1\tN\t<PRESENT>\t.\tPASS\tFEATURE=node\tGT"));
+    assert!(lines[7].ends_with("GT\t1/1\t1/1\t1/1\t0/1\t0/0"));
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.view.bgzip")
+        .arg("-o")
+        .arg("data/output/gfa2bin.view.bgzip.vcf.gz")
+        .arg("--bgzip")
+        .arg("--index")
+        .arg("tbi");
+    cmd.assert().success();
+
+    let buffer = fs::read("data/output/gfa2bin.view.bgzip.vcf.gz")?;
+    assert_eq!(buffer[..4], [31, 139, 8, 4]);
+    let buffer = fs::read("data/output/gfa2bin.view.bgzip.vcf.gz.tbi")?;
+    assert_eq!(buffer[..4], [31, 139, 8, 4]);
+
+    fs::remove_file("data/output/gfa2bin.view.bgzip.bed")?;
+    fs::remove_file("data/output/gfa2bin.view.bgzip.bim")?;
+    fs::remove_file("data/output/gfa2bin.view.bgzip.fam")?;
+    fs::remove_file("data/output/gfa2bin.view.bgzip.vcf")?;
+    fs::remove_file("data/output/gfa2bin.view.bgzip.vcf.gz")?;
+    fs::remove_file("data/output/gfa2bin.view.bgzip.vcf.gz.tbi")?;
+
+    Ok(())
+}
+
+#[test]
+/// Convert plink to VCF
+///
+/// Missing genotypes (d, e) are ./.
+fn view_missing_test() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.view.missing")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#")
+        .arg("--reference")
+        .arg("a#1#")
+        .arg("--missing");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.view.missing")
+        .arg("-o")
+        .arg("data/output/gfa2bin.view.missing.vcf");
+    cmd.assert().success();
+
+    let content = fs::read_to_string("data/output/gfa2bin.view.missing.vcf")?;
+    let lines = content.lines().collect::<Vec<&str>>();
+    assert!(lines[7].starts_with("Chr1\t1\t1\t"));
+    assert!(lines[7].ends_with("GT\t1/1\t1/1\t1/1\t./.\t./."));
+
+    fs::remove_file("data/output/gfa2bin.view.missing.bed")?;
+    fs::remove_file("data/output/gfa2bin.view.missing.bim")?;
+    fs::remove_file("data/output/gfa2bin.view.missing.fam")?;
+    fs::remove_file("data/output/gfa2bin.view.missing.vcf")?;
+
+    Ok(())
+}
+
+#[test]
+/// Convert plink to VCF with the ploidy from the graph
+///
+/// a, b, c and e have a single haplotype (haploid), d has two
+fn view_haploid_test() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("graph")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("-o")
+        .arg("./data/output/gfa2bin.view.haploid")
+        .arg("-f")
+        .arg("node")
+        .arg("--pansn")
+        .arg("#");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.view.haploid")
+        .arg("-g")
+        .arg("./data/example_data/gfa/testGraph.gfa")
+        .arg("--pansn")
+        .arg("#")
+        .arg("-o")
+        .arg("data/output/gfa2bin.view.haploid.vcf");
+    cmd.assert().success();
+
+    let content = fs::read_to_string("data/output/gfa2bin.view.haploid.vcf")?;
+    let lines = content.lines().collect::<Vec<&str>>();
+    assert!(lines[7].starts_with("graph\t1\t1\t"));
+    assert!(lines[7].ends_with("GT\t1\t1\t1\t0/1\t0"));
+
+    fs::remove_file("data/output/gfa2bin.view.haploid.bed")?;
+    fs::remove_file("data/output/gfa2bin.view.haploid.bim")?;
+    fs::remove_file("data/output/gfa2bin.view.haploid.fam")?;
+    fs::remove_file("data/output/gfa2bin.view.haploid.vcf")?;
+
+    Ok(())
+}
+
+#[test]
+/// Test for "gfa2bin view"
+///
+/// Missing input is an I/O error (exit code 3)
+fn view_missing_input() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfa2bin")?;
+    cmd.arg("view")
+        .arg("-p")
+        .arg("data/output/gfa2bin.view.does_not_exist")
+        .arg("-o")
+        .arg("data/output/gfa2bin.view.does_not_exist.vcf");
+    cmd.assert()
+        .failure()
+        .code(3)
+        .stderr(predicates::str::contains("I/O error: "));
+    Ok(())
+}